@@ -0,0 +1,247 @@
+use crate::crosscats::CrossCatsError;
+use crate::db::DbError;
+use crate::isbn::IsbnError;
+use crate::referee::UnsupportedEntity;
+use crate::sites::{SiteMatrixUnavailable, UnknownWiki};
+use crate::viaf::ViafError;
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+
+/// Error returned by server handlers, serialized as
+/// `{"error": {"code": <HTTP status>, "message": <reason>}}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+
+    /// Database and site matrix outages are reported as 503, unsupported referee entities
+    /// as 422, unknown wikis as 400, other errors use `fallback`
+    pub fn from_anyhow(error: anyhow::Error, fallback: fn(String) -> Self) -> Self {
+        let error = match error.downcast::<DbError>() {
+            Ok(db_error) => return db_error.into(),
+            Err(error) => error,
+        };
+        let error = match error.downcast::<UnknownWiki>() {
+            Ok(unknown_wiki) => return unknown_wiki.into(),
+            Err(error) => error,
+        };
+        let error = match error.downcast::<SiteMatrixUnavailable>() {
+            Ok(unavailable) => return unavailable.into(),
+            Err(error) => error,
+        };
+        match error.downcast::<UnsupportedEntity>() {
+            Ok(unsupported) => unsupported.into(),
+            Err(error) => fallback(error.to_string()),
+        }
+    }
+
+    pub const fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({"error": {"code": self.status.as_u16(), "message": self.message}})
+    }
+}
+
+/// Library code reports errors as bare status codes; use the canonical reason as message
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        let message = status.canonical_reason().unwrap_or("Unknown error");
+        Self::new(status, message)
+    }
+}
+
+impl From<IsbnError> for ApiError {
+    fn from(error: IsbnError) -> Self {
+        Self::bad_request(error.to_string())
+    }
+}
+
+/// VIAF failures are upstream failures: 504 for timeouts, 502 otherwise
+impl From<ViafError> for ApiError {
+    fn from(error: ViafError) -> Self {
+        let status = match &error {
+            ViafError::Empty => StatusCode::NOT_FOUND,
+            _ if error.is_timeout() => StatusCode::GATEWAY_TIMEOUT,
+            ViafError::Request(_)
+            | ViafError::Status(_)
+            | ViafError::Parse(_)
+            | ViafError::TooLarge(_) => StatusCode::BAD_GATEWAY,
+        };
+        Self::new(status, error.to_string())
+    }
+}
+
+/// Items that are not categories are 422, as they exist but can not be processed
+impl From<CrossCatsError> for ApiError {
+    fn from(error: CrossCatsError) -> Self {
+        match error {
+            CrossCatsError::NotACategory(_) => {
+                Self::new(StatusCode::UNPROCESSABLE_ENTITY, error.to_string())
+            }
+            CrossCatsError::NotFound(_) | CrossCatsError::NoSitelinks(_) => {
+                Self::not_found(error.to_string())
+            }
+            CrossCatsError::UnknownWiki(_) => Self::bad_request(error.to_string()),
+            CrossCatsError::Status(status) => status.into(),
+        }
+    }
+}
+
+impl From<UnknownWiki> for ApiError {
+    fn from(error: UnknownWiki) -> Self {
+        Self::bad_request(error.to_string())
+    }
+}
+
+impl From<SiteMatrixUnavailable> for ApiError {
+    fn from(error: SiteMatrixUnavailable) -> Self {
+        Self::new(StatusCode::SERVICE_UNAVAILABLE, error.to_string())
+    }
+}
+
+impl From<UnsupportedEntity> for ApiError {
+    fn from(error: UnsupportedEntity) -> Self {
+        Self::new(StatusCode::UNPROCESSABLE_ENTITY, error.to_string())
+    }
+}
+
+impl From<DbError> for ApiError {
+    fn from(error: DbError) -> Self {
+        Self::new(StatusCode::SERVICE_UNAVAILABLE, error.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.to_json())).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_error_json_shape() {
+        let error = ApiError::not_found("No such item");
+        assert_eq!(
+            error.to_json(),
+            json!({"error": {"code": 404, "message": "No such item"}})
+        );
+    }
+
+    #[test]
+    fn test_api_error_from_status_code() {
+        let error = ApiError::from(StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(error.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(error.to_json()["error"]["message"], "Service Unavailable");
+    }
+
+    #[test]
+    fn test_api_error_from_isbn_error() {
+        let error = ApiError::from(IsbnError::BadCheckDigit);
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            error.to_json()["error"]["message"],
+            IsbnError::BadCheckDigit.to_string()
+        );
+    }
+
+    #[test]
+    fn test_api_error_from_viaf_error() {
+        let error = ApiError::from(ViafError::Status(500));
+        assert_eq!(error.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            error.to_json()["error"]["message"],
+            "VIAF returned error status: 500"
+        );
+        assert_eq!(
+            ApiError::from(ViafError::Empty).status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn test_api_error_from_crosscats_error() {
+        let error = ApiError::from(CrossCatsError::NotACategory("Q42".to_string()));
+        assert_eq!(error.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            error.to_json()["error"]["message"],
+            "Q42 is not a category item (P31:Q4167836)"
+        );
+        let error = ApiError::from(CrossCatsError::NoSitelinks("Q9649201".to_string()));
+        assert_eq!(error.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            ApiError::from(CrossCatsError::Status(StatusCode::SERVICE_UNAVAILABLE)).status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[test]
+    fn test_api_error_from_anyhow() {
+        let error = ApiError::from_anyhow(anyhow::anyhow!("bad query"), ApiError::bad_request);
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(error.to_json()["error"]["message"], "bad query");
+    }
+
+    #[test]
+    fn test_api_error_from_anyhow_unsupported_entity() {
+        let unsupported = UnsupportedEntity {
+            entity: "Q140".to_string(),
+            property: "P31".to_string(),
+            target: "Q16521".to_string(),
+        };
+        let error = ApiError::from_anyhow(unsupported.into(), ApiError::not_found);
+        assert_eq!(error.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            error.to_json()["error"]["message"],
+            "Q140 is not supported, because it has P31:Q16521"
+        );
+    }
+
+    #[test]
+    fn test_api_error_from_anyhow_unknown_wiki() {
+        let error = ApiError::from_anyhow(UnknownWiki::new("enwikii").into(), ApiError::not_found);
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(error.to_json()["error"]["message"], "Unknown wiki: enwikii");
+    }
+
+    #[test]
+    fn test_api_error_from_anyhow_site_matrix_unavailable() {
+        let error = ApiError::from_anyhow(SiteMatrixUnavailable.into(), ApiError::not_found);
+        assert_eq!(error.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn test_api_error_into_response_status() {
+        let response = ApiError::internal("boom").into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,110 @@
+use reqwest::Response;
+
+/// Maximum size of a downloaded response body, unless `max_body_bytes` is configured
+pub const DEFAULT_MAX_BODY_BYTES: usize = 5 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum BodyError {
+    /// The response body could not be read
+    Request(reqwest::Error),
+    /// The response body is larger than this many bytes
+    TooLarge(usize),
+}
+
+impl std::fmt::Display for BodyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Request(e) => write!(f, "Failed to read response: {e}"),
+            Self::TooLarge(max_bytes) => {
+                write!(f, "Response is larger than {max_bytes} bytes")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BodyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Request(e) => Some(e),
+            Self::TooLarge(_) => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for BodyError {
+    fn from(error: reqwest::Error) -> Self {
+        Self::Request(error)
+    }
+}
+
+/// Reads the body of `response` chunk by chunk, and aborts once it is larger than
+/// `max_bytes`, so a huge download can not exhaust memory.
+pub async fn read_bytes(mut response: Response, max_bytes: usize) -> Result<Vec<u8>, BodyError> {
+    if response
+        .content_length()
+        .is_some_and(|length| length > max_bytes as u64)
+    {
+        return Err(BodyError::TooLarge(max_bytes));
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > max_bytes {
+            return Err(BodyError::TooLarge(max_bytes));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Like `read_bytes`, decoded as UTF-8. Invalid sequences are replaced.
+pub async fn read_text(response: Response, max_bytes: usize) -> Result<String, BodyError> {
+    let body = read_bytes(response, max_bytes).await?;
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+    use reqwest::Client;
+
+    /// Starts a local HTTP server that answers with `body` as HTML. Without `content_length`,
+    /// the body is sent without a length, until the connection is closed.
+    async fn html_server(body: String, content_length: bool) -> String {
+        let length = if content_length {
+            format!("Content-Length: {}\r\n", body.len())
+        } else {
+            String::new()
+        };
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n{length}Connection: close\r\n\r\n{body}"
+        );
+        test_server::raw(move |_| response.clone()).await.0
+    }
+
+    async fn get(url: &str) -> Response {
+        Client::new().get(url).send().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_read_text_within_limit() {
+        let url = html_server("<p>small</p>".to_string(), true).await;
+        assert_eq!(
+            read_text(get(&url).await, 100).await.unwrap(),
+            "<p>small</p>"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_bytes_aborts_over_limit() {
+        let body = "x".repeat(10_000);
+        let url = html_server(body.clone(), true).await;
+        let result = read_bytes(get(&url).await, 1000).await;
+        assert!(matches!(result, Err(BodyError::TooLarge(1000))));
+
+        // Without a Content-Length, the limit applies while streaming
+        let url = html_server(body, false).await;
+        let result = read_bytes(get(&url).await, 1000).await;
+        assert!(matches!(result, Err(BodyError::TooLarge(1000))));
+    }
+}
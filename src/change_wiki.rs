@@ -1,12 +1,244 @@
-use crate::TOOLFORGE_DB;
+use crate::config::Config;
+use crate::db;
+use crate::referee::Referee;
+use crate::sites::{Sites, WikiValidator};
 use anyhow::Result;
-use std::collections::HashMap;
+use futures::future::BoxFuture;
+use mediawiki::Api;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
 use wikimisc::mysql_async::{from_row, prelude::Queryable};
 
+/// Maximum number of titles per API query
+const API_TITLES_PER_QUERY: usize = 50;
+
+/// Page property set on disambiguation pages by the Disambiguator extension
+const DISAMBIGUATION_PAGE_PROP: &str = "disambiguation";
+
+/// How many wiki APIs are kept
+const MAX_CACHED_APIS: usize = 64;
+/// How long a wiki API is kept, before its siteinfo is loaded again
+const CACHED_API_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// One API per wiki, as creating one loads the siteinfo of the wiki
+static WIKI_APIS: LazyLock<ApiCache> =
+    LazyLock::new(|| ApiCache::new(CACHED_API_TTL, MAX_CACHED_APIS));
+
+/// Wiki APIs by URL, for at most `ttl` and `max_entries` wikis
+#[derive(Debug)]
+struct ApiCache {
+    apis: Mutex<HashMap<String, (Instant, Arc<Api>)>>,
+    ttl: Duration,
+    max_entries: usize,
+}
+
+impl ApiCache {
+    fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            apis: Mutex::default(),
+            ttl,
+            max_entries,
+        }
+    }
+
+    /// The API for `api_url`, created if it is not cached. A failed creation is not kept.
+    async fn get(&self, api_url: &str) -> Result<Arc<Api>> {
+        if let Some(api) = self.cached(api_url) {
+            return Ok(api);
+        }
+        let api = Arc::new(Api::new(api_url).await?);
+        self.insert(api_url, api.clone());
+        Ok(api)
+    }
+
+    /// Drops the API for `api_url` if it is expired
+    fn cached(&self, api_url: &str) -> Option<Arc<Api>> {
+        let mut apis = self.apis.lock().ok()?;
+        let (loaded, api) = apis.get(api_url)?;
+        if loaded.elapsed() < self.ttl {
+            return Some(api.clone());
+        }
+        apis.remove(api_url);
+        None
+    }
+
+    /// Drops expired entries first, then the oldest ones, to make room.
+    fn insert(&self, api_url: &str, api: Arc<Api>) {
+        if self.ttl.is_zero() || self.max_entries == 0 {
+            return;
+        }
+        let Ok(mut apis) = self.apis.lock() else {
+            return;
+        };
+        if !apis.contains_key(api_url) && apis.len() >= self.max_entries {
+            apis.retain(|_, (loaded, _)| loaded.elapsed() < self.ttl);
+        }
+        while !apis.contains_key(api_url) && apis.len() >= self.max_entries {
+            let oldest = apis
+                .iter()
+                .min_by_key(|(_, (loaded, _))| *loaded)
+                .map(|(api_url, _)| api_url.to_owned());
+            let Some(oldest) = oldest else {
+                break;
+            };
+            apis.remove(&oldest);
+        }
+        apis.insert(api_url.to_string(), (Instant::now(), api));
+    }
+}
+
+/// Looks up sitelinks in `wb_items_per_site`, so tests can use canned rows.
+/// Returns boxed futures, so the store can be held as `dyn SitelinkStore`.
+pub trait SitelinkStore: std::fmt::Debug + Send + Sync {
+    /// `(page, item)` for each of `titles` that has an item, on `site`
+    fn items_for_pages<'a>(
+        &'a self,
+        site: &'a str,
+        titles: Vec<String>,
+    ) -> BoxFuture<'a, Result<Vec<(String, String)>>>;
+
+    /// `(item, page)` for each of the numeric `item_ids` that has a page on `site`
+    fn pages_for_items<'a>(
+        &'a self,
+        site: &'a str,
+        item_ids: Vec<String>,
+    ) -> BoxFuture<'a, Result<Vec<(String, String)>>>;
+}
+
+/// Queries `wb_items_per_site` on the Wikidata replica
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReplicaSitelinks;
+
+impl SitelinkStore for ReplicaSitelinks {
+    fn items_for_pages<'a>(
+        &'a self,
+        site: &'a str,
+        titles: Vec<String>,
+    ) -> BoxFuture<'a, Result<Vec<(String, String)>>> {
+        Box::pin(Self::query_items_per_site(
+            "ips_site_page,concat('Q',ips_item_id)",
+            "ips_site_page",
+            site,
+            titles,
+        ))
+    }
+
+    fn pages_for_items<'a>(
+        &'a self,
+        site: &'a str,
+        item_ids: Vec<String>,
+    ) -> BoxFuture<'a, Result<Vec<(String, String)>>> {
+        Box::pin(Self::query_items_per_site(
+            "concat('Q',ips_item_id),ips_site_page",
+            "ips_item_id",
+            site,
+            item_ids,
+        ))
+    }
+}
+
+impl ReplicaSitelinks {
+    /// Selects `select` from `wb_items_per_site` for `site`, where `column` is one of `values`
+    async fn query_items_per_site(
+        select: &str,
+        column: &str,
+        site: &str,
+        values: Vec<String>,
+    ) -> Result<Vec<(String, String)>> {
+        // An empty IN () is invalid SQL
+        if values.is_empty() {
+            return Ok(vec![]);
+        }
+        let placeholders: String = std::iter::repeat_n("?", values.len())
+            .collect::<Vec<_>>()
+            .join(",");
+        let sql = format!(
+            "SELECT {select} FROM wb_items_per_site WHERE ips_site_id=? AND {column} IN ({placeholders})"
+        );
+        // Prepend the site as the first positional parameter
+        let mut params: Vec<String> = Vec::with_capacity(values.len() + 1);
+        params.push(site.to_string());
+        params.extend(values);
+        let mut conn = db::get_connection("wikidata").await?;
+        let results = conn
+            .exec_iter(sql, params)
+            .await?
+            .map_and_drop(from_row::<(String, String)>)
+            .await?;
+        drop(conn);
+        Ok(results)
+    }
+}
+
+/// Where `ChangeWiki` checks wiki codes, looks up sitelinks, and loads pages from, so
+/// tests can run without the site matrix, the replica, and the wikis
+#[derive(Clone, Debug)]
+pub struct ChangeWikiSources {
+    wikis: Arc<dyn WikiValidator>,
+    sitelinks: Arc<dyn SitelinkStore>,
+    /// The API for all wikis, instead of their own ones, if set
+    api_url: Option<String>,
+}
+
+impl Default for ChangeWikiSources {
+    /// The site matrix, the replica, and the API of each wiki
+    fn default() -> Self {
+        Self::new(Arc::new(Sites), Arc::new(ReplicaSitelinks), None)
+    }
+}
+
+impl ChangeWikiSources {
+    pub fn new(
+        wikis: Arc<dyn WikiValidator>,
+        sitelinks: Arc<dyn SitelinkStore>,
+        api_url: Option<String>,
+    ) -> Self {
+        Self {
+            wikis,
+            sitelinks,
+            api_url,
+        }
+    }
+
+    /// The shared API of `wiki`
+    async fn api(&self, wiki: &str) -> Result<Arc<Api>> {
+        let api_url = self.api_url.clone().unwrap_or_else(|| {
+            let server = Referee::get_web_server_for_wiki(wiki);
+            format!("https://{server}/w/api.php")
+        });
+        WIKI_APIS.get(&api_url).await
+    }
+}
+
+/// A target page of `convert_all_with_disambiguations`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TargetPage {
+    pub title: String,
+    pub disambiguation: bool,
+}
+
+/// The item for a single page title, and the title it was found under
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TitleItem {
+    pub item: String,
+    /// The normalized title, or the redirect target
+    pub resolved_title: String,
+    pub via_redirect: bool,
+}
+
 #[derive(Debug)]
 pub struct ChangeWiki {
     wiki_from: String,
     titles: Vec<String>,
+    resolve_redirects: bool,
+    sources: ChangeWikiSources,
+    /// Lowercase namespace names and aliases of `wiki_from`, from its siteinfo
+    namespaces: OnceCell<HashSet<String>>,
 }
 
 impl ChangeWiki {
@@ -14,11 +246,29 @@ impl ChangeWiki {
         ChangeWiki {
             wiki_from: Self::normalize_wiki(wiki_from),
             titles,
+            resolve_redirects: false,
+            sources: ChangeWikiSources::default(),
+            namespaces: OnceCell::new(),
         }
     }
 
+    /// Uses `sources` instead of the site matrix, the replica, and the wikis
+    pub fn with_sources(mut self, sources: ChangeWikiSources) -> Self {
+        self.sources = sources;
+        self
+    }
+
+    /// Resolve redirects on the source wiki before looking up titles. Off by default.
+    pub const fn with_redirect_resolution(mut self, resolve_redirects: bool) -> Self {
+        self.resolve_redirects = resolve_redirects;
+        self
+    }
+
+    /// Fails with `UnknownWiki` for wikis that do not exist, or are not allowed
     pub async fn convert(&self, wiki_to: &str) -> Result<HashMap<String, String>> {
         let wiki_to = Self::normalize_wiki(wiki_to);
+        self.sources.wikis.validate(&self.wiki_from).await?;
+        self.sources.wikis.validate(&wiki_to).await?;
         if self.wiki_from == wiki_to {
             return Ok(self
                 .titles
@@ -33,7 +283,7 @@ impl ChangeWiki {
         } else {
             let site2wd = self.site2wd().await?;
             let items = site2wd.values().cloned().collect();
-            let tmp = Self::new("wikidatawiki", items);
+            let tmp = Self::new("wikidatawiki", items).with_sources(self.sources.clone());
             let wd2site = tmp.wd2site(&wiki_to).await?;
             Ok(site2wd
                 .iter()
@@ -45,59 +295,336 @@ impl ChangeWiki {
         }
     }
 
+    /// Like `convert`, but maps every input title, in input order, to its target
+    /// page, or `None` if there is no mapping.
+    pub async fn convert_all(&self, wiki_to: &str) -> Result<Vec<(String, Option<String>)>> {
+        let results = self.convert(wiki_to).await?;
+        Ok(self.with_missing(&results))
+    }
+
+    /// Like `convert_all`, but also reports whether each target page is a disambiguation
+    /// page on `wiki_to`. Wikidata items are never disambiguation pages.
+    pub async fn convert_all_with_disambiguations(
+        &self,
+        wiki_to: &str,
+    ) -> Result<Vec<(String, Option<TargetPage>)>> {
+        let results = self.convert_all(wiki_to).await?;
+        let wiki_to = Self::normalize_wiki(wiki_to);
+        let disambiguations = if wiki_to == "wikidatawiki" {
+            HashSet::new()
+        } else {
+            let targets: Vec<String> = results
+                .iter()
+                .filter_map(|(_, target)| target.clone())
+                .collect();
+            self.load_disambiguation_pages(&wiki_to, &targets).await?
+        };
+        Ok(Self::with_disambiguations(results, &disambiguations))
+    }
+
+    fn with_disambiguations(
+        results: Vec<(String, Option<String>)>,
+        disambiguations: &HashSet<String>,
+    ) -> Vec<(String, Option<TargetPage>)> {
+        results
+            .into_iter()
+            .map(|(title, target)| {
+                let target = target.map(|target| TargetPage {
+                    disambiguation: disambiguations.contains(&target),
+                    title: target,
+                });
+                (title, target)
+            })
+            .collect()
+    }
+
+    fn with_missing(&self, results: &HashMap<String, String>) -> Vec<(String, Option<String>)> {
+        self.titles
+            .iter()
+            .map(|title| {
+                let target = results
+                    .get(title)
+                    .or_else(|| results.get(&self.lookup_key(title)))
+                    .cloned();
+                (title.to_owned(), target)
+            })
+            .collect()
+    }
+
+    /// The key under which `convert` reports the result for an input title
+    fn lookup_key(&self, title: &str) -> String {
+        if self.wiki_from == "wikidatawiki" {
+            title.to_string()
+        } else {
+            self.normalize(title)
+        }
+    }
+
+    /// The item for the page `title` on `wiki`, following a redirect, or `None` if there is
+    /// no such page, or it has no item.
+    pub async fn title_to_item(
+        sources: &ChangeWikiSources,
+        wiki: &str,
+        title: &str,
+    ) -> Result<Option<TitleItem>> {
+        let change_wiki = Self::new(wiki, vec![title.to_string()]).with_sources(sources.clone());
+        sources.wikis.validate(&change_wiki.wiki_from).await?;
+        let redirects = change_wiki.load_redirects().await?;
+        let normalized_title = change_wiki.normalize(title);
+        let results = change_wiki.site2wd_with_redirects(&redirects).await?;
+        Ok(Self::title_item(&normalized_title, &results, &redirects))
+    }
+
+    fn title_item(
+        normalized_title: &str,
+        results: &HashMap<String, String>,
+        redirects: &HashMap<String, String>,
+    ) -> Option<TitleItem> {
+        let item = results.get(normalized_title)?;
+        let redirect = redirects.get(normalized_title);
+        Some(TitleItem {
+            item: item.to_owned(),
+            resolved_title: redirect
+                .map_or(normalized_title, String::as_str)
+                .to_string(),
+            via_redirect: redirect.is_some(),
+        })
+    }
+
     async fn wd2site(&self, wiki_to: &str) -> Result<HashMap<String, String>> {
-        let mut conn = TOOLFORGE_DB.get_connection("wikidata").await?;
-        let mut ret: HashMap<String, String> = HashMap::new();
-        for chunk in self.titles.chunks(5000) {
-            let item_ids: Vec<String> = chunk.iter().map(|t| t[1..].to_string()).collect();
-            let placeholders: String = std::iter::repeat_n("?", item_ids.len())
-                .collect::<Vec<_>>()
-                .join(",");
-            let sql = format!(
-                "SELECT concat('Q',ips_item_id),ips_site_page FROM wb_items_per_site WHERE ips_site_id=? AND ips_item_id IN ({placeholders})"
-            );
-            // Prepend wiki_to as the first positional parameter
-            let mut params: Vec<String> = Vec::with_capacity(item_ids.len() + 1);
-            params.push(wiki_to.to_string());
-            params.extend(item_ids);
-            let results = conn
-                .exec_iter(sql, params)
-                .await?
-                .map_and_drop(from_row::<(String, String)>)
-                .await?;
-            ret.extend(results);
+        let item_ids: Vec<String> = self.titles.iter().map(|t| t[1..].to_string()).collect();
+        Self::query_in_chunks(&item_ids, Config::db_chunk_size(), |chunk| {
+            self.sources.sitelinks.pages_for_items(wiki_to, chunk)
+        })
+        .await
+    }
+
+    async fn site2wd(&self) -> Result<HashMap<String, String>> {
+        let redirects = if self.resolve_redirects {
+            self.load_redirects().await?
+        } else {
+            HashMap::new()
+        };
+        self.site2wd_with_redirects(&redirects).await
+    }
+
+    /// Looks up the redirect targets of the titles in `redirects`, keyed by source title
+    async fn site2wd_with_redirects(
+        &self,
+        redirects: &HashMap<String, String>,
+    ) -> Result<HashMap<String, String>> {
+        self.load_namespaces().await?;
+        let lookup_titles: Vec<String> = self
+            .titles
+            .iter()
+            .map(|t| {
+                let t = self.normalize(t);
+                redirects.get(&t).cloned().unwrap_or(t)
+            })
+            .collect();
+        let mut ret = Self::query_in_chunks(&lookup_titles, Config::db_chunk_size(), |chunk| {
+            self.sources
+                .sitelinks
+                .items_for_pages(&self.wiki_from, chunk)
+        })
+        .await?;
+        self.apply_redirects(&mut ret, redirects);
+        Ok(ret)
+    }
+
+    /// Runs `query` for each chunk of at most `chunk_size` values, and merges the results.
+    async fn query_in_chunks<F, Fut>(
+        values: &[String],
+        chunk_size: usize,
+        mut query: F,
+    ) -> Result<HashMap<String, String>>
+    where
+        F: FnMut(Vec<String>) -> Fut,
+        Fut: Future<Output = Result<Vec<(String, String)>>>,
+    {
+        let mut ret = HashMap::new();
+        for chunk in values.chunks(chunk_size.max(1)) {
+            ret.extend(query(chunk.to_vec()).await?);
         }
-        drop(conn);
         Ok(ret)
     }
 
-    async fn site2wd(&self) -> Result<HashMap<String, String>> {
-        let wiki_from = &self.wiki_from;
-        let mut conn = TOOLFORGE_DB.get_connection("wikidata").await?;
-        let mut ret: HashMap<String, String> = HashMap::new();
-        for chunk in self.titles.chunks(5000) {
-            let titles: Vec<String> = chunk.iter().map(|t| t.replace('_', " ")).collect();
-            let placeholders: String = std::iter::repeat_n("?", titles.len())
-                .collect::<Vec<_>>()
-                .join(",");
-            let sql = format!(
-                "SELECT ips_site_page,concat('Q',ips_item_id) FROM wb_items_per_site WHERE ips_site_id=? AND ips_site_page IN ({placeholders})"
-            );
-            // Prepend wiki_from as the first positional parameter
-            let mut params: Vec<String> = Vec::with_capacity(titles.len() + 1);
-            params.push(wiki_from.to_string());
-            params.extend(titles);
-            let results = conn
-                .exec_iter(sql, params)
-                .await?
-                .map_and_drop(from_row::<(String, String)>)
-                .await?;
-            ret.extend(results);
+    /// Maps each source title (with spaces) to its redirect target on the source wiki.
+    /// Titles that are not redirects are not included.
+    async fn load_redirects(&self) -> Result<HashMap<String, String>> {
+        self.load_namespaces().await?;
+        let api = self.sources.api(&self.wiki_from).await?;
+        let titles: Vec<String> = self.titles.iter().map(|t| self.normalize(t)).collect();
+        let mut ret = HashMap::new();
+        for chunk in titles.chunks(API_TITLES_PER_QUERY) {
+            let params = api.params_into(&[
+                ("action", "query"),
+                ("titles", &chunk.join("|")),
+                ("redirects", "1"),
+            ]);
+            let result = api.get_query_api_json(&params).await?;
+            ret.extend(Self::parse_redirects(chunk, &result));
+        }
+        Ok(ret)
+    }
+
+    /// The titles on `wiki` that are disambiguation pages
+    async fn load_disambiguation_pages(
+        &self,
+        wiki: &str,
+        titles: &[String],
+    ) -> Result<HashSet<String>> {
+        let api = self.sources.api(wiki).await?;
+        let mut ret = HashSet::new();
+        for chunk in titles.chunks(API_TITLES_PER_QUERY) {
+            let params = api.params_into(&[
+                ("action", "query"),
+                ("titles", &chunk.join("|")),
+                ("prop", "pageprops"),
+                ("ppprop", DISAMBIGUATION_PAGE_PROP),
+            ]);
+            let result = api.get_query_api_json(&params).await?;
+            ret.extend(Self::parse_disambiguation_pages(&result));
         }
-        drop(conn);
         Ok(ret)
     }
 
+    /// Pages are keyed by page ID, or a list with `formatversion=2`
+    fn parse_disambiguation_pages(result: &Value) -> HashSet<String> {
+        let pages: Vec<&Value> = match &result["query"]["pages"] {
+            Value::Object(pages) => pages.values().collect(),
+            Value::Array(pages) => pages.iter().collect(),
+            _ => vec![],
+        };
+        pages
+            .into_iter()
+            .filter(|page| page["pageprops"].get(DISAMBIGUATION_PAGE_PROP).is_some())
+            .filter_map(|page| page["title"].as_str().map(str::to_string))
+            .collect()
+    }
+
+    /// Follows the API `normalized` and `redirects` mappings for each title
+    fn parse_redirects(titles: &[String], result: &Value) -> HashMap<String, String> {
+        let mapping = |key: &str| -> HashMap<String, String> {
+            result["query"][key]
+                .as_array()
+                .map(|entries| {
+                    entries
+                        .iter()
+                        .filter_map(|e| {
+                            Some((
+                                e["from"].as_str()?.to_string(),
+                                e["to"].as_str()?.to_string(),
+                            ))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let normalized = mapping("normalized");
+        let redirects = mapping("redirects");
+        titles
+            .iter()
+            .filter_map(|title| {
+                let normalized_title = normalized.get(title).unwrap_or(title);
+                let target = redirects.get(normalized_title)?;
+                Some((title.to_owned(), target.to_owned()))
+            })
+            .collect()
+    }
+
+    /// Re-keys results for redirect targets to the source titles that were requested
+    fn apply_redirects(
+        &self,
+        ret: &mut HashMap<String, String>,
+        redirects: &HashMap<String, String>,
+    ) {
+        let requested: Vec<String> = self.titles.iter().map(|t| self.normalize(t)).collect();
+        for (source, target) in redirects {
+            let item = if requested.contains(target) {
+                ret.get(target).cloned()
+            } else {
+                ret.remove(target)
+            };
+            if let Some(item) = item {
+                ret.insert(source.to_owned(), item);
+            }
+        }
+    }
+
+    /// Loads the namespaces of the source wiki, once. Titles are normalized without
+    /// namespace prefixes until then.
+    async fn load_namespaces(&self) -> Result<&HashSet<String>> {
+        self.namespaces
+            .get_or_try_init(|| async {
+                let api = self.sources.api(&self.wiki_from).await?;
+                Ok::<_, anyhow::Error>(Self::parse_namespaces(api.get_site_info()))
+            })
+            .await
+    }
+
+    /// The lowercase namespace names, canonical names, and aliases in a siteinfo result.
+    /// The main namespace has no name, so it is not included.
+    fn parse_namespaces(site_info: &Value) -> HashSet<String> {
+        let name_keys = ["name", "canonical", "alias", "*"];
+        let namespaces = site_info["query"]["namespaces"]
+            .as_object()
+            .into_iter()
+            .flat_map(|namespaces| namespaces.values());
+        let aliases = site_info["query"]["namespacealiases"]
+            .as_array()
+            .into_iter()
+            .flatten();
+        namespaces
+            .chain(aliases)
+            .flat_map(|namespace| name_keys.iter().filter_map(|key| namespace[key].as_str()))
+            .filter(|name| !name.is_empty())
+            .map(|name| name.replace('_', " ").to_lowercase())
+            .collect()
+    }
+
+    /// Normalizes a title of the source wiki, with its namespaces if they are loaded
+    fn normalize(&self, title: &str) -> String {
+        let no_namespaces = HashSet::new();
+        let namespaces = self.namespaces.get().unwrap_or(&no_namespaces);
+        Self::normalize_title(&self.wiki_from, title, namespaces)
+    }
+
+    /// Normalizes a page title the way `MediaWiki` does: underscores become spaces,
+    /// whitespace runs are collapsed, and the first letter of the title (after a
+    /// namespace prefix) is uppercased. Wiktionaries are case-sensitive, so their
+    /// first letters are left alone.
+    /// Only prefixes in `namespaces` (lowercase) count as namespaces, as in `category:Foo`;
+    /// `Star Wars: Episode I` is left as-is.
+    pub fn normalize_title(wiki: &str, title: &str, namespaces: &HashSet<String>) -> String {
+        let title = title
+            .replace('_', " ")
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if wiki.ends_with("wiktionary") {
+            return title;
+        }
+        match title.split_once(':') {
+            Some((namespace, page)) if namespaces.contains(&namespace.trim().to_lowercase()) => {
+                format!(
+                    "{}:{}",
+                    Self::uppercase_first(namespace.trim()),
+                    Self::uppercase_first(page.trim_start())
+                )
+            }
+            _ => Self::uppercase_first(&title),
+        }
+    }
+
+    fn uppercase_first(s: &str) -> String {
+        let mut chars = s.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        }
+    }
+
     /// Normalize a wiki name to a safe lowercase string of only ASCII letters and underscores.
     fn normalize_wiki(wiki: &str) -> String {
         wiki.trim()
@@ -109,13 +636,105 @@ impl ChangeWiki {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::TOOLFORGE_DB;
+    use crate::sites::UnknownWiki;
+    use crate::test_server;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     async fn check_db_connection() -> bool {
         TOOLFORGE_DB.get_connection("termstore").await.is_ok()
     }
 
+    /// Rows of `wb_items_per_site`: site, page, item
+    const SITELINKS: &[(&str, &str, &str)] = &[
+        ("enwiki", "Magnus Manske", "Q13520818"),
+        ("enwiki", "United Kingdom", "Q145"),
+        ("enwiki", "Paris", "Q90"),
+        ("dewiki", "Vereinigtes Königreich", "Q145"),
+        ("frwiki", "Paris", "Q90"),
+    ];
+
+    /// Redirects on every wiki of the canned API: source, target
+    const REDIRECTS: &[(&str, &str)] = &[("UK", "United Kingdom")];
+
+    /// Accepts the wikis in `SITELINKS`, and Wikidata
+    #[derive(Debug)]
+    struct KnownWikis;
+
+    impl WikiValidator for KnownWikis {
+        fn validate<'a>(&'a self, wiki: &'a str) -> BoxFuture<'a, Result<()>> {
+            let known =
+                wiki == "wikidatawiki" || SITELINKS.iter().any(|(site, _, _)| *site == wiki);
+            Box::pin(async move {
+                if known {
+                    Ok(())
+                } else {
+                    Err(UnknownWiki::new(wiki).into())
+                }
+            })
+        }
+    }
+
+    /// Answers from `SITELINKS`
+    #[derive(Debug)]
+    struct CannedSitelinks;
+
+    impl SitelinkStore for CannedSitelinks {
+        fn items_for_pages<'a>(
+            &'a self,
+            site: &'a str,
+            titles: Vec<String>,
+        ) -> BoxFuture<'a, Result<Vec<(String, String)>>> {
+            let rows: Vec<(String, String)> = SITELINKS
+                .iter()
+                .filter(|(s, page, _)| *s == site && titles.iter().any(|t| t.as_str() == *page))
+                .map(|(_, page, item)| (page.to_string(), item.to_string()))
+                .collect();
+            Box::pin(async { Ok(rows) })
+        }
+
+        fn pages_for_items<'a>(
+            &'a self,
+            site: &'a str,
+            item_ids: Vec<String>,
+        ) -> BoxFuture<'a, Result<Vec<(String, String)>>> {
+            let rows: Vec<(String, String)> = SITELINKS
+                .iter()
+                .filter(|(s, _, item)| *s == site && item_ids.iter().any(|id| item[1..] == **id))
+                .map(|(_, page, item)| (item.to_string(), page.to_string()))
+                .collect();
+            Box::pin(async { Ok(rows) })
+        }
+    }
+
+    /// A wiki API that follows `REDIRECTS`
+    async fn canned_redirects_api() -> String {
+        test_server::wiki_api(|params| {
+            params.get("redirects")?;
+            let redirects: Vec<Value> = params
+                .get("titles")?
+                .split('|')
+                .filter_map(|title| REDIRECTS.iter().find(|(source, _)| *source == title))
+                .map(|(source, target)| json!({"from": source, "to": target}))
+                .collect();
+            Some(json!({"batchcomplete": "", "query": {"redirects": redirects}}))
+        })
+        .await
+    }
+
+    /// Sources with the sitelinks in `SITELINKS`, and the redirects in `REDIRECTS`
+    pub(crate) async fn canned_sources() -> ChangeWikiSources {
+        let api_url = canned_redirects_api().await;
+        ChangeWikiSources::new(
+            Arc::new(KnownWikis),
+            Arc::new(CannedSitelinks),
+            Some(api_url),
+        )
+    }
+
     #[tokio::test]
     async fn test_wd2site() {
         if !check_db_connection().await {
@@ -140,6 +759,441 @@ mod tests {
         assert_eq!(result.get("Magnus Manske").unwrap(), "Q13520818");
     }
 
+    #[tokio::test]
+    async fn test_site2wd_resolves_redirects() {
+        let sources = canned_sources().await;
+        // "UK" is a redirect to "United Kingdom"
+        let change_wiki = ChangeWiki::new("enwiki", vec!["UK".to_string()])
+            .with_sources(sources.clone())
+            .with_redirect_resolution(true);
+        let result = change_wiki.site2wd().await.unwrap();
+        assert_eq!(result.get("UK").map(String::as_str), Some("Q145"));
+
+        let change_wiki = ChangeWiki::new("enwiki", vec!["UK".to_string()]).with_sources(sources);
+        let result = change_wiki.site2wd().await.unwrap();
+        assert!(result.get("UK").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_title_to_item() {
+        let sources = canned_sources().await;
+        let result = ChangeWiki::title_to_item(&sources, "enwiki", "Magnus_Manske")
+            .await
+            .unwrap();
+        assert_eq!(
+            result,
+            Some(TitleItem {
+                item: "Q13520818".to_string(),
+                resolved_title: "Magnus Manske".to_string(),
+                via_redirect: false,
+            })
+        );
+        let result = ChangeWiki::title_to_item(&sources, "enwiki", "UK")
+            .await
+            .unwrap();
+        assert_eq!(
+            result.map(|ti| (ti.item, ti.via_redirect)),
+            Some(("Q145".to_string(), true))
+        );
+        let result = ChangeWiki::title_to_item(&sources, "enwiki", "No such page")
+            .await
+            .unwrap();
+        assert_eq!(result, None);
+        assert!(
+            ChangeWiki::title_to_item(&sources, "enwikii", "UK")
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_title_item_via_redirect() {
+        let results = HashMap::from([("UK".to_string(), "Q145".to_string())]);
+        let redirects = HashMap::from([("UK".to_string(), "United Kingdom".to_string())]);
+        let title_item = ChangeWiki::title_item("UK", &results, &redirects).unwrap();
+        assert_eq!(title_item.resolved_title, "United Kingdom");
+        assert!(title_item.via_redirect);
+        assert_eq!(ChangeWiki::title_item("Paris", &results, &redirects), None);
+    }
+
+    // ── query_in_chunks ───────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_query_in_chunks_merges_chunks() {
+        let values = vec!["Q1".to_string(), "Q2".to_string()];
+        let mut calls = vec![];
+        let result = ChangeWiki::query_in_chunks(&values, 1, |chunk| {
+            calls.push(chunk.clone());
+            async move {
+                Ok(chunk
+                    .into_iter()
+                    .map(|q| (q.clone(), format!("Page {q}")))
+                    .collect())
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(calls, vec![vec!["Q1".to_string()], vec!["Q2".to_string()]]);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result["Q1"], "Page Q1");
+        assert_eq!(result["Q2"], "Page Q2");
+    }
+
+    #[tokio::test]
+    async fn test_query_in_chunks_empty_input_runs_no_query() {
+        let mut calls = 0;
+        let result = ChangeWiki::query_in_chunks(&[], 5000, |_chunk| {
+            calls += 1;
+            async { Ok(vec![]) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(calls, 0);
+        assert!(result.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_query_items_per_site_empty_values() {
+        let result = ReplicaSitelinks::query_items_per_site(
+            "ips_site_page",
+            "ips_item_id",
+            "enwiki",
+            vec![],
+        )
+        .await
+        .unwrap();
+        assert!(result.is_empty());
+    }
+
+    // ── redirects ─────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_parse_redirects_follows_normalization() {
+        let titles = vec!["uK".to_string(), "Paris".to_string()];
+        let result = serde_json::json!({"query": {
+            "normalized": [{"from": "uK", "to": "UK"}],
+            "redirects": [{"from": "UK", "to": "United Kingdom"}],
+        }});
+        let redirects = ChangeWiki::parse_redirects(&titles, &result);
+        assert_eq!(redirects.len(), 1);
+        assert_eq!(redirects["uK"], "United Kingdom");
+    }
+
+    #[test]
+    fn test_apply_redirects_rekeys_to_source_title() {
+        let mut ret = HashMap::from([
+            ("United Kingdom".to_string(), "Q145".to_string()),
+            ("Paris".to_string(), "Q90".to_string()),
+        ]);
+        let redirects = HashMap::from([("UK".to_string(), "United Kingdom".to_string())]);
+        let cw = ChangeWiki::new("enwiki", vec!["UK".to_string(), "Paris".to_string()]);
+        cw.apply_redirects(&mut ret, &redirects);
+        assert_eq!(ret.len(), 2);
+        assert_eq!(ret["UK"], "Q145");
+        assert_eq!(ret["Paris"], "Q90");
+    }
+
+    #[test]
+    fn test_apply_redirects_keeps_requested_target() {
+        let mut ret = HashMap::from([("United Kingdom".to_string(), "Q145".to_string())]);
+        let redirects = HashMap::from([("UK".to_string(), "United Kingdom".to_string())]);
+        let cw = ChangeWiki::new(
+            "enwiki",
+            vec!["UK".to_string(), "United_Kingdom".to_string()],
+        );
+        cw.apply_redirects(&mut ret, &redirects);
+        assert_eq!(ret["UK"], "Q145");
+        assert_eq!(ret["United Kingdom"], "Q145");
+    }
+
+    // ── disambiguation pages ──────────────────────────────────────────────────
+
+    #[test]
+    fn test_parse_disambiguation_pages() {
+        let result = serde_json::json!({"query": {"pages": {
+            "19694": {"pageid": 19694, "ns": 0, "title": "Mercury", "pageprops": {"disambiguation": ""}},
+            "22989": {"pageid": 22989, "ns": 0, "title": "Paris"},
+        }}});
+        assert_eq!(
+            ChangeWiki::parse_disambiguation_pages(&result),
+            HashSet::from(["Mercury".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_with_disambiguations_flags_targets() {
+        let results = vec![
+            ("Merkur".to_string(), Some("Mercury".to_string())),
+            ("Paris".to_string(), Some("Paris".to_string())),
+            ("Xyz".to_string(), None),
+        ];
+        let disambiguations = HashSet::from(["Mercury".to_string()]);
+        let flagged = ChangeWiki::with_disambiguations(results, &disambiguations);
+        assert_eq!(
+            flagged[0].1,
+            Some(TargetPage {
+                title: "Mercury".to_string(),
+                disambiguation: true,
+            })
+        );
+        assert_eq!(flagged[1].1.as_ref().map(|t| t.disambiguation), Some(false));
+        assert_eq!(flagged[2].1, None);
+    }
+
+    /// A wiki API that flags the titles in `disambiguations` as disambiguation pages
+    async fn canned_pageprops_api(disambiguations: &'static [&'static str]) -> String {
+        crate::test_server::wiki_api(move |params| {
+            let pages: Vec<Value> = params
+                .get("titles")?
+                .split('|')
+                .map(|title| {
+                    let mut page = serde_json::json!({"ns": 0, "title": title});
+                    if disambiguations.contains(&title) {
+                        page["pageprops"] = serde_json::json!({"disambiguation": ""});
+                    }
+                    page
+                })
+                .collect();
+            Some(serde_json::json!({"query": {"pages": pages}}))
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_load_disambiguation_pages() {
+        let api_url = canned_pageprops_api(&["Mercury"]).await;
+        let sources = ChangeWikiSources::new(
+            Arc::new(KnownWikis),
+            Arc::new(CannedSitelinks),
+            Some(api_url),
+        );
+        let cw = ChangeWiki::new("dewiki", vec![]).with_sources(sources);
+        let titles = vec!["Mercury".to_string(), "Paris".to_string()];
+        let disambiguations = cw
+            .load_disambiguation_pages("enwiki", &titles)
+            .await
+            .unwrap();
+        assert_eq!(disambiguations, HashSet::from(["Mercury".to_string()]));
+        let results = vec![
+            ("Mercury".to_string(), Some("Mercury".to_string())),
+            ("Paris".to_string(), Some("Paris".to_string())),
+        ];
+        let flagged = ChangeWiki::with_disambiguations(results, &disambiguations);
+        assert_eq!(flagged[0].1.as_ref().map(|t| t.disambiguation), Some(true));
+        assert_eq!(flagged[1].1.as_ref().map(|t| t.disambiguation), Some(false));
+    }
+
+    // ── convert_all ───────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_with_missing_keeps_input_order_and_nulls() {
+        let titles = vec![
+            "No_such_page_xyz".to_string(),
+            "paris".to_string(),
+            "Berlin".to_string(),
+        ];
+        let cw = ChangeWiki::new("enwiki", titles);
+        let results = HashMap::from([
+            ("Paris".to_string(), "Q90".to_string()),
+            ("Berlin".to_string(), "Q64".to_string()),
+        ]);
+        assert_eq!(
+            cw.with_missing(&results),
+            vec![
+                ("No_such_page_xyz".to_string(), None),
+                ("paris".to_string(), Some("Q90".to_string())),
+                ("Berlin".to_string(), Some("Q64".to_string())),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_convert_all_reports_missing_titles() {
+        let titles = vec![
+            "Paris".to_string(),
+            "This page does not exist 8d7f6a".to_string(),
+        ];
+        let cw = ChangeWiki::new("enwiki", titles).with_sources(canned_sources().await);
+        let result = cw.convert_all("wikidatawiki").await.unwrap();
+        assert_eq!(
+            result,
+            vec![
+                ("Paris".to_string(), Some("Q90".to_string())),
+                ("This page does not exist 8d7f6a".to_string(), None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_convert_between_wikis_via_items() {
+        let titles = vec!["UK".to_string(), "Paris".to_string()];
+        let cw = ChangeWiki::new("enwiki", titles)
+            .with_sources(canned_sources().await)
+            .with_redirect_resolution(true);
+        let result = cw.convert_all("dewiki").await.unwrap();
+        assert_eq!(
+            result,
+            vec![
+                ("UK".to_string(), Some("Vereinigtes Königreich".to_string())),
+                ("Paris".to_string(), None),
+            ]
+        );
+    }
+
+    // ── API cache ─────────────────────────────────────────────────────────────
+
+    /// A wiki API, and the number of siteinfo requests to it
+    async fn counting_siteinfo_api() -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let api_hits = hits.clone();
+        let api_url = test_server::wiki_api(move |params| {
+            if params.get("meta").map(String::as_str) == Some("siteinfo") {
+                api_hits.fetch_add(1, Ordering::SeqCst);
+            }
+            None
+        })
+        .await;
+        (api_url, hits)
+    }
+
+    #[tokio::test]
+    async fn test_api_cache_loads_siteinfo_once() {
+        let (api_url, hits) = counting_siteinfo_api().await;
+        let cache = ApiCache::new(Duration::from_secs(60), 2);
+        let first = cache.get(&api_url).await.unwrap();
+        let second = cache.get(&api_url).await.unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_api_cache_drops_oldest_api() {
+        let (first_url, first_hits) = counting_siteinfo_api().await;
+        let (second_url, _) = counting_siteinfo_api().await;
+        let cache = ApiCache::new(Duration::from_secs(60), 1);
+        cache.get(&first_url).await.unwrap();
+        cache.get(&second_url).await.unwrap();
+        cache.get(&first_url).await.unwrap();
+        assert_eq!(first_hits.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.apis.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_api_cache_zero_ttl_keeps_nothing() {
+        let (api_url, hits) = counting_siteinfo_api().await;
+        let cache = ApiCache::new(Duration::ZERO, 2);
+        cache.get(&api_url).await.unwrap();
+        cache.get(&api_url).await.unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    // ── normalize_title ───────────────────────────────────────────────────────
+
+    fn namespaces(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_normalize_title_uppercases_first_letter() {
+        let none = HashSet::new();
+        assert_eq!(
+            ChangeWiki::normalize_title("enwiki", "paris", &none),
+            "Paris"
+        );
+        assert_eq!(ChangeWiki::normalize_title("dewiki", "über", &none), "Über");
+    }
+
+    #[test]
+    fn test_normalize_title_collapses_whitespace() {
+        assert_eq!(
+            ChangeWiki::normalize_title("enwiki", "  United   Kingdom ", &HashSet::new()),
+            "United Kingdom"
+        );
+        assert_eq!(
+            ChangeWiki::normalize_title("enwiki", "United__Kingdom", &HashSet::new()),
+            "United Kingdom"
+        );
+    }
+
+    #[test]
+    fn test_normalize_title_namespace_prefix() {
+        assert_eq!(
+            ChangeWiki::normalize_title(
+                "enwiki",
+                "category:living_people",
+                &namespaces(&["category"])
+            ),
+            "Category:Living people"
+        );
+        assert_eq!(
+            ChangeWiki::normalize_title("dewiki", "kategorie:mann", &namespaces(&["kategorie"])),
+            "Kategorie:Mann"
+        );
+        assert_eq!(
+            ChangeWiki::normalize_title("enwiki", "user_talk: foo", &namespaces(&["user talk"])),
+            "User talk:Foo"
+        );
+    }
+
+    #[test]
+    fn test_normalize_title_colon_in_title() {
+        let namespaces = namespaces(&["category"]);
+        assert_eq!(
+            ChangeWiki::normalize_title("enwiki", "star Wars: episode I", &namespaces),
+            "Star Wars: episode I"
+        );
+        // Not a namespace of the wiki
+        assert_eq!(
+            ChangeWiki::normalize_title("enwiki", "foo:bar", &namespaces),
+            "Foo:bar"
+        );
+    }
+
+    #[test]
+    fn test_parse_namespaces() {
+        let site_info = serde_json::json!({"query": {
+            "namespaces": {
+                "0": {"id": 0, "name": "", "canonical": ""},
+                "14": {"id": 14, "name": "Kategorie", "canonical": "Category"},
+                "3": {"id": 3, "name": "Benutzer Diskussion", "canonical": "User talk"},
+            },
+            "namespacealiases": [{"id": 4, "alias": "WP"}],
+        }});
+        assert_eq!(
+            ChangeWiki::parse_namespaces(&site_info),
+            namespaces(&[
+                "kategorie",
+                "category",
+                "benutzer diskussion",
+                "user talk",
+                "wp"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_normalize_title_wiktionary_is_case_sensitive() {
+        assert_eq!(
+            ChangeWiki::normalize_title("enwiktionary", "paris", &HashSet::new()),
+            "paris"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_site2wd_normalizes_titles() {
+        let titles = vec!["paris".to_string(), "United   Kingdom".to_string()];
+        let result = ChangeWiki::new("enwiki", titles)
+            .with_sources(canned_sources().await)
+            .site2wd()
+            .await
+            .unwrap();
+        assert_eq!(result.get("Paris").map(String::as_str), Some("Q90"));
+        assert_eq!(
+            result.get("United Kingdom").map(String::as_str),
+            Some("Q145")
+        );
+    }
+
     // ── normalize_wiki ────────────────────────────────────────────────────────
 
     #[test]
@@ -203,7 +1257,7 @@ mod tests {
             "Douglas Adams".to_string(),
             "The Hitchhiker's Guide".to_string(),
         ];
-        let cw = ChangeWiki::new("enwiki", titles.clone());
+        let cw = ChangeWiki::new("enwiki", titles.clone()).with_sources(canned_sources().await);
         let result = cw.convert("enwiki").await.unwrap();
         assert_eq!(result.len(), titles.len());
         for title in &titles {
@@ -219,7 +1273,7 @@ mod tests {
     async fn test_convert_same_wiki_after_normalization() {
         // "EnWiki" and "enwiki" normalise to the same string, so no DB is hit
         let titles = vec!["Some Page".to_string()];
-        let cw = ChangeWiki::new("enwiki", titles.clone());
+        let cw = ChangeWiki::new("enwiki", titles.clone()).with_sources(canned_sources().await);
         let result = cw.convert("EnWiki").await.unwrap();
         assert_eq!(result.len(), 1);
         assert_eq!(result.get("Some Page").unwrap(), "Some Page");
@@ -227,7 +1281,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_convert_same_wiki_empty_titles() {
-        let cw = ChangeWiki::new("enwiki", vec![]);
+        let cw = ChangeWiki::new("enwiki", vec![]).with_sources(canned_sources().await);
         let result = cw.convert("enwiki").await.unwrap();
         assert!(
             result.is_empty(),
@@ -237,7 +1291,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_convert_same_wiki_single_title() {
-        let cw = ChangeWiki::new("frwiki", vec!["Paris".to_string()]);
+        let cw = ChangeWiki::new("frwiki", vec!["Paris".to_string()])
+            .with_sources(canned_sources().await);
         let result = cw.convert("frwiki").await.unwrap();
         assert_eq!(result.get("Paris").unwrap(), "Paris");
     }
@@ -1,34 +1,201 @@
-use async_lazy::Lazy;
+use crate::body_limit;
+use crate::config::{Config, CrossCatsConfig, DEFAULT_SOURCE_TIMEOUT};
+use crate::rate_limit::RateLimiter;
+use crate::sites::{Sites, UnknownWiki};
 use axum::http::StatusCode;
 use futures::StreamExt;
-use futures::future::join_all;
+use futures::future::{BoxFuture, join_all};
+use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::{
     collections::HashMap,
     sync::{Arc, LazyLock},
 };
-use tools_interface::{PetScan, Tool};
 use url::Url;
-use wikibase::mediawiki::api::Api;
 use wikibase_rest_api::prelude::*;
-use wikimisc::site_matrix::SiteMatrix;
 
-static SITE_MATRIX: Lazy<SiteMatrix> = Lazy::new(|| {
-    Box::pin(async {
-        CrossCats::load_site_matrix()
-            .await
-            .expect("Could not load site matrix")
-    })
-});
+const CATEGORY: &str = "Q4167836";
+const DISAMBIGUATION: &str = "Q4167410";
+const LIST: &str = "Q13406463";
+/// Items that are instances (P31) of these are never suggested, by default
+const DEFAULT_EXCLUDED_INSTANCE_OF: &[&str] = &[DISAMBIGUATION, LIST, CATEGORY];
+
+/// Minimum number of categories an item must be in, by default
+pub const DEFAULT_MIN_COUNT: usize = 1;
+
+/// Saved `PetScan` query used as the base template for category queries
+pub const DEFAULT_PETSCAN_ID: u64 = 33506467;
+
+/// `PetScan` instance that is queried, by default
+pub const DEFAULT_PETSCAN_URL: &str = "https://petscan.wmflabs.org/";
+
+/// Category depth that is never exceeded on any wiki, by default
+pub const DEFAULT_MAX_DEPTH: u32 = 10;
 
 static REST_API: LazyLock<Arc<RestApi>> =
     LazyLock::new(|| Arc::new(RestApi::wikidata().expect("Could not create RestApi")));
+static HTTP_CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    Client::builder()
+        .user_agent(Config::user_agent())
+        .timeout(Config::timeout("petscan", DEFAULT_SOURCE_TIMEOUT))
+        .build()
+        .expect("Failed to build PetScan HTTP client")
+});
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct ItemInfo {
     count: usize,
     local_page: Option<String>,
     already_in_category: bool,
+    /// Wikis whose category contained the item
+    sources: Vec<String>,
+}
+
+impl ItemInfo {
+    pub const fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn local_page(&self) -> Option<&str> {
+        self.local_page.as_deref()
+    }
+
+    pub fn sources(&self) -> &[String] {
+        &self.sources
+    }
+}
+
+/// Category depth per wiki. Category trees differ in depth between wikis, and a large
+/// depth on a big wiki can make `PetScan` time out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DepthLimits {
+    max_depth: u32,
+    wiki_depths: HashMap<String, u32>,
+}
+
+impl Default for DepthLimits {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_DEPTH)
+    }
+}
+
+impl DepthLimits {
+    pub fn new(max_depth: u32) -> Self {
+        Self {
+            max_depth,
+            wiki_depths: HashMap::new(),
+        }
+    }
+
+    /// Uses `depth` for `wiki`, instead of the requested one
+    pub fn with_wiki_depth(mut self, wiki: &str, depth: u32) -> Self {
+        self.wiki_depths.insert(wiki.to_string(), depth);
+        self
+    }
+
+    /// Uses `crosscats.max_depth`, and the per-wiki depths in `crosscats.wiki_depths`
+    /// from the configuration, if set.
+    pub fn from_config(config: &CrossCatsConfig) -> Self {
+        let max_depth = config.max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+        config
+            .wiki_depths
+            .iter()
+            .fold(Self::new(max_depth), |limits, (wiki, depth)| {
+                limits.with_wiki_depth(wiki, *depth)
+            })
+    }
+
+    pub const fn max_depth(&self) -> u32 {
+        self.max_depth
+    }
+
+    /// The depth to query `wiki` with: its configured depth, or the requested one,
+    /// but never more than the maximum depth
+    pub fn depth_for_wiki(&self, wiki: &str, requested: u32) -> u32 {
+        let depth = self.wiki_depths.get(wiki).copied().unwrap_or(requested);
+        if depth > self.max_depth {
+            tracing::warn!(
+                "CrossCats depth {depth} for {wiki} exceeds the maximum of {}",
+                self.max_depth
+            );
+        }
+        depth.min(self.max_depth)
+    }
+}
+
+/// Why `CrossCats` can not make suggestions for an item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CrossCatsError {
+    /// The item does not exist
+    NotFound(String),
+    /// The item is not an instance of Wikimedia category (Q4167836)
+    NotACategory(String),
+    /// The category item has no sitelinks
+    NoSitelinks(String),
+    /// The target wiki does not exist, or is not allowed
+    UnknownWiki(String),
+    /// Another failure, like an unavailable service
+    Status(StatusCode),
+}
+
+impl std::fmt::Display for CrossCatsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound(item) => write!(f, "{item} was not found"),
+            Self::NotACategory(item) => {
+                write!(f, "{item} is not a category item (P31:{CATEGORY})")
+            }
+            Self::NoSitelinks(item) => write!(f, "{item} has no sitelinks"),
+            Self::UnknownWiki(wiki) => write!(f, "{}", UnknownWiki::new(wiki)),
+            Self::Status(status) => write!(
+                f,
+                "{}",
+                status.canonical_reason().unwrap_or("Unknown error")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CrossCatsError {}
+
+impl From<StatusCode> for CrossCatsError {
+    fn from(status: StatusCode) -> Self {
+        Self::Status(status)
+    }
+}
+
+/// Makes cross-category suggestions, so tests can serve canned ones.
+/// Returns a boxed future, so the source can be held as `dyn CrossCatsSource`.
+pub trait CrossCatsSource: std::fmt::Debug + Send + Sync {
+    /// Like `CrossCats::cross_cats`
+    fn suggestions<'a>(
+        &'a self,
+        category_item_id: &'a str,
+        depth: u32,
+        target_language: &'a str,
+        petscan_id: u64,
+        min_count: usize,
+    ) -> BoxFuture<'a, Result<HashMap<String, ItemInfo>, CrossCatsError>>;
+}
+
+impl CrossCatsSource for CrossCats {
+    fn suggestions<'a>(
+        &'a self,
+        category_item_id: &'a str,
+        depth: u32,
+        target_language: &'a str,
+        petscan_id: u64,
+        min_count: usize,
+    ) -> BoxFuture<'a, Result<HashMap<String, ItemInfo>, CrossCatsError>> {
+        Box::pin(Self::cross_cats(
+            category_item_id,
+            depth,
+            target_language,
+            petscan_id,
+            min_count,
+        ))
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
@@ -39,22 +206,40 @@ impl CrossCats {
         category_item_id: &str,
         depth: u32,
         target_language: &str,
-    ) -> Result<HashMap<String, ItemInfo>, StatusCode> {
+        petscan_id: u64,
+        min_count: usize,
+    ) -> Result<HashMap<String, ItemInfo>, CrossCatsError> {
         let category_item = Self::get_category_item(category_item_id).await?;
-        Self::validate_category_item(&category_item)?;
+        Self::validate_category_item(category_item_id, &category_item)?;
 
         // Get the sites for the category
         let category_pages = category_item.sitelinks().sitelinks();
+        if category_pages.is_empty() {
+            return Err(CrossCatsError::NoSitelinks(category_item_id.to_string()));
+        }
 
         // Get the items in the categories of the sites, via PetScan
         let target_wiki = format!("{target_language}wiki");
+        Sites::validate_wiki(&target_wiki).await.map_err(|e| {
+            match e.downcast::<UnknownWiki>() {
+                Ok(e) => CrossCatsError::UnknownWiki(e.wiki),
+                Err(_) => CrossCatsError::Status(StatusCode::SERVICE_UNAVAILABLE),
+            }
+        })?;
         let mut target_language_index = None;
+        let depth_limits = DepthLimits::from_config(Config::crosscats());
         let mut futures = Vec::new();
+        let mut wikis = Vec::new();
         for category_sitelink in category_pages {
             if category_sitelink.wiki() == target_wiki {
                 target_language_index = Some(futures.len());
             }
-            futures.push(Self::items_in_local_category(category_sitelink, depth));
+            wikis.push(category_sitelink.wiki().to_string());
+            futures.push(Self::items_in_local_category(
+                category_sitelink,
+                depth_limits.depth_for_wiki(category_sitelink.wiki(), depth),
+                petscan_id,
+            ));
         }
         let results = join_all(futures).await;
 
@@ -66,66 +251,107 @@ impl CrossCats {
             .cloned()
             .collect();
 
-        let mut item_info = HashMap::new();
-        for item in items.iter() {
-            item_info
-                .entry(item.to_owned())
-                .or_insert_with(|| ItemInfo {
-                    count: 0,
-                    local_page: None,
-                    already_in_category: false,
-                })
-                .count += 1;
-        }
-
+        let mut item_info = Self::aggregate_item_info(&wikis, &results);
         Self::remove_local_page_already_in_category(target_language_index, results, &mut item_info);
         Self::get_local_pages(target_wiki, items, &mut item_info).await?;
 
-        // Remove non-local items, and those already in the category
+        Self::filter_item_info(&mut item_info, min_count);
+        Ok(item_info)
+    }
+
+    /// Counts the categories each item is in, and records their wikis
+    fn aggregate_item_info(
+        wikis: &[String],
+        results: &[Result<Vec<String>, StatusCode>],
+    ) -> HashMap<String, ItemInfo> {
+        let mut item_info: HashMap<String, ItemInfo> = HashMap::new();
+        for (wiki, result) in wikis.iter().zip(results) {
+            let Ok(items) = result else {
+                continue;
+            };
+            for item in items {
+                let info = item_info.entry(item.to_owned()).or_default();
+                info.count += 1;
+                info.sources.push(wiki.to_owned());
+            }
+        }
+        item_info
+    }
+
+    /// Removes non-local items, those already in the category, and those found in fewer
+    /// than `min_count` categories
+    fn filter_item_info(item_info: &mut HashMap<String, ItemInfo>, min_count: usize) {
         item_info.retain(|_, v| v.local_page.is_some());
         item_info.retain(|_, v| !v.already_in_category);
+        item_info.retain(|_, v| v.count >= min_count);
+    }
 
-        Ok(item_info)
+    /// Uses `crosscats.petscan_id` from the configuration, if set.
+    pub fn petscan_id_from_config(config: &CrossCatsConfig) -> u64 {
+        config.petscan_id.unwrap_or(DEFAULT_PETSCAN_ID)
     }
 
-    fn validate_category_item(category_item: &Item) -> Result<(), StatusCode> {
-        // Check if the item represents a category
-        match category_item
-            .statements()
+    /// Uses `crosscats.petscan_url` from the configuration, if set, like a staging `PetScan`
+    /// or a mock server.
+    pub fn petscan_url_from_config(config: &CrossCatsConfig) -> String {
+        config
+            .petscan_url
+            .as_deref()
+            .unwrap_or(DEFAULT_PETSCAN_URL)
+            .to_string()
+    }
+
+    /// Uses `crosscats.excluded_instance_of` from the configuration, if set.
+    pub fn excluded_instance_of_from_config(config: &CrossCatsConfig) -> Vec<String> {
+        match &config.excluded_instance_of {
+            Some(excluded) => excluded.clone(),
+            None => DEFAULT_EXCLUDED_INSTANCE_OF
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+
+    fn instance_of(item: &Item) -> impl Iterator<Item = &String> {
+        item.statements()
             .property("P31")
             .iter()
             .filter_map(|statement| match statement.value() {
                 StatementValue::Value(StatementValueContent::String(s)) => Some(s),
                 _ => None,
             })
-            .find(|s| *s == "Q4167836")
-        {
+    }
+
+    fn validate_category_item(
+        category_item_id: &str,
+        category_item: &Item,
+    ) -> Result<(), CrossCatsError> {
+        // Check if the item represents a category
+        match Self::instance_of(category_item).find(|s| *s == CATEGORY) {
             Some(_) => Ok(()),
-            None => Err(StatusCode::NOT_FOUND),
+            None => Err(CrossCatsError::NotACategory(category_item_id.to_string())),
         }
     }
 
-    async fn get_category_item(category_item_id: &str) -> Result<Item, StatusCode> {
-        let category_item_id = EntityId::Item(category_item_id.to_string());
-        let category_item = Item::get(category_item_id, &REST_API)
-            .await
-            .map_err(|_| StatusCode::NOT_FOUND)?;
-        Ok(category_item)
+    fn without_excluded_instances(items: Vec<Item>, excluded: &[String]) -> Vec<Item> {
+        items
+            .into_iter()
+            .filter(|item| !Self::instance_of(item).any(|q| excluded.contains(q)))
+            .collect()
     }
 
-    async fn load_site_matrix() -> Result<SiteMatrix, StatusCode> {
-        let api = Api::new("https://www.wikidata.org/w/api.php")
-            .await
-            .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
-        SiteMatrix::new(&api)
+    async fn get_category_item(category_item_id: &str) -> Result<Item, CrossCatsError> {
+        let entity_id = EntityId::Item(category_item_id.to_string());
+        let category_item = Item::get(entity_id, &REST_API)
             .await
-            .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)
+            .map_err(|_| CrossCatsError::NotFound(category_item_id.to_string()))?;
+        Ok(category_item)
     }
 
     async fn get_language_project_for_wiki(wiki: &str) -> Result<(String, String), StatusCode> {
-        let url = SITE_MATRIX
-            .force()
+        let url = Sites::site_matrix()
             .await
+            .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?
             .get_server_url_for_wiki(wiki)
             .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
         let parsed_url = Url::parse(&url).map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
@@ -139,6 +365,7 @@ impl CrossCats {
     async fn items_in_local_category(
         category_sitelink: &Sitelink,
         depth: u32,
+        petscan_id: u64,
     ) -> Result<Vec<String>, StatusCode> {
         let category_page = category_sitelink
             .title()
@@ -148,30 +375,78 @@ impl CrossCats {
             .to_string();
         let wiki = category_sitelink.wiki();
         let (language, project) = Self::get_language_project_for_wiki(wiki).await?;
-        let mut petscan = PetScan::new(33506467);
-        petscan
-            .parameters_mut()
-            .push(("language".to_string(), language));
-        petscan
-            .parameters_mut()
-            .push(("project".to_string(), project));
-        petscan
-            .parameters_mut()
-            .push(("categories".to_string(), category_page));
-        petscan
-            .parameters_mut()
-            .push(("depth".to_string(), format!("{depth}")));
-        petscan
-            .run()
+        let parameters = Self::category_parameters(language, project, category_page, depth);
+        let petscan_url = Self::petscan_url_from_config(Config::crosscats());
+        Self::run_petscan(&petscan_url, petscan_id, &parameters).await
+    }
+
+    /// The URL of the `PetScan` query `petscan_id` at `base_url`, with `parameters`
+    fn petscan_request_url(
+        base_url: &str,
+        petscan_id: u64,
+        parameters: &[(String, String)],
+    ) -> Result<Url, StatusCode> {
+        let psid = petscan_id.to_string();
+        let fixed = [
+            ("psid", psid.as_str()),
+            ("format", "json"),
+            ("output_compatability", "quick-intersection"),
+            ("doit", "1"),
+        ];
+        let parameters = parameters
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()));
+        Url::parse_with_params(base_url, fixed.into_iter().chain(parameters))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    /// Runs the `PetScan` query `petscan_id` at `base_url`
+    async fn run_petscan(
+        base_url: &str,
+        petscan_id: u64,
+        parameters: &[(String, String)],
+    ) -> Result<Vec<String>, StatusCode> {
+        let url = Self::petscan_request_url(base_url, petscan_id, parameters)?;
+        RateLimiter::global().wait(url.as_str()).await;
+        let response = HTTP_CLIENT
+            .get(url)
+            .send()
             .await
+            .and_then(|response| response.error_for_status())
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        let items = petscan
-            .pages()
-            .iter()
-            .filter(|page| page.page_namespace == 0) // only main namespace
-            .map(|page| page.metadata.wikidata.to_owned())
-            .collect::<Vec<String>>();
-        Ok(items)
+        let text = body_limit::read_text(response, Config::max_body_bytes())
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let json: Value =
+            serde_json::from_str(&text).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        Ok(Self::parse_petscan_items(&json))
+    }
+
+    /// Wikidata items of the main namespace pages in a `quick-intersection` result
+    fn parse_petscan_items(json: &Value) -> Vec<String> {
+        json["pages"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|page| page["page_namespace"].as_i64() == Some(0)) // only main namespace
+            .filter_map(|page| page["metadata"]["wikidata"].as_str())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// The `PetScan` parameters for the pages in a category tree on a wiki
+    fn category_parameters(
+        language: String,
+        project: String,
+        category_page: String,
+        depth: u32,
+    ) -> Vec<(String, String)> {
+        vec![
+            ("language".to_string(), language),
+            ("project".to_string(), project),
+            ("categories".to_string(), category_page),
+            ("depth".to_string(), format!("{depth}")),
+        ]
     }
 
     async fn get_local_pages(
@@ -193,18 +468,14 @@ impl CrossCats {
             .filter_map(|res: Result<Item, RestApiError>| async move { res.ok() })
             .collect()
             .await;
+        let excluded = Self::excluded_instance_of_from_config(Config::crosscats());
+        let loaded_items = Self::without_excluded_instances(loaded_items, &excluded);
 
         for item in &loaded_items {
             let q = match item.id().id() {
                 Ok(id) => id.to_string(),
                 Err(_) => continue,
             };
-            let is_disambiguation = item.statements().property("P31").iter().any(|statement| {
-                matches!(statement.value(), StatementValue::Value(StatementValueContent::String(s)) if s == "Q4167410")
-            });
-            if is_disambiguation {
-                continue;
-            }
             if let Some(sitelink) = item.sitelinks().get_wiki(&target_wiki) {
                 if let Some(info) = item_info.get_mut(&q) {
                     info.local_page = Some(sitelink.title().to_string());
@@ -231,3 +502,253 @@ impl CrossCats {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+    use serde_json::json;
+
+    fn crosscats_config(value: Value) -> CrossCatsConfig {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_petscan_id_from_config() {
+        let config = crosscats_config(json!({"petscan_id": 12345}));
+        assert_eq!(CrossCats::petscan_id_from_config(&config), 12345);
+    }
+
+    #[test]
+    fn test_petscan_id_default() {
+        assert_eq!(
+            CrossCats::petscan_id_from_config(&CrossCatsConfig::default()),
+            DEFAULT_PETSCAN_ID
+        );
+        let config = crosscats_config(json!({}));
+        assert_eq!(
+            CrossCats::petscan_id_from_config(&config),
+            DEFAULT_PETSCAN_ID
+        );
+    }
+
+    #[test]
+    fn test_unknown_wiki_error() {
+        assert_eq!(
+            CrossCatsError::UnknownWiki("enwikii".to_string()).to_string(),
+            "Unknown wiki: enwikii"
+        );
+    }
+
+    #[test]
+    fn test_petscan_url_from_config() {
+        let config = crosscats_config(json!({"petscan_url": "http://localhost:8000/"}));
+        assert_eq!(
+            CrossCats::petscan_url_from_config(&config),
+            "http://localhost:8000/"
+        );
+        assert_eq!(
+            CrossCats::petscan_url_from_config(&CrossCatsConfig::default()),
+            DEFAULT_PETSCAN_URL
+        );
+    }
+
+    #[test]
+    fn test_petscan_request_url() {
+        let parameters = vec![("categories".to_string(), "Polish skydivers".to_string())];
+        let url =
+            CrossCats::petscan_request_url("http://localhost:8000/", 12345, &parameters).unwrap();
+        assert_eq!(url.host_str(), Some("localhost"));
+        assert_eq!(url.port(), Some(8000));
+        let query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        assert!(query.contains(&("psid".to_string(), "12345".to_string())));
+        assert!(query.contains(&("categories".to_string(), "Polish skydivers".to_string())));
+        assert!(CrossCats::petscan_request_url("not a url", 12345, &parameters).is_err());
+        // A missing trailing slash does not change the request
+        assert_eq!(
+            CrossCats::petscan_request_url("https://petscan.wmflabs.org", 12345, &parameters),
+            CrossCats::petscan_request_url(DEFAULT_PETSCAN_URL, 12345, &parameters)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_petscan_mock_server() {
+        let router = axum::Router::new().route(
+            "/",
+            axum::routing::get(|| async {
+                axum::Json(json!({"pages": [
+                    {"page_namespace": 0, "metadata": {"wikidata": "Q1"}},
+                    {"page_namespace": 14, "metadata": {"wikidata": "Q2"}},
+                    {"page_namespace": 0, "metadata": {"wikidata": "Q3"}}
+                ]}))
+            }),
+        );
+        let base_url = test_server::serve(router).await;
+        let items = CrossCats::run_petscan(&base_url, 12345, &[]).await.unwrap();
+        assert_eq!(items, vec!["Q1", "Q3"]);
+    }
+
+    #[test]
+    fn test_depth_limits_from_config() {
+        let config = crosscats_config(json!({
+            "max_depth": 4,
+            "wiki_depths": {"enwiki": 2, "dewiki": 8}
+        }));
+        let limits = DepthLimits::from_config(&config);
+        assert_eq!(limits.max_depth(), 4);
+        // The per-wiki depth is used instead of the requested one
+        assert_eq!(limits.depth_for_wiki("enwiki", 3), 2);
+        assert_eq!(limits.depth_for_wiki("frwiki", 3), 3);
+        // The maximum depth limits requested and configured depths alike
+        assert_eq!(limits.depth_for_wiki("frwiki", 100), 4);
+        assert_eq!(limits.depth_for_wiki("dewiki", 1), 4);
+    }
+
+    #[test]
+    fn test_depth_limits_default() {
+        let limits = DepthLimits::from_config(&CrossCatsConfig::default());
+        assert_eq!(limits, DepthLimits::default());
+        assert_eq!(limits.depth_for_wiki("enwiki", 3), 3);
+        assert_eq!(limits.depth_for_wiki("enwiki", 1000), DEFAULT_MAX_DEPTH);
+    }
+
+    fn item_info(count: usize, local_page: Option<&str>, already_in_category: bool) -> ItemInfo {
+        ItemInfo {
+            count,
+            local_page: local_page.map(str::to_string),
+            already_in_category,
+            sources: vec![],
+        }
+    }
+
+    #[test]
+    fn test_aggregate_item_info_records_sources() {
+        let wikis = vec![
+            "dewiki".to_string(),
+            "frwiki".to_string(),
+            "nlwiki".to_string(),
+        ];
+        let results = vec![
+            Ok(vec!["Q1".to_string(), "Q2".to_string()]),
+            Err(StatusCode::INTERNAL_SERVER_ERROR),
+            Ok(vec!["Q1".to_string()]),
+        ];
+        let item_info = CrossCats::aggregate_item_info(&wikis, &results);
+        assert_eq!(item_info["Q1"].count, 2);
+        assert_eq!(item_info["Q1"].sources, vec!["dewiki", "nlwiki"]);
+        assert_eq!(item_info["Q2"].count, 1);
+        assert_eq!(item_info["Q2"].sources, vec!["dewiki"]);
+        let json = serde_json::to_value(&item_info["Q1"]).unwrap();
+        assert_eq!(json["sources"], json!(["dewiki", "nlwiki"]));
+    }
+
+    #[test]
+    fn test_filter_item_info_min_count() {
+        let mut item_info = HashMap::from([
+            ("Q1".to_string(), item_info(1, Some("One"), false)),
+            ("Q2".to_string(), item_info(2, Some("Two"), false)),
+            ("Q3".to_string(), item_info(3, Some("Three"), false)),
+        ]);
+        CrossCats::filter_item_info(&mut item_info, 2);
+        let mut remaining: Vec<&String> = item_info.keys().collect();
+        remaining.sort();
+        assert_eq!(remaining, vec!["Q2", "Q3"]);
+    }
+
+    #[test]
+    fn test_filter_item_info_default_keeps_local_pages() {
+        let mut item_info = HashMap::from([
+            ("Q1".to_string(), item_info(1, Some("One"), false)),
+            ("Q2".to_string(), item_info(5, None, false)),
+            ("Q3".to_string(), item_info(5, Some("Three"), true)),
+        ]);
+        CrossCats::filter_item_info(&mut item_info, DEFAULT_MIN_COUNT);
+        let remaining: Vec<&String> = item_info.keys().collect();
+        assert_eq!(remaining, vec!["Q1"]);
+    }
+
+    fn item_with_instance_of(q: &str) -> Item {
+        let mut statement = Statement::default();
+        statement.set_property(PropertyType::property("P31"));
+        statement.set_value(StatementValue::Value(StatementValueContent::String(
+            q.to_string(),
+        )));
+        let mut item = Item::default();
+        item.statements_mut()
+            .statements_mut()
+            .entry("P31".to_string())
+            .or_default()
+            .push(statement);
+        item
+    }
+
+    #[test]
+    fn test_without_excluded_instances_drops_list() {
+        let items = vec![item_with_instance_of(LIST), item_with_instance_of("Q5")];
+        let excluded = CrossCats::excluded_instance_of_from_config(&CrossCatsConfig::default());
+        let items = CrossCats::without_excluded_instances(items, &excluded);
+        assert_eq!(items.len(), 1);
+        assert!(CrossCats::instance_of(&items[0]).all(|q| q == "Q5"));
+    }
+
+    #[test]
+    fn test_excluded_instance_of_from_config() {
+        let config = crosscats_config(json!({"excluded_instance_of": ["Q11266439"]}));
+        assert_eq!(
+            CrossCats::excluded_instance_of_from_config(&config),
+            vec!["Q11266439".to_string()]
+        );
+        let defaults = CrossCats::excluded_instance_of_from_config(&CrossCatsConfig::default());
+        assert!(defaults.contains(&DISAMBIGUATION.to_string()));
+        assert!(defaults.contains(&LIST.to_string()));
+        assert!(defaults.contains(&CATEGORY.to_string()));
+    }
+
+    #[test]
+    fn test_validate_category_item() {
+        assert!(
+            CrossCats::validate_category_item("Q9649201", &item_with_instance_of(CATEGORY)).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_validate_category_item_human() {
+        let error =
+            CrossCats::validate_category_item("Q42", &item_with_instance_of("Q5")).unwrap_err();
+        assert_eq!(error, CrossCatsError::NotACategory("Q42".to_string()));
+        assert_eq!(
+            error.to_string(),
+            "Q42 is not a category item (P31:Q4167836)"
+        );
+    }
+
+    #[test]
+    fn test_category_parameters() {
+        let parameters = CrossCats::category_parameters(
+            "de".to_string(),
+            "wikipedia".to_string(),
+            "Physiker".to_string(),
+            2,
+        );
+        assert!(parameters.contains(&("categories".to_string(), "Physiker".to_string())));
+        assert!(parameters.contains(&("depth".to_string(), "2".to_string())));
+    }
+
+    #[test]
+    fn test_category_request_uses_configured_petscan_id() {
+        let config = crosscats_config(json!({"petscan_id": 12345}));
+        let parameters = CrossCats::category_parameters(
+            "de".to_string(),
+            "wikipedia".to_string(),
+            "Physiker".to_string(),
+            2,
+        );
+        let petscan_id = CrossCats::petscan_id_from_config(&config);
+        let url =
+            CrossCats::petscan_request_url(DEFAULT_PETSCAN_URL, petscan_id, &parameters).unwrap();
+        let query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        assert!(query.contains(&("psid".to_string(), "12345".to_string())));
+        assert!(query.contains(&("language".to_string(), "de".to_string())));
+        assert!(query.contains(&("categories".to_string(), "Physiker".to_string())));
+    }
+}
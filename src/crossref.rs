@@ -0,0 +1,216 @@
+use crate::body_limit;
+use crate::config::{Config, DEFAULT_SOURCE_TIMEOUT};
+use crate::isbn::ISBN2wiki;
+use crate::reference::{DataValue, Reference};
+use crate::retry::Retry;
+use anyhow::{Result, anyhow};
+use reqwest::Client;
+use serde::Deserialize;
+use std::sync::LazyLock;
+use wikibase_rest_api::prelude::*;
+
+/// Crossref work types that describe a whole book, not e.g. one of its chapters
+const BOOK_TYPES: &[&str] = &["book", "monograph", "edited-book", "reference-book"];
+
+static HTTP_CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    Client::builder()
+        .user_agent(Config::user_agent())
+        .timeout(Config::timeout("crossref", DEFAULT_SOURCE_TIMEOUT))
+        .build()
+        .expect("Failed to build Crossref HTTP client")
+});
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct CrossrefDate {
+    #[serde(rename = "date-parts", default)]
+    date_parts: Vec<Vec<i32>>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct CrossrefWork {
+    #[serde(rename = "DOI")]
+    doi: String,
+    #[serde(rename = "type", default)]
+    work_type: String,
+    #[serde(default)]
+    publisher: Option<String>,
+    #[serde(rename = "container-title", default)]
+    container_title: Vec<String>,
+    #[serde(rename = "published-print", default)]
+    published_print: Option<CrossrefDate>,
+    #[serde(default)]
+    issued: Option<CrossrefDate>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct CrossrefMessage {
+    #[serde(default)]
+    items: Vec<CrossrefWork>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct CrossrefResponse {
+    message: CrossrefMessage,
+}
+
+impl CrossrefResponse {
+    pub async fn load_from_crossref(isbn2wiki: &ISBN2wiki) -> Result<()> {
+        let isbn = isbn2wiki
+            .isbn()
+            .ok_or_else(|| anyhow!("No ISBN found"))?
+            .replace('-', "");
+        let url = format!("https://api.crossref.org/works?filter=isbn:{isbn}");
+
+        let response = Retry::from_config().get(&HTTP_CLIENT, &url).await?;
+        let json = body_limit::read_text(response, Config::max_body_bytes()).await?;
+        let Some(work) = Self::parse_crossref_json(&json)? else {
+            return Ok(());
+        };
+        let reference = Reference::prop("P356", &work.doi.to_uppercase());
+        Self::add_publisher_and_series(isbn2wiki, &work, reference.clone());
+        Self::add_publication_date(isbn2wiki, &work, reference);
+        Ok(())
+    }
+
+    /// Returns the first work that is a whole book. Crossref does not know many books,
+    /// so no match is not an error.
+    fn parse_crossref_json(json: &str) -> Result<Option<CrossrefWork>> {
+        let response: CrossrefResponse = serde_json::from_str(json)?;
+        Ok(response
+            .message
+            .items
+            .into_iter()
+            .find(|work| BOOK_TYPES.contains(&work.work_type.as_str())))
+    }
+
+    /// Publisher (P123) and series (P179) are items on Wikidata, but Crossref only has names.
+    /// They are stored as `DataValue::String` placeholders; a downstream step must resolve
+    /// them to items before they can be used as statements.
+    fn add_publisher_and_series(isbn2wiki: &ISBN2wiki, work: &CrossrefWork, reference: Reference) {
+        if let Some(publisher) = &work.publisher {
+            isbn2wiki.add_reference(
+                "P123",
+                DataValue::String(publisher.to_owned()),
+                reference.clone(),
+            );
+        }
+        if let Some(series) = work.container_title.first() {
+            isbn2wiki.add_reference("P179", DataValue::String(series.to_owned()), reference);
+        }
+    }
+
+    fn add_publication_date(isbn2wiki: &ISBN2wiki, work: &CrossrefWork, reference: Reference) {
+        let date = work.published_print.as_ref().or(work.issued.as_ref());
+        if let Some((time, precision)) = date.and_then(Self::parse_date) {
+            isbn2wiki.add_reference("P577", DataValue::Date { time, precision }, reference);
+        }
+    }
+
+    /// Converts Crossref date parts (year, optional month, optional day) to a Wikidata time.
+    fn parse_date(date: &CrossrefDate) -> Option<(String, TimePrecision)> {
+        match date.date_parts.first()?.as_slice() {
+            [year] => Some((format!("+{year:04}-01-01T00:00:00Z"), TimePrecision::Year)),
+            [year, month] => Some((
+                format!("+{year:04}-{month:02}-01T00:00:00Z"),
+                TimePrecision::Month,
+            )),
+            [year, month, day, ..] => Some((
+                format!("+{year:04}-{month:02}-{day:02}T00:00:00Z"),
+                TimePrecision::Day,
+            )),
+            [] => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed_isbn2wiki() -> ISBN2wiki {
+        let isbn2wiki = ISBN2wiki::new("9783319242767").unwrap();
+        let json = include_str!("../test_files/crossref.json");
+        let work = CrossrefResponse::parse_crossref_json(json)
+            .expect("parsing test JSON should succeed")
+            .expect("test JSON should contain a book");
+        let reference = Reference::prop("P356", &work.doi.to_uppercase());
+        CrossrefResponse::add_publisher_and_series(&isbn2wiki, &work, reference.clone());
+        CrossrefResponse::add_publication_date(&isbn2wiki, &work, reference);
+        isbn2wiki
+    }
+
+    #[test]
+    fn test_parse_crossref_json_reads_publisher() {
+        let work =
+            CrossrefResponse::parse_crossref_json(include_str!("../test_files/crossref.json"))
+                .unwrap()
+                .unwrap();
+        assert_eq!(work.doi, "10.1007/978-3-319-24277-4");
+        assert_eq!(
+            work.publisher.as_deref(),
+            Some("Springer International Publishing")
+        );
+    }
+
+    #[test]
+    fn test_parse_crossref_json_sets_publisher_and_series() {
+        let isbn2wiki = parsed_isbn2wiki();
+        let values = isbn2wiki.values.lock().unwrap();
+        let reference = Reference::prop("P356", "10.1007/978-3-319-24277-4");
+        let publisher = DataValue::String("Springer International Publishing".to_string());
+        assert!(values["P123"][&publisher].contains(&reference));
+        let series = DataValue::String("Lecture Notes in Computer Science".to_string());
+        assert!(values["P179"][&series].contains(&reference));
+    }
+
+    #[test]
+    fn test_parse_crossref_json_prefers_print_date() {
+        let isbn2wiki = parsed_isbn2wiki();
+        let values = isbn2wiki.values.lock().unwrap();
+        let dates: Vec<&DataValue> = values["P577"].keys().collect();
+        assert_eq!(
+            dates,
+            vec![&DataValue::Date {
+                time: "+2015-01-01T00:00:00Z".to_string(),
+                precision: TimePrecision::Year,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_crossref_json_skips_chapters() {
+        let json = r#"{"status":"ok","message":{"items":[
+            {"DOI":"10.1/chapter","type":"book-chapter","publisher":"Chapter Press"},
+            {"DOI":"10.1/book","type":"monograph","publisher":"Book Press"}
+        ]}}"#;
+        let work = CrossrefResponse::parse_crossref_json(json)
+            .unwrap()
+            .unwrap();
+        assert_eq!(work.doi, "10.1/book");
+    }
+
+    #[test]
+    fn test_parse_crossref_json_without_books_is_ok() {
+        let json = r#"{"status":"ok","message":{"items":[]}}"#;
+        assert_eq!(CrossrefResponse::parse_crossref_json(json).unwrap(), None);
+        let json =
+            r#"{"status":"ok","message":{"items":[{"DOI":"10.1/x","type":"book-chapter"}]}}"#;
+        assert_eq!(CrossrefResponse::parse_crossref_json(json).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_date_precisions() {
+        let date = |parts: Vec<i32>| CrossrefDate {
+            date_parts: vec![parts],
+        };
+        assert_eq!(
+            CrossrefResponse::parse_date(&date(vec![2015, 11])),
+            Some(("+2015-11-01T00:00:00Z".to_string(), TimePrecision::Month))
+        );
+        assert_eq!(
+            CrossrefResponse::parse_date(&date(vec![2015, 11, 4])),
+            Some(("+2015-11-04T00:00:00Z".to_string(), TimePrecision::Day))
+        );
+        assert_eq!(CrossrefResponse::parse_date(&date(vec![])), None);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::TOOLFORGE_DB;
+use wikimisc::mysql_async::{Conn, prelude::Queryable};
+
+/// Number of times a connection is requested before giving up
+const CONNECTION_ATTEMPTS: usize = 2;
+
+/// A Toolforge database connection could not be established
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DbError {
+    pool: String,
+    message: String,
+}
+
+impl std::fmt::Display for DbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Database '{}' is unavailable: {}",
+            self.pool, self.message
+        )
+    }
+}
+
+impl std::error::Error for DbError {}
+
+/// Source of connections that can be checked for liveness
+pub(crate) trait Connector {
+    type Connection;
+    async fn connect(&self) -> Result<Self::Connection, String>;
+    async fn is_alive(&self, connection: &mut Self::Connection) -> bool;
+    /// Closes a dead connection, so the pool does not hand it out again
+    async fn discard(&self, connection: Self::Connection);
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ToolforgeConnector<'a> {
+    pool: &'a str,
+}
+
+impl Connector for ToolforgeConnector<'_> {
+    type Connection = Conn;
+
+    async fn connect(&self) -> Result<Conn, String> {
+        TOOLFORGE_DB
+            .get_connection(self.pool)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn is_alive(&self, connection: &mut Conn) -> bool {
+        connection.query_drop("SELECT 1").await.is_ok()
+    }
+
+    async fn discard(&self, connection: Conn) {
+        let _ = connection.disconnect().await;
+    }
+}
+
+/// Returns a connection from a `TOOLFORGE_DB` pool that has answered `SELECT 1`.
+/// A stale connection is discarded and replaced once.
+pub async fn get_connection(pool: &str) -> Result<Conn, DbError> {
+    validated_connection(pool, &ToolforgeConnector { pool }).await
+}
+
+pub(crate) async fn validated_connection<C: Connector>(
+    pool: &str,
+    connector: &C,
+) -> Result<C::Connection, DbError> {
+    let mut message = String::new();
+    for _attempt in 0..CONNECTION_ATTEMPTS {
+        match connector.connect().await {
+            Ok(mut connection) => {
+                if connector.is_alive(&mut connection).await {
+                    return Ok(connection);
+                }
+                connector.discard(connection).await;
+                message = "connection failed validation".to_string();
+            }
+            Err(e) => message = e,
+        }
+    }
+    Err(DbError {
+        pool: pool.to_string(),
+        message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Hands out numbered connections; the ones in `dead` fail validation
+    #[derive(Debug, Default)]
+    struct MockConnector {
+        dead: Vec<usize>,
+        fail_connect: bool,
+        connects: Mutex<usize>,
+        discarded: Mutex<Vec<usize>>,
+    }
+
+    impl Connector for MockConnector {
+        type Connection = usize;
+
+        async fn connect(&self) -> Result<usize, String> {
+            let mut connects = self.connects.lock().unwrap();
+            *connects += 1;
+            if self.fail_connect {
+                return Err("connection refused".to_string());
+            }
+            Ok(*connects)
+        }
+
+        async fn is_alive(&self, connection: &mut usize) -> bool {
+            !self.dead.contains(connection)
+        }
+
+        async fn discard(&self, connection: usize) {
+            self.discarded.lock().unwrap().push(connection);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validated_connection_alive() {
+        let connector = MockConnector::default();
+        let connection = validated_connection("wikidata", &connector).await.unwrap();
+        assert_eq!(connection, 1);
+        assert_eq!(*connector.connects.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_validated_connection_retries_stale() {
+        let connector = MockConnector {
+            dead: vec![1],
+            ..Default::default()
+        };
+        let connection = validated_connection("wikidata", &connector).await.unwrap();
+        assert_eq!(connection, 2);
+        assert_eq!(*connector.discarded.lock().unwrap(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_validated_connection_gives_up() {
+        let connector = MockConnector {
+            dead: vec![1, 2],
+            ..Default::default()
+        };
+        let error = validated_connection("wikidata", &connector)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Database 'wikidata' is unavailable: connection failed validation"
+        );
+        assert_eq!(*connector.connects.lock().unwrap(), CONNECTION_ATTEMPTS);
+    }
+
+    #[tokio::test]
+    async fn test_validated_connection_connect_error() {
+        let connector = MockConnector {
+            fail_connect: true,
+            ..Default::default()
+        };
+        let error = validated_connection("termstore", &connector)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Database 'termstore' is unavailable: connection refused"
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use crate::api_error::ApiError;
+use crate::isbn::ISBN2wiki;
+use crate::location::{DEFAULT_P131_LIMIT, DEFAULT_P131_RADIUS_KM, Location};
+use crate::person::{NameOrder, NameSearch, Person, WikidataNameSearch};
+use crate::wikidata::Wikidata;
+use axum::http::StatusCode;
+use mediawiki::Api;
+use serde_json::{Map, Value, json};
+use wikibase_rest_api::Patch as _;
+
+/// Which inferrers apply to an item, based on its existing claims
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Plan {
+    /// Label of a human (P31=Q5), for name/gender inference
+    human_name: Option<String>,
+    /// Coordinates (P625), for P131 suggestions
+    coordinates: Option<(f64, f64)>,
+    /// Has an ISBN (P212 or P957), for ISBN enrichment
+    has_isbn: bool,
+}
+
+/// Runs all applicable inferrers for an item, and groups their suggestions by source.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Enrich;
+
+impl Enrich {
+    /// Returns a JSON object with one key per inferrer that was run.
+    /// A failing inferrer reports `{"error": ...}` without affecting the others.
+    /// Fails with 404 if the item does not exist, and with 502 or 504 if it can not be loaded.
+    pub async fn enrich(item_id: &str) -> Result<Map<String, Value>, ApiError> {
+        let api = Wikidata::get_wikidata_api()
+            .await
+            .map_err(|_| ApiError::new(StatusCode::BAD_GATEWAY, "Wikidata API unavailable"))?;
+        Self::enrich_with(&api, &WikidataNameSearch, item_id).await
+    }
+
+    /// Like `enrich`, loading the item from `api`, and name items with `search`
+    pub(crate) async fn enrich_with(
+        api: &Api,
+        search: &dyn NameSearch,
+        item_id: &str,
+    ) -> Result<Map<String, Value>, ApiError> {
+        let entity = Self::load_entity(api, item_id).await?;
+        let plan = Self::plan(&entity);
+        let mut ret = Map::new();
+        if let Some(name) = &plan.human_name {
+            let result = Person::name_gender_with(search, name, NameOrder::Auto).await;
+            ret.insert("name_gender".to_string(), Self::result_json(result));
+        }
+        if let Some((latitude, longitude)) = plan.coordinates {
+            let result = Location::p131(
+                latitude,
+                longitude,
+                DEFAULT_P131_RADIUS_KM,
+                DEFAULT_P131_LIMIT,
+            )
+            .await;
+            ret.insert("P131".to_string(), Self::result_json(result));
+        }
+        if plan.has_isbn {
+            ret.insert("isbn".to_string(), Self::isbn_patch(item_id).await);
+        }
+        Ok(ret)
+    }
+
+    async fn load_entity(api: &Api, item_id: &str) -> Result<Value, ApiError> {
+        let params = api.params_into(&[
+            ("action", "wbgetentities"),
+            ("ids", item_id),
+            ("props", "labels|claims"),
+        ]);
+        let result = api
+            .get_query_api_json(&params)
+            .await
+            .map_err(Self::wikidata_error)?;
+        if let Some(error) = result.get("error") {
+            return Err(ApiError::new(
+                StatusCode::BAD_GATEWAY,
+                format!("Wikidata API error: {error}"),
+            ));
+        }
+        let entity = &result["entities"][item_id];
+        if !entity.is_object() || entity.get("missing").is_some() {
+            return Err(ApiError::not_found(format!("Item {item_id} not found")));
+        }
+        Ok(entity.to_owned())
+    }
+
+    /// Wikidata failures are upstream failures: 504 for timeouts, 502 otherwise
+    fn wikidata_error(error: impl std::error::Error + 'static) -> ApiError {
+        let timed_out = std::iter::successors(Some(&error as &dyn std::error::Error), |error| {
+            error.source()
+        })
+        .any(|error| {
+            error
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(reqwest::Error::is_timeout)
+        });
+        let status = if timed_out {
+            StatusCode::GATEWAY_TIMEOUT
+        } else {
+            StatusCode::BAD_GATEWAY
+        };
+        ApiError::new(status, format!("Wikidata request failed: {error}"))
+    }
+
+    fn plan(entity: &Value) -> Plan {
+        let claims = &entity["claims"];
+        let is_human = Self::claim_values(claims, "P31").any(|v| v["id"] == "Q5");
+        let human_name = if is_human { Self::label(entity) } else { None };
+        let coordinates = Self::claim_values(claims, "P625")
+            .find_map(|v| Some((v["latitude"].as_f64()?, v["longitude"].as_f64()?)));
+        let has_isbn = ["P212", "P957"]
+            .iter()
+            .any(|property| Self::claim_values(claims, property).next().is_some());
+        Plan {
+            human_name,
+            coordinates,
+            has_isbn,
+        }
+    }
+
+    /// Main snak values of all claims for a property
+    fn claim_values<'a>(claims: &'a Value, property: &str) -> impl Iterator<Item = &'a Value> {
+        claims[property]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|claim| &claim["mainsnak"]["datavalue"]["value"])
+            .filter(|value| !value.is_null())
+    }
+
+    /// English label, or any label if there is no English one
+    fn label(entity: &Value) -> Option<String> {
+        let labels = entity["labels"].as_object()?;
+        labels.get("en").or_else(|| labels.values().next())?["value"]
+            .as_str()
+            .map(str::to_string)
+    }
+
+    async fn isbn_patch(item_id: &str) -> Value {
+        let Some(mut isbn2wiki) = ISBN2wiki::new_from_item(item_id).await else {
+            return json!({"error": "No valid ISBN found"});
+        };
+        if let Err(e) = isbn2wiki.retrieve().await {
+            return json!({"error": e.to_string()});
+        }
+        match isbn2wiki.generate_patch(item_id) {
+            Ok(patch) => json!(patch.patch()),
+            Err(e) => json!({"error": e.to_string()}),
+        }
+    }
+
+    fn result_json<T: serde::Serialize>(result: Result<T, StatusCode>) -> Value {
+        match result {
+            Ok(value) => json!(value),
+            Err(status) => json!({"error": status.canonical_reason().unwrap_or("Unknown error")}),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+    use futures::future::BoxFuture;
+
+    fn entity(claims: Value) -> Value {
+        json!({
+            "labels": {"en": {"language": "en", "value": "Magnus Manske"}},
+            "claims": claims,
+        })
+    }
+
+    fn claim(value: Value) -> Value {
+        json!({"mainsnak": {"datavalue": {"value": value}}})
+    }
+
+    #[test]
+    fn test_plan_human() {
+        let entity = entity(json!({"P31": [claim(json!({"id": "Q5"}))]}));
+        let plan = Enrich::plan(&entity);
+        assert_eq!(plan.human_name.as_deref(), Some("Magnus Manske"));
+        assert_eq!(plan.coordinates, None);
+        assert!(!plan.has_isbn);
+    }
+
+    #[test]
+    fn test_plan_non_human_has_no_name() {
+        let entity = entity(json!({"P31": [claim(json!({"id": "Q515"}))]}));
+        assert_eq!(Enrich::plan(&entity).human_name, None);
+    }
+
+    #[test]
+    fn test_plan_coordinates_and_isbn() {
+        let entity = entity(json!({
+            "P625": [claim(json!({"latitude": 51.5, "longitude": -0.12}))],
+            "P212": [claim(json!("978-0-14-032872-1"))],
+        }));
+        let plan = Enrich::plan(&entity);
+        assert_eq!(plan.coordinates, Some((51.5, -0.12)));
+        assert!(plan.has_isbn);
+    }
+
+    #[test]
+    fn test_label_fallback() {
+        let entity = json!({"labels": {"de": {"language": "de", "value": "Berlin"}}});
+        assert_eq!(Enrich::label(&entity).as_deref(), Some("Berlin"));
+        assert_eq!(Enrich::label(&json!({})), None);
+    }
+
+    /// Canned name items for "Heinrich Manske"
+    #[derive(Debug)]
+    struct StubNameSearch;
+
+    impl NameSearch for StubNameSearch {
+        fn search<'a>(
+            &'a self,
+            name: &'a str,
+            class: &'a str,
+        ) -> BoxFuture<'a, Result<Vec<String>, StatusCode>> {
+            let items = match (name, class) {
+                ("Heinrich", "Q12308941") => vec!["Q1".to_string()],
+                ("Manske", "Q101352") => vec!["Q2".to_string()],
+                _ => vec![],
+            };
+            Box::pin(async move { Ok(items) })
+        }
+    }
+
+    async fn canned_api() -> Api {
+        let mut human = entity(json!({"P31": [claim(json!({"id": "Q5"}))]}));
+        human["labels"]["en"]["value"] = json!("Heinrich Manske");
+        let url = test_server::canned_wiki_api(json!({ "Q13520818": human })).await;
+        Api::new(&url).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_enrich_human() {
+        let api = canned_api().await;
+        let result = Enrich::enrich_with(&api, &StubNameSearch, "Q13520818")
+            .await
+            .unwrap();
+        let properties: Vec<&str> = result["name_gender"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|statement| statement["mainsnak"]["property"].as_str())
+            .collect();
+        assert_eq!(properties, vec!["P734", "P21", "P735"]);
+        assert!(!result.contains_key("P131"));
+        assert!(!result.contains_key("isbn"));
+    }
+
+    #[tokio::test]
+    async fn test_enrich_missing_item_is_not_found() {
+        let api = canned_api().await;
+        let error = Enrich::enrich_with(&api, &StubNameSearch, "Q1")
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), StatusCode::NOT_FOUND);
+    }
+}
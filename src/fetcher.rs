@@ -0,0 +1,297 @@
+use crate::body_limit::{self, BodyError};
+use crate::config::Config;
+use crate::metrics::Metrics;
+use crate::retry::Retry;
+use anyhow::Result;
+use encoding_rs::{Encoding, UTF_8};
+use futures::future::BoxFuture;
+use regex::Regex;
+use reqwest::Client;
+use std::sync::LazyLock;
+use std::time::Duration;
+use url::Url;
+
+static RE_CHARSET: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)charset\s*=\s*["']?([\w.:-]+)"#).unwrap());
+static RE_META_CHARSET: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)<meta[^>]+charset\s*=\s*["']?([\w.:-]+)"#).unwrap());
+
+// Default maximum size of a PDF that will be downloaded for text extraction
+pub const DEFAULT_MAX_PDF_BYTES: usize = 10 * 1024 * 1024;
+
+/// Some websites only answer browsers
+const BROWSER_USER_AGENT: &str =
+    "Mozilla/5.0 (Windows; U; Windows NT 5.1; rv:1.7.3) Gecko/20041001 Firefox/0.10.1";
+
+/// Hosts on these domains get the tool's own user agent, as the Wikimedia user agent
+/// policy requires
+const WIKIMEDIA_DOMAINS: &[&str] = &[
+    "mediawiki.org",
+    "wikibooks.org",
+    "wikidata.org",
+    "wikimedia.org",
+    "wikinews.org",
+    "wikipedia.org",
+    "wikiquote.org",
+    "wikisource.org",
+    "wikiversity.org",
+    "wikivoyage.org",
+    "wiktionary.org",
+];
+
+/// Timeout for fetching a page, unless `timeouts.referee` is configured
+const DEFAULT_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A `<meta charset>` tag has to be in this many bytes at the start of a page
+const META_CHARSET_BYTES: usize = 1024;
+
+/// Loads the contents of URLs for the `Referee`, so tests can use canned pages.
+/// Contents that can not be used, like error pages, are returned as an empty string.
+/// Returns a boxed future, so the fetcher can be held as `dyn ContentFetcher`.
+pub trait ContentFetcher: std::fmt::Debug + Send + Sync {
+    fn fetch<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<String>>;
+}
+
+/// Fetches URLs over HTTP with retries, converting PDFs to text
+#[derive(Debug, Clone)]
+pub struct HttpFetcher {
+    client: Client,
+    wikimedia_client: Client,
+    retry: Retry,
+    max_pdf_bytes: usize,
+    max_body_bytes: usize,
+}
+
+impl HttpFetcher {
+    /// Uses `referee.max_pdf_bytes`, `max_body_bytes` for other pages, `timeouts.referee`,
+    /// and the retry settings from the configuration.
+    pub fn new() -> Result<Self> {
+        let timeout = Config::timeout("referee", DEFAULT_FETCH_TIMEOUT);
+        let client = Client::builder()
+            .user_agent(BROWSER_USER_AGENT)
+            .timeout(timeout)
+            .build()?;
+        let wikimedia_client = Client::builder()
+            .user_agent(Config::user_agent())
+            .timeout(timeout)
+            .build()?;
+        Ok(Self {
+            client,
+            wikimedia_client,
+            retry: Retry::from_config(),
+            max_pdf_bytes: Config::referee()
+                .max_pdf_bytes
+                .unwrap_or(DEFAULT_MAX_PDF_BYTES),
+            max_body_bytes: Config::max_body_bytes(),
+        })
+    }
+
+    /// Whether `url` is on a Wikimedia wiki, or a subdomain of one
+    fn is_wikimedia_url(url: &str) -> bool {
+        let Some(host) = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_lowercase))
+        else {
+            return false;
+        };
+        WIKIMEDIA_DOMAINS.iter().any(|domain| {
+            host == *domain
+                || host
+                    .strip_suffix(domain)
+                    .is_some_and(|prefix| prefix.ends_with('.'))
+        })
+    }
+
+    /// The client for `url`, with the user agent its host expects
+    fn client_for_url(&self, url: &str) -> &Client {
+        if Self::is_wikimedia_url(url) {
+            &self.wikimedia_client
+        } else {
+            &self.client
+        }
+    }
+
+    /// Extracts the text from a PDF. Returns an empty string on failure,
+    /// or if the PDF is larger than `max_bytes`.
+    pub fn pdf2text(bytes: &[u8], max_bytes: usize) -> String {
+        if bytes.len() > max_bytes {
+            return String::new();
+        }
+        pdf_extract::extract_text_from_mem(bytes).unwrap_or_default()
+    }
+
+    /// Decodes `body` with the charset of the `content_type`, or of a `<meta charset>` tag
+    /// at the start of the page. Without either, or for an unknown charset, as UTF-8.
+    fn decode_body(body: &[u8], content_type: &str) -> String {
+        let label = |re: &Regex, s: &str| {
+            re.captures(s)
+                .and_then(|caps| caps.get(1))
+                .map(|label| label.as_str().to_string())
+        };
+        let head = String::from_utf8_lossy(&body[..body.len().min(META_CHARSET_BYTES)]);
+        let encoding = label(&RE_CHARSET, content_type)
+            .or_else(|| label(&RE_META_CHARSET, &head))
+            .and_then(|label| Encoding::for_label(label.as_bytes()))
+            .unwrap_or(UTF_8);
+        let (text, _, _) = encoding.decode(body);
+        text.into_owned()
+    }
+
+    /// Reads the body of `response`, or nothing if it is larger than `max_bytes`
+    async fn read_body(response: reqwest::Response, max_bytes: usize) -> Result<Vec<u8>> {
+        match body_limit::read_bytes(response, max_bytes).await {
+            Ok(body) => Ok(body),
+            Err(BodyError::TooLarge(_)) => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn fetch_url(&self, url: &str) -> Result<String> {
+        let response = self.retry.get(self.client_for_url(url), url).await?;
+        let status = response.status();
+
+        if !status.is_success() {
+            return Ok(String::new());
+        }
+
+        let content_type = response
+            .headers()
+            .get("content-type")
+            .map_or(String::new(), |ct| ct.to_str().unwrap_or("").to_string());
+
+        if content_type.is_empty() {
+            return Ok(String::new());
+        }
+
+        if content_type.starts_with("application/pdf") {
+            let bytes = Self::read_body(response, self.max_pdf_bytes).await?;
+            if bytes.is_empty() {
+                return Ok(String::new());
+            }
+            let max_pdf_bytes = self.max_pdf_bytes;
+            // Keep the request span, so logging from the blocking task can be correlated
+            let span = tracing::Span::current();
+            let text = tokio::task::spawn_blocking(move || {
+                span.in_scope(|| Self::pdf2text(&bytes, max_pdf_bytes))
+            })
+            .await
+            .unwrap_or_default();
+            return Ok(text);
+        }
+
+        let body = Self::read_body(response, self.max_body_bytes).await?;
+        Ok(Self::decode_body(&body, &content_type))
+    }
+}
+
+impl ContentFetcher for HttpFetcher {
+    fn fetch<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let result = self.fetch_url(url).await;
+            if result.is_err() {
+                Metrics::global().record_upstream_failure("referee");
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    #[test]
+    fn test_is_wikimedia_url() {
+        assert!(HttpFetcher::is_wikimedia_url(
+            "https://www.wikidata.org/w/api.php?action=wbsearchentities"
+        ));
+        assert!(HttpFetcher::is_wikimedia_url(
+            "https://de.wikipedia.org/wiki/Berlin"
+        ));
+        assert!(HttpFetcher::is_wikimedia_url("https://wikidata.org/"));
+        assert!(!HttpFetcher::is_wikimedia_url(
+            "https://notwikipedia.org/wiki/Berlin"
+        ));
+        assert!(!HttpFetcher::is_wikimedia_url(
+            "https://example.com/wikipedia.org"
+        ));
+        assert!(!HttpFetcher::is_wikimedia_url("not a URL"));
+    }
+
+    #[test]
+    fn test_decode_body_charset() {
+        // "Müller" in ISO-8859-1
+        let latin1 = b"<p>M\xfcller</p>";
+        assert_eq!(
+            HttpFetcher::decode_body(latin1, "text/html; charset=ISO-8859-1"),
+            "<p>Müller</p>"
+        );
+        let page = b"<html><head><meta charset=\"windows-1252\"></head><p>\x93M\xfcller\x94</p>";
+        assert!(HttpFetcher::decode_body(page, "text/html").contains("<p>“Müller”</p>"));
+        let page = b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=iso-8859-1\"><p>M\xfcller</p>";
+        assert!(HttpFetcher::decode_body(page, "text/html").ends_with("<p>Müller</p>"));
+        // The header wins over the page
+        let utf8 = "<meta charset=\"iso-8859-1\"><p>Müller</p>";
+        assert!(
+            HttpFetcher::decode_body(utf8.as_bytes(), "text/html; charset=utf-8")
+                .ends_with("<p>Müller</p>")
+        );
+        assert_eq!(
+            HttpFetcher::decode_body("<p>Müller</p>".as_bytes(), "text/html"),
+            "<p>Müller</p>"
+        );
+        assert_eq!(
+            HttpFetcher::decode_body(latin1, "text/html; charset=unknown"),
+            "<p>M\u{fffd}ller</p>"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_fetcher_skips_pages_over_limit() {
+        let page = |paragraphs: usize| {
+            let body = "<p>Some text about the subject.</p>".repeat(paragraphs);
+            ([(axum::http::header::CONTENT_TYPE, "text/html")], body)
+        };
+        let router = axum::Router::new()
+            .route("/small", axum::routing::get(move || async move { page(1) }))
+            .route(
+                "/large",
+                axum::routing::get(move || async move { page(1000) }),
+            );
+        let base = test_server::serve(router).await;
+
+        let fetcher = HttpFetcher {
+            max_body_bytes: 1000,
+            ..HttpFetcher::new().unwrap()
+        };
+        let small = fetcher.fetch(&format!("{base}/small")).await.unwrap();
+        assert_eq!(small, "<p>Some text about the subject.</p>");
+        let large = fetcher.fetch(&format!("{base}/large")).await.unwrap();
+        assert!(large.is_empty());
+    }
+
+    #[test]
+    fn test_pdf2text_finds_known_phrase() {
+        let pdf = include_bytes!("../test_files/sample.pdf");
+        let text = HttpFetcher::pdf2text(pdf, DEFAULT_MAX_PDF_BYTES);
+        assert!(
+            text.contains("Douglas Adams"),
+            "unexpected PDF text: {text}"
+        );
+    }
+
+    #[test]
+    fn test_pdf2text_respects_size_limit() {
+        let pdf = include_bytes!("../test_files/sample.pdf");
+        assert_eq!(HttpFetcher::pdf2text(pdf, 100), "");
+    }
+
+    #[test]
+    fn test_pdf2text_invalid_pdf_returns_empty() {
+        assert_eq!(
+            HttpFetcher::pdf2text(b"not a PDF", DEFAULT_MAX_PDF_BYTES),
+            ""
+        );
+    }
+}
@@ -1,12 +1,22 @@
+use crate::config::{Config, GivenNamesConfig};
 use crate::wikidata::Wikidata;
 use anyhow::{Result, anyhow};
-use mediawiki::Api;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use tokio::sync::OnceCell;
+use std::fs::File;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, SystemTime};
+use tokio::sync::RwLock;
 
 // Not in use now, might be useful for Person?
 
-#[derive(Debug)]
+const DEFAULT_CACHE_FILE: &str = "given_names_cache.json";
+const DEFAULT_CACHE_TTL_HOURS: u64 = 24 * 7;
+
+static GIVEN_NAMES: LazyLock<RwLock<Option<Arc<GivenNames>>>> = LazyLock::new(|| RwLock::new(None));
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct GivenNames {
     male: HashMap<String, usize>,
     female: HashMap<String, usize>,
@@ -14,17 +24,34 @@ pub struct GivenNames {
 
 impl GivenNames {
     #![allow(clippy::missing_panics_doc)]
-    pub async fn get_static() -> &'static GivenNames {
-        static ONCE: OnceCell<GivenNames> = OnceCell::const_new();
-        let api = Wikidata::get_wikidata_api()
-            .await
-            .expect("Wikidata API not available");
-        ONCE.get_or_init(|| async move {
-            GivenNames::new(&api)
+    /// Returns the shared given names, loading them from the cache file if it is fresh,
+    /// or from SPARQL otherwise.
+    pub async fn get_static() -> Arc<GivenNames> {
+        if let Some(given_names) = GIVEN_NAMES.read().await.as_ref() {
+            return given_names.clone();
+        }
+        let mut cached = GIVEN_NAMES.write().await;
+        if let Some(given_names) = cached.as_ref() {
+            return given_names.clone();
+        }
+        let (path, ttl) = Self::cache_settings_from_config(Config::given_names());
+        let given_names = match Self::load_cache(Path::new(&path), ttl) {
+            Some(given_names) => given_names,
+            None => Self::build(&path)
                 .await
-                .expect("Failed to fetch given names")
-        })
-        .await
+                .expect("Failed to fetch given names"),
+        };
+        let given_names = Arc::new(given_names);
+        *cached = Some(given_names.clone());
+        given_names
+    }
+
+    /// Rebuilds the given names from SPARQL, ignoring the cache file, and updates the cache.
+    pub async fn refresh() -> Result<Arc<GivenNames>> {
+        let (path, _ttl) = Self::cache_settings_from_config(Config::given_names());
+        let given_names = Arc::new(Self::build(&path).await?);
+        *GIVEN_NAMES.write().await = Some(given_names.clone());
+        Ok(given_names)
     }
 
     pub fn is_male(&self, name: &str) -> bool {
@@ -39,6 +66,45 @@ impl GivenNames {
         self.male.get(name).or(self.female.get(name)).cloned()
     }
 
+    /// Reads `given_names.cache_file` and `given_names.cache_ttl_hours` from the configuration.
+    fn cache_settings_from_config(config: &GivenNamesConfig) -> (String, Duration) {
+        let path = config
+            .cache_file
+            .as_deref()
+            .unwrap_or(DEFAULT_CACHE_FILE)
+            .to_string();
+        let hours = config.cache_ttl_hours.unwrap_or(DEFAULT_CACHE_TTL_HOURS);
+        (path, Duration::from_secs(hours * 3600))
+    }
+
+    /// Loads from SPARQL and writes the cache file. A failure to write the cache is not fatal.
+    async fn build(path: &str) -> Result<Self> {
+        let given_names = Self::new().await?;
+        if let Err(e) = given_names.save_cache(Path::new(path)) {
+            tracing::warn!("Could not write given names cache {path}: {e}");
+        }
+        Ok(given_names)
+    }
+
+    /// Returns `None` if the cache file is missing, older than `ttl`, or corrupt.
+    fn load_cache(path: &Path, ttl: Duration) -> Option<Self> {
+        let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        if age > ttl {
+            return None;
+        }
+        let reader = std::io::BufReader::new(File::open(path).ok()?);
+        serde_json::from_reader(reader).ok()
+    }
+
+    fn save_cache(&self, path: &Path) -> Result<()> {
+        let writer = std::io::BufWriter::new(File::create(path)?);
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
     fn extract_names_for_gender(
         bindings: &[serde_json::Value],
         gender_qid: &str,
@@ -61,14 +127,14 @@ impl GivenNames {
             .collect()
     }
 
-    async fn new(api: &Api) -> Result<Self> {
+    async fn new() -> Result<Self> {
         // Load all male and female given names from SPARQL
         let sparql = "SELECT ?q ?qLabel ?gender {
         	VALUES ?gender { wd:Q11879590 wd:Q12308941 } .
          	?q wdt:P31 ?gender .
           	SERVICE wikibase:label { bd:serviceParam wikibase:language \"[AUTO_LANGUAGE],en,mul\" }
            }";
-        let json = api.sparql_query(sparql).await?;
+        let json = Wikidata::sparql_query(sparql).await?;
         let bindings = json["results"]["bindings"]
             .as_array()
             .ok_or(anyhow!("results.bindings are not an array"))?;
@@ -77,3 +143,70 @@ impl GivenNames {
         Ok(Self { male, female })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn cache_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("wd_infernal_{name}_{}.json", std::process::id()))
+    }
+
+    fn given_names() -> GivenNames {
+        GivenNames {
+            male: HashMap::from([("heinrich".to_string(), 1)]),
+            female: HashMap::from([("elisabeth".to_string(), 2)]),
+        }
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let path = cache_path("given_names_round_trip");
+        given_names().save_cache(&path).unwrap();
+        let loaded = GivenNames::load_cache(&path, Duration::from_secs(3600)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.is_male("heinrich"));
+        assert!(!loaded.is_female("heinrich"));
+        assert!(loaded.is_female("elisabeth"));
+        assert_eq!(loaded.name2qid("elisabeth"), Some(2));
+    }
+
+    #[test]
+    fn test_cache_stale() {
+        let path = cache_path("given_names_stale");
+        given_names().save_cache(&path).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        let loaded = GivenNames::load_cache(&path, Duration::ZERO);
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.is_none());
+    }
+
+    #[test]
+    fn test_cache_corrupt() {
+        let path = cache_path("given_names_corrupt");
+        std::fs::write(&path, "{not json").unwrap();
+        let loaded = GivenNames::load_cache(&path, Duration::from_secs(3600));
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.is_none());
+    }
+
+    #[test]
+    fn test_cache_missing() {
+        let path = cache_path("given_names_missing");
+        assert!(GivenNames::load_cache(&path, Duration::from_secs(3600)).is_none());
+    }
+
+    #[test]
+    fn test_cache_settings_from_config() {
+        let (path, ttl) = GivenNames::cache_settings_from_config(&GivenNamesConfig::default());
+        assert_eq!(path, DEFAULT_CACHE_FILE);
+        assert_eq!(ttl, Duration::from_secs(DEFAULT_CACHE_TTL_HOURS * 3600));
+        let config =
+            serde_json::from_value(json!({"cache_file": "/tmp/x.json", "cache_ttl_hours": 2}))
+                .unwrap();
+        let (path, ttl) = GivenNames::cache_settings_from_config(&config);
+        assert_eq!(path, "/tmp/x.json");
+        assert_eq!(ttl, Duration::from_secs(7200));
+    }
+}
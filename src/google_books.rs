@@ -1,5 +1,8 @@
+use crate::body_limit;
+use crate::config::{Config, DEFAULT_SOURCE_TIMEOUT};
 use crate::isbn::ISBN2wiki;
 use crate::reference::{DataValue, Reference};
+use crate::retry::Retry;
 use anyhow::{Result, anyhow};
 use regex::Regex;
 use reqwest::Client;
@@ -19,9 +22,12 @@ static HTTP_CLIENT: LazyLock<Client> = LazyLock::new(|| {
         .user_agent(
             "Mozilla/5.0 (Windows; U; Windows NT 5.1; rv:1.7.3) Gecko/20041001 Firefox/0.10.1",
         )
+        .timeout(Config::timeout("google_books", DEFAULT_SOURCE_TIMEOUT))
         .build()
         .expect("Failed to build Google Books HTTP client")
 });
+/// References with a Google Books ID are stated in (P248) Google Books
+const GOOGLE_BOOKS: &str = "Q3176095";
 
 #[derive(Debug, Deserialize, PartialEq)]
 struct GoogleBooksEntry {
@@ -54,9 +60,12 @@ impl GoogleBooksFeed {
             .replace('-', "");
         let url =
             format!("https://books.google.com/books/feeds/volumes?q=isbn:{isbn}&max-results=25");
+        Self::load_from_url(isbn2wiki, &HTTP_CLIENT, &url).await
+    }
 
-        let response = HTTP_CLIENT.get(&url).send().await?;
-        let xml = response.text().await?;
+    async fn load_from_url(isbn2wiki: &ISBN2wiki, client: &Client, url: &str) -> Result<()> {
+        let response = Retry::from_config().get(client, url).await?;
+        let xml = body_limit::read_text(response, Config::max_body_bytes()).await?;
         Self::parse_google_books_xml(isbn2wiki, &xml)
     }
 
@@ -64,11 +73,11 @@ impl GoogleBooksFeed {
         let xml = xml.replace("<dc:", "<dc_").replace("</dc:", "</dc_"); // To avoid XML namespace problems with serde
         let feed: GoogleBooksFeed = serde_xml_rs::from_str(&xml)?; // Does not work properly
         let entry = feed
-            .entry
-            .first()
+            .select_entry(&Self::queried_isbns(isbn2wiki))
             .ok_or_else(|| anyhow!("No entry found in Google books"))?;
 
         let google_books_id = Self::extract_google_book_identifiers(isbn2wiki, entry)?;
+        let reference = Reference::prop("P675", &google_books_id).with_stated_in(GOOGLE_BOOKS);
 
         if let Some(language) = entry.language.first() {
             isbn2wiki.add_reference(
@@ -77,8 +86,9 @@ impl GoogleBooksFeed {
                     label: entry.title.to_owned(),
                     language: language.to_owned(),
                 },
-                Reference::prop("P675", &google_books_id),
+                reference.clone(),
             );
+            isbn2wiki.add_language(language, reference.clone());
         }
 
         for format in &entry.format {
@@ -88,14 +98,14 @@ impl GoogleBooksFeed {
                 isbn2wiki.add_reference(
                     "P1104",
                     DataValue::Quantity(pages),
-                    Reference::prop("P675", &google_books_id),
+                    reference.clone(),
                 );
             }
             if format == "book" {
                 isbn2wiki.add_reference(
                     "P31",
                     DataValue::Entity("Q571".to_string()),
-                    Reference::prop("P675", &google_books_id),
+                    reference.clone(),
                 );
             }
         }
@@ -109,7 +119,7 @@ impl GoogleBooksFeed {
                         time,
                         precision: TimePrecision::Year,
                     },
-                    Reference::prop("P675", &google_books_id),
+                    reference.clone(),
                 );
             }
         }
@@ -118,13 +128,39 @@ impl GoogleBooksFeed {
             isbn2wiki.add_reference(
                 "P225",
                 DataValue::String(creator.to_owned()),
-                Reference::prop("P675", &google_books_id),
+                reference.clone(),
             );
         }
 
         Ok(())
     }
 
+    /// Prefers the entry with an ISBN matching the queried one, falling back to the first entry.
+    fn select_entry(&self, isbns: &[String]) -> Option<&GoogleBooksEntry> {
+        self.entry
+            .iter()
+            .find(|entry| Self::entry_isbns(entry).any(|isbn| isbns.contains(&isbn)))
+            .or_else(|| self.entry.first())
+    }
+
+    /// The queried ISBN-10 and ISBN-13, without hyphens
+    fn queried_isbns(isbn2wiki: &ISBN2wiki) -> Vec<String> {
+        let isbn10 = isbn2wiki
+            .isbn10
+            .map(|isbn| isbn.to_string().replace('-', ""));
+        let isbn13 = isbn2wiki
+            .isbn13
+            .map(|isbn| isbn.to_string().replace('-', ""));
+        isbn10.into_iter().chain(isbn13).collect()
+    }
+
+    fn entry_isbns(entry: &GoogleBooksEntry) -> impl Iterator<Item = String> + '_ {
+        entry.dc_identifier.iter().filter_map(|identifier| {
+            Self::capture1(&RE_ISBN_10, identifier)
+                .or_else(|| Self::capture1(&RE_ISBN_13, identifier))
+        })
+    }
+
     /// Extract the first capture group from a regex match as a `String`.
     fn capture1(re: &Regex, text: &str) -> Option<String> {
         re.captures(text)?.get(1).map(|m| m.as_str().to_string())
@@ -168,6 +204,7 @@ impl GoogleBooksFeed {
 mod tests {
     use super::*;
     use crate::reference::DataValue;
+    use crate::test_server;
 
     // NOTE: serde-xml-rs does not correctly deserialise most `dc:*` fields from
     // Google Books Atom feeds. The XML pre-processing replaces `<dc:foo>` with
@@ -213,6 +250,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_google_books_xml_references_are_stated_in_google_books() {
+        let isbn2wiki = parsed_isbn2wiki();
+        let values = isbn2wiki.values.lock().unwrap();
+        let reference = Reference::prop("P675", "1gLCoQEACAAJ").with_stated_in(GOOGLE_BOOKS);
+        let found = values
+            .values()
+            .flat_map(|dv2refs| dv2refs.values())
+            .any(|references| references.contains(&reference));
+        assert!(
+            found,
+            "Google Books references should be stated in Google Books"
+        );
+    }
+
     // serde-xml-rs does not match dc_title → P1476 is never populated
     #[test]
     #[ignore = "serde-xml-rs does not deserialise dc_title; P1476 is never populated"]
@@ -310,6 +362,33 @@ mod tests {
         assert!(found, "P225 should contain the author name Tolkien");
     }
 
+    #[test]
+    fn test_parse_google_books_xml_prefers_matching_entry() {
+        // The first entry is a reprint with a different ISBN, the second one matches
+        let isbn2wiki = ISBN2wiki::new("9782267027006").unwrap();
+        let xml = include_str!("../test_files/google_books_two_entries.xml");
+        GoogleBooksFeed::parse_google_books_xml(&isbn2wiki, xml).unwrap();
+        let values = isbn2wiki.values.lock().unwrap();
+        let ids: Vec<&DataValue> = values["P675"].keys().collect();
+        assert_eq!(ids, vec![&DataValue::String("1gLCoQEACAAJ".to_string())]);
+        assert!(
+            !values["P212"]
+                .keys()
+                .any(|dv| matches!(dv, DataValue::String(s) if s == "978-0-26-110325-2")),
+            "the non-matching entry's ISBN must not be used"
+        );
+    }
+
+    #[test]
+    fn test_parse_google_books_xml_falls_back_to_first_entry() {
+        let isbn2wiki = ISBN2wiki::new("9780140328721").unwrap();
+        let xml = include_str!("../test_files/google_books_two_entries.xml");
+        GoogleBooksFeed::parse_google_books_xml(&isbn2wiki, xml).unwrap();
+        let values = isbn2wiki.values.lock().unwrap();
+        let ids: Vec<&DataValue> = values["P675"].keys().collect();
+        assert_eq!(ids, vec![&DataValue::String("xHxWPgAACAAJ".to_string())]);
+    }
+
     #[test]
     fn test_parse_google_books_xml_no_entry_returns_error() {
         // A valid feed with zero entries should return an error
@@ -325,4 +404,35 @@ mod tests {
             "a feed with no entries should return an error"
         );
     }
+
+    #[tokio::test]
+    async fn test_load_from_url_timeout_keeps_other_sources() {
+        let url = format!("{}feeds", test_server::hanging().await);
+        let client = Client::builder()
+            .timeout(std::time::Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let isbn2wiki = ISBN2wiki::new("9780140328721").unwrap();
+        let json = include_str!("../test_files/openlibrary.json");
+        let (google_books, open_library) = futures::join!(
+            GoogleBooksFeed::load_from_url(&isbn2wiki, &client, &url),
+            async {
+                crate::open_library::OpenLibraryBook::parse_openlibrary_json(&isbn2wiki, json)
+                    .map(|_book| ())
+            }
+        );
+        let error = google_books.unwrap_err();
+        assert!(
+            error
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(reqwest::Error::is_timeout),
+            "{error}"
+        );
+        ISBN2wiki::combine_source_results(vec![
+            ("Google Books", Err(error)),
+            ("OpenLibrary", open_library),
+        ])
+        .unwrap();
+        assert!(isbn2wiki.values.lock().unwrap().contains_key("P648"));
+    }
 }
@@ -1,22 +1,36 @@
-use crate::TOOLFORGE_DB;
-use anyhow::Result;
+use crate::config::Config;
+use crate::db;
+use anyhow::{Result, anyhow};
 use regex::Regex;
 use std::sync::LazyLock;
 use wikimisc::mysql_async::{Params, from_row, params, prelude::Queryable};
 
 static RE_INITIAL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b([A-Z])\b\.? *").unwrap());
+static RE_ITEM_ID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^Q\d+$").unwrap());
 
-/// Searches for items with a label that matches a human name with initials.
+/// Instance-of target used when none is given: human
+pub const DEFAULT_INSTANCE_OF: &str = "Q5";
+
+/// Searches for items with a label that matches a name with initials.
+/// Items must link to `instance_of`, which is usually `DEFAULT_INSTANCE_OF` (human).
 #[derive(Debug, Copy, Clone)]
 pub struct InitialSearch;
 
 impl InitialSearch {
-    pub async fn run(query: &str) -> Result<Vec<String>> {
+    /// `language` optionally restricts matching terms to one language code.
+    pub async fn run(
+        query: &str,
+        instance_of: &str,
+        language: Option<&str>,
+    ) -> Result<Vec<String>> {
+        if !RE_ITEM_ID.is_match(instance_of) {
+            return Err(anyhow!("Invalid instance-of item: {instance_of}"));
+        }
         let query = query.trim();
-        let candidate_items = Self::get_candidate_items_from_term_store(query).await?;
+        let candidate_items = Self::get_candidate_items_from_term_store(query, language).await?;
         let futures = candidate_items
-            .chunks(5000)
-            .map(Self::filter_chunk)
+            .chunks(Config::db_chunk_size())
+            .map(|chunk| Self::filter_chunk(chunk, instance_of))
             .collect::<Vec<_>>();
         let results = futures::future::try_join_all(futures)
             .await?
@@ -26,7 +40,54 @@ impl InitialSearch {
         Ok(results)
     }
 
-    async fn filter_chunk(chunk: &[String]) -> Result<Vec<String>> {
+    async fn filter_chunk(chunk: &[String], instance_of: &str) -> Result<Vec<String>> {
+        let (sql, params) = Self::filter_query(chunk, instance_of);
+        let mut conn = db::get_connection("wikidata").await?;
+        let results = conn
+            .exec_iter(sql, params)
+            .await?
+            .map_and_drop(from_row::<String>)
+            .await?;
+        drop(conn);
+        Ok(results)
+    }
+
+    /// For queries like "John F. Kennedy" or "John Fitzgerald Kennedy", where the first name
+    /// is spelled out, a `LIKE` pattern that keeps the full first word, like "John %Kennedy".
+    /// If a middle name is spelled out, the abbreviated form "John F. Kennedy" is added as a
+    /// regular expression; the all-initials pattern already matches the other forms.
+    /// Returns `None` if the query starts with an initial, or has no middle names.
+    fn abbreviated_name_patterns(query: &str) -> Option<(String, Vec<String>)> {
+        let words: Vec<&str> = query.split_whitespace().collect();
+        let [first, middle @ .., last] = words.as_slice() else {
+            return None;
+        };
+        if middle.is_empty() || Self::is_initial(first) {
+            return None;
+        }
+        let initial = |word: &str| {
+            let letter = word.chars().next().map(String::from).unwrap_or_default();
+            format!(r"{}\. ?", regex::escape(&letter))
+        };
+        let mut patterns = vec![];
+        if !middle.iter().all(|word| Self::is_initial(word)) {
+            let middle_initials: String = middle.iter().map(|word| initial(word)).collect();
+            patterns.push(format!(
+                "{} {middle_initials}{}",
+                regex::escape(first),
+                regex::escape(last)
+            ));
+        }
+        Some((format!("{first} %{last}"), patterns))
+    }
+
+    /// A single letter, with or without a period
+    fn is_initial(word: &str) -> bool {
+        word.trim_end_matches('.').chars().count() < 2
+    }
+
+    /// SQL and positional parameters keeping the items in `chunk` that link to `instance_of`
+    fn filter_query(chunk: &[String], instance_of: &str) -> (String, Vec<String>) {
         let placeholders: String = std::iter::repeat_n("?", chunk.len())
             .collect::<Vec<_>>()
             .join(",");
@@ -37,11 +98,22 @@ impl InitialSearch {
 				AND page_namespace=0
 				AND pl_from=page_id
 				AND pl_target_id=lt_id
-				AND lt_title='Q5'"#
+				AND lt_title=?"#
         );
-        let mut conn = TOOLFORGE_DB.get_connection("wikidata").await?;
+        let mut params = chunk.to_vec();
+        params.push(instance_of.to_string());
+        (sql, params)
+    }
+
+    async fn get_candidate_items_from_term_store(
+        query: &str,
+        language: Option<&str>,
+    ) -> Result<Vec<String>> {
+        let sql = Self::candidate_sql(language.is_some());
+        let params = Self::generate_query_parameters(query, language);
+        let mut conn = db::get_connection("termstore").await?;
         let results = conn
-            .exec_iter(sql, chunk.to_vec())
+            .exec_iter(sql, params)
             .await?
             .map_and_drop(from_row::<String>)
             .await?;
@@ -49,33 +121,51 @@ impl InitialSearch {
         Ok(results)
     }
 
-    async fn get_candidate_items_from_term_store(query: &str) -> Result<Vec<String>> {
-        const BASE_SQL: &str = r#"SELECT DISTINCT concat('Q',`wbit_item_id`) AS `item`
+    /// Term store SQL. The language of a term is stored in `wbt_text_in_lang`,
+    /// so the language filter restricts the already joined `wbxl_language`.
+    fn candidate_sql(filter_language: bool) -> String {
+        let language_filter = if filter_language {
+            " AND `wbxl_language`=:language"
+        } else {
+            ""
+        };
+        format!(
+            r#"SELECT DISTINCT concat('Q',`wbit_item_id`) AS `item`
 	    	FROM `wbt_item_terms`,`wbt_term_in_lang`,`wbt_text_in_lang`
 	     	WHERE `wbit_term_in_lang_id`=`wbtl_id`
-	      	AND `wbtl_text_in_lang_id`=`wbxl_id`
+	      	AND `wbtl_text_in_lang_id`=`wbxl_id`{language_filter}
 	       	AND `wbxl_text_id` IN (SELECT `wbx_id` FROM `wbt_text`
 	       		WHERE `wbx_text` LIKE :q1
 	         	AND `wbx_text` RLIKE :q2
-	        )"#;
-        let params = Self::generate_query_parameters(query);
-        let mut conn = TOOLFORGE_DB.get_connection("termstore").await?;
-        let results = conn
-            .exec_iter(BASE_SQL, params)
-            .await?
-            .map_and_drop(from_row::<String>)
-            .await?;
-        drop(conn);
-        Ok(results)
+	        )"#
+        )
     }
 
-    fn generate_query_parameters(query: &str) -> Params {
-        let q1 = RE_INITIAL.replace_all(query, "$1%_").to_string(); // 'A%_A%_Saveliev'
-        let q2 = RE_INITIAL.replace_all(query, "$1.*? "); // '^A.*? A.*? Saveliev$'
-        let q2 = format!("^{q2}$");
-        params! {
-            "q1" => q1,
-            "q2" => q2,
+    fn generate_query_parameters(query: &str, language: Option<&str>) -> Params {
+        let all_initials = RE_INITIAL
+            .replace_all(query, "$1.*? ") // 'A.*? A.*? Saveliev'
+            .to_string();
+        let (q1, q2) = match Self::abbreviated_name_patterns(query) {
+            Some((like, abbreviated)) => {
+                let patterns: Vec<String> =
+                    std::iter::once(all_initials).chain(abbreviated).collect();
+                (like, format!("^({})$", patterns.join("|")))
+            }
+            None => (
+                RE_INITIAL.replace_all(query, "$1%_").to_string(), // 'A%_A%_Saveliev'
+                format!("^{all_initials}$"),
+            ),
+        };
+        match language {
+            Some(language) => params! {
+                "q1" => q1,
+                "q2" => q2,
+                "language" => language,
+            },
+            None => params! {
+                "q1" => q1,
+                "q2" => q2,
+            },
         }
     }
 }
@@ -83,6 +173,7 @@ impl InitialSearch {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::TOOLFORGE_DB;
 
     async fn check_db_connection() -> bool {
         TOOLFORGE_DB.get_connection("termstore").await.is_ok()
@@ -95,10 +186,100 @@ mod tests {
             "q1" => "A%_A%_Saveliev",
             "q2" => "^A.*? A.*? Saveliev$",
         };
-        let params = InitialSearch::generate_query_parameters(query);
+        let params = InitialSearch::generate_query_parameters(query, None);
         assert_eq!(params, expected);
     }
 
+    #[test]
+    fn test_generate_query_parameters_full_first_name() {
+        let query = "John F. Kennedy";
+        let expected = params! {
+            "q1" => "John %Kennedy",
+            "q2" => r"^(John F.*? Kennedy)$",
+        };
+        let params = InitialSearch::generate_query_parameters(query, None);
+        assert_eq!(params, expected);
+    }
+
+    #[test]
+    fn test_generate_query_parameters_full_middle_name() {
+        let query = "John Fitzgerald Kennedy";
+        let expected = params! {
+            "q1" => "John %Kennedy",
+            "q2" => r"^(John Fitzgerald Kennedy|John F\. ?Kennedy)$",
+        };
+        let params = InitialSearch::generate_query_parameters(query, None);
+        assert_eq!(params, expected);
+    }
+
+    #[test]
+    fn test_abbreviated_name_patterns() {
+        let (like, patterns) =
+            InitialSearch::abbreviated_name_patterns("John Fitzgerald W. Kennedy").unwrap();
+        assert_eq!(like, "John %Kennedy");
+        assert_eq!(patterns, vec![r"John F\. ?W\. ?Kennedy"]);
+        let q2 = Regex::new(&format!("^({})$", patterns.join("|"))).unwrap();
+        assert!(q2.is_match("John F. W. Kennedy"));
+        assert!(q2.is_match("John F.W. Kennedy"));
+        // Only initials in the middle: the all-initials pattern matches all forms
+        let (like, patterns) =
+            InitialSearch::abbreviated_name_patterns("John F. W. Kennedy").unwrap();
+        assert_eq!(like, "John %Kennedy");
+        assert!(patterns.is_empty());
+        // Leading initial: only the all-initials pattern applies
+        assert_eq!(
+            InitialSearch::abbreviated_name_patterns("J. F. Kennedy"),
+            None
+        );
+        // No middle names
+        assert_eq!(
+            InitialSearch::abbreviated_name_patterns("John Kennedy"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_candidate_sql_language_filter() {
+        let sql = InitialSearch::candidate_sql(true);
+        assert!(sql.contains("AND `wbxl_language`=:language"));
+        let sql = InitialSearch::candidate_sql(false);
+        assert!(!sql.contains("wbxl_language"));
+        assert!(!sql.contains(":language"));
+    }
+
+    #[test]
+    fn test_generate_query_parameters_with_language() {
+        let expected = params! {
+            "q1" => "A%_A%_Saveliev",
+            "q2" => "^A.*? A.*? Saveliev$",
+            "language" => "ru",
+        };
+        let params = InitialSearch::generate_query_parameters("A.A.Saveliev", Some("ru"));
+        assert_eq!(params, expected);
+    }
+
+    #[test]
+    fn test_filter_query_uses_instance_of() {
+        let chunk = vec!["Q1".to_string(), "Q2".to_string()];
+        let (sql, params) = InitialSearch::filter_query(&chunk, "Q4830453");
+        assert!(sql.contains("page_title IN (?,?)"));
+        assert!(sql.contains("lt_title=?"));
+        assert_eq!(params, vec!["Q1", "Q2", "Q4830453"]);
+    }
+
+    #[test]
+    fn test_filter_query_default_is_human() {
+        let chunk = vec!["Q1".to_string()];
+        let (_sql, params) = InitialSearch::filter_query(&chunk, DEFAULT_INSTANCE_OF);
+        assert_eq!(params.last().map(String::as_str), Some("Q5"));
+    }
+
+    #[tokio::test]
+    async fn test_run_rejects_invalid_instance_of() {
+        let result = InitialSearch::run("H.M.Manske", "Q5' OR 1=1", None).await;
+        assert!(result.is_err());
+    }
+
     #[tokio::test]
     async fn test_initial_search() {
         if !check_db_connection().await {
@@ -106,7 +287,7 @@ mod tests {
             return;
         }
         let query = "H.M.Manske";
-        let results = match InitialSearch::run(query).await {
+        let results = match InitialSearch::run(query, DEFAULT_INSTANCE_OF, None).await {
             Ok(r) => r,
             Err(_) => return, // DB pool disconnected under concurrent test load; skip
         };
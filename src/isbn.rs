@@ -1,52 +1,270 @@
+use crate::config::{Config, DEFAULT_SOURCE_TIMEOUT, IsbnConfig};
+use crate::crossref::CrossrefResponse;
+use crate::fetcher::{ContentFetcher, HttpFetcher};
 use crate::google_books::GoogleBooksFeed;
+use crate::metrics::Metrics;
+use crate::open_library::OpenLibraryBook;
+use crate::rate_limit::RateLimiter;
 use crate::reference::{DataValue, Reference};
+use crate::retry::Retry;
+use crate::wikidata::Wikidata;
 use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
 use grscraper::MetadataRequestBuilder;
 use isbn::{Isbn10, Isbn13};
+use mediawiki::Api;
 use regex::Regex;
-use std::collections::{HashMap, HashSet};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::{LazyLock, Mutex};
 use wikibase_rest_api::prelude::*;
 
 static RE_GOODREADS_ID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"/(\d+)\.jpg$").unwrap());
+/// Thumbnail size suffix of Goodreads cover URLs, like `._SY475_.jpg`
+static RE_GOODREADS_SIZE_SUFFIX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\.(_[A-Z]{2}\d+)+_\.jpg$").unwrap());
+/// The Goodreads scraper does not expose its URLs; it only talks to this host
+/// A date as Goodreads writes it, after "Published" or "First published"
+static RE_GOODREADS_PUBLISHED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:First published|Published)\s+((?:[A-Z][a-z]+\s+(?:\d{1,2},\s+)?)?\d{4})")
+        .unwrap()
+});
+static RE_HTML_TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").unwrap());
+const GOODREADS_URL: &str = "https://www.goodreads.com/";
+/// Goodreads contributor role whose names are added as strings, and their property
+const AUTHOR_ROLE: (&str, &str) = ("Author", "P225");
+/// Other Goodreads contributor roles, and the property for them. These properties need
+/// items, so the names are only suggestions until they are resolved.
+const CONTRIBUTOR_ROLES: &[(&str, &str)] = &[
+    ("Translator", "P655"),
+    ("Illustrator", "P110"),
+    ("Editor", "P98"),
+];
+// P31 values for the generated item
+const EDITION: &str = "Q3331189";
+const BOOK: &str = "Q571";
+/// References with a Goodreads work ID are stated in (P248) Goodreads
+const GOODREADS: &str = "Q2359213";
+
+/// ISO 639-2 codes, bibliographic and terminology, for languages with an ISO 639-1 code
+const ISO_639_2_CODES: &[(&str, &str)] = &[
+    ("ara", "ar"),
+    ("ces", "cs"),
+    ("chi", "zh"),
+    ("cze", "cs"),
+    ("dan", "da"),
+    ("deu", "de"),
+    ("dut", "nl"),
+    ("ell", "el"),
+    ("eng", "en"),
+    ("fin", "fi"),
+    ("fra", "fr"),
+    ("fre", "fr"),
+    ("ger", "de"),
+    ("gre", "el"),
+    ("heb", "he"),
+    ("hun", "hu"),
+    ("ita", "it"),
+    ("jpn", "ja"),
+    ("kor", "ko"),
+    ("lat", "la"),
+    ("nld", "nl"),
+    ("nor", "no"),
+    ("pol", "pl"),
+    ("por", "pt"),
+    ("rus", "ru"),
+    ("spa", "es"),
+    ("swe", "sv"),
+    ("tur", "tr"),
+    ("ukr", "uk"),
+    ("zho", "zh"),
+];
+/// Regional variants with their own Wikidata language code; others use the language only
+const REGIONAL_LANGUAGE_CODES: &[&str] = &["en-ca", "en-gb", "pt-br", "zh-hans", "zh-hant"];
+/// Language code for monolingual text in an unknown language
+const UNDETERMINED_LANGUAGE: &str = "und";
+
+type PropertyValues = HashMap<String, HashMap<DataValue, HashSet<Reference>>>;
+
 static LANGUAGE_LABELS: LazyLock<HashMap<String, String>> = LazyLock::new(|| {
     let json_string = include_str!("../static/languages.json");
     serde_json::from_str(json_string).unwrap()
 });
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsbnError {
+    /// Contains characters other than digits, hyphens, spaces, or a final X for ISBN-10
+    NotNumeric,
+    /// Neither 10 nor 13 digits
+    WrongLength(usize),
+    BadCheckDigit,
+    /// Valid check digit, but not in a known registration group
+    Unrecognized,
+}
+
+impl std::fmt::Display for IsbnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IsbnError::NotNumeric => write!(f, "ISBN contains invalid characters"),
+            IsbnError::WrongLength(len) => {
+                write!(f, "ISBN has {len} digits, expected 10 or 13")
+            }
+            IsbnError::BadCheckDigit => write!(f, "ISBN check digit is wrong"),
+            IsbnError::Unrecognized => write!(f, "ISBN is not in a known registration group"),
+        }
+    }
+}
+
+impl std::error::Error for IsbnError {}
+
+/// Loads the data for an ISBN, so tests can use canned source data.
+/// Returns a boxed future, so the source can be held as `dyn IsbnSource`.
+pub trait IsbnSource: std::fmt::Debug + Send + Sync {
+    fn retrieve<'a>(&'a self, isbn2wiki: &'a mut ISBN2wiki) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Loads from all sources, with `ISBN2wiki::retrieve`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AllIsbnSources;
+
+impl IsbnSource for AllIsbnSources {
+    fn retrieve<'a>(&'a self, isbn2wiki: &'a mut ISBN2wiki) -> BoxFuture<'a, Result<()>> {
+        Box::pin(isbn2wiki.retrieve())
+    }
+}
+
+/// A distinct value of a property, and the sources that provided it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValueSources {
+    pub value: String,
+    pub sources: Vec<String>,
+}
+
 #[derive(Debug, Default)]
 pub struct ISBN2wiki {
     pub isbn10: Option<Isbn10>,
     pub isbn13: Option<Isbn13>,
-    pub values: Mutex<HashMap<String, HashMap<DataValue, HashSet<Reference>>>>,
+    pub values: Mutex<PropertyValues>,
+    /// Type the generated item as an edition (Q3331189) even without a Goodreads work ID
+    pub type_as_edition: bool,
+    /// Type the generated item as an edition if there is a Goodreads work ID
+    pub edition_from_work_id: bool,
+    /// Full-size Goodreads cover, a candidate for upload to Commons and P18.
+    /// This is a suggestion only, not a statement.
+    pub cover_image: Mutex<Option<String>>,
+    /// Names of contributors by the property for their role in `CONTRIBUTOR_ROLES`.
+    /// These are suggestions only, not statements.
+    pub contributors: Mutex<BTreeMap<String, Vec<String>>>,
 }
 
 impl ISBN2wiki {
-    pub fn new(isbn: &str) -> Option<Self> {
-        let isbn_digits = Self::str2digits(isbn);
-        let isbn_10: Option<[u8; 10]> = Self::vec2array(isbn_digits.clone()).ok();
-        let isbn_13: Option<[u8; 13]> = Self::vec2array(isbn_digits).ok();
+    pub fn new(isbn: &str) -> Result<Self, IsbnError> {
+        let (isbn10, isbn13) = Self::parse_isbn(isbn)?;
         let mut ret = ISBN2wiki {
-            isbn10: isbn_10.and_then(|isbn_array| Isbn10::new(isbn_array).ok()),
-            isbn13: isbn_13.and_then(|isbn_array| Isbn13::new(isbn_array).ok()),
+            isbn10,
+            isbn13,
+            edition_from_work_id: Self::edition_from_work_id_from_config(Config::isbn()),
             ..Default::default()
         };
 
-        ret.add_isbn_values_as_statements()?;
+        ret.add_isbn_values_as_statements()
+            .ok_or(IsbnError::Unrecognized)?;
 
-        Some(ret)
+        Ok(ret)
+    }
+
+    /// Parses an ISBN-10 or ISBN-13 in any common formatting, and checks its check digit
+    fn parse_isbn(isbn: &str) -> Result<(Option<Isbn10>, Option<Isbn13>), IsbnError> {
+        let digits = Self::parse_isbn_digits(isbn)?;
+        let ret = match digits.len() {
+            10 => {
+                if Self::isbn10_check_digit(&digits[..9]) != digits[9] {
+                    return Err(IsbnError::BadCheckDigit);
+                }
+                let digits: [u8; 10] =
+                    Self::vec2array(digits).map_err(|_| IsbnError::WrongLength(10))?;
+                let isbn = Isbn10::new(digits).map_err(|_| IsbnError::Unrecognized)?;
+                (Some(isbn), None)
+            }
+            13 => {
+                if Self::isbn13_check_digit(&digits[..12]) != digits[12] {
+                    return Err(IsbnError::BadCheckDigit);
+                }
+                let digits: [u8; 13] =
+                    Self::vec2array(digits).map_err(|_| IsbnError::WrongLength(13))?;
+                let isbn = Isbn13::new(digits).map_err(|_| IsbnError::Unrecognized)?;
+                (None, Some(isbn))
+            }
+            len => return Err(IsbnError::WrongLength(len)),
+        };
+        Ok(ret)
+    }
+
+    /// Parses an ISBN into digits, ignoring hyphens and spaces.
+    /// A final X of an ISBN-10 is returned as 10.
+    fn parse_isbn_digits(isbn: &str) -> Result<Vec<u8>, IsbnError> {
+        let chars: Vec<char> = Self::strip_isbn_prefix(isbn)
+            .chars()
+            .filter(|c| *c != '-' && *c != ' ')
+            .collect();
+        let mut digits = Vec::with_capacity(chars.len());
+        for (pos, c) in chars.iter().enumerate() {
+            match c.to_digit(10) {
+                Some(digit) => digits.push(digit as u8),
+                None if matches!(c, 'X' | 'x') && pos == 9 && chars.len() == 10 => digits.push(10),
+                None => return Err(IsbnError::NotNumeric),
+            }
+        }
+        Ok(digits)
+    }
+
+    /// Removes a leading "ISBN", "ISBN:", or "ISBN-13:", in any case
+    fn strip_isbn_prefix(isbn: &str) -> &str {
+        let isbn = isbn.trim();
+        let Some(rest) = isbn
+            .get(..4)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("isbn"))
+            .map(|_| &isbn[4..])
+        else {
+            return isbn;
+        };
+        let rest = ["-10:", "-13:", "10:", "13:"]
+            .iter()
+            .find_map(|length| rest.strip_prefix(length))
+            .unwrap_or(rest);
+        rest.trim_start_matches([':', ' '])
+    }
+
+    /// Check digit for the first 9 digits of an ISBN-10; 10 stands for X
+    fn isbn10_check_digit(digits: &[u8]) -> u8 {
+        let sum: u32 = digits
+            .iter()
+            .zip((2..=10).rev())
+            .map(|(digit, weight)| u32::from(*digit) * weight)
+            .sum();
+        ((11 - sum % 11) % 11) as u8
+    }
+
+    /// Check digit for the first 12 digits of an ISBN-13
+    fn isbn13_check_digit(digits: &[u8]) -> u8 {
+        let sum: u32 = digits
+            .iter()
+            .enumerate()
+            .map(|(pos, digit)| u32::from(*digit) * if pos % 2 == 0 { 1 } else { 3 })
+            .sum();
+        ((10 - sum % 10) % 10) as u8
     }
 
     pub async fn new_from_item(item_id: &str) -> Option<Self> {
         let entity_id = EntityId::new(item_id).ok()?;
         let api = RestApi::wikidata().ok()?;
         let statements = Statements::get(&entity_id, &api).await.ok()?;
-        let isbn10 = Self::extract_isbn_string(&statements, "P957")
-            .and_then(|digits| Self::vec2array(digits).ok())
-            .and_then(|arr| Isbn10::new(arr).ok());
-        let isbn13 = Self::extract_isbn_string(&statements, "P212")
-            .and_then(|digits| Self::vec2array(digits).ok())
-            .and_then(|arr| Isbn13::new(arr).ok());
+        let values: Vec<String> = ["P212", "P957"]
+            .iter()
+            .flat_map(|property| Self::extract_isbn_strings(&statements, property))
+            .collect();
+        let (isbn10, isbn13) = Self::isbns_from_values(&values);
 
         if isbn10.is_none() && isbn13.is_none() {
             return None;
@@ -55,6 +273,7 @@ impl ISBN2wiki {
         let mut ret = ISBN2wiki {
             isbn10,
             isbn13,
+            edition_from_work_id: Self::edition_from_work_id_from_config(Config::isbn()),
             ..Default::default()
         };
 
@@ -63,18 +282,35 @@ impl ISBN2wiki {
         Some(ret)
     }
 
-    fn extract_isbn_string(statements: &Statements, property: &str) -> Option<Vec<u8>> {
+    /// The values of all `property` statements
+    fn extract_isbn_strings(statements: &Statements, property: &str) -> Vec<String> {
         statements
             .statements()
             .iter()
             .filter(|(prop, _s)| *prop == property)
-            .filter_map(|(_prop, s)| s.first())
+            .flat_map(|(_prop, s)| s.iter())
             .filter_map(|s| match s.value() {
-                StatementValue::Value(StatementValueContent::String(s)) => Some(s),
+                StatementValue::Value(StatementValueContent::String(s)) => Some(s.to_owned()),
                 _ => None,
             })
-            .map(|s| Self::str2digits(s))
-            .next()
+            .collect()
+    }
+
+    /// The first valid ISBN-10 and ISBN-13 in `values`, whatever property they are from.
+    /// Invalid values, like ones with a wrong check digit, are skipped.
+    fn isbns_from_values(values: &[String]) -> (Option<Isbn10>, Option<Isbn13>) {
+        let mut isbn10 = None;
+        let mut isbn13 = None;
+        for value in values {
+            match Self::parse_isbn(value) {
+                Ok((ten, thirteen)) => {
+                    isbn10 = isbn10.or(ten);
+                    isbn13 = isbn13.or(thirteen);
+                }
+                Err(e) => tracing::warn!("Invalid ISBN {value}: {e}"),
+            }
+        }
+        (isbn10, isbn13)
     }
 
     fn vec2array<T, const N: usize>(v: Vec<T>) -> Result<[T; N]> {
@@ -92,11 +328,46 @@ impl ISBN2wiki {
         }
     }
 
+    /// Loads data from all sources. A failing or timed out source does not discard the data
+    /// of the others; only if all sources fail, the first error is returned.
     pub async fn retrieve(&mut self) -> Result<()> {
         let f1 = self.load_from_goodreads();
         let f2 = GoogleBooksFeed::load_from_google_books(self);
-        futures::try_join!(f1, f2)?;
-        Ok(())
+        let f3 = OpenLibraryBook::load_from_openlibrary(self);
+        let f4 = self.load_from_crossref();
+        let (r1, r2, r3, r4) = futures::join!(f1, f2, f3, f4);
+        Self::combine_source_results(vec![
+            ("Goodreads", r1),
+            ("Google Books", r2),
+            ("OpenLibrary", r3),
+            ("Crossref", r4),
+        ])
+    }
+
+    pub(crate) fn combine_source_results(results: Vec<(&str, Result<()>)>) -> Result<()> {
+        let mut first_error = None;
+        let mut any_ok = false;
+        for (source, result) in results {
+            match result {
+                Ok(()) => any_ok = true,
+                Err(e) => {
+                    tracing::warn!("{source} failed: {e}");
+                    let source = source.to_lowercase().replace(' ', "_");
+                    Metrics::global().record_upstream_failure(&format!("isbn_{source}"));
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        match first_error {
+            Some(e) if !any_ok => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Adds publisher (P123) and series (P179) names as unresolved string placeholders,
+    /// as well as the publication date (P577).
+    async fn load_from_crossref(&self) -> Result<()> {
+        CrossrefResponse::load_from_crossref(self).await
     }
 
     async fn load_from_goodreads(&self) -> Result<()> {
@@ -104,27 +375,34 @@ impl ISBN2wiki {
             .isbn()
             .ok_or_else(|| anyhow!("No ISBN found"))?
             .replace('-', "");
-        let metadata = MetadataRequestBuilder::default()
-            .with_isbn(&isbn)
-            .execute()
-            .await
-            .map_err(|_e| anyhow!("Failed to retrieve metadata"))?
+        // The scraper has no client settings, so the timeout is applied to the whole request
+        let timeout = Config::timeout("goodreads", DEFAULT_SOURCE_TIMEOUT);
+        // The scraper does not expose HTTP status codes, so every error is treated as transient
+        let metadata = Retry::from_config()
+            .run(
+                || async {
+                    RateLimiter::global().wait(GOODREADS_URL).await;
+                    let request = MetadataRequestBuilder::default().with_isbn(&isbn).execute();
+                    match tokio::time::timeout(timeout, request).await {
+                        Ok(result) => result.map_err(|_e| anyhow!("Failed to retrieve metadata")),
+                        Err(_) => Err(anyhow!("Goodreads timed out after {timeout:?}")),
+                    }
+                },
+                |result| result.is_err(),
+            )
+            .await?
             .ok_or(anyhow!("No metadata found"))?;
 
-        let goodreads_thumbnail_url = match metadata.image_url {
-            Some(url) => url,
-            None => return Err(anyhow!("No ID found")),
-        };
-        let goodreads_work_id = match RE_GOODREADS_ID.captures(goodreads_thumbnail_url.as_str()) {
-            Some(captures) => {
-                if let Some(first_group) = captures.get(1) {
-                    first_group.as_str().to_string()
-                } else {
-                    return Err(anyhow!("No ID found"));
-                }
-            }
-            None => return Err(anyhow!("No ID found")),
+        let Some((goodreads_work_id, cover_image)) = metadata
+            .image_url
+            .as_deref()
+            .and_then(Self::parse_goodreads_image_url)
+        else {
+            return Err(anyhow!("No ID found"));
         };
+        if let Ok(mut cover) = self.cover_image.lock() {
+            *cover = Some(cover_image);
+        }
 
         self.add_reference(
             "P8383",
@@ -133,37 +411,27 @@ impl ISBN2wiki {
         );
 
         if let Some(publication_date) = metadata.publication_date {
-            let publication_date = publication_date.format("%Y-%m-%dT%H:%M:%SZ").to_string();
-            let precision = if publication_date.contains("-01-01T") {
-                TimePrecision::Year
-            } else {
-                TimePrecision::Day
-            };
+            let publication_info = Self::load_goodreads_publication_info(&isbn).await;
+            let (time, precision) =
+                Self::goodreads_publication_date(&publication_date, &publication_info);
             self.add_reference(
                 "P577",
-                DataValue::Date {
-                    time: publication_date,
-                    precision,
-                },
-                Reference::prop("P8383", &goodreads_work_id),
+                DataValue::Date { time, precision },
+                Self::goodreads_reference(&goodreads_work_id),
             );
         }
 
-        for contributor in metadata.contributors {
-            if contributor.role == "Author" {
-                self.add_reference(
-                    "P225",
-                    DataValue::String(contributor.name.to_owned()),
-                    Reference::prop("P8383", &goodreads_work_id),
-                );
-            }
-        }
+        let contributors = metadata
+            .contributors
+            .iter()
+            .map(|contributor| (contributor.name.as_str(), contributor.role.as_str()));
+        self.add_goodreads_contributors(contributors, &goodreads_work_id);
 
         if let Some(pages) = metadata.page_count {
             self.add_reference(
                 "P1104",
                 DataValue::Quantity(pages),
-                Reference::prop("P8383", &goodreads_work_id),
+                Self::goodreads_reference(&goodreads_work_id),
             );
         }
 
@@ -182,7 +450,7 @@ impl ISBN2wiki {
                     label: metadata.title.to_owned(),
                     language: language_code.to_owned(),
                 },
-                Reference::prop("P8383", &goodreads_work_id),
+                Self::goodreads_reference(&goodreads_work_id),
             );
 
             if let Some(subtitle) = &metadata.subtitle {
@@ -192,67 +460,443 @@ impl ISBN2wiki {
                         label: subtitle.to_owned(),
                         language: language_code.to_owned(),
                     },
-                    Reference::prop("P8383", &goodreads_work_id),
+                    Self::goodreads_reference(&goodreads_work_id),
                 );
             }
+
+            self.add_language(language_code, Self::goodreads_reference(&goodreads_work_id));
         }
 
         Ok(())
     }
 
+    /// A reference to a Goodreads work, stated in Goodreads
+    fn goodreads_reference(work_id: &str) -> Reference {
+        Reference::prop("P8383", work_id).with_stated_in(GOODREADS)
+    }
+
+    /// The publication dates as written on the Goodreads page of the ISBN, like
+    /// "January 1, 1988", "July 1988", or "1988". Empty if the page can not be loaded.
+    async fn load_goodreads_publication_info(isbn: &str) -> Vec<String> {
+        let url = format!("{GOODREADS_URL}search?q={isbn}");
+        RateLimiter::global().wait(GOODREADS_URL).await;
+        let html = match HttpFetcher::new() {
+            Ok(fetcher) => fetcher.fetch(&url).await,
+            Err(e) => Err(e),
+        };
+        match html {
+            Ok(html) => Self::parse_goodreads_publication_info(&html),
+            Err(e) => {
+                tracing::warn!("Could not load the Goodreads page for {isbn}: {e}");
+                Metrics::global().record_upstream_failure("isbn_goodreads_page");
+                vec![]
+            }
+        }
+    }
+
+    /// The "Published" and "First published" dates of a Goodreads book page
+    fn parse_goodreads_publication_info(html: &str) -> Vec<String> {
+        let text = RE_HTML_TAG.replace_all(html, " ");
+        RE_GOODREADS_PUBLISHED
+            .captures_iter(&text)
+            .filter_map(|c| c.get(1))
+            .map(|date| {
+                date.as_str()
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+
+    /// The scraper only has a timestamp, which is the same for "1988" and "January 1, 1988",
+    /// so the precision is that of the first date written on the Goodreads page that matches
+    /// the timestamp. Without one, only the year is certain.
+    fn goodreads_publication_date(
+        date: &DateTime<Utc>,
+        publication_info: &[String],
+    ) -> (String, TimePrecision) {
+        let truncated = |precision: &TimePrecision| {
+            match precision {
+                TimePrecision::Year => date.format("+%Y-01-01T00:00:00Z"),
+                TimePrecision::Month => date.format("+%Y-%m-01T00:00:00Z"),
+                _ => date.format("+%Y-%m-%dT00:00:00Z"),
+            }
+            .to_string()
+        };
+        publication_info
+            .iter()
+            .filter_map(|text| OpenLibraryBook::parse_date(text))
+            .find(|(time, precision)| *time == truncated(precision))
+            .unwrap_or_else(|| (truncated(&TimePrecision::Year), TimePrecision::Year))
+    }
+
+    /// Adds the names of `(name, role)` authors as strings. The names of contributors
+    /// with a role in `CONTRIBUTOR_ROLES` are kept as suggestions, since their properties
+    /// need items. Other roles are ignored.
+    fn add_goodreads_contributors<'a>(
+        &self,
+        contributors: impl Iterator<Item = (&'a str, &'a str)>,
+        goodreads_work_id: &str,
+    ) {
+        let (author_role, author_property) = AUTHOR_ROLE;
+        for (name, role) in contributors {
+            let role = role.trim();
+            if role.eq_ignore_ascii_case(author_role) {
+                self.add_reference(
+                    author_property,
+                    DataValue::String(name.to_owned()),
+                    Self::goodreads_reference(goodreads_work_id),
+                );
+                continue;
+            }
+            let Some((_, property)) = CONTRIBUTOR_ROLES
+                .iter()
+                .find(|(r, _)| r.eq_ignore_ascii_case(role))
+            else {
+                continue;
+            };
+            if let Ok(mut suggestions) = self.contributors.lock() {
+                let names = suggestions.entry(property.to_string()).or_default();
+                if !names.iter().any(|n| n == name) {
+                    names.push(name.to_owned());
+                }
+            }
+        }
+    }
+
+    /// Returns the Goodreads work ID, and the cover URL without the thumbnail size suffix.
+    fn parse_goodreads_image_url(image_url: &str) -> Option<(String, String)> {
+        let cover_image = RE_GOODREADS_SIZE_SUFFIX
+            .replace(image_url.trim(), ".jpg")
+            .into_owned();
+        let work_id = RE_GOODREADS_ID
+            .captures(&cover_image)?
+            .get(1)?
+            .as_str()
+            .to_string();
+        Some((work_id, cover_image))
+    }
+
+    pub fn cover_image(&self) -> Option<String> {
+        self.cover_image.lock().ok()?.clone()
+    }
+
+    /// Names of translators, illustrators and editors, by property
+    pub fn contributors(&self) -> BTreeMap<String, Vec<String>> {
+        self.contributors
+            .lock()
+            .map(|contributors| contributors.clone())
+            .unwrap_or_default()
+    }
+
+    /// Items that already have the ISBN-13 as P212 or the ISBN-10 as P957, found with a
+    /// `haswbstatement` search on the shared Wikidata API. Fails if the search fails, so
+    /// an unavailable search is never reported as "no existing items".
+    pub async fn existing_items(&self) -> Result<Vec<String>> {
+        let api = Wikidata::get_wikidata_api()
+            .await
+            .map_err(|status| anyhow!("Wikidata API unavailable: {status}"))?;
+        self.existing_items_with(&api).await
+    }
+
+    async fn existing_items_with(&self, api: &Api) -> Result<Vec<String>> {
+        let Some(search) = self.existing_items_search() else {
+            return Ok(vec![]);
+        };
+        let params = api.params_into(&[
+            ("action", "query"),
+            ("list", "search"),
+            ("srnamespace", "0"),
+            ("srsearch", &search),
+        ]);
+        let result = api.get_query_api_json(&params).await?;
+        Self::parse_search_items(&result)
+    }
+
+    /// Searches the hyphenated and the plain-digit ISBNs, as both are used on Wikidata
+    fn existing_items_search(&self) -> Option<String> {
+        let mut conditions = vec![];
+        if let Some(isbn) = self.isbn13 {
+            if let Ok(hyphenated) = isbn.hyphenate() {
+                conditions.push(format!("P212={hyphenated}"));
+            }
+            conditions.push(format!("P212={isbn}"));
+        }
+        if let Some(isbn) = self.isbn10 {
+            if let Ok(hyphenated) = isbn.hyphenate() {
+                conditions.push(format!("P957={hyphenated}"));
+            }
+            conditions.push(format!("P957={isbn}"));
+        }
+        if conditions.is_empty() {
+            return None;
+        }
+        Some(format!("haswbstatement:{}", conditions.join("|")))
+    }
+
+    /// Item IDs from a `list=search` result
+    fn parse_search_items(result: &serde_json::Value) -> Result<Vec<String>> {
+        let results = result["query"]["search"]
+            .as_array()
+            .ok_or_else(|| anyhow!("Unexpected search result: {result}"))?;
+        Ok(results
+            .iter()
+            .filter_map(|result| result["title"].as_str().map(str::to_string))
+            .collect())
+    }
+
+    /// Adds `reference` to the `value` of `property`. Monolingual texts, like titles, get
+    /// Wikidata language codes, and the same text from different sources is one value.
     pub fn add_reference(&self, property: &str, value: DataValue, reference: Reference) {
         // TODO handle poisoned mutex, or just ignore? unlikely event, no real fallout
         if let Ok(mut values) = self.values.lock() {
-            values
-                .entry(property.to_string())
-                .or_default()
-                .entry(value)
-                .or_default()
-                .insert(reference);
+            let dv2refs = values.entry(property.to_string()).or_default();
+            let value = Self::reconcile_monolingual(dv2refs, value);
+            dv2refs.entry(value).or_default().insert(reference);
+        }
+    }
+
+    /// Normalizes the language of a monolingual text. The same text in an undetermined
+    /// language is merged into the one in a known language; different texts, like
+    /// translations, are kept apart.
+    fn reconcile_monolingual(
+        dv2refs: &mut HashMap<DataValue, HashSet<Reference>>,
+        value: DataValue,
+    ) -> DataValue {
+        let DataValue::Monolingual { label, language } = value else {
+            return value;
+        };
+        let language = Self::wikidata_language_code(&language);
+        let known_language = dv2refs.keys().find_map(|dv| match dv {
+            DataValue::Monolingual {
+                label: other_label,
+                language: other_language,
+            } if *other_label == label && *other_language != UNDETERMINED_LANGUAGE => {
+                Some(other_language.to_owned())
+            }
+            _ => None,
+        });
+        match known_language {
+            Some(known_language) if language == UNDETERMINED_LANGUAGE => DataValue::Monolingual {
+                label,
+                language: known_language,
+            },
+            _ => {
+                let undetermined = DataValue::Monolingual {
+                    label: label.to_owned(),
+                    language: UNDETERMINED_LANGUAGE.to_string(),
+                };
+                let value = DataValue::Monolingual { label, language };
+                if value != undetermined {
+                    if let Some(references) = dv2refs.remove(&undetermined) {
+                        dv2refs.entry(value.clone()).or_default().extend(references);
+                    }
+                }
+                value
+            }
         }
     }
 
+    /// The Wikidata code for a BCP-47 language tag, like "en" for "eng" or "en_US",
+    /// and "pt-br" for "pt-BR"
+    fn wikidata_language_code(language: &str) -> String {
+        let language = language.trim().to_lowercase().replace('_', "-");
+        let (primary, region) = match language.split_once('-') {
+            Some((primary, region)) => (primary, Some(region)),
+            None => (language.as_str(), None),
+        };
+        let primary = ISO_639_2_CODES
+            .iter()
+            .find(|(code, _)| *code == primary)
+            .map_or(primary, |(_, code)| *code);
+        if primary.is_empty() {
+            return UNDETERMINED_LANGUAGE.to_string();
+        }
+        match region {
+            Some(region)
+                if REGIONAL_LANGUAGE_CODES.contains(&format!("{primary}-{region}").as_str()) =>
+            {
+                format!("{primary}-{region}")
+            }
+            _ => primary.to_string(),
+        }
+    }
+
+    /// Adds a "language of work or name" (P407) statement for an ISO 639-1 code, like "en"
+    /// or "en-US". Codes without a known language item are skipped.
+    pub fn add_language(&self, language_code: &str, reference: Reference) {
+        let code = language_code
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        if let Some(item) = Wikidata::language_item(&code) {
+            self.add_reference("P407", DataValue::Entity(item.to_string()), reference);
+        }
+    }
+
+    /// Adds a publisher (P123) statement, if the name matches exactly one publisher item.
+    pub async fn add_publisher_by_name(&self, name: &str, reference: Reference) {
+        let Ok(api) = Wikidata::get_wikidata_api().await else {
+            return;
+        };
+        let Ok(items) = Wikidata::search_single_name(&api, name, "Q2085381").await else {
+            return;
+        };
+        if let [item] = items.as_slice() {
+            self.add_reference("P123", DataValue::Entity(item.to_owned()), reference);
+        }
+    }
+
+    /// Adds P957 (ISBN-10) and P212 (ISBN-13) statements, deriving the missing one
+    /// where possible.
     fn add_isbn_values_as_statements(&mut self) -> Option<()> {
         if self.isbn10.is_none() && self.isbn13.is_none() {
             return None;
         }
+        if self.isbn10.is_none() {
+            self.isbn10 = self.isbn13.and_then(Self::isbn13_to_isbn10);
+        }
+        if self.isbn13.is_none() {
+            self.isbn13 = self.isbn10.and_then(Self::isbn10_to_isbn13);
+        }
         if let Some(isbn) = self.isbn10 {
             self.add_reference(
                 "P957",
-                DataValue::String(isbn.hyphenate().ok()?.to_string()),
+                DataValue::String(
+                    isbn.hyphenate()
+                        .map(|hyphenated| hyphenated.to_string())
+                        .unwrap_or_else(|_| isbn.to_string()),
+                ),
                 Reference::default(), // No reference for ISBN
             );
         }
         if let Some(isbn) = self.isbn13 {
             self.add_reference(
                 "P212",
-                DataValue::String(isbn.hyphenate().ok()?.to_string()),
+                DataValue::String(
+                    isbn.hyphenate()
+                        .map(|hyphenated| hyphenated.to_string())
+                        .unwrap_or_else(|_| isbn.to_string()),
+                ),
                 Reference::default(), // No reference for ISBN
             );
         }
         Some(())
     }
 
-    pub fn generate_item(&self) -> Result<Item> {
-        let mut ret = Item::default();
+    /// Only 978-prefixed ISBN-13s have an ISBN-10 equivalent.
+    /// Works on the raw digits, so ISBNs outside the known ranges convert too.
+    fn isbn13_to_isbn10(isbn13: Isbn13) -> Option<Isbn10> {
+        let digits = Self::str2digits(&isbn13.to_string());
+        if digits.len() != 13 || !digits.starts_with(&[9, 7, 8]) {
+            return None;
+        }
+        let mut digits = digits[3..12].to_vec();
+        digits.push(Self::isbn10_check_digit(&digits));
+        Isbn10::new(Self::vec2array(digits).ok()?).ok()
+    }
+
+    fn isbn10_to_isbn13(isbn10: Isbn10) -> Option<Isbn13> {
+        // The check digit, maybe X, is recomputed anyway
+        let digits = Self::str2digits(&isbn10.to_string());
+        let mut digits: Vec<u8> = [9, 7, 8]
+            .into_iter()
+            .chain(digits.into_iter().take(9))
+            .collect();
+        if digits.len() != 12 {
+            return None;
+        }
+        digits.push(Self::isbn13_check_digit(&digits));
+        Isbn13::new(Self::vec2array(digits).ok()?).ok()
+    }
+
+    /// Reports, per property, the distinct values and the sources that provided them.
+    /// Only properties with more than one distinct value are included.
+    pub fn conflicts(&self) -> Result<BTreeMap<String, Vec<ValueSources>>> {
         let values = self
             .values
             .lock()
             .map_err(|_| anyhow!("Values lock poisoned"))?;
+        let mut ret = BTreeMap::new();
+        for (property, dv2refs) in values.iter().filter(|(_, dv2refs)| dv2refs.len() > 1) {
+            let mut value_sources: Vec<ValueSources> = dv2refs
+                .iter()
+                .map(|(datavalue, references)| {
+                    let mut sources: Vec<String> = references
+                        .iter()
+                        .filter_map(Reference::source_name)
+                        .collect();
+                    sources.sort();
+                    sources.dedup();
+                    ValueSources {
+                        value: datavalue.to_string(),
+                        sources,
+                    }
+                })
+                .collect();
+            value_sources.sort_by(|a, b| a.value.cmp(&b.value));
+            ret.insert(property.to_owned(), value_sources);
+        }
+        Ok(ret)
+    }
 
-        for (property, dv2refs) in values.iter() {
-            for (datavalue, references) in dv2refs {
-                let expected_value = datavalue.as_statement_value();
-                let mut statement = Statement::default();
-                statement.set_property(PropertyType::property(property.to_owned()));
-                statement.set_value(expected_value);
-                Self::add_new_references_to_statement(&mut statement, references);
-                ret.statements_mut()
-                    .statements_mut()
-                    .entry(property.to_owned())
-                    .or_default()
-                    .push(statement);
-            }
+    /// Whether a Goodreads work ID types the item as an edition, from
+    /// `isbn.edition_from_work_id` in the configuration. On by default.
+    pub fn edition_from_work_id_from_config(config: &IsbnConfig) -> bool {
+        config.edition_from_work_id.unwrap_or(true)
+    }
+
+    /// Returns a copy of the values, typed as an edition rather than a generic book
+    /// if there is a Goodreads work ID (and `edition_from_work_id` is set),
+    /// or `type_as_edition` is set.
+    fn values_with_edition_typing(&self) -> Result<PropertyValues> {
+        let mut values = self
+            .values
+            .lock()
+            .map_err(|_| anyhow!("Values lock poisoned"))?
+            .clone();
+        let work_ids: Vec<String> = values
+            .get("P8383")
+            .filter(|_| self.edition_from_work_id)
+            .map(|dv2refs| {
+                dv2refs
+                    .keys()
+                    .filter_map(|dv| match dv {
+                        DataValue::String(s) => Some(s.to_owned()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        if work_ids.is_empty() && !self.type_as_edition {
+            return Ok(values);
+        }
+        let p31 = values.entry("P31".to_string()).or_default();
+        p31.remove(&DataValue::Entity(BOOK.to_string()));
+        let edition_refs = p31
+            .entry(DataValue::Entity(EDITION.to_string()))
+            .or_default();
+        edition_refs.extend(work_ids.iter().map(|id| Self::goodreads_reference(id)));
+        Ok(values)
+    }
+
+    pub fn generate_item(&self) -> Result<Item> {
+        let mut ret = Item::default();
+        let values = self.values_with_edition_typing()?;
+
+        for (property, datavalue, references) in Self::sorted_values(&values) {
+            let expected_value = datavalue.as_statement_value();
+            let mut statement = Statement::default();
+            statement.set_property(PropertyType::property(property.to_owned()));
+            statement.set_value(expected_value);
+            Self::add_new_references_to_statement(&mut statement, &references);
+            ret.statements_mut()
+                .statements_mut()
+                .entry(property.to_owned())
+                .or_default()
+                .push(statement);
         }
 
         // TODO labels etc
@@ -263,60 +907,54 @@ impl ISBN2wiki {
         let entity_id = EntityId::new(item_id)?;
         let statements_old = Statements::default();
         let mut statements_new = statements_old.clone();
-        let values = self
-            .values
-            .lock()
-            .map_err(|_| anyhow!("Values lock poisoned"))?;
-
-        for (property, dv2refs) in values.iter() {
-            for (datavalue, references) in dv2refs {
-                let expected_value = datavalue.as_statement_value();
-                let mut statements: Vec<&mut Statement> = vec![];
-                if let Some(existing) = statements_new.statements_mut().get_mut(property) {
-                    let tmp: Vec<_> = existing
-                        .iter_mut()
-                        .filter(|statement| *statement.value() == expected_value)
-                        .collect();
-                    statements.extend(tmp);
-                }
+        let values = self.values_with_edition_typing()?;
+
+        for (property, datavalue, references) in Self::sorted_values(&values) {
+            let expected_value = datavalue.as_statement_value();
+            let mut statements: Vec<&mut Statement> = vec![];
+            if let Some(existing) = statements_new.statements_mut().get_mut(property) {
+                let tmp: Vec<_> = existing
+                    .iter_mut()
+                    .filter(|statement| *statement.value() == expected_value)
+                    .collect();
+                statements.extend(tmp);
+            }
 
-                // If more than one statement, remove deprecated
-                if statements.len() > 1 {
-                    statements
-                        .retain(|s| *s.rank() != wikibase_rest_api::StatementRank::Deprecated);
-                }
+            // If more than one statement, remove deprecated
+            if statements.len() > 1 {
+                statements.retain(|s| *s.rank() != wikibase_rest_api::StatementRank::Deprecated);
+            }
 
-                // If more than one statement, with at least one preferred, keep only preferred
-                if statements.len() > 1
-                    && statements
-                        .iter()
-                        .any(|s| *s.rank() == wikibase_rest_api::StatementRank::Preferred)
-                {
-                    statements.retain(|s| *s.rank() == wikibase_rest_api::StatementRank::Preferred);
-                }
+            // If more than one statement, with at least one preferred, keep only preferred
+            if statements.len() > 1
+                && statements
+                    .iter()
+                    .any(|s| *s.rank() == wikibase_rest_api::StatementRank::Preferred)
+            {
+                statements.retain(|s| *s.rank() == wikibase_rest_api::StatementRank::Preferred);
+            }
 
-                if statements.len() > 1 {
-                    // More than one possible statement, not sure which, skip
-                    continue;
-                }
+            if statements.len() > 1 {
+                // More than one possible statement, not sure which, skip
+                continue;
+            }
 
-                // Only one or no statements, add references to existing,
-                // or create new statement with references
-                match statements.first_mut() {
-                    Some(statement) => Self::add_new_references_to_statement(statement, references),
-                    None => {
-                        let mut statement = Statement::default();
-                        statement.new_id_for_entity(&entity_id);
-                        statement.set_property(PropertyType::property(property.to_owned()));
-                        statement.set_value(expected_value);
-                        Self::add_new_references_to_statement(&mut statement, references);
-                        drop(statements);
-                        statements_new
-                            .statements_mut()
-                            .entry(property.to_owned())
-                            .or_default()
-                            .push(statement);
-                    }
+            // Only one or no statements, add references to existing,
+            // or create new statement with references
+            match statements.first_mut() {
+                Some(statement) => Self::add_new_references_to_statement(statement, &references),
+                None => {
+                    let mut statement = Statement::default();
+                    statement.new_id_for_entity(&entity_id);
+                    statement.set_property(PropertyType::property(property.to_owned()));
+                    statement.set_value(expected_value);
+                    Self::add_new_references_to_statement(&mut statement, &references);
+                    drop(statements);
+                    statements_new
+                        .statements_mut()
+                        .entry(property.to_owned())
+                        .or_default()
+                        .push(statement);
                 }
             }
         }
@@ -325,7 +963,26 @@ impl ISBN2wiki {
         Ok(patch)
     }
 
-    fn add_new_references_to_statement(statement: &mut Statement, references: &HashSet<Reference>) {
+    /// Properties, values, and their references in a stable order, so the generated
+    /// item or patch only depends on the retrieved data, not on `HashMap` iteration order.
+    fn sorted_values(values: &PropertyValues) -> Vec<(&str, &DataValue, Vec<&Reference>)> {
+        let mut ret: Vec<_> = values
+            .iter()
+            .flat_map(|(property, dv2refs)| {
+                dv2refs.iter().map(move |(datavalue, references)| {
+                    let mut references: Vec<&Reference> = references.iter().collect();
+                    references.sort();
+                    (property.as_str(), datavalue, references)
+                })
+            })
+            .collect();
+        ret.sort_by_cached_key(|(property, datavalue, _)| {
+            (property.to_string(), datavalue.sort_key())
+        });
+        ret
+    }
+
+    fn add_new_references_to_statement(statement: &mut Statement, references: &[&Reference]) {
         for reference in references {
             if !statement
                 .references()
@@ -339,17 +996,202 @@ impl ISBN2wiki {
         }
     }
 
+    /// The digits of `isbn`, ignoring other characters.
+    /// A final X (or x) is kept as 10, the check digit of some ISBN-10s.
     fn str2digits(isbn: &str) -> Vec<u8> {
-        isbn.chars()
+        let mut digits = isbn
+            .chars()
             .filter_map(|c| c.to_digit(10))
             .map(|c| c as u8)
-            .collect::<Vec<u8>>()
+            .collect::<Vec<u8>>();
+        if isbn.trim_end().ends_with(['X', 'x']) {
+            digits.push(10);
+        }
+        digits
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
+    use wikibase_rest_api::Patch as _;
+
+    /// A Wikidata API that answers searches for `srsearch` with Q123, and fails others
+    async fn canned_search_api(srsearch: String) -> String {
+        crate::test_server::wiki_api(move |params| {
+            let result = match params.get("srsearch")? {
+                search if *search == srsearch => {
+                    json!({"query": {"search": [{"ns": 0, "title": "Q123"}]}})
+                }
+                _ => json!({"error": {"code": "unexpected-search"}}),
+            };
+            Some(result)
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_existing_items_found() {
+        let isbn2wiki = ISBN2wiki::new("9782267027006").unwrap();
+        let search = isbn2wiki.existing_items_search().unwrap();
+        assert!(search.starts_with("haswbstatement:P212=978-2-267-02700-6|P212=9782267027006|"));
+        assert!(search.ends_with("|P957=2267027003"));
+        let api = Api::new(&canned_search_api(search).await).await.unwrap();
+        let items = isbn2wiki.existing_items_with(&api).await.unwrap();
+        assert_eq!(items, vec!["Q123".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_search_items_unusable_result() {
+        assert!(ISBN2wiki::parse_search_items(&json!({})).is_err());
+        assert!(ISBN2wiki::parse_search_items(&json!({"error": {"code": "ratelimited"}})).is_err());
+        let empty = ISBN2wiki::parse_search_items(&json!({"query": {"search": []}})).unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_add_language_english() {
+        let isbn2wiki = ISBN2wiki::new("9780140328721").unwrap();
+        isbn2wiki.add_language("en", Reference::prop("P8383", "6693"));
+        isbn2wiki.add_language("xx", Reference::prop("P8383", "6693"));
+        let values = isbn2wiki.values.lock().unwrap();
+        let languages: Vec<&DataValue> = values["P407"].keys().collect();
+        assert_eq!(languages, vec![&DataValue::Entity("Q1860".to_string())]);
+        assert!(
+            values["P407"][&DataValue::Entity("Q1860".to_string())]
+                .contains(&Reference::prop("P8383", "6693"))
+        );
+    }
+
+    #[test]
+    fn test_add_language_region_code() {
+        let isbn2wiki = ISBN2wiki::new("9780140328721").unwrap();
+        isbn2wiki.add_language("pt-BR", Reference::none());
+        let values = isbn2wiki.values.lock().unwrap();
+        assert!(values["P407"].contains_key(&DataValue::Entity("Q5146".to_string())));
+    }
+
+    fn goodreads_date(date: &str, published: &str) -> (String, TimePrecision) {
+        let date = DateTime::parse_from_rfc3339(date)
+            .unwrap()
+            .with_timezone(&Utc);
+        ISBN2wiki::goodreads_publication_date(&date, &[published.to_string()])
+    }
+
+    #[test]
+    fn test_goodreads_publication_date_year_only() {
+        assert_eq!(
+            goodreads_date("1988-01-01T00:00:00Z", "1988"),
+            ("+1988-01-01T00:00:00Z".to_string(), TimePrecision::Year)
+        );
+    }
+
+    #[test]
+    fn test_goodreads_publication_date_january_first() {
+        assert_eq!(
+            goodreads_date("1988-01-01T00:00:00Z", "January 1, 1988"),
+            ("+1988-01-01T00:00:00Z".to_string(), TimePrecision::Day)
+        );
+    }
+
+    #[test]
+    fn test_goodreads_publication_date_month() {
+        assert_eq!(
+            goodreads_date("1988-07-01T00:00:00Z", "July 1988"),
+            ("+1988-07-01T00:00:00Z".to_string(), TimePrecision::Month)
+        );
+    }
+
+    #[test]
+    fn test_goodreads_publication_date_mid_year() {
+        assert_eq!(
+            goodreads_date("1988-07-14T08:00:00Z", "July 14, 1988"),
+            ("+1988-07-14T00:00:00Z".to_string(), TimePrecision::Day)
+        );
+    }
+
+    #[test]
+    fn test_goodreads_publication_date_without_matching_info() {
+        assert_eq!(
+            goodreads_date("1988-07-14T08:00:00Z", "March 3, 2001"),
+            ("+1988-01-01T00:00:00Z".to_string(), TimePrecision::Year)
+        );
+        let date = DateTime::parse_from_rfc3339("1988-07-14T08:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            ISBN2wiki::goodreads_publication_date(&date, &[]),
+            ("+1988-01-01T00:00:00Z".to_string(), TimePrecision::Year)
+        );
+    }
+
+    #[test]
+    fn test_parse_goodreads_publication_info() {
+        let html = r#"<p data-testid="publicationInfo">First published January 1, 1988</p>
+            <dt>Published</dt><dd><div>July
+            1988 by Puffin</div></dd>"#;
+        assert_eq!(
+            ISBN2wiki::parse_goodreads_publication_info(html),
+            vec!["January 1, 1988".to_string(), "July 1988".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_combine_source_results() {
+        let failed = || Err(anyhow!("timed out"));
+        assert!(
+            ISBN2wiki::combine_source_results(vec![
+                ("Goodreads", failed()),
+                ("OpenLibrary", Ok(()))
+            ])
+            .is_ok()
+        );
+        let error = ISBN2wiki::combine_source_results(vec![
+            ("Goodreads", failed()),
+            ("OpenLibrary", failed()),
+        ])
+        .unwrap_err();
+        assert_eq!(error.to_string(), "timed out");
+    }
+
+    // ── isbns_from_values ─────────────────────────────────────────────────────
+
+    fn hyphenated(values: &[&str]) -> (Option<String>, Option<String>) {
+        let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        let (isbn10, isbn13) = ISBN2wiki::isbns_from_values(&values);
+        (
+            isbn10
+                .and_then(|isbn| isbn.hyphenate().ok())
+                .map(|isbn| isbn.to_string()),
+            isbn13
+                .and_then(|isbn| isbn.hyphenate().ok())
+                .map(|isbn| isbn.to_string()),
+        )
+    }
+
+    #[test]
+    fn test_isbns_from_values_hyphenated_isbn13() {
+        assert_eq!(
+            hyphenated(&["978-2-267-02700-6"]),
+            (None, Some("978-2-267-02700-6".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_isbns_from_values_tolerates_formatting() {
+        let (isbn10, isbn13) = hyphenated(&["ISBN-13: 978 2 267 02700 6", "0-8044-2957-X"]);
+        assert_eq!(isbn13.as_deref(), Some("978-2-267-02700-6"));
+        assert!(isbn10.is_some_and(|isbn| isbn.ends_with('X')));
+    }
+
+    #[test]
+    fn test_isbns_from_values_skips_invalid() {
+        // Wrong check digit, then a valid ISBN-13
+        let (_, isbn13) = hyphenated(&["978-2-267-02700-7", "9782267027006"]);
+        assert_eq!(isbn13.as_deref(), Some("978-2-267-02700-6"));
+        assert_eq!(hyphenated(&["ISBN 123"]), (None, None));
+    }
 
     // ── str2digits ────────────────────────────────────────────────────────────
 
@@ -375,6 +1217,15 @@ mod tests {
         assert_eq!(ISBN2wiki::str2digits("ISBN 123"), vec![1, 2, 3]);
     }
 
+    #[test]
+    fn test_str2digits_keeps_final_x() {
+        let digits = ISBN2wiki::str2digits("0-8044-2957-X");
+        assert_eq!(digits, vec![0, 8, 0, 4, 4, 2, 9, 5, 7, 10]);
+        let isbn10 = Isbn10::new(ISBN2wiki::vec2array(digits).unwrap());
+        assert!(isbn10.is_ok());
+        assert_eq!(ISBN2wiki::str2digits("080442957x").len(), 10);
+    }
+
     #[test]
     fn test_str2digits_empty_string() {
         assert_eq!(ISBN2wiki::str2digits(""), Vec::<u8>::new());
@@ -414,19 +1265,87 @@ mod tests {
     }
 
     #[test]
-    fn test_new_invalid_returns_none() {
+    fn test_new_invalid_returns_error() {
         // All-zeros is not a valid ISBN
         assert!(
-            ISBN2wiki::new("0000000000000").is_none(),
-            "invalid ISBN must return None"
+            ISBN2wiki::new("0000000000000").is_err(),
+            "invalid ISBN must return an error"
         );
     }
 
     #[test]
-    fn test_new_empty_returns_none() {
-        assert!(
-            ISBN2wiki::new("").is_none(),
-            "empty string must return None"
+    fn test_new_empty_returns_error() {
+        assert_eq!(
+            ISBN2wiki::new("").unwrap_err(),
+            IsbnError::WrongLength(0),
+            "empty string must return an error"
+        );
+    }
+
+    #[test]
+    fn test_new_wrong_length() {
+        assert_eq!(
+            ISBN2wiki::new("978226702700").unwrap_err(),
+            IsbnError::WrongLength(12)
+        );
+    }
+
+    #[test]
+    fn test_new_bad_check_digit() {
+        assert_eq!(
+            ISBN2wiki::new("9782267027007").unwrap_err(),
+            IsbnError::BadCheckDigit
+        );
+        assert_eq!(
+            ISBN2wiki::new("2267027004").unwrap_err(),
+            IsbnError::BadCheckDigit
+        );
+    }
+
+    #[test]
+    fn test_new_not_numeric() {
+        assert_eq!(
+            ISBN2wiki::new("97822670A7006").unwrap_err(),
+            IsbnError::NotNumeric
+        );
+        // X is only valid as the last character of an ISBN-10
+        assert_eq!(
+            ISBN2wiki::new("978226702700X").unwrap_err(),
+            IsbnError::NotNumeric
+        );
+    }
+
+    #[test]
+    fn test_new_isbn10_with_x_check_digit() {
+        let isbn2wiki = ISBN2wiki::new("080442957X").expect("ISBN-10 with X should be accepted");
+        assert!(isbn2wiki.isbn10.is_some());
+    }
+
+    #[test]
+    fn test_new_accepts_prefix_spaces_and_lowercase_x() {
+        for input in [
+            "ISBN 0-8044-2957-X",
+            "isbn080442957x",
+            "ISBN-10: 0 8044 2957 X",
+            "ISBN: 080442957X",
+        ] {
+            let isbn2wiki =
+                ISBN2wiki::new(input).unwrap_or_else(|e| panic!("{input} should be accepted: {e}"));
+            assert!(isbn2wiki.isbn10.is_some());
+        }
+        let isbn2wiki = ISBN2wiki::new("ISBN-13: 978-2-267-02700-6").unwrap();
+        assert!(isbn2wiki.isbn13.is_some());
+    }
+
+    #[test]
+    fn test_check_digits() {
+        assert_eq!(
+            ISBN2wiki::isbn10_check_digit(&[2, 2, 6, 7, 0, 2, 7, 0, 0]),
+            3
+        );
+        assert_eq!(
+            ISBN2wiki::isbn13_check_digit(&[9, 7, 8, 2, 2, 6, 7, 0, 2, 7, 0, 0]),
+            6
         );
     }
 
@@ -442,6 +1361,56 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_new_978_isbn13_emits_isbn10() {
+        let isbn2wiki = ISBN2wiki::new("9782267027006").unwrap();
+        let isbn10 = isbn2wiki
+            .isbn10
+            .expect("978 ISBN-13 should have an ISBN-10");
+        assert_eq!(
+            ISBN2wiki::str2digits(&isbn10.hyphenate().unwrap().to_string()),
+            vec![2, 2, 6, 7, 0, 2, 7, 0, 0, 3]
+        );
+        let values = isbn2wiki.values.lock().unwrap();
+        assert!(values.contains_key("P212"));
+        assert!(values.contains_key("P957"));
+    }
+
+    #[test]
+    fn test_new_979_isbn13_emits_only_isbn13() {
+        let isbn2wiki = ISBN2wiki::new("9791090636071").unwrap();
+        assert!(isbn2wiki.isbn10.is_none());
+        let values = isbn2wiki.values.lock().unwrap();
+        assert!(values.contains_key("P212"));
+        assert!(!values.contains_key("P957"));
+    }
+
+    #[test]
+    fn test_new_isbn10_emits_isbn13() {
+        let isbn2wiki = ISBN2wiki::new("2267027003").unwrap();
+        let isbn13 = isbn2wiki.isbn13.expect("ISBN-10 should have an ISBN-13");
+        assert_eq!(
+            ISBN2wiki::str2digits(&isbn13.hyphenate().unwrap().to_string()),
+            vec![9, 7, 8, 2, 2, 6, 7, 0, 2, 7, 0, 0, 6]
+        );
+        assert!(isbn2wiki.values.lock().unwrap().contains_key("P212"));
+    }
+
+    #[test]
+    fn test_isbn10_x_check_digit_round_trip() {
+        let isbn2wiki = ISBN2wiki::new("080442957X").unwrap();
+        let isbn13 = isbn2wiki.isbn13.expect("ISBN-10 should have an ISBN-13");
+        assert_eq!(
+            ISBN2wiki::str2digits(&isbn13.to_string()),
+            vec![9, 7, 8, 0, 8, 0, 4, 4, 2, 9, 5, 7, 3]
+        );
+        let isbn10 = ISBN2wiki::isbn13_to_isbn10(isbn13).unwrap();
+        assert_eq!(
+            ISBN2wiki::str2digits(&isbn10.to_string()),
+            vec![0, 8, 0, 4, 4, 2, 9, 5, 7, 10]
+        );
+    }
+
     // ── isbn() fallback ───────────────────────────────────────────────────────
 
     #[test]
@@ -499,6 +1468,74 @@ mod tests {
         );
     }
 
+    fn title(label: &str, language: &str) -> DataValue {
+        DataValue::Monolingual {
+            label: label.to_string(),
+            language: language.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_add_reference_merges_title_languages() {
+        let isbn2wiki = ISBN2wiki::new("9780140328721").unwrap();
+        let google_books = Reference::prop("P675", "wrTtAAAAMAAJ");
+        let goodreads = Reference::prop("P8383", "6693");
+        isbn2wiki.add_reference(
+            "P1476",
+            title("Fantastic Mr Fox", "en"),
+            google_books.clone(),
+        );
+        isbn2wiki.add_reference("P1476", title("Fantastic Mr Fox", "eng"), goodreads.clone());
+        let values = isbn2wiki.values.lock().unwrap();
+        assert_eq!(values["P1476"].len(), 1);
+        let references = &values["P1476"][&title("Fantastic Mr Fox", "en")];
+        assert!(references.contains(&google_books));
+        assert!(references.contains(&goodreads));
+    }
+
+    #[test]
+    fn test_add_reference_merges_undetermined_title_language() {
+        let isbn2wiki = ISBN2wiki::new("9780140328721").unwrap();
+        let open_library = Reference::prop("P648", "OL7353617M");
+        isbn2wiki.add_reference(
+            "P1476",
+            title("Fantastic Mr Fox", "und"),
+            open_library.clone(),
+        );
+        isbn2wiki.add_reference("P1476", title("Fantastic Mr Fox", "en"), Reference::none());
+        isbn2wiki.add_reference(
+            "P1476",
+            title("Fantastic Mr Fox", "und"),
+            open_library.clone(),
+        );
+        let values = isbn2wiki.values.lock().unwrap();
+        assert_eq!(values["P1476"].len(), 1);
+        assert!(values["P1476"][&title("Fantastic Mr Fox", "en")].contains(&open_library));
+    }
+
+    #[test]
+    fn test_add_reference_keeps_translated_titles() {
+        let isbn2wiki = ISBN2wiki::new("9780140328721").unwrap();
+        isbn2wiki.add_reference("P1476", title("Fantastic Mr Fox", "en"), Reference::none());
+        isbn2wiki.add_reference(
+            "P1476",
+            title("Der fantastische Mr. Fox", "ger"),
+            Reference::none(),
+        );
+        let values = isbn2wiki.values.lock().unwrap();
+        assert_eq!(values["P1476"].len(), 2);
+        assert!(values["P1476"].contains_key(&title("Der fantastische Mr. Fox", "de")));
+    }
+
+    #[test]
+    fn test_wikidata_language_code() {
+        assert_eq!(ISBN2wiki::wikidata_language_code("eng"), "en");
+        assert_eq!(ISBN2wiki::wikidata_language_code("en_US"), "en");
+        assert_eq!(ISBN2wiki::wikidata_language_code("pt-BR"), "pt-br");
+        assert_eq!(ISBN2wiki::wikidata_language_code("FR"), "fr");
+        assert_eq!(ISBN2wiki::wikidata_language_code(""), "und");
+    }
+
     #[test]
     fn test_add_reference_accumulates_different_references() {
         let isbn2wiki = ISBN2wiki::new("9782267027006").unwrap();
@@ -513,6 +1550,61 @@ mod tests {
         );
     }
 
+    // ── conflicts ─────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_conflicts_reports_disagreeing_sources() {
+        let isbn2wiki = ISBN2wiki::new("9782267027006").unwrap();
+        isbn2wiki.add_reference(
+            "P1104",
+            DataValue::Quantity(320),
+            Reference::prop("P675", "BookID"),
+        );
+        isbn2wiki.add_reference(
+            "P1104",
+            DataValue::Quantity(318),
+            Reference::prop("P8383", "GoodreadsID"),
+        );
+        isbn2wiki.add_reference(
+            "P1104",
+            DataValue::Quantity(318),
+            Reference::prop("P648", "OL1M"),
+        );
+        let conflicts = isbn2wiki.conflicts().unwrap();
+        assert_eq!(
+            conflicts["P1104"],
+            vec![
+                ValueSources {
+                    value: "318".to_string(),
+                    sources: vec!["Goodreads".to_string(), "OpenLibrary".to_string()],
+                },
+                ValueSources {
+                    value: "320".to_string(),
+                    sources: vec!["Google Books".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conflicts_ignores_agreeing_sources() {
+        let isbn2wiki = ISBN2wiki::new("9782267027006").unwrap();
+        isbn2wiki.add_reference(
+            "P1104",
+            DataValue::Quantity(320),
+            Reference::prop("P675", "BookID"),
+        );
+        isbn2wiki.add_reference(
+            "P1104",
+            DataValue::Quantity(320),
+            Reference::prop("P8383", "GoodreadsID"),
+        );
+        let conflicts = isbn2wiki.conflicts().unwrap();
+        assert!(!conflicts.contains_key("P1104"));
+        // The ISBN itself has a single value
+        assert!(!conflicts.contains_key("P212"));
+    }
+
     // ── generate_item ─────────────────────────────────────────────────────────
 
     #[test]
@@ -547,8 +1639,144 @@ mod tests {
         );
     }
 
+    // ── edition typing ───────────────────────────────────────────────────────
+
+    fn p31_values(item: &Item) -> Vec<String> {
+        item.statements()
+            .property("P31")
+            .iter()
+            .filter_map(|s| match s.value() {
+                StatementValue::Value(StatementValueContent::String(s)) => Some(s.to_owned()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_generate_item_typed_as_edition_with_work_id() {
+        let isbn2wiki = ISBN2wiki::new("9782267027006").unwrap();
+        isbn2wiki.add_reference(
+            "P8383",
+            DataValue::String("123".to_string()),
+            Reference::none(),
+        );
+        isbn2wiki.add_reference(
+            "P31",
+            DataValue::Entity(BOOK.to_string()),
+            Reference::prop("P675", "1gLCoQEACAAJ"),
+        );
+        let item = isbn2wiki.generate_item().unwrap();
+        assert_eq!(p31_values(&item), vec![EDITION.to_string()]);
+    }
+
+    #[test]
+    fn test_generate_item_work_id_edition_typing_disabled() {
+        let mut isbn2wiki = ISBN2wiki::new("9782267027006").unwrap();
+        isbn2wiki.edition_from_work_id = false;
+        isbn2wiki.add_reference(
+            "P8383",
+            DataValue::String("123".to_string()),
+            Reference::none(),
+        );
+        isbn2wiki.add_reference(
+            "P31",
+            DataValue::Entity(BOOK.to_string()),
+            Reference::prop("P675", "1gLCoQEACAAJ"),
+        );
+        let item = isbn2wiki.generate_item().unwrap();
+        assert_eq!(p31_values(&item), vec![BOOK.to_string()]);
+    }
+
+    #[test]
+    fn test_edition_from_work_id_from_config() {
+        assert!(ISBN2wiki::edition_from_work_id_from_config(
+            &IsbnConfig::default()
+        ));
+        let config = IsbnConfig {
+            edition_from_work_id: Some(false),
+        };
+        assert!(!ISBN2wiki::edition_from_work_id_from_config(&config));
+    }
+
+    #[test]
+    fn test_generate_item_without_work_id_is_not_edition() {
+        let isbn2wiki = ISBN2wiki::new("9782267027006").unwrap();
+        isbn2wiki.add_reference(
+            "P31",
+            DataValue::Entity(BOOK.to_string()),
+            Reference::prop("P675", "1gLCoQEACAAJ"),
+        );
+        let item = isbn2wiki.generate_item().unwrap();
+        assert_eq!(p31_values(&item), vec![BOOK.to_string()]);
+    }
+
+    #[test]
+    fn test_generate_item_type_as_edition_flag() {
+        let mut isbn2wiki = ISBN2wiki::new("9782267027006").unwrap();
+        isbn2wiki.type_as_edition = true;
+        let item = isbn2wiki.generate_item().unwrap();
+        assert_eq!(p31_values(&item), vec![EDITION.to_string()]);
+    }
+
     // ── vec2array ────────────────────────────────────────────────────────────
 
+    #[test]
+    fn test_parse_goodreads_image_url() {
+        let (work_id, cover_image) = ISBN2wiki::parse_goodreads_image_url(
+            "https://i.gr-assets.com/images/S/compressed.photo.goodreads.com/books/1546071216l/5907.jpg",
+        )
+        .unwrap();
+        assert_eq!(work_id, "5907");
+        assert_eq!(
+            cover_image,
+            "https://i.gr-assets.com/images/S/compressed.photo.goodreads.com/books/1546071216l/5907.jpg"
+        );
+    }
+
+    #[test]
+    fn test_parse_goodreads_image_url_strips_size_suffix() {
+        let (work_id, cover_image) = ISBN2wiki::parse_goodreads_image_url(
+            "https://i.gr-assets.com/images/S/compressed.photo.goodreads.com/books/1546071216l/5907._SY475_.jpg",
+        )
+        .unwrap();
+        assert_eq!(work_id, "5907");
+        assert_eq!(
+            cover_image,
+            "https://i.gr-assets.com/images/S/compressed.photo.goodreads.com/books/1546071216l/5907.jpg"
+        );
+        assert_eq!(
+            ISBN2wiki::parse_goodreads_image_url("https://example.com/nophoto.png"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_add_goodreads_contributors_by_role() {
+        let isbn2wiki = ISBN2wiki::new("9780099448822").unwrap();
+        let contributors = [
+            ("Haruki Murakami", "Author"),
+            ("Jay Rubin", "Translator"),
+            ("Someone Else", "Narrator"),
+        ];
+        isbn2wiki.add_goodreads_contributors(contributors.into_iter(), "1180");
+        let values = isbn2wiki.values.lock().unwrap();
+        let reference = ISBN2wiki::goodreads_reference("1180");
+        let author = DataValue::String("Haruki Murakami".to_string());
+        assert!(values["P225"][&author].contains(&reference));
+        assert_eq!(values["P225"].len(), 1);
+        // Translators need items, so they are not statements
+        assert!(!values.contains_key("P655"));
+        assert!(!values.contains_key("P110"));
+        assert!(!values.contains_key("P98"));
+        drop(values);
+        assert_eq!(
+            isbn2wiki.contributors(),
+            BTreeMap::from([("P655".to_string(), vec!["Jay Rubin".to_string()])])
+        );
+        let item = isbn2wiki.generate_item().unwrap();
+        assert!(!json!(item).to_string().contains("Jay Rubin"));
+    }
+
     #[test]
     fn test_vec2array_correct_length() {
         let v = vec![1_u8, 2, 3];
@@ -583,6 +1811,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_generate_patch_is_deterministic() {
+        let title = || DataValue::Monolingual {
+            label: "La fraternité de l'anneau".to_string(),
+            language: "fr".to_string(),
+        };
+        let references = [
+            Reference::prop("P648", "OL1M"),
+            Reference::prop("P675", "1gLCoQEACAAJ"),
+        ];
+        let mut values = vec![("P1476", title())];
+        values.extend([544, 546, 560].map(|pages| ("P1104", DataValue::Quantity(pages))));
+
+        // The same references, added in opposite orders
+        let forward = ISBN2wiki::new("9782267027006").unwrap();
+        for (property, value) in &values {
+            for reference in &references {
+                forward.add_reference(property, value.clone(), reference.clone());
+            }
+        }
+        let backward = ISBN2wiki::new("9782267027006").unwrap();
+        for (property, value) in values.iter().rev() {
+            for reference in references.iter().rev() {
+                backward.add_reference(property, value.clone(), reference.clone());
+            }
+        }
+
+        let forward = json!(forward.generate_patch("Q1234").unwrap().patch()).to_string();
+        let backward = json!(backward.generate_patch("Q1234").unwrap().patch()).to_string();
+        assert_eq!(forward, backward);
+    }
+
+    #[test]
+    fn test_sorted_values_orders_properties_and_values() {
+        let isbn2wiki = ISBN2wiki::new("9782267027006").unwrap();
+        isbn2wiki.add_reference("P1104", DataValue::Quantity(560), Reference::none());
+        isbn2wiki.add_reference("P1104", DataValue::Quantity(544), Reference::none());
+        let values = isbn2wiki.values.lock().unwrap();
+        let sorted = ISBN2wiki::sorted_values(&values);
+        let properties: Vec<&str> = sorted.iter().map(|(property, _, _)| *property).collect();
+        assert!(properties.is_sorted());
+        let pages: Vec<String> = sorted
+            .iter()
+            .filter(|(property, _, _)| *property == "P1104")
+            .map(|(_, datavalue, _)| datavalue.to_string())
+            .collect();
+        assert_eq!(pages, vec!["544", "560"]);
+    }
+
     #[test]
     fn test_generate_patch_invalid_entity_id() {
         let isbn2wiki = ISBN2wiki::new("9782267027006").unwrap();
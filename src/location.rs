@@ -1,15 +1,231 @@
-use crate::wikidata::Wikidata;
+use crate::config::{Config, LocationConfig};
+use crate::wikidata::{QueryService, SparqlFetcher, Wikidata};
 use axum::http::StatusCode;
+use mediawiki::Api;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
 use wikibase::{Reference, Snak, Statement};
 
+pub const DEFAULT_P131_RADIUS_KM: f64 = 1.0;
+pub const DEFAULT_P131_LIMIT: usize = 5;
+const MAX_P131_RADIUS_KM: f64 = 100.0;
+const MAX_P131_LIMIT: usize = 100;
+const DEFAULT_MAX_P131_FALLBACK_RADIUS_KM: f64 = 16.0;
+/// Administrative hierarchies are rarely deeper than this, so anything longer is likely a loop
+const MAX_P131_CHAIN_DEPTH: usize = 20;
+/// `list=geosearch` does not search further than this
+const MAX_GEOSEARCH_RADIUS_M: f64 = 10_000.0;
+/// Nearby items to check for P131; also the `wbgetentities` maximum
+const GEOSEARCH_LIMIT: usize = 50;
+/// How long the P17 periods of an item are cached, unless
+/// `location.country_cache_ttl_secs` is configured
+const DEFAULT_COUNTRY_CACHE_TTL_SECS: u64 = 3600;
+/// How many items the P17 periods are cached for, unless
+/// `location.country_cache_max_entries` is configured
+const DEFAULT_COUNTRY_CACHE_MAX_ENTRIES: usize = 10_000;
+pub const EARTH: &str = "Q2";
+const ENTITY_PREFIX: &str = "http://www.wikidata.org/entity/";
+/// Globes with coordinates on Wikidata: Earth, Moon, Mars, Mercury, Venus, Ceres, Pluto,
+/// Io, Europa, Ganymede, Callisto, and Titan
+const SUPPORTED_GLOBES: &[&str] = &[
+    EARTH, "Q405", "Q111", "Q308", "Q313", "Q596", "Q339", "Q3123", "Q3143", "Q3169", "Q3134",
+    "Q2565",
+];
+
+/// How `p131` finds items near the coordinates
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum P131Method {
+    /// `wikibase:around` on the query service
+    #[default]
+    Sparql,
+    /// The `MediaWiki` `list=geosearch` API, a faster fallback when the query service is degraded.
+    /// It only knows Earth, and searches at most 10km.
+    Geosearch,
+}
+
+/// The nearby item with coordinates that a P131 value was taken from
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct P131Source {
+    pub item: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// From the queried coordinates
+    pub distance_km: f64,
+}
+
+/// A P131 statement, with the nearest item it was taken from, for verification
+#[derive(Clone, Debug, Serialize)]
+pub struct P131Suggestion {
+    pub statement: Statement,
+    pub source: P131Source,
+}
+
+/// Where `p131` looks for nearby items: the query service, and the Wikidata API for
+/// `list=geosearch`, so tests can use canned ones
+#[derive(Clone, Copy, Debug)]
+struct P131Lookup<'a> {
+    api: &'a Api,
+    fetcher: &'a dyn SparqlFetcher,
+}
+
+static COUNTRY_CACHE: LazyLock<CountryCache> =
+    LazyLock::new(|| CountryCache::from_config(Config::location()));
+
+/// A country (P17) of an item, with the years of its start (P580) and end (P582) qualifiers
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct CountryPeriod {
+    country: String,
+    year_from: Option<i32>,
+    year_to: Option<i32>,
+}
+
+/// The P17 periods per item, for `country_for_location_and_date`, for at most `ttl`
+/// and `max_entries` items
+#[derive(Debug)]
+pub struct CountryCache {
+    periods: Mutex<HashMap<String, (Instant, Vec<CountryPeriod>)>>,
+    ttl: Duration,
+    max_entries: usize,
+}
+
+impl CountryCache {
+    /// A `ttl` of zero disables the cache
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            periods: Mutex::default(),
+            ttl,
+            max_entries: DEFAULT_COUNTRY_CACHE_MAX_ENTRIES,
+        }
+    }
+
+    /// A `max_entries` of zero disables the cache
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Uses `location.country_cache_ttl_secs` and `location.country_cache_max_entries`
+    /// from the configuration, if set.
+    pub fn from_config(config: &LocationConfig) -> Self {
+        let seconds = config
+            .country_cache_ttl_secs
+            .unwrap_or(DEFAULT_COUNTRY_CACHE_TTL_SECS);
+        let max_entries = config
+            .country_cache_max_entries
+            .unwrap_or(DEFAULT_COUNTRY_CACHE_MAX_ENTRIES);
+        Self::new(Duration::from_secs(seconds)).with_max_entries(max_entries)
+    }
+
+    pub fn global() -> &'static Self {
+        &COUNTRY_CACHE
+    }
+
+    /// Forgets the periods of `item`, for example after its P17 statements were edited.
+    /// Exposed as `POST /country_cache/invalidate/:item`.
+    pub fn invalidate(&self, item: &str) {
+        if let Ok(mut periods) = self.periods.lock() {
+            periods.remove(item);
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut periods) = self.periods.lock() {
+            periods.clear();
+        }
+    }
+
+    /// Drops the periods of `item` if they are expired
+    fn get(&self, item: &str) -> Option<Vec<CountryPeriod>> {
+        let mut periods = self.periods.lock().ok()?;
+        let (loaded, item_periods) = periods.get(item)?;
+        if loaded.elapsed() < self.ttl {
+            return Some(item_periods.clone());
+        }
+        periods.remove(item);
+        None
+    }
+
+    /// Drops expired entries first, then the oldest ones, to make room.
+    fn insert(&self, item: &str, item_periods: Vec<CountryPeriod>) {
+        if self.ttl.is_zero() || self.max_entries == 0 {
+            return;
+        }
+        let Ok(mut periods) = self.periods.lock() else {
+            return;
+        };
+        if !periods.contains_key(item) && periods.len() >= self.max_entries {
+            periods.retain(|_, (loaded, _)| loaded.elapsed() < self.ttl);
+        }
+        while !periods.contains_key(item) && periods.len() >= self.max_entries {
+            let oldest = periods
+                .iter()
+                .min_by_key(|(_, (loaded, _))| *loaded)
+                .map(|(item, _)| item.to_owned());
+            let Some(oldest) = oldest else {
+                break;
+            };
+            periods.remove(&oldest);
+        }
+        periods.insert(item.to_string(), (Instant::now(), item_periods));
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Location;
 
 impl Location {
+    /// The P17 (country) statements for `place_q` in `year`, one per country whose period
+    /// contains the year. The P17 periods of the item are cached, so other years of the
+    /// same item need no query.
     pub async fn country_for_location_and_date(
         place_q: &str,
         year: i32,
     ) -> Result<Vec<Statement>, StatusCode> {
+        Self::country_for_location_and_date_with(
+            place_q,
+            year,
+            &QueryService,
+            CountryCache::global(),
+        )
+        .await
+    }
+
+    async fn country_for_location_and_date_with(
+        place_q: &str,
+        year: i32,
+        fetcher: &dyn SparqlFetcher,
+        cache: &CountryCache,
+    ) -> Result<Vec<Statement>, StatusCode> {
+        let periods = match cache.get(place_q) {
+            Some(periods) => periods,
+            None => {
+                let periods = Self::load_country_periods(place_q, fetcher).await?;
+                cache.insert(place_q, periods.clone());
+                periods
+            }
+        };
+        let statements = Self::countries_in_year(&periods, year)
+            .iter()
+            .map(|country| {
+                let snak = Snak::new_item("P17", country);
+                let reference = Reference::new(vec![
+                    Wikidata::infernal_reference_snak(),
+                    Snak::new_item("P3452", "Q131293105"), // inferred from place and date
+                ]);
+                Statement::new_normal(snak, vec![], vec![reference])
+            })
+            .collect();
+        Ok(statements)
+    }
+
+    async fn load_country_periods(
+        place_q: &str,
+        fetcher: &dyn SparqlFetcher,
+    ) -> Result<Vec<CountryPeriod>, StatusCode> {
         // get preferred and normal country statements, but not deprecated ones
         let sparql = format!(
             r#"SELECT ?country ?year_from ?year_to {{
@@ -20,72 +236,222 @@ impl Location {
 	      OPTIONAL {{ ?c pq:P582 ?date_to . BIND(year(?date_to) AS ?year_to) }}
 	      }}"#
         );
-        let api = Wikidata::get_wikidata_api().await?;
-        let json = match api.sparql_query(&sparql).await {
-            Ok(json) => json,
-            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-        };
-        let bindings = match json["results"]["bindings"].as_array() {
-            Some(b) => b,
-            None => return Ok(vec![]),
-        };
-        let mut no_years = None;
-        let mut both_years = None;
-        let mut one_year = None;
-        for b in bindings {
-            let country = match b["country"]["value"].as_str() {
-                Some(c) => c,
-                None => continue,
-            };
-            let country = match api.extract_entity_from_uri(country) {
-                Ok(c) => c,
-                Err(_) => continue,
-            };
-            let year_from = b["year_from"]["value"]
-                .as_str()
-                .and_then(|y| y.parse::<i32>().ok());
-            let year_to = b["year_to"]["value"]
-                .as_str()
-                .and_then(|y| y.parse::<i32>().ok());
-            if year_from.is_none() && year_to.is_none() {
-                no_years = Some(country);
-            } else if let (Some(year_from), Some(year_to)) = (&year_from, &year_to) {
-                if year >= *year_from && year <= *year_to {
-                    both_years = Some(country);
+        let json = fetcher
+            .query(&sparql)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        Ok(Self::parse_country_periods(&json))
+    }
+
+    fn parse_country_periods(json: &Value) -> Vec<CountryPeriod> {
+        let bindings = json["results"]["bindings"].as_array().into_iter().flatten();
+        bindings
+            .filter_map(|b| {
+                let country = b["country"]["value"]
+                    .as_str()?
+                    .strip_prefix(ENTITY_PREFIX)?;
+                let year = |key: &str| b[key]["value"].as_str().and_then(|y| y.parse::<i32>().ok());
+                Some(CountryPeriod {
+                    country: country.to_string(),
+                    year_from: year("year_from"),
+                    year_to: year("year_to"),
+                })
+            })
+            .collect()
+    }
+
+    /// All countries with both years around `year`. Without any, the countries with
+    /// a matching start or end year, or else the countries without years.
+    /// Sorted by Q-id, without duplicates.
+    fn countries_in_year(periods: &[CountryPeriod], year: i32) -> Vec<String> {
+        let mut no_years = vec![];
+        let mut both_years = vec![];
+        let mut one_year = vec![];
+        for period in periods {
+            let country = period.country.clone();
+            match (period.year_from, period.year_to) {
+                (None, None) => no_years.push(country),
+                (Some(year_from), Some(year_to)) => {
+                    if year >= year_from && year <= year_to {
+                        both_years.push(country);
+                    }
                 }
-            } else if let (Some(year_from), None) = (year_from, year_to) {
-                if year >= year_from {
-                    one_year = Some(country);
+                (Some(year_from), None) => {
+                    if year >= year_from {
+                        one_year.push(country);
+                    }
                 }
-            } else if let Some(year_to) = &year_to {
-                if year <= *year_to {
-                    one_year = Some(country);
+                (None, Some(year_to)) => {
+                    if year <= year_to {
+                        one_year.push(country);
+                    }
                 }
             }
         }
-        let mut statements = vec![];
-        if let Some(country) = both_years.or(one_year).or(no_years) {
-            let snak = Snak::new_item("P17", &country);
-            let reference = Reference::new(vec![
-                Wikidata::infernal_reference_snak(),
-                Snak::new_item("P3452", "Q131293105"), // inferred from place and date
-            ]);
-            let statement = Statement::new_normal(snak, vec![], vec![reference]);
-            statements.push(statement);
+        let mut countries = [both_years, one_year, no_years]
+            .into_iter()
+            .find(|countries| !countries.is_empty())
+            .unwrap_or_default();
+        countries.sort_by_key(|q| {
+            let numeric = q.get(1..).and_then(|n| n.parse::<u64>().ok());
+            (numeric.unwrap_or(u64::MAX), q.clone())
+        });
+        countries.dedup();
+        countries
+    }
+
+    /// Checks that the radius is positive and not too large, and the limit is sane.
+    fn validate_p131_params(radius_km: f64, limit: usize) -> Result<(), StatusCode> {
+        if !(radius_km > 0.0 && radius_km <= MAX_P131_RADIUS_KM) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        if limit == 0 || limit > MAX_P131_LIMIT {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        Ok(())
+    }
+
+    /// Finds P131 candidates from items with coordinates within `radius_km`, on Earth.
+    /// If there are none, the radius is doubled until there are, or until
+    /// `location.max_p131_fallback_radius_km` from the configuration is reached.
+    pub async fn p131(
+        latitude: f64,
+        longitude: f64,
+        radius_km: f64,
+        limit: usize,
+    ) -> Result<Vec<Statement>, StatusCode> {
+        Self::p131_on_globe(
+            latitude,
+            longitude,
+            radius_km,
+            limit,
+            EARTH,
+            P131Method::default(),
+        )
+        .await
+    }
+
+    /// Like `p131`, for coordinates on `globe` (an item ID like "Q405", or its URI).
+    /// Administrative territories are an Earth concept, so results are only meaningful there;
+    /// other globes only find what is explicitly modeled, like regions on Mars.
+    /// Returns no statements for unsupported globes. Other globes than Earth always use SPARQL.
+    pub async fn p131_on_globe(
+        latitude: f64,
+        longitude: f64,
+        radius_km: f64,
+        limit: usize,
+        globe: &str,
+        method: P131Method,
+    ) -> Result<Vec<Statement>, StatusCode> {
+        let suggestions =
+            Self::p131_suggestions_on_globe(latitude, longitude, radius_km, limit, globe, method)
+                .await?;
+        Ok(suggestions
+            .into_iter()
+            .map(|suggestion| suggestion.statement)
+            .collect())
+    }
+
+    /// Like `p131_on_globe`, with the nearest source item of each statement
+    pub async fn p131_suggestions_on_globe(
+        latitude: f64,
+        longitude: f64,
+        radius_km: f64,
+        limit: usize,
+        globe: &str,
+        method: P131Method,
+    ) -> Result<Vec<P131Suggestion>, StatusCode> {
+        Self::validate_p131_params(radius_km, limit)?;
+        let Some(center) = Self::wkt_point(latitude, longitude, globe) else {
+            return Ok(vec![]);
+        };
+        let api = Wikidata::get_wikidata_api().await?;
+        let lookup = P131Lookup {
+            api: &api,
+            fetcher: &QueryService,
+        };
+        Self::p131_suggestions_with(
+            lookup, latitude, longitude, &center, radius_km, limit, method,
+        )
+        .await
+    }
+
+    /// Like `p131_suggestions_on_globe`, for the `wkt_point` of the coordinates
+    async fn p131_suggestions_with(
+        lookup: P131Lookup<'_>,
+        latitude: f64,
+        longitude: f64,
+        center: &str,
+        radius_km: f64,
+        limit: usize,
+        method: P131Method,
+    ) -> Result<Vec<P131Suggestion>, StatusCode> {
+        let max_radius_km = Self::max_p131_fallback_radius_km(Config::location());
+        for radius_km in Self::fallback_radii(radius_km, max_radius_km) {
+            let entities = match method {
+                // Only Earth points have no globe prefix
+                P131Method::Geosearch if center.starts_with("Point(") => {
+                    Self::p131_entities_geosearch(lookup.api, latitude, longitude, radius_km, limit)
+                        .await?
+                }
+                _ => Self::p131_entities(lookup.fetcher, center, radius_km, limit).await?,
+            };
+            if entities.is_empty() {
+                continue;
+            }
+            let suggestions = entities
+                .into_iter()
+                .map(|(entity, source)| P131Suggestion {
+                    statement: Self::p131_statement(&entity),
+                    source,
+                })
+                .collect();
+            return Ok(suggestions);
+        }
+        Ok(vec![])
+    }
+
+    fn p131_statement(entity: &str) -> Statement {
+        let snak = Snak::new_item("P131", entity);
+        let reference = Reference::new(vec![
+            Wikidata::infernal_reference_snak(),
+            Snak::new_item("P3452", "Q96623327"), // inferred from coordinate location
+        ]);
+        Statement::new_normal(snak, vec![], vec![reference])
+    }
+
+    /// The `geo:wktLiteral` for the coordinates. Earth is the default in WDQS, other globes
+    /// are prefixed with their entity URI. Returns `None` for unsupported globes.
+    fn wkt_point(latitude: f64, longitude: f64, globe: &str) -> Option<String> {
+        let globe = globe
+            .trim()
+            .trim_start_matches(ENTITY_PREFIX)
+            .to_uppercase();
+        if !SUPPORTED_GLOBES.contains(&globe.as_str()) {
+            return None;
+        }
+        let point = format!("Point({longitude} {latitude})");
+        if globe == EARTH {
+            Some(point)
+        } else {
+            Some(format!("<{ENTITY_PREFIX}{globe}> {point}"))
         }
-        Ok(statements)
     }
 
-    pub async fn p131(latitude: f64, longitude: f64) -> Result<Vec<Statement>, StatusCode> {
-        // TODO try list=geosearch?
-        let radius_km = 1;
+    /// The distinct P131 values near `center`, sorted, with the nearest item they were taken from
+    async fn p131_entities(
+        fetcher: &dyn SparqlFetcher,
+        center: &str,
+        radius_km: f64,
+        limit: usize,
+    ) -> Result<Vec<(String, P131Source)>, StatusCode> {
         let sparql = format!(
-            r#"SELECT ?p131 {{
+            r#"SELECT ?p131 ?q ?coords ?distance {{
 		        ?q wdt:P625 ?loc ; wdt:P131 ?p131 .
 
 		        SERVICE wikibase:around {{
 		          ?q wdt:P625 ?coords .
-		          bd:serviceParam wikibase:center "Point({longitude} {latitude})"^^geo:wktLiteral .
+		          bd:serviceParam wikibase:center "{center}"^^geo:wktLiteral .
 		          bd:serviceParam wikibase:radius "{radius_km}" .
 		          bd:serviceParam wikibase:distance ?distance
 		        }}
@@ -95,41 +461,279 @@ impl Location {
 		      }}
 		    }}
 		    ORDER BY DESC(?distance)
-		    LIMIT 5"#
+		    LIMIT {limit}"#
         );
-        let api = Wikidata::get_wikidata_api().await?;
-        let json = match api.sparql_query(&sparql).await {
-            Ok(json) => json,
-            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+        let json = fetcher
+            .query(&sparql)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        Ok(Self::sparql_p131_sources(&json))
+    }
+
+    /// Keeps the nearest source for each P131 value of the `p131_entities` query results
+    fn sparql_p131_sources(json: &Value) -> Vec<(String, P131Source)> {
+        let mut nearest: BTreeMap<String, P131Source> = BTreeMap::new();
+        let bindings = json["results"]["bindings"].as_array().into_iter().flatten();
+        for binding in bindings {
+            let Some((p131, source)) = Self::sparql_p131_source(binding) else {
+                continue;
+            };
+            let is_nearer = nearest
+                .get(&p131)
+                .is_none_or(|existing| source.distance_km < existing.distance_km);
+            if is_nearer {
+                nearest.insert(p131, source);
+            }
+        }
+        nearest.into_iter().collect()
+    }
+
+    fn sparql_p131_source(binding: &Value) -> Option<(String, P131Source)> {
+        let p131 = binding["p131"]["value"]
+            .as_str()?
+            .strip_prefix(ENTITY_PREFIX)?;
+        let item = binding["q"]["value"]
+            .as_str()?
+            .strip_prefix(ENTITY_PREFIX)?;
+        // A `geo:wktLiteral` like "Point(0.13 52.19)", possibly prefixed with a globe URI
+        let point = binding["coords"]["value"]
+            .as_str()?
+            .split("Point(")
+            .nth(1)?;
+        let mut parts = point.trim_end_matches(')').split_whitespace();
+        let longitude = parts.next()?.parse().ok()?;
+        let latitude = parts.next()?.parse().ok()?;
+        let distance_km = binding["distance"]["value"].as_str()?.parse().ok()?;
+        let source = P131Source {
+            item: item.to_string(),
+            latitude,
+            longitude,
+            distance_km,
         };
-        let mut entities = api.entities_from_sparql_result(&json, "p131");
-        entities.sort();
-        entities.dedup();
-        let statements: Vec<_> = entities
+        Some((p131.to_string(), source))
+    }
+
+    /// Like `p131_entities`, but finds the nearby items with `list=geosearch`,
+    /// and reads their P131 from the entities.
+    async fn p131_entities_geosearch(
+        api: &Api,
+        latitude: f64,
+        longitude: f64,
+        radius_km: f64,
+        limit: usize,
+    ) -> Result<Vec<(String, P131Source)>, StatusCode> {
+        let coord = format!("{latitude}|{longitude}");
+        let radius_m = (radius_km * 1000.0)
+            .min(MAX_GEOSEARCH_RADIUS_M)
+            .round()
+            .to_string();
+        let gslimit = GEOSEARCH_LIMIT.to_string();
+        let params = api.params_into(&[
+            ("action", "query"),
+            ("list", "geosearch"),
+            ("gscoord", &coord),
+            ("gsradius", &radius_m),
+            ("gslimit", &gslimit),
+            ("gsnamespace", "0"),
+        ]);
+        let result = api
+            .get_query_api_json(&params)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let sources = Self::geosearch_items(&result);
+        if sources.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let ids = sources
             .iter()
-            .map(|entity| {
-                let snak = Snak::new_item("P131", entity);
-                let reference = Reference::new(vec![
-                    Wikidata::infernal_reference_snak(),
-                    Snak::new_item("P3452", "Q96623327"), // inferred from coordinate location
-                ]);
-                Statement::new_normal(snak, vec![], vec![reference])
+            .map(|source| source.item.as_str())
+            .collect::<Vec<_>>()
+            .join("|");
+        let params = api.params_into(&[
+            ("action", "wbgetentities"),
+            ("ids", &ids),
+            ("props", "claims"),
+        ]);
+        let result = api
+            .get_query_api_json(&params)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        Ok(Self::p131_values(&result["entities"], &sources, limit))
+    }
+
+    /// The items of a `list=geosearch` result, nearest first
+    fn geosearch_items(result: &Value) -> Vec<P131Source> {
+        result["query"]["geosearch"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|page| {
+                Some(P131Source {
+                    item: page["title"].as_str()?.to_string(),
+                    latitude: page["lat"].as_f64()?,
+                    longitude: page["lon"].as_f64()?,
+                    distance_km: page["dist"].as_f64()? / 1000.0,
+                })
             })
-            .collect();
-        Ok(statements)
+            .collect()
+    }
+
+    /// The distinct non-deprecated P131 values of the items, taken from the nearest items first,
+    /// up to `limit`, with the item they were taken from. Sorted like the SPARQL results.
+    fn p131_values(
+        entities: &Value,
+        sources: &[P131Source],
+        limit: usize,
+    ) -> Vec<(String, P131Source)> {
+        let mut ret: Vec<(String, P131Source)> = vec![];
+        let claims = sources
+            .iter()
+            .filter_map(|source| {
+                let claims = entities[&source.item]["claims"]["P131"].as_array()?;
+                Some(claims.iter().map(move |claim| (claim, source)))
+            })
+            .flatten()
+            .filter(|(claim, _)| claim["rank"] != "deprecated");
+        for (claim, source) in claims {
+            let Some(id) = claim["mainsnak"]["datavalue"]["value"]["id"].as_str() else {
+                continue;
+            };
+            if !ret.iter().any(|(entity, _)| entity == id) {
+                ret.push((id.to_string(), source.to_owned()));
+            }
+            if ret.len() >= limit {
+                break;
+            }
+        }
+        ret.sort_by(|a, b| a.0.cmp(&b.0));
+        ret
+    }
+
+    /// Uses `location.max_p131_fallback_radius_km` from the configuration, if set.
+    fn max_p131_fallback_radius_km(config: &LocationConfig) -> f64 {
+        config
+            .max_p131_fallback_radius_km
+            .unwrap_or(DEFAULT_MAX_P131_FALLBACK_RADIUS_KM)
+            .min(MAX_P131_RADIUS_KM)
+    }
+
+    /// The initial radius, doubled until the maximum is reached
+    fn fallback_radii(radius_km: f64, max_radius_km: f64) -> Vec<f64> {
+        let mut radii = vec![radius_km];
+        let mut radius_km = radius_km;
+        while radius_km < max_radius_km {
+            radius_km = (radius_km * 2.0).min(max_radius_km);
+            radii.push(radius_km);
+        }
+        radii
+    }
+
+    /// Returns the nearest P131 for the coordinates, followed by its P131 parents on Wikidata,
+    /// up to an item without a P131 (usually a country).
+    pub async fn p131_chain(latitude: f64, longitude: f64) -> Result<Vec<Statement>, StatusCode> {
+        let api = Wikidata::get_wikidata_api().await?;
+        let lookup = P131Lookup {
+            api: &api,
+            fetcher: &QueryService,
+        };
+        Self::p131_chain_with(lookup, latitude, longitude).await
+    }
+
+    async fn p131_chain_with(
+        lookup: P131Lookup<'_>,
+        latitude: f64,
+        longitude: f64,
+    ) -> Result<Vec<Statement>, StatusCode> {
+        let Some(center) = Self::wkt_point(latitude, longitude, EARTH) else {
+            return Ok(vec![]);
+        };
+        let suggestions = Self::p131_suggestions_with(
+            lookup,
+            latitude,
+            longitude,
+            &center,
+            DEFAULT_P131_RADIUS_KM,
+            DEFAULT_P131_LIMIT,
+            P131Method::default(),
+        )
+        .await?;
+        let Some(first) = Self::nearest_p131_item(&suggestions) else {
+            return Ok(vec![]);
+        };
+
+        let mut chain = vec![first.clone()];
+        let mut visited = HashSet::from([first]);
+        while chain.len() < MAX_P131_CHAIN_DEPTH {
+            let current = &chain[chain.len() - 1];
+            let sparql = format!("SELECT ?parent {{ wd:{current} wdt:P131 ?parent }}");
+            let json = lookup
+                .fetcher
+                .query(&sparql)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            let parents = lookup.api.entities_from_sparql_result(&json, "parent");
+            match Self::next_in_chain(parents, &visited) {
+                Some(parent) => {
+                    visited.insert(parent.clone());
+                    chain.push(parent);
+                }
+                None => break,
+            }
+        }
+
+        Ok(chain
+            .iter()
+            .map(|entity| Self::p131_statement(entity))
+            .collect())
+    }
+
+    /// The P131 value taken from the nearest source item
+    fn nearest_p131_item(suggestions: &[P131Suggestion]) -> Option<String> {
+        suggestions
+            .iter()
+            .min_by(|a, b| a.source.distance_km.total_cmp(&b.source.distance_km))
+            .and_then(|suggestion| Self::statement_item(&suggestion.statement))
+    }
+
+    fn statement_item(statement: &Statement) -> Option<String> {
+        match statement.main_snak().data_value().as_ref()?.value() {
+            wikibase::Value::Entity(ev) => Some(ev.id().to_string()),
+            _ => None,
+        }
+    }
+
+    /// Picks the next parent that has not been visited yet; first by ID, to be deterministic.
+    fn next_in_chain(mut parents: Vec<String>, visited: &HashSet<String>) -> Option<String> {
+        parents.sort();
+        parents.into_iter().find(|parent| !visited.contains(parent))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_server;
+    use futures::future::BoxFuture;
+    use serde_json::json;
     use wikibase::{EntityType, EntityValue};
 
+    fn location_config(value: Value) -> LocationConfig {
+        serde_json::from_value(value).unwrap()
+    }
+
     #[tokio::test]
     async fn test_p131() {
         let latitude = 52.19422713089248;
         let longitude = 0.13009437319916947;
-        let result = Location::p131(latitude, longitude).await.unwrap();
+        let result = Location::p131(
+            latitude,
+            longitude,
+            DEFAULT_P131_RADIUS_KM,
+            DEFAULT_P131_LIMIT,
+        )
+        .await
+        .unwrap();
         assert_eq!(result.len(), 1);
         assert_eq!(
             result[0]
@@ -143,6 +747,519 @@ mod tests {
         );
     }
 
+    /// Answers queries that contain a pattern with its bindings, and others with no results.
+    /// Records the queries.
+    #[derive(Debug, Default)]
+    struct CannedQueries {
+        answers: Vec<(&'static str, Value)>,
+        queries: Mutex<Vec<String>>,
+    }
+
+    impl CannedQueries {
+        fn with_answer(mut self, pattern: &'static str, bindings: Vec<Value>) -> Self {
+            let json = json!({"results": {"bindings": bindings}});
+            self.answers.push((pattern, json));
+            self
+        }
+
+        fn queries(&self) -> Vec<String> {
+            self.queries.lock().unwrap().clone()
+        }
+    }
+
+    impl SparqlFetcher for CannedQueries {
+        fn query<'a>(&'a self, sparql: &'a str) -> BoxFuture<'a, anyhow::Result<Value>> {
+            self.queries.lock().unwrap().push(sparql.to_string());
+            let json = self
+                .answers
+                .iter()
+                .find(|(pattern, _)| sparql.contains(pattern))
+                .map_or_else(
+                    || json!({"results": {"bindings": []}}),
+                    |(_, json)| json.clone(),
+                );
+            Box::pin(async move { Ok(json) })
+        }
+    }
+
+    /// A row of the `p131_entities` query results
+    fn p131_row(p131: &str, q: &str, coords: &str, distance: &str) -> Value {
+        json!({
+            "p131": {"type": "uri", "value": format!("{ENTITY_PREFIX}{p131}")},
+            "q": {"type": "uri", "value": format!("{ENTITY_PREFIX}{q}")},
+            "coords": {"type": "literal", "value": coords},
+            "distance": {"type": "literal", "value": distance},
+        })
+    }
+
+    /// Items near Cambridge, in the City of Cambridge (Q21713103)
+    fn cambridge_rows() -> Vec<Value> {
+        vec![
+            p131_row("Q21713103", "Q10", "Point(0.13 52.19)", "0.4"),
+            p131_row("Q21713103", "Q11", "Point(0.12 52.2)", "0.9"),
+        ]
+    }
+
+    async fn canned_api() -> Api {
+        Api::new(&test_server::wiki_api(|_| None).await)
+            .await
+            .unwrap()
+    }
+
+    async fn sparql_suggestions(
+        fetcher: &CannedQueries,
+        radius_km: f64,
+        limit: usize,
+    ) -> Vec<P131Suggestion> {
+        let api = canned_api().await;
+        let lookup = P131Lookup { api: &api, fetcher };
+        let center = Location::wkt_point(52.19, 0.13, EARTH).unwrap();
+        Location::p131_suggestions_with(
+            lookup,
+            52.19,
+            0.13,
+            &center,
+            radius_km,
+            limit,
+            P131Method::Sparql,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_p131_query_uses_radius_and_limit() {
+        let fetcher = CannedQueries::default().with_answer("wikibase:around", cambridge_rows());
+        let suggestions = sparql_suggestions(&fetcher, 20.0, 50).await;
+        assert_eq!(suggestions.len(), 1);
+        let queries = fetcher.queries();
+        assert_eq!(queries.len(), 1);
+        assert!(queries[0].contains(r#"bd:serviceParam wikibase:radius "20""#));
+        assert!(queries[0].contains("ORDER BY DESC(?distance)"));
+        assert!(queries[0].contains("LIMIT 50"));
+    }
+
+    #[test]
+    fn test_next_in_chain_skips_visited() {
+        let visited = HashSet::from(["Q2".to_string()]);
+        let parents = vec!["Q3".to_string(), "Q2".to_string()];
+        assert_eq!(
+            Location::next_in_chain(parents, &visited),
+            Some("Q3".to_string())
+        );
+        let parents = vec!["Q2".to_string()];
+        assert_eq!(Location::next_in_chain(parents, &visited), None);
+        assert_eq!(Location::next_in_chain(vec![], &visited), None);
+    }
+
+    #[tokio::test]
+    async fn test_p131_chain_ends_at_country() {
+        let parent =
+            |q: &str| json!({"parent": {"type": "uri", "value": format!("{ENTITY_PREFIX}{q}")}});
+        // City of Cambridge, Cambridgeshire, England; England has no P131
+        let fetcher = CannedQueries::default()
+            .with_answer("wikibase:around", cambridge_rows())
+            .with_answer("wd:Q21713103 wdt:P131", vec![parent("Q23112")])
+            .with_answer("wd:Q23112 wdt:P131", vec![parent("Q21")]);
+        let api = canned_api().await;
+        let lookup = P131Lookup {
+            api: &api,
+            fetcher: &fetcher,
+        };
+        let chain = Location::p131_chain_with(lookup, 52.19, 0.13)
+            .await
+            .unwrap();
+        let items: Vec<String> = chain.iter().filter_map(Location::statement_item).collect();
+        assert_eq!(items, vec!["Q21713103", "Q23112", "Q21"]);
+        assert_eq!(fetcher.queries().len(), 4);
+    }
+
+    #[test]
+    fn test_fallback_radii_doubles_up_to_max() {
+        assert_eq!(
+            Location::fallback_radii(1.0, 16.0),
+            vec![1.0, 2.0, 4.0, 8.0, 16.0]
+        );
+        assert_eq!(Location::fallback_radii(3.0, 10.0), vec![3.0, 6.0, 10.0]);
+        // No fallback if the radius is already at the maximum
+        assert_eq!(Location::fallback_radii(20.0, 16.0), vec![20.0]);
+    }
+
+    #[test]
+    fn test_max_p131_fallback_radius_km_from_config() {
+        let config = location_config(json!({"max_p131_fallback_radius_km": 32.0}));
+        assert_eq!(
+            Location::max_p131_fallback_radius_km(&config).to_string(),
+            "32"
+        );
+        let config = location_config(json!({"max_p131_fallback_radius_km": 1000.0}));
+        assert_eq!(
+            Location::max_p131_fallback_radius_km(&config).to_string(),
+            MAX_P131_RADIUS_KM.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_p131_falls_back_to_larger_radius() {
+        // Nothing within 1km and 2km
+        let fetcher =
+            CannedQueries::default().with_answer(r#"wikibase:radius "4""#, cambridge_rows());
+        let suggestions = sparql_suggestions(&fetcher, 1.0, 5).await;
+        assert_eq!(suggestions.len(), 1);
+        let radii: Vec<bool> = ["1", "2", "4"]
+            .iter()
+            .zip(fetcher.queries())
+            .map(|(radius, query)| query.contains(&format!(r#"wikibase:radius "{radius}""#)))
+            .collect();
+        assert_eq!(radii, vec![true, true, true]);
+        assert_eq!(fetcher.queries().len(), 3);
+    }
+
+    #[test]
+    fn test_wkt_point_earth_is_unchanged() {
+        assert_eq!(
+            Location::wkt_point(52.5, 0.13, EARTH),
+            Some("Point(0.13 52.5)".to_string())
+        );
+        assert_eq!(
+            Location::wkt_point(52.5, 0.13, "http://www.wikidata.org/entity/Q2"),
+            Some("Point(0.13 52.5)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_wkt_point_other_globes() {
+        assert_eq!(
+            Location::wkt_point(-4.5, 137.4, "q111"),
+            Some("<http://www.wikidata.org/entity/Q111> Point(137.4 -4.5)".to_string())
+        );
+        assert_eq!(
+            Location::wkt_point(0.67, 23.47, "http://www.wikidata.org/entity/Q405"),
+            Some("<http://www.wikidata.org/entity/Q405> Point(23.47 0.67)".to_string())
+        );
+        // The Sun is not a globe for coordinates
+        assert_eq!(Location::wkt_point(0.0, 0.0, "Q525"), None);
+        assert_eq!(Location::wkt_point(0.0, 0.0, "Moon"), None);
+    }
+
+    #[tokio::test]
+    async fn test_p131_on_unsupported_globe_is_empty() {
+        let result = Location::p131_on_globe(0.0, 0.0, 1.0, 5, "Q525", P131Method::Sparql)
+            .await
+            .unwrap();
+        assert!(result.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_p131_geosearch_matches_sparql() {
+        let api_url = test_server::wiki_api(|params| {
+            match (params.get("action")?.as_str(), params.get("list")) {
+                ("wbgetentities", _) => Some(json!({"entities": {"Q10": {"claims": {"P131": [
+                    {"rank": "normal", "mainsnak": {"datavalue": {"value": {"id": "Q21713103"}}}}
+                ]}}}})),
+                ("query", Some(_)) => Some(json!({"query": {"geosearch": [
+                    {"pageid": 1, "ns": 0, "title": "Q10", "lat": 52.19, "lon": 0.13, "dist": 400.0}
+                ]}})),
+                _ => None,
+            }
+        })
+        .await;
+        let api = Api::new(&api_url).await.unwrap();
+        let fetcher = CannedQueries::default().with_answer("wikibase:around", cambridge_rows());
+        let lookup = P131Lookup {
+            api: &api,
+            fetcher: &fetcher,
+        };
+        let center = Location::wkt_point(52.19, 0.13, EARTH).unwrap();
+        let center = center.as_str();
+        let suggestions = move |method| {
+            Location::p131_suggestions_with(
+                lookup,
+                52.19,
+                0.13,
+                center,
+                DEFAULT_P131_RADIUS_KM,
+                DEFAULT_P131_LIMIT,
+                method,
+            )
+        };
+        let sparql = suggestions(P131Method::Sparql).await.unwrap();
+        let geosearch = suggestions(P131Method::Geosearch).await.unwrap();
+        let items = |suggestions: &[P131Suggestion]| -> Vec<(String, String)> {
+            suggestions
+                .iter()
+                .filter_map(|s| {
+                    Some((
+                        Location::statement_item(&s.statement)?,
+                        s.source.item.clone(),
+                    ))
+                })
+                .collect()
+        };
+        assert_eq!(items(&sparql), items(&geosearch));
+        assert_eq!(
+            items(&geosearch),
+            vec![("Q21713103".to_string(), "Q10".to_string())]
+        );
+        // Geosearch does not use the query service
+        assert_eq!(fetcher.queries().len(), 1);
+    }
+
+    #[test]
+    fn test_geosearch_items_and_p131_values() {
+        let geosearch = serde_json::json!({"query": {"geosearch": [
+            {"pageid": 1, "ns": 0, "title": "Q10", "lat": 52.1, "lon": 0.1, "dist": 12.5},
+            {"pageid": 2, "ns": 0, "title": "Q20", "lat": 52.2, "lon": 0.2, "dist": 80.1},
+            {"pageid": 3, "ns": 0, "title": "Q30", "lat": 52.3, "lon": 0.3, "dist": 95.0},
+        ]}});
+        let items = Location::geosearch_items(&geosearch);
+        let ids: Vec<&str> = items.iter().map(|source| source.item.as_str()).collect();
+        assert_eq!(ids, vec!["Q10", "Q20", "Q30"]);
+        assert_eq!(items[0].distance_km.to_string(), "0.0125");
+
+        let p131 = |id: &str, rank: &str| serde_json::json!({"rank": rank, "mainsnak": {"datavalue": {"value": {"id": id}}}});
+        let entities = serde_json::json!({
+            "Q10": {"claims": {"P131": [p131("Q350", "normal"), p131("Q1", "deprecated")]}},
+            "Q20": {"claims": {}},
+            "Q30": {"claims": {"P131": [p131("Q350", "normal"), p131("Q23", "normal")]}},
+        });
+        let values = |limit| -> Vec<(String, String)> {
+            Location::p131_values(&entities, &items, limit)
+                .into_iter()
+                .map(|(p131, source)| (p131, source.item))
+                .collect()
+        };
+        assert_eq!(
+            values(5),
+            vec![
+                ("Q23".to_string(), "Q30".to_string()),
+                ("Q350".to_string(), "Q10".to_string())
+            ]
+        );
+        // The nearest items are used first
+        assert_eq!(values(1), vec![("Q350".to_string(), "Q10".to_string())]);
+    }
+
+    #[test]
+    fn test_sparql_p131_sources_keeps_nearest() {
+        let moon = "<http://www.wikidata.org/entity/Q405> Point(23.47 0.67)";
+        let json = serde_json::json!({"results": {"bindings": [
+            p131_row("Q350", "Q10", "Point(0.13 52.19)", "0.8"),
+            p131_row("Q350", "Q20", "Point(0.12 52.2)", "0.2"),
+            p131_row("Q23", "Q30", moon, "0.5"),
+        ]}});
+        let sources = Location::sparql_p131_sources(&json);
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].0, "Q23");
+        assert_eq!(sources[0].1.longitude.to_string(), "23.47");
+        assert_eq!(sources[0].1.latitude.to_string(), "0.67");
+        assert_eq!(sources[1].0, "Q350");
+        assert_eq!(sources[1].1.item, "Q20");
+        assert_eq!(sources[1].1.distance_km.to_string(), "0.2");
+    }
+
+    #[test]
+    fn test_nearest_p131_item() {
+        let suggestion = |p131: &str, distance_km: f64| P131Suggestion {
+            statement: Location::p131_statement(p131),
+            source: P131Source {
+                item: "Q1".to_string(),
+                latitude: 0.0,
+                longitude: 0.0,
+                distance_km,
+            },
+        };
+        // Suggestions are sorted by P131 value, not by distance
+        let suggestions = vec![suggestion("Q23", 0.5), suggestion("Q350", 0.2)];
+        assert_eq!(
+            Location::nearest_p131_item(&suggestions),
+            Some("Q350".to_string())
+        );
+        assert_eq!(Location::nearest_p131_item(&[]), None);
+    }
+
+    #[tokio::test]
+    async fn test_p131_suggestions_have_sources() {
+        let fetcher = CannedQueries::default().with_answer("wikibase:around", cambridge_rows());
+        let suggestions =
+            sparql_suggestions(&fetcher, DEFAULT_P131_RADIUS_KM, DEFAULT_P131_LIMIT).await;
+        assert_eq!(suggestions.len(), 1);
+        let source = &suggestions[0].source;
+        assert_eq!(source.item, "Q10");
+        assert_eq!(source.latitude.to_string(), "52.19");
+        assert_eq!(source.longitude.to_string(), "0.13");
+        assert_eq!(source.distance_km.to_string(), "0.4");
+    }
+
+    #[test]
+    fn test_validate_p131_params() {
+        assert!(Location::validate_p131_params(DEFAULT_P131_RADIUS_KM, DEFAULT_P131_LIMIT).is_ok());
+        assert_eq!(
+            Location::validate_p131_params(0.0, 5),
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            Location::validate_p131_params(-1.0, 5),
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            Location::validate_p131_params(f64::NAN, 5),
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            Location::validate_p131_params(MAX_P131_RADIUS_KM + 1.0, 5),
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            Location::validate_p131_params(1.0, 0),
+            Err(StatusCode::BAD_REQUEST)
+        );
+    }
+
+    /// Answers every query with `json`, and counts the queries
+    #[derive(Debug)]
+    struct CountingFetcher {
+        json: Value,
+        queries: Mutex<usize>,
+    }
+
+    impl CountingFetcher {
+        fn new(json: Value) -> Self {
+            Self {
+                json,
+                queries: Mutex::new(0),
+            }
+        }
+
+        fn queries(&self) -> usize {
+            *self.queries.lock().unwrap()
+        }
+    }
+
+    impl SparqlFetcher for CountingFetcher {
+        fn query<'a>(&'a self, _sparql: &'a str) -> BoxFuture<'a, anyhow::Result<Value>> {
+            *self.queries.lock().unwrap() += 1;
+            Box::pin(async { Ok(self.json.clone()) })
+        }
+    }
+
+    /// Q365 (Cologne) was in the Weimar Republic from 1919 to 1933, and is in Germany
+    fn cologne_countries() -> Value {
+        json!({"results": {"bindings": [
+            {"country": {"value": "http://www.wikidata.org/entity/Q41304"},
+             "year_from": {"value": "1919"}, "year_to": {"value": "1933"}},
+            {"country": {"value": "http://www.wikidata.org/entity/Q183"},
+             "year_from": {"value": "1949"}},
+        ]}})
+    }
+
+    fn statement_country(statements: &[Statement]) -> Option<String> {
+        match statements
+            .first()?
+            .main_snak()
+            .data_value()
+            .as_ref()?
+            .value()
+        {
+            wikibase::Value::Entity(entity) => Some(entity.id().to_string()),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_country_for_location_and_date_caches_periods() {
+        let fetcher = CountingFetcher::new(cologne_countries());
+        let cache = CountryCache::new(Duration::from_secs(60));
+        let statements =
+            Location::country_for_location_and_date_with("Q365", 1921, &fetcher, &cache)
+                .await
+                .unwrap();
+        assert_eq!(statement_country(&statements).as_deref(), Some("Q41304"));
+        let statements =
+            Location::country_for_location_and_date_with("Q365", 1990, &fetcher, &cache)
+                .await
+                .unwrap();
+        assert_eq!(statement_country(&statements).as_deref(), Some("Q183"));
+        assert_eq!(fetcher.queries(), 1);
+
+        cache.invalidate("Q365");
+        Location::country_for_location_and_date_with("Q365", 1990, &fetcher, &cache)
+            .await
+            .unwrap();
+        assert_eq!(fetcher.queries(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_country_cache_disabled_with_zero_ttl() {
+        let fetcher = CountingFetcher::new(cologne_countries());
+        let cache =
+            CountryCache::from_config(&location_config(json!({"country_cache_ttl_secs": 0})));
+        for year in [1921, 1990] {
+            Location::country_for_location_and_date_with("Q365", year, &fetcher, &cache)
+                .await
+                .unwrap();
+        }
+        assert_eq!(fetcher.queries(), 2);
+    }
+
+    #[test]
+    fn test_country_cache_evicts_oldest_entry() {
+        let cache = CountryCache::new(Duration::from_secs(60)).with_max_entries(2);
+        for item in ["Q1", "Q2", "Q3"] {
+            cache.insert(item, vec![period("Q183", None, None)]);
+        }
+        assert_eq!(cache.periods.lock().unwrap().len(), 2);
+        assert!(cache.get("Q1").is_none());
+        assert!(cache.get("Q3").is_some());
+    }
+
+    #[test]
+    fn test_country_cache_drops_expired_entries() {
+        let cache = CountryCache::new(Duration::from_millis(10)).with_max_entries(2);
+        cache.insert("Q1", vec![period("Q183", None, None)]);
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(cache.get("Q1").is_none());
+        assert_eq!(cache.periods.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_country_cache_max_entries_from_config() {
+        let cache = CountryCache::from_config(&LocationConfig::default());
+        assert_eq!(cache.max_entries, DEFAULT_COUNTRY_CACHE_MAX_ENTRIES);
+        let config = location_config(json!({"country_cache_max_entries": 5}));
+        assert_eq!(CountryCache::from_config(&config).max_entries, 5);
+    }
+
+    fn period(country: &str, year_from: Option<i32>, year_to: Option<i32>) -> CountryPeriod {
+        CountryPeriod {
+            country: country.to_string(),
+            year_from,
+            year_to,
+        }
+    }
+
+    #[test]
+    fn test_countries_in_year_overlapping_periods() {
+        // Q64 (Berlin) was in both Prussia and the German Empire
+        let periods = vec![
+            period("Q43287", Some(1871), Some(1918)),
+            period("Q27306", Some(1701), Some(1918)),
+            period("Q183", None, None),
+        ];
+        assert_eq!(
+            Location::countries_in_year(&periods, 1900),
+            vec!["Q27306", "Q43287"]
+        );
+        // The country without years is only used if no period matches
+        assert_eq!(Location::countries_in_year(&periods, 2000), vec!["Q183"]);
+    }
+
+    #[test]
+    fn test_countries_in_year_without_match() {
+        let periods = Location::parse_country_periods(&cologne_countries());
+        assert!(Location::countries_in_year(&periods, 1940).is_empty());
+    }
+
     #[tokio::test]
     async fn test_country_for_location_and_date() {
         let statements = Location::country_for_location_and_date("Q365", 1921)
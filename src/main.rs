@@ -45,20 +45,53 @@ use std::sync::LazyLock;
 use wikibase_rest_api::Patch as _;
 use wikimisc::toolforge_db::ToolforgeDB;
 
+pub mod api_error;
+pub mod body_limit;
 pub mod change_wiki;
+pub mod config;
 pub mod crosscats;
+pub mod crossref;
+pub mod db;
+pub mod enrich;
+pub mod fetcher;
 pub mod given_names;
 pub mod google_books;
 pub mod initial_search;
 pub mod isbn;
 pub mod location;
+pub mod metrics;
+pub mod open_library;
 pub mod person;
+pub mod quickstatements;
+pub mod rate_limit;
 pub mod referee;
 pub mod reference;
+pub mod retry;
 pub mod server;
+pub mod sites;
+#[cfg(test)]
+mod test_server;
 pub mod viaf;
 pub mod wikidata;
 
+/// The configuration file, or `Value::Null` if none was found.
+/// The path can be overridden via the `WD_INFERNAL_CONFIG` environment variable.
+pub static CONFIG: LazyLock<serde_json::Value> = LazyLock::new(|| {
+    let file = match std::env::var("WD_INFERNAL_CONFIG") {
+        Ok(path) => File::open(path),
+        Err(_) => File::open("config.json")
+            .or_else(|_| File::open("/data/project/wd-infernal/wd-infernal/config.json")),
+    };
+    match file {
+        Ok(file) => {
+            let reader = std::io::BufReader::new(file);
+            serde_json::from_reader(reader)
+                .unwrap_or_else(|e| panic!("Invalid configuration file: {e}"))
+        }
+        Err(_) => serde_json::Value::Null,
+    }
+});
+
 pub static TOOLFORGE_DB: LazyLock<ToolforgeDB> = LazyLock::new(|| {
     /* For local testing:
     ssh magnus@login.toolforge.org -L 3309:wikidatawiki.web.db.svc.eqiad.wmflabs:3306 -N &
@@ -66,14 +99,13 @@ pub static TOOLFORGE_DB: LazyLock<ToolforgeDB> = LazyLock::new(|| {
      */
 
     let mut ret = ToolforgeDB::default();
-    let file = File::open("config.json")
-        .or_else(|_| File::open("/data/project/wd-infernal/wd-infernal/config.json"));
-    if let Ok(file) = file {
-        let reader = std::io::BufReader::new(file);
-        let config: serde_json::Value = serde_json::from_reader(reader).unwrap();
-        ret.add_mysql_pool("wikidata", &config["wikidata"]).unwrap();
-        ret.add_mysql_pool("termstore", &config["termstore"])
+    if let Some(config) = config::Config::get() {
+        ret.add_mysql_pool("wikidata", &config.wikidata.to_value())
             .unwrap();
+        if let Some(termstore) = &config.termstore {
+            ret.add_mysql_pool("termstore", &termstore.to_value())
+                .unwrap();
+        }
     }
     ret
 });
@@ -81,12 +113,20 @@ pub static TOOLFORGE_DB: LazyLock<ToolforgeDB> = LazyLock::new(|| {
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     #![allow(clippy::use_debug, clippy::print_stdout)]
+    config::Config::validate()?;
     if std::env::args().len() > 2 {
         let command = std::env::args().nth(1).unwrap();
         match command.as_str() {
             "viaf" => {
                 let query = std::env::args().nth(2).unwrap();
-                let result = viaf::search_viaf_for_local_names(&query).await.unwrap();
+                let result = viaf::search_viaf_for_local_names(
+                    &query,
+                    viaf::ViafIndex::default(),
+                    1,
+                    viaf::DEFAULT_MAXIMUM_RECORDS,
+                )
+                .await
+                .unwrap();
                 println!("{result:#?}");
             }
             "isbn" => {
@@ -98,7 +138,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
             "name_gender" => {
                 let item_id = std::env::args().nth(2).unwrap();
-                let statements = person::Person::name_gender(&item_id).await.unwrap();
+                let statements =
+                    person::Person::name_gender(&item_id, person::NameOrder::default())
+                        .await
+                        .unwrap();
                 println!("{statements:?}");
             }
             "referee" => {
@@ -120,14 +163,28 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .parse()
                     .unwrap();
                 let language = std::env::args().nth(4).unwrap_or_else(|| "en".to_string());
-                let ret = crosscats::CrossCats::cross_cats(&item, depth, &language)
-                    .await
-                    .unwrap();
+                let petscan_id =
+                    crosscats::CrossCats::petscan_id_from_config(config::Config::crosscats());
+                let ret = crosscats::CrossCats::cross_cats(
+                    &item,
+                    depth,
+                    &language,
+                    petscan_id,
+                    crosscats::DEFAULT_MIN_COUNT,
+                )
+                .await
+                .unwrap();
                 println!("{ret:#?}");
             }
             "initial_search" => {
                 let query = std::env::args().nth(2).unwrap();
-                let ret = initial_search::InitialSearch::run(&query).await.unwrap();
+                let ret = initial_search::InitialSearch::run(
+                    &query,
+                    initial_search::DEFAULT_INSTANCE_OF,
+                    None,
+                )
+                .await
+                .unwrap();
                 println!("{ret:#?}");
             }
             other => {
@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+/// Upper bounds of the request latency buckets, in seconds
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// Cumulative counts, one per `LATENCY_BUCKETS` bound
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; LATENCY_BUCKETS.len()];
+        }
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// Request counts and latencies per route, and failed requests to upstream sources,
+/// in the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// By method, route, and status
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    latencies: Mutex<BTreeMap<String, Histogram>>,
+    upstream_failures: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Metrics {
+    pub fn global() -> &'static Self {
+        &METRICS
+    }
+
+    /// `route` is the route pattern, like `/referee/:item`, so labels stay few
+    pub fn record_request(&self, method: &str, route: &str, status: u16, duration: Duration) {
+        if let Ok(mut requests) = self.requests.lock() {
+            *requests
+                .entry((method.to_string(), route.to_string(), status))
+                .or_default() += 1;
+        }
+        if let Ok(mut latencies) = self.latencies.lock() {
+            latencies
+                .entry(route.to_string())
+                .or_default()
+                .observe(duration.as_secs_f64());
+        }
+    }
+
+    /// A request to `source`, like `viaf`, failed
+    pub fn record_upstream_failure(&self, source: &str) {
+        if let Ok(mut failures) = self.upstream_failures.lock() {
+            *failures.entry(source.to_string()).or_default() += 1;
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut ret = String::new();
+        ret += "# HELP http_requests_total Requests handled, by method, route, and status\n";
+        ret += "# TYPE http_requests_total counter\n";
+        if let Ok(requests) = self.requests.lock() {
+            for ((method, route, status), count) in requests.iter() {
+                let _ = writeln!(
+                    ret,
+                    "http_requests_total{{method=\"{}\",route=\"{}\",status=\"{status}\"}} {count}",
+                    Self::escape_label(method),
+                    Self::escape_label(route)
+                );
+            }
+        }
+        ret += "# HELP http_request_duration_seconds Request latency, by route\n";
+        ret += "# TYPE http_request_duration_seconds histogram\n";
+        if let Ok(latencies) = self.latencies.lock() {
+            for (route, histogram) in latencies.iter() {
+                let route = Self::escape_label(route);
+                for (bound, count) in LATENCY_BUCKETS.iter().zip(&histogram.buckets) {
+                    let _ = writeln!(
+                        ret,
+                        "http_request_duration_seconds_bucket{{route=\"{route}\",le=\"{bound}\"}} {count}"
+                    );
+                }
+                let _ = writeln!(
+                    ret,
+                    "http_request_duration_seconds_bucket{{route=\"{route}\",le=\"+Inf\"}} {}",
+                    histogram.count
+                );
+                let _ = writeln!(
+                    ret,
+                    "http_request_duration_seconds_sum{{route=\"{route}\"}} {}",
+                    histogram.sum
+                );
+                let _ = writeln!(
+                    ret,
+                    "http_request_duration_seconds_count{{route=\"{route}\"}} {}",
+                    histogram.count
+                );
+            }
+        }
+        ret += "# HELP upstream_failures_total Failed requests to upstream sources, by source\n";
+        ret += "# TYPE upstream_failures_total counter\n";
+        if let Ok(failures) = self.upstream_failures.lock() {
+            for (source, count) in failures.iter() {
+                let _ = writeln!(
+                    ret,
+                    "upstream_failures_total{{source=\"{}\"}} {count}",
+                    Self::escape_label(source)
+                );
+            }
+        }
+        ret
+    }
+
+    fn escape_label(value: &str) -> String {
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_requests_and_latencies() {
+        let metrics = Metrics::default();
+        let route = "/referee/:item";
+        metrics.record_request("GET", route, 200, Duration::from_millis(20));
+        metrics.record_request("GET", route, 200, Duration::from_secs(3));
+        let text = metrics.render();
+        assert!(text.contains(
+            "http_requests_total{method=\"GET\",route=\"/referee/:item\",status=\"200\"} 2\n"
+        ));
+        // Buckets are cumulative
+        assert!(text.contains(
+            "http_request_duration_seconds_bucket{route=\"/referee/:item\",le=\"0.025\"} 1\n"
+        ));
+        assert!(text.contains(
+            "http_request_duration_seconds_bucket{route=\"/referee/:item\",le=\"5\"} 2\n"
+        ));
+        assert!(text.contains(
+            "http_request_duration_seconds_bucket{route=\"/referee/:item\",le=\"+Inf\"} 2\n"
+        ));
+        assert!(text.contains("http_request_duration_seconds_count{route=\"/referee/:item\"} 2\n"));
+    }
+
+    #[test]
+    fn test_render_upstream_failures() {
+        let metrics = Metrics::default();
+        metrics.record_upstream_failure("viaf");
+        metrics.record_upstream_failure("viaf");
+        metrics.record_upstream_failure("say \"hi\"");
+        let text = metrics.render();
+        assert!(text.contains("# TYPE upstream_failures_total counter\n"));
+        assert!(text.contains("upstream_failures_total{source=\"viaf\"} 2\n"));
+        assert!(text.contains("upstream_failures_total{source=\"say \\\"hi\\\"\"} 1\n"));
+    }
+}
@@ -0,0 +1,231 @@
+use crate::body_limit;
+use crate::config::{Config, DEFAULT_SOURCE_TIMEOUT};
+use crate::isbn::ISBN2wiki;
+use crate::rate_limit::RateLimiter;
+use crate::reference::{DataValue, Reference};
+use anyhow::{Result, anyhow};
+use chrono::NaiveDate;
+use regex::Regex;
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use wikibase_rest_api::prelude::*;
+
+static RE_YEAR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\d{4})$").unwrap());
+static HTTP_CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    Client::builder()
+        .user_agent(Config::user_agent())
+        .timeout(Config::timeout("open_library", DEFAULT_SOURCE_TIMEOUT))
+        .build()
+        .expect("Failed to build OpenLibrary HTTP client")
+});
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct OpenLibraryName {
+    name: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct OpenLibraryBook {
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    publishers: Vec<OpenLibraryName>,
+    #[serde(default)]
+    publish_date: Option<String>,
+    #[serde(default)]
+    number_of_pages: Option<i64>,
+    #[serde(default)]
+    identifiers: HashMap<String, Vec<String>>,
+}
+
+impl OpenLibraryBook {
+    pub async fn load_from_openlibrary(isbn2wiki: &ISBN2wiki) -> Result<()> {
+        let isbn = isbn2wiki
+            .isbn()
+            .ok_or_else(|| anyhow!("No ISBN found"))?
+            .replace('-', "");
+        let url =
+            format!("https://openlibrary.org/api/books?bibkeys=ISBN:{isbn}&format=json&jscmd=data");
+
+        RateLimiter::global().wait(&url).await;
+        let response = HTTP_CLIENT.get(&url).send().await?;
+        let json = body_limit::read_text(response, Config::max_body_bytes()).await?;
+        let book = Self::parse_openlibrary_json(isbn2wiki, &json)?;
+
+        // Publishers are only names, they need to be resolved to items
+        let openlibrary_id = book.openlibrary_id().unwrap_or_default();
+        for publisher in &book.publishers {
+            isbn2wiki
+                .add_publisher_by_name(&publisher.name, Reference::prop("P648", &openlibrary_id))
+                .await;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn parse_openlibrary_json(isbn2wiki: &ISBN2wiki, json: &str) -> Result<Self> {
+        let books: HashMap<String, OpenLibraryBook> = serde_json::from_str(json)?;
+        let book = books
+            .into_values()
+            .next()
+            .ok_or_else(|| anyhow!("No book found in OpenLibrary"))?;
+        let openlibrary_id = book
+            .openlibrary_id()
+            .ok_or_else(|| anyhow!("No OpenLibrary ID found"))?;
+
+        isbn2wiki.add_reference(
+            "P648",
+            DataValue::String(openlibrary_id.clone()),
+            Reference::none(),
+        );
+
+        if let Some(title) = &book.title {
+            // OpenLibrary does not provide the language of the title
+            isbn2wiki.add_reference(
+                "P1476",
+                DataValue::Monolingual {
+                    label: title.to_owned(),
+                    language: "und".to_string(),
+                },
+                Reference::prop("P648", &openlibrary_id),
+            );
+        }
+
+        if let Some(pages) = book.number_of_pages {
+            isbn2wiki.add_reference(
+                "P1104",
+                DataValue::Quantity(pages),
+                Reference::prop("P648", &openlibrary_id),
+            );
+        }
+
+        if let Some((time, precision)) = book.publish_date.as_deref().and_then(Self::parse_date) {
+            isbn2wiki.add_reference(
+                "P577",
+                DataValue::Date { time, precision },
+                Reference::prop("P648", &openlibrary_id),
+            );
+        }
+
+        Ok(book)
+    }
+
+    fn openlibrary_id(&self) -> Option<String> {
+        self.identifiers.get("openlibrary")?.first().cloned()
+    }
+
+    /// Parses `OpenLibrary` publish dates like "1988", "October 1988", or "October 1, 1988".
+    pub(crate) fn parse_date(date: &str) -> Option<(String, TimePrecision)> {
+        let date = date.trim();
+        if let Some(year) = RE_YEAR.captures(date).and_then(|c| c.get(1)) {
+            let year = year.as_str();
+            return Some((format!("+{year}-01-01T00:00:00Z"), TimePrecision::Year));
+        }
+        for format in ["%B %d, %Y", "%b %d, %Y", "%d %B %Y", "%Y-%m-%d"] {
+            if let Ok(d) = NaiveDate::parse_from_str(date, format) {
+                let time = d.format("+%Y-%m-%dT00:00:00Z").to_string();
+                return Some((time, TimePrecision::Day));
+            }
+        }
+        for format in ["%d %B %Y", "%d %b %Y"] {
+            if let Ok(d) = NaiveDate::parse_from_str(&format!("1 {date}"), format) {
+                let time = d.format("+%Y-%m-01T00:00:00Z").to_string();
+                return Some((time, TimePrecision::Month));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed_isbn2wiki() -> ISBN2wiki {
+        let isbn2wiki = ISBN2wiki::new("9780140328721").unwrap();
+        let json = include_str!("../test_files/openlibrary.json");
+        OpenLibraryBook::parse_openlibrary_json(&isbn2wiki, json)
+            .expect("parsing test JSON should succeed");
+        isbn2wiki
+    }
+
+    #[test]
+    fn test_parse_openlibrary_json_sets_p648() {
+        let isbn2wiki = parsed_isbn2wiki();
+        let values = isbn2wiki.values.lock().unwrap();
+        let found = values["P648"]
+            .keys()
+            .any(|dv| matches!(dv, DataValue::String(s) if s == "OL7353617M"));
+        assert!(
+            found,
+            "P648 should contain the OpenLibrary ID from the JSON"
+        );
+    }
+
+    #[test]
+    fn test_parse_openlibrary_json_sets_title() {
+        let isbn2wiki = parsed_isbn2wiki();
+        let values = isbn2wiki.values.lock().unwrap();
+        let found = values["P1476"].keys().any(
+            |dv| matches!(dv, DataValue::Monolingual { label, .. } if label == "Fantastic Mr. Fox"),
+        );
+        assert!(found, "P1476 should contain the title from the JSON");
+    }
+
+    #[test]
+    fn test_parse_openlibrary_json_sets_pages_and_date() {
+        let isbn2wiki = parsed_isbn2wiki();
+        let values = isbn2wiki.values.lock().unwrap();
+        assert!(
+            values["P1104"]
+                .keys()
+                .any(|dv| matches!(dv, DataValue::Quantity(96)))
+        );
+        assert!(values["P577"].keys().any(|dv| matches!(
+            dv,
+            DataValue::Date { time, precision }
+                if time == "+1988-10-01T00:00:00Z" && *precision == TimePrecision::Day
+        )));
+    }
+
+    #[test]
+    fn test_parse_openlibrary_json_references_openlibrary() {
+        let isbn2wiki = parsed_isbn2wiki();
+        let values = isbn2wiki.values.lock().unwrap();
+        let references = values["P1104"].get(&DataValue::Quantity(96)).unwrap();
+        assert!(references.contains(&Reference::prop("P648", "OL7353617M")));
+    }
+
+    #[test]
+    fn test_parse_openlibrary_json_returns_publishers() {
+        let isbn2wiki = ISBN2wiki::new("9780140328721").unwrap();
+        let json = include_str!("../test_files/openlibrary.json");
+        let book = OpenLibraryBook::parse_openlibrary_json(&isbn2wiki, json).unwrap();
+        assert_eq!(book.publishers.len(), 1);
+        assert_eq!(book.publishers[0].name, "Puffin");
+    }
+
+    #[test]
+    fn test_parse_openlibrary_json_empty_returns_error() {
+        let isbn2wiki = ISBN2wiki::new("9780140328721").unwrap();
+        assert!(OpenLibraryBook::parse_openlibrary_json(&isbn2wiki, "{}").is_err());
+    }
+
+    #[test]
+    fn test_parse_date_precisions() {
+        assert_eq!(
+            OpenLibraryBook::parse_date("1988"),
+            Some(("+1988-01-01T00:00:00Z".to_string(), TimePrecision::Year))
+        );
+        assert_eq!(
+            OpenLibraryBook::parse_date("October 1988"),
+            Some(("+1988-10-01T00:00:00Z".to_string(), TimePrecision::Month))
+        );
+        assert_eq!(
+            OpenLibraryBook::parse_date("October 1, 1988"),
+            Some(("+1988-10-01T00:00:00Z".to_string(), TimePrecision::Day))
+        );
+        assert_eq!(OpenLibraryBook::parse_date("sometime"), None);
+    }
+}
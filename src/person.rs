@@ -1,12 +1,18 @@
 use crate::wikidata::Wikidata;
 use axum::http::StatusCode;
-use futures::future::join_all;
+use futures::future::{BoxFuture, join_all};
 use mediawiki::Api;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::sync::LazyLock;
 use tokio::sync::RwLock;
 use wikibase::{Reference, Snak, Statement};
 
+// P31 classes of name items
+const MALE_GIVEN_NAME: &str = "Q12308941";
+const FEMALE_GIVEN_NAME: &str = "Q11879590";
+const FAMILY_NAME: &str = "Q101352";
+
 /// Cache mapping (lowercase first name, P31 gender class Q-id) to matching Q-ids.
 type NameGenderCache = HashMap<(String, String), Vec<String>>;
 
@@ -14,24 +20,281 @@ type NameGenderCache = HashMap<(String, String), Vec<String>>;
 static NAME_GENDER_CACHE: LazyLock<RwLock<NameGenderCache>> =
     LazyLock::new(|| RwLock::new(HashMap::new()));
 
+/// Lowercase nobiliary particles that belong to the following surname, e.g. "van Beethoven".
+static NAME_PARTICLES: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
+    [
+        "van", "von", "de", "la", "le", "der", "den", "des", "di", "da", "del", "della", "dos",
+        "das", "du", "ten", "ter", "zu",
+    ]
+    .into_iter()
+    .collect()
+});
+
+/// Whether a first name is a male and/or female given name on Wikidata
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum GenderClassification {
+    Male,
+    Female,
+    Both,
+    Unknown,
+}
+
+impl GenderClassification {
+    const fn from_matches(male: &[String], female: &[String]) -> Self {
+        match (male.is_empty(), female.is_empty()) {
+            (false, true) => Self::Male,
+            (true, false) => Self::Female,
+            (false, false) => Self::Both,
+            (true, true) => Self::Unknown,
+        }
+    }
+}
+
+/// Male and female given-name items matching a single first name
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FirstNameGender {
+    pub name: String,
+    pub male: Vec<String>,
+    pub female: Vec<String>,
+    pub classification: GenderClassification,
+}
+
+/// Whether the family name comes last ("Magnus Manske") or first ("Mao Zedong")
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NameOrder {
+    #[default]
+    GivenFirst,
+    FamilyFirst,
+    /// Guess from the script of the name
+    Auto,
+}
+
+impl NameOrder {
+    /// Resolves `Auto`: names in Chinese, Japanese, or Korean script are family-first.
+    fn resolve(self, name: &str) -> Self {
+        match self {
+            Self::Auto if name.chars().any(Self::is_cjk) => Self::FamilyFirst,
+            Self::Auto => Self::GivenFirst,
+            order => order,
+        }
+    }
+
+    const fn is_cjk(c: char) -> bool {
+        matches!(c,
+            '\u{3040}'..='\u{30FF}' // Hiragana, Katakana
+            | '\u{3400}'..='\u{4DBF}' // CJK Extension A
+            | '\u{4E00}'..='\u{9FFF}' // CJK Unified Ideographs
+            | '\u{AC00}'..='\u{D7AF}' // Hangul syllables
+        )
+    }
+}
+
+/// Finds the single name item with a label and P31 class, like `Wikidata::search_single_name`.
+/// Lets batches be tested without Wikidata.
+/// Returns a boxed future, so the search can be held as `dyn NameSearch`.
+pub(crate) trait NameSearch: std::fmt::Debug + Send + Sync {
+    fn search<'a>(
+        &'a self,
+        name: &'a str,
+        class: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<String>, StatusCode>>;
+}
+
+/// Searches Wikidata with the shared API, caching given name results
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct WikidataNameSearch;
+
+impl NameSearch for WikidataNameSearch {
+    fn search<'a>(
+        &'a self,
+        name: &'a str,
+        class: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<String>, StatusCode>> {
+        Box::pin(async move {
+            let api = Wikidata::get_wikidata_api().await?;
+            if class == FAMILY_NAME {
+                return Wikidata::search_single_name(&api, name, class).await;
+            }
+            Person::cached_search_single_name(&api, name, class).await
+        })
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Person;
 
 impl Person {
-    pub async fn name_gender(name: &str) -> Result<Vec<Statement>, StatusCode> {
+    pub async fn name_gender(name: &str, order: NameOrder) -> Result<Vec<Statement>, StatusCode> {
         let mut statements = vec![];
-        let mut parts = name.split_whitespace().collect::<Vec<_>>();
-        let last_name = match parts.pop() {
-            Some(last_name) => last_name,
+        let (first_names, last_name) = match Self::split_name(name, order) {
+            Some(split) => split,
             None => return Ok(statements), // No name, return empty set
         };
-        let first_names = parts;
         let api = Wikidata::get_wikidata_api().await?;
-        Self::add_last_name(last_name, &api, &mut statements).await?;
+        Self::add_last_name(&last_name, &api, &mut statements).await?;
         Self::add_first_names_gender(first_names, &api, &mut statements).await?;
         Ok(statements)
     }
 
+    /// Like `name_gender`, with the name items found by `search`
+    pub(crate) async fn name_gender_with(
+        search: &dyn NameSearch,
+        name: &str,
+        order: NameOrder,
+    ) -> Result<Vec<Statement>, StatusCode> {
+        let names = [name.to_string()];
+        Self::name_gender_batch_with(search, &names, order)
+            .await
+            .remove(name)
+            .unwrap_or_else(|| Ok(vec![]))
+    }
+
+    /// Like `name_gender` for each of `names`, by name, with the name items found by
+    /// `search`. Each distinct first name and surname is only searched once for the batch.
+    pub(crate) async fn name_gender_batch_with(
+        search: &dyn NameSearch,
+        names: &[String],
+        order: NameOrder,
+    ) -> HashMap<String, Result<Vec<Statement>, StatusCode>> {
+        let splits: Vec<_> = names
+            .iter()
+            .map(|name| (name, Self::split_name(name, order)))
+            .collect();
+
+        // Given names are matched case-insensitively, surnames exactly
+        let mut lookups: HashMap<(String, &str), &str> = HashMap::new();
+        for (first_names, last_name) in splits.iter().filter_map(|(_, split)| split.as_ref()) {
+            for first_name in first_names {
+                for class in [MALE_GIVEN_NAME, FEMALE_GIVEN_NAME] {
+                    lookups
+                        .entry((first_name.to_lowercase(), class))
+                        .or_insert(first_name);
+                }
+            }
+            lookups
+                .entry((last_name.to_owned(), FAMILY_NAME))
+                .or_insert(last_name);
+        }
+        let futures = lookups.into_iter().map(|(key, name)| async move {
+            let result = search.search(name, key.1).await;
+            (key, result)
+        });
+        let results: HashMap<_, _> = join_all(futures).await.into_iter().collect();
+
+        splits
+            .into_iter()
+            .map(|(name, split)| {
+                let Some((first_names, last_name)) = split else {
+                    return (name.to_owned(), Ok(vec![]));
+                };
+                let statements = Self::batch_statements(&results, &first_names, &last_name);
+                (name.to_owned(), statements)
+            })
+            .collect()
+    }
+
+    /// Builds the `name_gender` statements from the batch search results.
+    /// Like there, failed given name searches are ignored, but a failed surname search is not.
+    fn batch_statements(
+        results: &HashMap<(String, &str), Result<Vec<String>, StatusCode>>,
+        first_names: &[&str],
+        last_name: &str,
+    ) -> Result<Vec<Statement>, StatusCode> {
+        let surnames = results
+            .get(&(last_name.to_string(), FAMILY_NAME))
+            .cloned()
+            .unwrap_or(Ok(vec![]))?;
+        let given_names = |class| -> Vec<String> {
+            let mut items: Vec<String> = first_names
+                .iter()
+                .filter_map(|first_name| results.get(&(first_name.to_lowercase(), class)))
+                .filter_map(|result| result.as_ref().ok())
+                .flatten()
+                .cloned()
+                .collect();
+            items.sort();
+            items.dedup();
+            items
+        };
+        let mut statements: Vec<Statement> =
+            Self::last_name_statement(&surnames).into_iter().collect();
+        statements.extend(Self::first_names_statements(
+            given_names(MALE_GIVEN_NAME),
+            given_names(FEMALE_GIVEN_NAME),
+        ));
+        Ok(statements)
+    }
+
+    /// Classifies each first name of a full name, including ambiguous ones that
+    /// `name_gender` does not emit a P21 statement for.
+    pub async fn first_names_gender(
+        name: &str,
+        order: NameOrder,
+    ) -> Result<Vec<FirstNameGender>, StatusCode> {
+        let first_names = match Self::split_name(name, order) {
+            Some((first_names, _last_name)) => first_names,
+            None => return Ok(vec![]),
+        };
+        let api = Wikidata::get_wikidata_api().await?;
+        let futures: Vec<_> = first_names
+            .iter()
+            .map(|first_name| Self::first_name_gender(&api, first_name))
+            .collect();
+        join_all(futures).await.into_iter().collect()
+    }
+
+    async fn first_name_gender(api: &Api, first_name: &str) -> Result<FirstNameGender, StatusCode> {
+        // Male and female given name
+        let male = Self::cached_search_single_name(api, first_name, MALE_GIVEN_NAME).await?;
+        let female = Self::cached_search_single_name(api, first_name, FEMALE_GIVEN_NAME).await?;
+        Ok(FirstNameGender {
+            name: first_name.to_string(),
+            classification: GenderClassification::from_matches(&male, &female),
+            male,
+            female,
+        })
+    }
+
+    /// Splits a full name into first names and surname. The surname is the last token,
+    /// including any preceding nobiliary particles ("van", "de la", ...) and hyphen-joined
+    /// parts ("Smith - Jones"). At least one first name is kept, so "Van Morrison" keeps "Van".
+    /// For family-first names, the surname is simply the first token.
+    fn split_name(name: &str, order: NameOrder) -> Option<(Vec<&str>, String)> {
+        let mut parts = name.split_whitespace().collect::<Vec<_>>();
+        if order.resolve(name) == NameOrder::FamilyFirst {
+            if parts.is_empty() {
+                return None;
+            }
+            let family_name = parts.remove(0).to_string();
+            return Some((parts, family_name));
+        }
+        let mut last_name = parts.pop()?.to_string();
+        while let Some(&previous) = parts.last() {
+            if previous == "-" || previous.ends_with('-') || last_name.starts_with('-') {
+                let previous = previous.trim_end_matches('-');
+                let rest = last_name.trim_start_matches('-');
+                last_name = if previous.is_empty() {
+                    format!("-{rest}")
+                } else {
+                    format!("{previous}-{rest}")
+                };
+                parts.pop();
+            } else {
+                break;
+            }
+        }
+        while parts.len() > 1 && parts.last().copied().is_some_and(Self::is_name_particle) {
+            let particle = parts.pop()?;
+            last_name = format!("{particle} {last_name}");
+        }
+        Some((parts, last_name))
+    }
+
+    fn is_name_particle(part: &str) -> bool {
+        NAME_PARTICLES.contains(part.to_lowercase().as_str())
+    }
+
     /// Look up a single first name + gender class, using the cache when possible.
     async fn cached_search_single_name(
         api: &Api,
@@ -103,12 +366,20 @@ impl Person {
         statements: &mut Vec<Statement>,
     ) -> Result<(), StatusCode> {
         let mut results = join_all([
-            Self::get_given_names_for_gender(&first_names, api, "Q12308941"), // Male given name
-            Self::get_given_names_for_gender(&first_names, api, "Q11879590"), // Female given name
+            Self::get_given_names_for_gender(&first_names, api, MALE_GIVEN_NAME),
+            Self::get_given_names_for_gender(&first_names, api, FEMALE_GIVEN_NAME),
         ])
         .await;
-        let mut female = results.pop().unwrap()?;
-        let mut male = results.pop().unwrap()?;
+        let female = results.pop().unwrap()?;
+        let male = results.pop().unwrap()?;
+        statements.extend(Self::first_names_statements(male, female));
+        Ok(())
+    }
+
+    /// The gender (P21) and given name (P735) statements, if the given names are
+    /// only male or only female. Items that are both are ignored.
+    fn first_names_statements(mut male: Vec<String>, mut female: Vec<String>) -> Vec<Statement> {
+        let mut statements = vec![];
         let both: Vec<_> = male
             .iter()
             .filter(|x| female.contains(x))
@@ -141,7 +412,7 @@ impl Person {
                 .collect();
             statements.extend(name_statements);
         }
-        Ok(())
+        statements
     }
 
     async fn add_last_name(
@@ -149,18 +420,23 @@ impl Person {
         api: &Api,
         statements: &mut Vec<Statement>,
     ) -> Result<(), StatusCode> {
-        let results = Wikidata::search_single_name(api, last_name, "Q101352").await?;
-        if let [entity] = results.as_slice() {
-            let snak = Snak::new_item("P734", entity);
-            let reference = Reference::new(vec![
-                Wikidata::infernal_reference_snak(),
-                Snak::new_item("P3452", "Q97033143"), // inferred from person's full name
-            ]);
-            let statement = Statement::new_normal(snak, vec![], vec![reference]);
-            statements.push(statement);
-        }
+        let results = Wikidata::search_single_name(api, last_name, FAMILY_NAME).await?;
+        statements.extend(Self::last_name_statement(&results));
         Ok(())
     }
+
+    /// The family name (P734) statement, if the search found exactly one item
+    fn last_name_statement(results: &[String]) -> Option<Statement> {
+        let [entity] = results else {
+            return None;
+        };
+        let snak = Snak::new_item("P734", entity);
+        let reference = Reference::new(vec![
+            Wikidata::infernal_reference_snak(),
+            Snak::new_item("P3452", "Q97033143"), // inferred from person's full name
+        ]);
+        Some(Statement::new_normal(snak, vec![], vec![reference]))
+    }
 }
 
 #[cfg(test)]
@@ -177,10 +453,219 @@ mod tests {
         }
     }
 
+    /// Canned name items, recording every search
+    #[derive(Debug, Default)]
+    struct CountingSearch {
+        searches: std::sync::Mutex<Vec<(String, String)>>,
+    }
+
+    impl CountingSearch {
+        fn count(&self, name: &str, class: &str) -> usize {
+            let searches = self.searches.lock().unwrap();
+            searches
+                .iter()
+                .filter(|(n, c)| n == name && c == class)
+                .count()
+        }
+    }
+
+    impl NameSearch for CountingSearch {
+        fn search<'a>(
+            &'a self,
+            name: &'a str,
+            class: &'a str,
+        ) -> BoxFuture<'a, Result<Vec<String>, StatusCode>> {
+            self.searches
+                .lock()
+                .unwrap()
+                .push((name.to_string(), class.to_string()));
+            let items = match (name, class) {
+                ("Heinrich", MALE_GIVEN_NAME) => vec!["Q1".to_string()],
+                ("Manske", FAMILY_NAME) => vec!["Q2".to_string()],
+                ("Heine", FAMILY_NAME) => vec!["Q3".to_string()],
+                _ => vec![],
+            };
+            Box::pin(async move { Ok(items) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_name_gender_batch_searches_shared_first_name_once() {
+        let search = CountingSearch::default();
+        let names = vec!["Heinrich Manske".to_string(), "Heinrich Heine".to_string()];
+        let results = Person::name_gender_batch_with(&search, &names, NameOrder::GivenFirst).await;
+        assert_eq!(search.count("Heinrich", MALE_GIVEN_NAME), 1);
+        assert_eq!(search.count("Heinrich", FEMALE_GIVEN_NAME), 1);
+        assert_eq!(search.count("Manske", FAMILY_NAME), 1);
+
+        let values = |name: &str| -> Vec<(String, String)> {
+            results[name]
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|s| {
+                    (
+                        s.main_snak().property().to_string(),
+                        snak_item_value(s).unwrap(),
+                    )
+                })
+                .collect()
+        };
+        let expected = |surname: &str| {
+            vec![
+                ("P734".to_string(), surname.to_string()),
+                ("P21".to_string(), "Q6581097".to_string()),
+                ("P735".to_string(), "Q1".to_string()),
+            ]
+        };
+        assert_eq!(values("Heinrich Manske"), expected("Q2"));
+        assert_eq!(values("Heinrich Heine"), expected("Q3"));
+    }
+
+    fn split(name: &str) -> Option<(Vec<&str>, String)> {
+        Person::split_name(name, NameOrder::GivenFirst)
+    }
+
+    #[test]
+    fn test_split_name_simple() {
+        assert_eq!(
+            split("Heinrich Magnus Manske"),
+            Some((vec!["Heinrich", "Magnus"], "Manske".to_string()))
+        );
+        assert_eq!(split("Manske"), Some((vec![], "Manske".to_string())));
+        assert_eq!(split("   "), None);
+    }
+
+    #[test]
+    fn test_split_name_particles() {
+        assert_eq!(
+            split("Ludwig van Beethoven"),
+            Some((vec!["Ludwig"], "van Beethoven".to_string()))
+        );
+        assert_eq!(
+            split("José de la Cruz"),
+            Some((vec!["José"], "de la Cruz".to_string()))
+        );
+        assert_eq!(
+            split("Johann Van Der Berg"),
+            Some((vec!["Johann"], "Van Der Berg".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_split_name_particle_as_first_name() {
+        assert_eq!(
+            split("Van Morrison"),
+            Some((vec!["Van"], "Morrison".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_split_name_hyphenated() {
+        assert_eq!(
+            split("Anna Smith-Jones"),
+            Some((vec!["Anna"], "Smith-Jones".to_string()))
+        );
+        assert_eq!(
+            split("Anna Smith - Jones"),
+            Some((vec!["Anna"], "Smith-Jones".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_split_name_family_first() {
+        assert_eq!(
+            Person::split_name("Mao Zedong", NameOrder::FamilyFirst),
+            Some((vec!["Zedong"], "Mao".to_string()))
+        );
+        assert_eq!(Person::split_name(" ", NameOrder::FamilyFirst), None);
+    }
+
+    #[test]
+    fn test_name_order_auto() {
+        assert_eq!(NameOrder::Auto.resolve("毛 泽东"), NameOrder::FamilyFirst);
+        assert_eq!(NameOrder::Auto.resolve("山田 太郎"), NameOrder::FamilyFirst);
+        assert_eq!(NameOrder::Auto.resolve("김 민준"), NameOrder::FamilyFirst);
+        assert_eq!(
+            NameOrder::Auto.resolve("Magnus Manske"),
+            NameOrder::GivenFirst
+        );
+        assert_eq!(
+            NameOrder::GivenFirst.resolve("山田 太郎"),
+            NameOrder::GivenFirst
+        );
+    }
+
+    #[tokio::test]
+    async fn test_name_gender_family_first() {
+        let results = Person::name_gender("Manske Heinrich", NameOrder::FamilyFirst)
+            .await
+            .unwrap();
+        let last_names: Vec<_> = results
+            .iter()
+            .filter(|s| s.main_snak().property() == "P734")
+            .collect();
+        assert_eq!(last_names.len(), 1);
+        // Manske (family name)
+        assert_eq!(snak_item_value(last_names[0]).as_deref(), Some("Q1891133"));
+    }
+
+    #[test]
+    fn test_gender_classification_from_matches() {
+        let q = |id: &str| vec![id.to_string()];
+        assert_eq!(
+            GenderClassification::from_matches(&q("Q1"), &[]),
+            GenderClassification::Male
+        );
+        assert_eq!(
+            GenderClassification::from_matches(&[], &q("Q2")),
+            GenderClassification::Female
+        );
+        assert_eq!(
+            GenderClassification::from_matches(&q("Q1"), &q("Q2")),
+            GenderClassification::Both
+        );
+        assert_eq!(
+            GenderClassification::from_matches(&[], &[]),
+            GenderClassification::Unknown
+        );
+    }
+
+    #[tokio::test]
+    async fn test_first_names_gender_unisex() {
+        // "Alex" is both a male and a female given name
+        let genders = Person::first_names_gender("Alex Manske", NameOrder::GivenFirst)
+            .await
+            .unwrap();
+        assert_eq!(genders.len(), 1);
+        assert_eq!(genders[0].name, "Alex");
+        assert_eq!(genders[0].classification, GenderClassification::Both);
+
+        let results = Person::name_gender("Alex Manske", NameOrder::GivenFirst)
+            .await
+            .unwrap();
+        assert!(
+            !results.iter().any(|s| s.main_snak().property() == "P21"),
+            "An ambiguous first name should not produce a gender statement"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_first_names_gender_male() {
+        let genders = Person::first_names_gender("Heinrich Manske", NameOrder::GivenFirst)
+            .await
+            .unwrap();
+        assert_eq!(genders.len(), 1);
+        assert_eq!(genders[0].classification, GenderClassification::Male);
+        assert!(!genders[0].male.is_empty());
+    }
+
     #[tokio::test]
     async fn test_name_gender_male() {
         // "Heinrich Magnus Manske" — two male given names + last name + gender
-        let results = Person::name_gender("Heinrich Magnus Manske").await.unwrap();
+        let results = Person::name_gender("Heinrich Magnus Manske", NameOrder::GivenFirst)
+            .await
+            .unwrap();
         assert_eq!(
             results.len(),
             4,
@@ -203,7 +688,9 @@ mod tests {
     #[tokio::test]
     async fn test_name_gender_female() {
         // "Elisabeth Manske" — a clearly female first name
-        let results = Person::name_gender("Elisabeth Manske").await.unwrap();
+        let results = Person::name_gender("Elisabeth Manske", NameOrder::GivenFirst)
+            .await
+            .unwrap();
         // Should contain a gender statement for female
         let gender_statements: Vec<_> = results
             .iter()
@@ -224,7 +711,9 @@ mod tests {
     #[tokio::test]
     async fn test_name_gender_empty() {
         // Empty string: no name parts at all
-        let results = Person::name_gender("").await.unwrap();
+        let results = Person::name_gender("", NameOrder::GivenFirst)
+            .await
+            .unwrap();
         assert!(
             results.is_empty(),
             "Empty name should produce no statements"
@@ -234,7 +723,9 @@ mod tests {
     #[tokio::test]
     async fn test_name_gender_single_word() {
         // Single word is treated as last name only, no first names
-        let results = Person::name_gender("Manske").await.unwrap();
+        let results = Person::name_gender("Manske", NameOrder::GivenFirst)
+            .await
+            .unwrap();
         // Should have at most a last name statement (P734), no gender
         let gender_statements: Vec<_> = results
             .iter()
@@ -249,7 +740,9 @@ mod tests {
     #[tokio::test]
     async fn test_name_gender_references() {
         // Verify that every statement has at least one reference containing the infernal snak (P887)
-        let results = Person::name_gender("Heinrich Manske").await.unwrap();
+        let results = Person::name_gender("Heinrich Manske", NameOrder::GivenFirst)
+            .await
+            .unwrap();
         assert!(!results.is_empty());
         for statement in &results {
             let refs = statement.references();
@@ -267,8 +760,12 @@ mod tests {
     #[tokio::test]
     async fn test_name_gender_consistent_calls() {
         // Calling twice with the same input should yield the same result
-        let r1 = Person::name_gender("Heinrich Manske").await.unwrap();
-        let r2 = Person::name_gender("Heinrich Manske").await.unwrap();
+        let r1 = Person::name_gender("Heinrich Manske", NameOrder::GivenFirst)
+            .await
+            .unwrap();
+        let r2 = Person::name_gender("Heinrich Manske", NameOrder::GivenFirst)
+            .await
+            .unwrap();
         assert_eq!(
             r1.len(),
             r2.len(),
@@ -283,7 +780,9 @@ mod tests {
     #[tokio::test]
     async fn test_name_gender_has_given_name_statements() {
         // For an unambiguous male name, given name (P735) statements should be present
-        let results = Person::name_gender("Heinrich Manske").await.unwrap();
+        let results = Person::name_gender("Heinrich Manske", NameOrder::GivenFirst)
+            .await
+            .unwrap();
         let given_name_stmts: Vec<_> = results
             .iter()
             .filter(|s| s.main_snak().property() == "P735")
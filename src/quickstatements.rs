@@ -0,0 +1,170 @@
+use wikibase::{Snak, Statement, Value};
+
+/// Subject for statements about an item that is not known; the commands for it create
+/// a new item first
+pub const LAST: &str = "LAST";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct QuickStatements;
+
+impl QuickStatements {
+    /// QuickStatements v1 commands adding the statements to `item`, one line per reference.
+    /// Statements with values that QuickStatements can not express are skipped.
+    /// For `LAST`, the commands start with `CREATE`.
+    pub fn commands(item: &str, statements: &[Statement]) -> String {
+        let lines: Vec<String> = statements
+            .iter()
+            .flat_map(|statement| Self::statement_lines(item, statement))
+            .collect();
+        if lines.is_empty() {
+            return String::new();
+        }
+        let create = (item == LAST).then(|| "CREATE".to_string());
+        create
+            .into_iter()
+            .chain(lines)
+            .map(|line| line + "\n")
+            .collect()
+    }
+
+    fn statement_lines(item: &str, statement: &Statement) -> Vec<String> {
+        let Some(mut line) = Self::snak_fields(statement.main_snak(), "P") else {
+            return vec![];
+        };
+        line.insert(0, item.to_string());
+        for qualifier in statement.qualifiers() {
+            line.extend(Self::snak_fields(qualifier, "P").unwrap_or_default());
+        }
+        if statement.references().is_empty() {
+            return vec![line.join("\t")];
+        }
+        statement
+            .references()
+            .iter()
+            .map(|reference| {
+                let sources = reference
+                    .snaks()
+                    .iter()
+                    .filter_map(|snak| Self::snak_fields(snak, "S"))
+                    .flatten();
+                line.iter()
+                    .cloned()
+                    .chain(sources)
+                    .collect::<Vec<String>>()
+                    .join("\t")
+            })
+            .collect()
+    }
+
+    /// The property, with `prefix` instead of "P", and the value of the snak
+    fn snak_fields(snak: &Snak, prefix: &str) -> Option<Vec<String>> {
+        let property = snak.property().strip_prefix('P')?;
+        let value = Self::value(snak.data_value().as_ref()?.value())?;
+        Some(vec![format!("{prefix}{property}"), value])
+    }
+
+    fn value(value: &Value) -> Option<String> {
+        match value {
+            Value::Entity(entity) => Some(entity.id().to_string()),
+            Value::StringValue(s) => Some(Self::quoted(s)),
+            Value::MonoLingual(text) => {
+                Some(format!("{}:{}", text.language(), Self::quoted(text.text())))
+            }
+            Value::Time(time) => Some(format!("{}/{}", time.time(), time.precision())),
+            Value::Coordinate(coordinate) => Some(format!(
+                "@{}/{}",
+                coordinate.latitude(),
+                coordinate.longitude()
+            )),
+            _ => None,
+        }
+    }
+
+    /// A string in double quotes, with quotes and backslashes in it escaped
+    fn quoted(s: &str) -> String {
+        let escaped = s.replace('\\', "\\\\").replace('"', "\\\"");
+        format!("\"{escaped}\"")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wikidata::Wikidata;
+    use wikibase::{DataValue, DataValueType, Reference, SnakDataType, SnakType, TimeValue};
+
+    #[test]
+    fn test_commands_gender_statement() {
+        let reference = Reference::new(vec![
+            Wikidata::infernal_reference_snak(),
+            Snak::new_item("P3452", "Q69652498"),
+        ]);
+        let statement =
+            Statement::new_normal(Snak::new_item("P21", "Q6581097"), vec![], vec![reference]);
+        assert_eq!(
+            QuickStatements::commands(LAST, &[statement]),
+            "CREATE\nLAST\tP21\tQ6581097\tS887\tQ131287902\tS3452\tQ69652498\n"
+        );
+    }
+
+    #[test]
+    fn test_commands_last_without_statements() {
+        assert_eq!(QuickStatements::commands(LAST, &[]), "");
+    }
+
+    #[test]
+    fn test_value_string_escaping() {
+        let value = Value::StringValue(r#"The "Bounty" C:\Path"#.to_string());
+        assert_eq!(
+            QuickStatements::value(&value),
+            Some(r#""The \"Bounty\" C:\\Path""#.to_string())
+        );
+    }
+
+    #[test]
+    fn test_commands_line_per_reference() {
+        let references = vec![
+            Reference::new(vec![Snak::new_item("P248", "Q1")]),
+            Reference::new(vec![Snak::new_item("P248", "Q2")]),
+        ];
+        let statement = Statement::new_normal(Snak::new_item("P131", "Q64"), vec![], references);
+        assert_eq!(
+            QuickStatements::commands("Q42", &[statement]),
+            "Q42\tP131\tQ64\tS248\tQ1\nQ42\tP131\tQ64\tS248\tQ2\n"
+        );
+    }
+
+    #[test]
+    fn test_value_time() {
+        let time = TimeValue::new(
+            0,
+            0,
+            "http://www.wikidata.org/entity/Q1985727",
+            11,
+            "+1952-03-11T00:00:00Z",
+            0,
+        );
+        let snak = Snak::new(
+            SnakDataType::Time,
+            "P569",
+            SnakType::Value,
+            Some(DataValue::new(DataValueType::Time, Value::Time(time))),
+        );
+        assert_eq!(
+            QuickStatements::snak_fields(&snak, "P"),
+            Some(vec![
+                "P569".to_string(),
+                "+1952-03-11T00:00:00Z/11".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_commands_without_references() {
+        let statement = Statement::new_normal(Snak::new_item("P17", "Q183"), vec![], vec![]);
+        assert_eq!(
+            QuickStatements::commands("Q64", &[statement]),
+            "Q64\tP17\tQ183\n"
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use crate::config::{Config, RateLimitConfig};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+const DEFAULT_REQUESTS_PER_SECOND: f64 = 5.0;
+const DEFAULT_BURST: f64 = 1.0;
+/// Beyond this many hosts, idle ones are forgotten
+const MAX_HOSTS: usize = 1000;
+/// Any waiting request of a host idle for this long has been sent
+const IDLE_HOST: Duration = Duration::from_secs(60);
+
+static RATE_LIMITER: LazyLock<RateLimiter> =
+    LazyLock::new(|| RateLimiter::from_config(Config::rate_limit()));
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    /// Negative if requests are already waiting for tokens
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Per-host token bucket for outbound requests, so external sites are not hammered.
+/// All outbound GETs share the `global` limiter.
+#[derive(Debug)]
+pub struct RateLimiter {
+    requests_per_second: f64,
+    burst: f64,
+    hosts: HashMap<String, f64>,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: f64, burst: f64) -> Self {
+        Self {
+            requests_per_second,
+            burst: burst.max(1.0),
+            hosts: HashMap::new(),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Uses a different rate for `host`. A rate of 0 disables limiting for the host.
+    pub fn with_host(mut self, host: &str, requests_per_second: f64) -> Self {
+        self.hosts.insert(host.to_lowercase(), requests_per_second);
+        self
+    }
+
+    /// Uses `rate_limit.requests_per_second`, `rate_limit.burst`, and the per-host rates in
+    /// `rate_limit.hosts` from the configuration, if set.
+    pub fn from_config(config: &RateLimitConfig) -> Self {
+        let requests_per_second = config
+            .requests_per_second
+            .unwrap_or(DEFAULT_REQUESTS_PER_SECOND);
+        let burst = config.burst.unwrap_or(DEFAULT_BURST);
+        config.hosts.iter().fold(
+            Self::new(requests_per_second, burst),
+            |limiter, (host, rate)| limiter.with_host(host, *rate),
+        )
+    }
+
+    pub fn global() -> &'static Self {
+        &RATE_LIMITER
+    }
+
+    /// Waits until a request to the host of `url` is allowed.
+    /// URLs without a host are not limited.
+    pub async fn wait(&self, url: &str) {
+        if let Some(delay) = self.reserve(url) {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    fn rate(&self, host: &str) -> f64 {
+        self.hosts
+            .get(host)
+            .copied()
+            .unwrap_or(self.requests_per_second)
+    }
+
+    /// Takes a token for the host of `url`, and returns how long to wait for it
+    fn reserve(&self, url: &str) -> Option<Duration> {
+        let host = Url::parse(url).ok()?.host_str()?.to_lowercase();
+        let rate = self.rate(&host);
+        if rate <= 0.0 {
+            return None;
+        }
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().ok()?;
+        if buckets.len() >= MAX_HOSTS {
+            buckets.retain(|_, bucket| now.saturating_duration_since(bucket.updated) < IDLE_HOST);
+        }
+        let bucket = buckets.entry(host).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(self.burst) - 1.0;
+        bucket.updated = now;
+        (bucket.tokens < 0.0).then(|| Duration::from_secs_f64(-bucket.tokens / rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_wait_spaces_requests_to_one_host() {
+        let limiter = RateLimiter::new(20.0, 1.0);
+        let start = Instant::now();
+        for _ in 0..5 {
+            limiter.wait("https://example.org/page").await;
+        }
+        // The first request is immediate, the other four are 50ms apart
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(190), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(1), "{elapsed:?}");
+    }
+
+    #[test]
+    fn test_reserve_is_per_host() {
+        let limiter = RateLimiter::new(1.0, 1.0);
+        assert_eq!(limiter.reserve("https://a.example/1"), None);
+        assert!(limiter.reserve("https://a.example/2").is_some());
+        assert_eq!(limiter.reserve("https://b.example/1"), None);
+        assert_eq!(limiter.reserve("not a url"), None);
+    }
+
+    #[test]
+    fn test_reserve_allows_burst() {
+        let limiter = RateLimiter::new(1.0, 3.0);
+        for _ in 0..3 {
+            assert_eq!(limiter.reserve("https://a.example/"), None);
+        }
+        assert!(limiter.reserve("https://a.example/").is_some());
+    }
+
+    #[test]
+    fn test_from_config_host_rates() {
+        let config = json!({
+            "requests_per_second": 2.0,
+            "hosts": {"viaf.org": 0.5, "localhost": 0}
+        });
+        let limiter = RateLimiter::from_config(&serde_json::from_value(config).unwrap());
+        assert_eq!(limiter.rate("viaf.org").to_string(), "0.5");
+        assert_eq!(limiter.rate("example.org").to_string(), "2");
+        // A rate of 0 disables limiting
+        assert_eq!(limiter.reserve("http://localhost/"), None);
+        assert_eq!(limiter.reserve("http://localhost/"), None);
+    }
+
+    #[test]
+    fn test_from_config_defaults() {
+        let limiter = RateLimiter::from_config(&RateLimitConfig::default());
+        assert_eq!(
+            limiter.rate("example.org").to_string(),
+            DEFAULT_REQUESTS_PER_SECOND.to_string()
+        );
+    }
+}
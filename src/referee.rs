@@ -1,19 +1,27 @@
+use crate::config::{Config, RefereeConfig};
+use crate::fetcher::{ContentFetcher, HttpFetcher};
+use crate::wikidata::Wikidata;
 use anyhow::{Result, anyhow};
 use futures::future::join_all;
 use futures::join;
-
-use regex::Regex;
-use reqwest::Client;
+use futures::stream::{self, BoxStream, FuturesUnordered, Stream, StreamExt};
+use lingua::{LanguageDetector, LanguageDetectorBuilder};
+use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{
     cmp::Ordering,
-    collections::{HashMap, HashSet},
-    sync::LazyLock,
+    collections::{BTreeMap, HashMap, HashSet},
+    future::Future,
+    path::PathBuf,
+    sync::{Arc, LazyLock, Mutex},
+    time::{Duration, Instant, SystemTime},
 };
+use tokio::sync::Semaphore;
+use url::Url;
 use wikibase::{
-    DataValueType, Entity, EntityTrait, Snak, SnakDataType, Statement,
-    entity_container::EntityContainer, mediawiki::Api,
+    DataValue, DataValueType, Entity, EntityTrait, LocaleString, Snak, SnakDataType, SnakType,
+    Statement, TimeValue, entity_container::EntityContainer, mediawiki::Api,
 };
 
 /// Temporary struct for parsing months data from JSON
@@ -25,6 +33,8 @@ struct MonthsJsonRow {
     short: Option<String>,
 }
 
+static RE_PROPERTY_ID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^P\d+$").unwrap());
+
 static RE_WIKI: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"\b(wikipedia|wikimedia|wik[a-z-]+)\.org/").unwrap());
 
@@ -37,19 +47,33 @@ static RE_HTML_TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<.+?>").unwr
 static RE_WHITESPACE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[\r\t ]+").unwrap());
 static RE_NEWLINES: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\n+").unwrap());
 static RE_SPACES: LazyLock<Regex> = LazyLock::new(|| Regex::new(r" +").unwrap());
-
-// Language detection regexes
-static RE_LANG_EN: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"\b(he|she|it|is|from|to|was|the|a|an|born|died)\b").unwrap());
-static RE_LANG_DE: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r"\b(er|sie|sind|ist|es|das|ein|eine|war|geboren|gestorben)\b").unwrap()
+static RE_HTML_TITLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap());
+static RE_HTML_H1: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<h1[^>]*>(.*?)</h1>").unwrap());
+static RE_META_TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)<meta\b[^>]*>").unwrap());
+static RE_META_REFRESH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)http-equiv\s*=\s*["']?refresh"#).unwrap());
+/// The target of a meta refresh; a refresh without one only reloads the page
+static RE_META_REFRESH_URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)content\s*=\s*["']?[^"'>]*url\s*="#).unwrap());
+
+// Texts shorter than this (in characters) are too short for reliable language detection
+const MIN_LANGUAGE_DETECTION_TEXT_LENGTH: usize = 20;
+
+// Only the start of a page is used for language detection, to keep it fast on large pages
+const MAX_LANGUAGE_DETECTION_TEXT_LENGTH: usize = 5000;
+
+// Language used when detection fails or is not confident enough
+const DEFAULT_LANGUAGE: &str = "en";
+
+// Low accuracy mode only loads the small trigram models, instead of about 1GB for all languages
+static LANGUAGE_DETECTOR: LazyLock<LanguageDetector> = LazyLock::new(|| {
+    LanguageDetectorBuilder::from_all_languages()
+        .with_minimum_relative_distance(0.1)
+        .with_low_accuracy_mode()
+        .build()
 });
-static RE_LANG_IT: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"\b(è|una|della|la|nel|si|su|una|di)\b").unwrap());
-static RE_LANG_FR: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"\b(est|un|une|et|le|les|la|il|a|de|par)\b").unwrap());
-static RE_LANG_ES: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"\b(el|es|un|de|a|la|es|conlas|dos)\b").unwrap());
 
 // Time value parsing regex
 static RE_TIME_VALUE: LazyLock<Regex> =
@@ -83,7 +107,24 @@ static MONTHS: LazyLock<HashMap<u32, HashStringOptionString>> = LazyLock::new(||
     ret
 });
 
-// Do not generate references for these item types
+// Polish dates use the genitive of the month name, as in "5 marca 1952"
+const POLISH_GENITIVE_MONTHS: [&str; 12] = [
+    "stycznia",
+    "lutego",
+    "marca",
+    "kwietnia",
+    "maja",
+    "czerwca",
+    "lipca",
+    "sierpnia",
+    "września",
+    "października",
+    "listopada",
+    "grudnia",
+];
+
+// Do not generate references for items with these (property, target) claims,
+// unless `referee.unsupported_entity_markers` is configured
 const UNSUPPORTED_ENTITY_MARKERS: &[(&str, &str)] = &[
     ("P31", "Q13442814"), // Scholarly article
     ("P31", "Q16521"),    // Taxon
@@ -92,7 +133,7 @@ const UNSUPPORTED_ENTITY_MARKERS: &[(&str, &str)] = &[
     ("P31", "Q5296"),     // main page
 ];
 
-// Do not create references for these properties
+// Do not create references for these properties, and those in `referee.no_refs_for_properties`
 const NO_REFS_FOR_PROPERTIES: &[&str] = &["P225", "P373", "P973", "P1472", "P1889"];
 
 // URLs containing any of these patterns will not be loaded
@@ -113,10 +154,509 @@ const BAD_PROP_STATEMENT: &[(&str, &str)] = &[
     ("P373", "."), // Commons category, no point checking any URL
 ];
 
+// Default maximum number of simultaneous URL fetches per Referee run
+const DEFAULT_MAX_CONCURRENT_FETCHES: usize = 8;
+
+// Maximum number of hosts whose robots.txt is kept in the cache
+const MAX_ROBOTS_TXT_HOSTS: usize = 1000;
+
+const GREGORIAN_CALENDAR: &str = "http://www.wikidata.org/entity/Q1985727";
+
+/// Default maximum age of the URL contents cache, in hours.
+const DEFAULT_URL_CACHE_TTL_HOURS: u64 = 24;
+
+/// Default maximum number of URLs whose contents are kept in memory
+const DEFAULT_URL_CACHE_MAX_ENTRIES: usize = 1000;
+
+// Characters of text kept before and after a match, by default and at most
+const DEFAULT_CONTEXT_CHARS: usize = 60;
+const MAX_CONTEXT_CHARS: usize = 300;
+
+// Pages with less text than this are treated as error pages
+const MIN_PAGE_TEXT_CHARS: usize = 50;
+
+// Phrases of "not found" pages that are served with a 2xx status,
+// unless `referee.soft_not_found_phrases` is configured
+const SOFT_NOT_FOUND_PHRASES: &[&str] = &[
+    "page not found",
+    "record not found",
+    "no record found",
+    "no records found",
+    "could not be found",
+    "does not exist",
+    "error 404",
+    "404 not found",
+    "seite nicht gefunden",
+    "nicht gefunden",
+    "existiert nicht",
+    "page introuvable",
+    "page non trouvée",
+    "n'existe pas",
+    "página no encontrada",
+    "no existe",
+    "pagina non trovata",
+    "pagina niet gevonden",
+];
+
+// Languages whose labels are used for item values without a label in the page language
+const DEFAULT_FALLBACK_LANGUAGES: &[&str] = &["en"];
+
+/// The robots.txt user agent token of the tool. Rules for `*` apply if there are none for it.
+const ROBOTS_TXT_AGENT: &str = "wd-infernal";
+
+const WIKIDATA_API_URL: &str = "https://www.wikidata.org/w/api.php";
+
 type UniqueUrlCandidates = HashMap<String, UrlCandidate>;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// The `Referee` does not generate references for the entity, because of a marker claim
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedEntity {
+    pub entity: String,
+    pub property: String,
+    pub target: String,
+}
+
+impl std::fmt::Display for UnsupportedEntity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} is not supported, because it has {}:{}",
+            self.entity, self.property, self.target
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedEntity {}
+
+/// Whether the `Referee` generates references for an entity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntitySupport {
+    Supported,
+    Missing,
+    Unsupported(UnsupportedEntity),
+}
+
+/// Limits the number of futures running at the same time, across all users of the limiter.
+#[derive(Debug, Clone)]
+pub struct FetchLimiter {
+    semaphore: Arc<Semaphore>,
+}
+
+impl FetchLimiter {
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_concurrent.max(1))),
+        }
+    }
+
+    /// Runs `future` once a slot is available.
+    pub async fn run<F: Future>(&self, future: F) -> F::Output {
+        // The semaphore is never closed, so acquiring can not fail
+        let _permit = self.semaphore.acquire().await.ok();
+        future.await
+    }
+}
+
+impl Default for FetchLimiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_CONCURRENT_FETCHES)
+    }
+}
+
+/// Caches robots.txt contents per origin (scheme, host, and port).
+/// A missing robots.txt is cached as an empty string, which allows everything.
+#[derive(Debug, Default)]
+pub struct RobotsTxtCache {
+    bodies: Mutex<HashMap<String, String>>,
+}
+
+impl RobotsTxtCache {
+    fn origin(url: &str) -> Option<String> {
+        let url = Url::parse(url).ok()?;
+        Some(url.origin().ascii_serialization())
+    }
+
+    /// Returns `None` if the robots.txt for the URL is not cached yet.
+    fn is_allowed(&self, url: &str) -> Option<bool> {
+        let origin = Self::origin(url)?;
+        let bodies = self.bodies.lock().ok()?;
+        let body = bodies.get(&origin)?;
+        Some(Self::is_allowed_by_robots_txt(body, url))
+    }
+
+    fn insert(&self, origin: String, body: String) {
+        if let Ok(mut bodies) = self.bodies.lock() {
+            if bodies.len() >= MAX_ROBOTS_TXT_HOSTS {
+                bodies.clear();
+            }
+            bodies.insert(origin, body);
+        }
+    }
+
+    fn is_allowed_by_robots_txt(robots_txt: &str, url: &str) -> bool {
+        robotstxt::DefaultMatcher::default().one_agent_allowed_by_robots(
+            robots_txt,
+            ROBOTS_TXT_AGENT,
+            url,
+        )
+    }
+}
+
+#[derive(Debug)]
+struct CachedContents {
+    contents: String,
+    cached: Instant,
+    last_used: u64,
+}
+
+/// URL contents in memory, with the order in which they were used
+#[derive(Debug, Default)]
+struct MemoryCache {
+    entries: HashMap<String, CachedContents>,
+    /// Incremented on each use, to find the least recently used entry
+    clock: u64,
+}
+
+impl MemoryCache {
+    fn get(&mut self, url: &str, ttl: Duration) -> Option<String> {
+        if self.entries.get(url)?.cached.elapsed() > ttl {
+            self.entries.remove(url);
+            return None;
+        }
+        self.clock += 1;
+        let entry = self.entries.get_mut(url)?;
+        entry.last_used = self.clock;
+        Some(entry.contents.clone())
+    }
+
+    /// Drops expired entries first, then the least recently used ones, to make room.
+    fn insert(&mut self, url: &str, contents: &str, ttl: Duration, max_entries: usize) {
+        if max_entries == 0 {
+            return;
+        }
+        if !self.entries.contains_key(url) && self.entries.len() >= max_entries {
+            self.entries
+                .retain(|_, entry| entry.cached.elapsed() <= ttl);
+        }
+        while !self.entries.contains_key(url) && self.entries.len() >= max_entries {
+            let least_recently_used = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(url, _)| url.to_owned());
+            let Some(least_recently_used) = least_recently_used else {
+                break;
+            };
+            self.entries.remove(&least_recently_used);
+        }
+        self.clock += 1;
+        let entry = CachedContents {
+            contents: contents.to_string(),
+            cached: Instant::now(),
+            last_used: self.clock,
+        };
+        self.entries.insert(url.to_string(), entry);
+    }
+}
+
+/// Caches the contents of fetched URLs in memory, for at most `ttl` and `max_entries` URLs,
+/// and optionally on disk across runs.
+/// Empty contents, like those of failed fetches, are not cached, so they are fetched again.
+#[derive(Debug)]
+pub struct UrlContentsCache {
+    contents: Mutex<MemoryCache>,
+    ttl: Duration,
+    max_entries: usize,
+    disk: Option<(PathBuf, Duration)>,
+}
+
+impl Default for UrlContentsCache {
+    fn default() -> Self {
+        Self::in_memory(
+            Duration::from_secs(DEFAULT_URL_CACHE_TTL_HOURS * 3600),
+            DEFAULT_URL_CACHE_MAX_ENTRIES,
+        )
+    }
+}
+
+impl UrlContentsCache {
+    /// Caches in memory only, for at most `ttl` and `max_entries` URLs.
+    pub fn in_memory(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            contents: Mutex::default(),
+            ttl,
+            max_entries,
+            disk: None,
+        }
+    }
+
+    /// Caches on disk in `directory`, for at most `ttl`.
+    pub fn on_disk(directory: impl Into<PathBuf>, ttl: Duration) -> Self {
+        Self {
+            disk: Some((directory.into(), ttl)),
+            ..Self::in_memory(ttl, DEFAULT_URL_CACHE_MAX_ENTRIES)
+        }
+    }
+
+    /// Reads `referee.url_cache_dir`, `referee.url_cache_ttl_hours`, and
+    /// `referee.url_cache_max_entries` from the configuration.
+    /// Without a cache directory, contents are only cached in memory.
+    pub fn from_config(config: &RefereeConfig) -> Self {
+        let hours = config
+            .url_cache_ttl_hours
+            .unwrap_or(DEFAULT_URL_CACHE_TTL_HOURS);
+        let ttl = Duration::from_secs(hours * 3600);
+        let max_entries = config
+            .url_cache_max_entries
+            .unwrap_or(DEFAULT_URL_CACHE_MAX_ENTRIES);
+        let disk = config
+            .url_cache_dir
+            .as_ref()
+            .map(|directory| (PathBuf::from(directory), ttl));
+        Self {
+            disk,
+            ..Self::in_memory(ttl, max_entries)
+        }
+    }
+
+    fn get(&self, url: &str) -> Option<String> {
+        if let Some(contents) = self.contents.lock().ok()?.get(url, self.ttl) {
+            return Some(contents);
+        }
+        let contents = self.load_from_disk(url)?;
+        self.insert_in_memory(url, &contents);
+        Some(contents)
+    }
+
+    fn insert(&self, url: &str, contents: &str) {
+        if contents.is_empty() {
+            return;
+        }
+        self.insert_in_memory(url, contents);
+        if let Err(e) = self.save_to_disk(url, contents) {
+            tracing::warn!("Could not write URL cache for {url}: {e}");
+        }
+    }
+
+    fn insert_in_memory(&self, url: &str, contents: &str) {
+        if let Ok(mut cached) = self.contents.lock() {
+            cached.insert(url, contents, self.ttl, self.max_entries);
+        }
+    }
+
+    /// The file name is a hash of the URL; the file starts with the URL itself,
+    /// to guard against hash collisions.
+    fn disk_path(&self, url: &str) -> Option<(PathBuf, Duration)> {
+        let (directory, ttl) = self.disk.as_ref()?;
+        let hash = url.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        Some((directory.join(format!("{hash:016x}.txt")), *ttl))
+    }
+
+    fn load_from_disk(&self, url: &str) -> Option<String> {
+        let (path, ttl) = self.disk_path(url)?;
+        let modified = std::fs::metadata(&path).ok()?.modified().ok()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        if age > ttl {
+            return None;
+        }
+        let file = std::fs::read_to_string(&path).ok()?;
+        let (cached_url, contents) = file.split_once('\n')?;
+        (cached_url == url).then(|| contents.to_string())
+    }
+
+    fn save_to_disk(&self, url: &str, contents: &str) -> Result<()> {
+        let Some((path, _ttl)) = self.disk_path(url) else {
+            return Ok(());
+        };
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        std::fs::write(path, format!("{url}\n{contents}"))?;
+        Ok(())
+    }
+}
+
+/// URLs that will not be loaded, on top of the built-in `BAD_URLS`.
+#[derive(Debug, Clone, Default)]
+pub struct UrlBlacklist {
+    substrings: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl UrlBlacklist {
+    pub fn new(substrings: Vec<String>, patterns: &[String]) -> Self {
+        let patterns = patterns
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(re) => Some(re),
+                Err(e) => {
+                    tracing::warn!("Skipping invalid URL blacklist pattern '{pattern}': {e}");
+                    None
+                }
+            })
+            .collect();
+        Self {
+            substrings,
+            patterns,
+        }
+    }
+
+    /// Reads `referee.bad_urls` (substrings) and `referee.bad_url_patterns` (regular expressions)
+    /// from the configuration.
+    pub fn from_config(config: &RefereeConfig) -> Self {
+        Self::new(config.bad_urls.clone(), &config.bad_url_patterns)
+    }
+
+    fn validate_url(&self, url: &str) -> Result<()> {
+        let is_bad = BAD_URLS.iter().any(|bad_url| url.contains(bad_url))
+            || self.substrings.iter().any(|bad_url| url.contains(bad_url))
+            || self.patterns.iter().any(|re| re.is_match(url));
+        if is_bad {
+            return Err(anyhow!("Bad URL"));
+        }
+        Ok(())
+    }
+}
+
+/// Detects "not found" and redirect pages that are served with a 2xx status,
+/// so generic page text does not produce matches.
+#[derive(Debug, Clone)]
+pub struct SoftNotFound {
+    phrases: Vec<String>,
+}
+
+impl Default for SoftNotFound {
+    fn default() -> Self {
+        Self::new(
+            SOFT_NOT_FOUND_PHRASES
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        )
+    }
+}
+
+impl SoftNotFound {
+    /// Phrases are matched ignoring case
+    pub fn new(phrases: Vec<String>) -> Self {
+        let phrases = phrases
+            .iter()
+            .map(|phrase| phrase.trim().to_lowercase())
+            .filter(|phrase| !phrase.is_empty())
+            .collect();
+        Self { phrases }
+    }
+
+    /// Reads `referee.soft_not_found_phrases` from the configuration.
+    pub fn from_config(config: &RefereeConfig) -> Self {
+        match &config.soft_not_found_phrases {
+            Some(phrases) => Self::new(phrases.clone()),
+            None => Self::default(),
+        }
+    }
+
+    /// Meta refresh redirects to another URL, pages with very little `text`, and pages
+    /// with a "not found" phrase in the title or a top-level heading of the `html`.
+    /// Phrases elsewhere in the page are ignored, as articles can use them too.
+    fn is_soft_not_found(&self, html: &str, text: &str) -> bool {
+        if Self::is_meta_refresh_redirect(html) {
+            return true;
+        }
+        if text.trim().chars().count() < MIN_PAGE_TEXT_CHARS {
+            return true;
+        }
+        let headings: Vec<String> = [&*RE_HTML_TITLE, &*RE_HTML_H1]
+            .iter()
+            .flat_map(|re| re.captures_iter(html))
+            .filter_map(|caps| caps.get(1))
+            .map(|heading| heading.as_str().to_lowercase())
+            .collect();
+        self.phrases
+            .iter()
+            .any(|phrase| headings.iter().any(|heading| heading.contains(phrase)))
+    }
+
+    fn is_meta_refresh_redirect(html: &str) -> bool {
+        RE_META_TAG.find_iter(html).any(|tag| {
+            RE_META_REFRESH.is_match(tag.as_str()) && RE_META_REFRESH_URL.is_match(tag.as_str())
+        })
+    }
+}
+
+/// What happened to a URL, for debugging missed references
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum UrlOutcome {
+    Blacklisted,
+    BlockedByRobotsTxt,
+    FetchFailed,
+    FetchEmpty,
+    SoftNotFound,
+    AlreadyReferenced,
+    BadCombination,
+    NoPatternMatch,
+    Matched,
+}
+
+/// The outcome for a URL, per statement once the page is loaded
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UrlDebugEntry {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statement_id: Option<String>,
+    pub outcome: UrlOutcome,
+    /// The detected language of the page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+/// The URL outcomes of referee runs. Only the first outcome for a URL and statement
+/// is kept, so a blacklisted URL is not also reported as empty.
+#[derive(Debug, Default)]
+pub struct DebugLog {
+    entries: Mutex<Vec<UrlDebugEntry>>,
+}
+
+impl DebugLog {
+    fn record(&self, entry: UrlDebugEntry) {
+        if let Ok(mut entries) = self.entries.lock() {
+            let known = entries
+                .iter()
+                .any(|e| e.url == entry.url && e.statement_id == entry.statement_id);
+            if !known {
+                entries.push(entry);
+            }
+        }
+    }
+
+    pub fn entries(&self) -> Vec<UrlDebugEntry> {
+        let mut entries = self
+            .entries
+            .lock()
+            .map(|entries| entries.clone())
+            .unwrap_or_default();
+        entries.sort();
+        entries
+    }
+
+    /// The number of entries per outcome, and the entries
+    pub fn summary(&self) -> Value {
+        let entries = self.entries();
+        let mut outcomes: BTreeMap<UrlOutcome, usize> = BTreeMap::new();
+        for entry in &entries {
+            *outcomes.entry(entry.outcome).or_default() += 1;
+        }
+        serde_json::json!({"outcomes": outcomes, "urls": entries})
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 enum UrlType {
+    #[default]
     WikiExternal,
     ExternalId,
     DirectWebsite,
@@ -139,6 +679,40 @@ pub struct UrlCandidate {
     stated_in: Option<String>,
     language: String,
     text: String,
+    /// The ISO 639-1 code of the "language of work or name" (P407) qualifier of a website
+    website_language: Option<String>,
+}
+
+/// An official website (P856) or "described at URL" (P973) with the language of its
+/// "language of work or name" (P407) qualifier, if any
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct DirectWebsite {
+    url: String,
+    property: &'static str,
+    language: Option<String>,
+}
+
+/// A search pattern for a statement value. Patterns from aliases are not `exact`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SearchPattern {
+    pattern: String,
+    exact: bool,
+}
+
+impl SearchPattern {
+    const fn exact(pattern: String) -> Self {
+        Self {
+            pattern,
+            exact: true,
+        }
+    }
+
+    const fn alias(pattern: String) -> Self {
+        Self {
+            pattern,
+            exact: false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd, Eq, Ord)]
@@ -148,22 +722,45 @@ pub struct TextPart {
     after: String,
 }
 
+/// A statement before and after adding the reference for a candidate, for previews
+#[derive(Debug, Clone, Serialize)]
+pub struct ReferencePreview {
+    pub statement_id: String,
+    pub before: Statement,
+    pub after: Statement,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConciseUrlCandidate {
     statement_id: String,
     url: String,
+    #[serde(skip)]
+    url_type: UrlType,
     property: Option<String>,
     external_id: Option<String>,
     stated_in: Option<String>,
     language: String,
     texts: Vec<TextPart>,
+    score: f32,
+    #[serde(skip)]
+    exact_match: bool,
+    /// The subject name as written on the page, for "subject named as" (P1810)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    named_as: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    website_language: Option<String>,
+    /// The website language matches the statement
+    #[serde(skip)]
+    language_match: bool,
 }
 
+/// Candidates are the same if they only differ in `texts` or `stated_in`, which is derived
+/// from `property`, or in the URL scheme.
 impl Ord for ConciseUrlCandidate {
     fn cmp(&self, other: &Self) -> Ordering {
         self.statement_id
             .cmp(&other.statement_id)
-            .then(self.url.cmp(&other.url))
+            .then(self.url_key().cmp(other.url_key()))
             .then(self.property.cmp(&other.property))
             .then(self.external_id.cmp(&other.external_id))
             .then(self.language.cmp(&other.language))
@@ -179,7 +776,7 @@ impl PartialOrd for ConciseUrlCandidate {
 impl PartialEq for ConciseUrlCandidate {
     fn eq(&self, other: &Self) -> bool {
         self.statement_id == other.statement_id
-            && self.url == other.url
+            && self.url_key() == other.url_key()
             && self.property == other.property
             && self.external_id == other.external_id
             && self.language == other.language
@@ -188,7 +785,104 @@ impl PartialEq for ConciseUrlCandidate {
 
 impl Eq for ConciseUrlCandidate {}
 
+impl TextPart {
+    pub fn before(&self) -> &str {
+        &self.before
+    }
+
+    pub fn regexp_match(&self) -> &str {
+        &self.regexp_match
+    }
+
+    pub fn after(&self) -> &str {
+        &self.after
+    }
+}
+
 impl ConciseUrlCandidate {
+    pub fn statement_id(&self) -> &str {
+        &self.statement_id
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn property(&self) -> Option<&str> {
+        self.property.as_deref()
+    }
+
+    pub fn texts(&self) -> &[TextPart] {
+        &self.texts
+    }
+
+    pub const fn score(&self) -> f32 {
+        self.score
+    }
+
+    pub fn named_as(&self) -> Option<&str> {
+        self.named_as.as_deref()
+    }
+
+    /// The reference for this candidate: the external ID (with "stated in", if known,
+    /// and "subject named as", if the subject name was found on the page)
+    /// or the reference URL, the retrieval date, and the infernal heuristic.
+    pub fn as_reference(&self) -> wikibase::Reference {
+        let mut snaks = vec![];
+        match (&self.url_type, &self.property, &self.external_id) {
+            (UrlType::ExternalId, Some(property), Some(external_id)) => {
+                if let Some(stated_in) = &self.stated_in {
+                    snaks.push(Snak::new_item("P248", stated_in));
+                }
+                snaks.push(Self::string_snak(
+                    SnakDataType::ExternalId,
+                    property,
+                    external_id,
+                ));
+                if let Some(named_as) = &self.named_as {
+                    snaks.push(Self::string_snak(SnakDataType::String, "P1810", named_as));
+                }
+            }
+            _ => snaks.push(Self::string_snak(SnakDataType::Url, "P854", &self.url)),
+        }
+        let today = chrono::Utc::now().format("+%Y-%m-%dT00:00:00Z").to_string();
+        let retrieved = TimeValue::new(0, 0, GREGORIAN_CALENDAR, 11, &today, 0);
+        snaks.push(Snak::new(
+            SnakDataType::Time,
+            "P813",
+            SnakType::Value,
+            Some(DataValue::new(
+                DataValueType::Time,
+                wikibase::Value::Time(retrieved),
+            )),
+        ));
+        snaks.push(Wikidata::infernal_reference_snak());
+        wikibase::Reference::new(snaks)
+    }
+
+    fn string_snak(datatype: SnakDataType, property: &str, value: &str) -> Snak {
+        Snak::new(
+            datatype,
+            property,
+            SnakType::Value,
+            Some(DataValue::new(
+                DataValueType::StringType,
+                wikibase::Value::StringValue(value.to_string()),
+            )),
+        )
+    }
+
+    /// The statement with the reference for this candidate added
+    fn preview(&self, statement: &Statement) -> ReferencePreview {
+        let mut after = statement.clone();
+        after.add_reference(self.as_reference());
+        ReferencePreview {
+            statement_id: self.statement_id.clone(),
+            before: statement.clone(),
+            after,
+        }
+    }
+
     fn new(statement_id: &str, uc: &UrlCandidate, tp: &TextPart) -> Self {
         Self {
             statement_id: statement_id.to_string(),
@@ -198,93 +892,416 @@ impl ConciseUrlCandidate {
             stated_in: uc.stated_in.clone(),
             language: uc.language.clone(),
             texts: vec![tp.clone()],
+            score: 0.0,
+            exact_match: false,
+            url_type: uc.url_type.clone(),
+            named_as: None,
+            website_language: uc.website_language.clone(),
+            language_match: false,
+        }
+    }
+
+    /// The URL without its scheme, so http and https variants compare equal
+    fn url_key(&self) -> &str {
+        self.url
+            .strip_prefix("https://")
+            .or_else(|| self.url.strip_prefix("http://"))
+            .unwrap_or(&self.url)
+    }
+
+    /// Lowercases the host, removes default ports, and removes the trailing slash of URLs
+    /// without query or fragment. Unparseable URLs are left as they are.
+    fn canonical_url(url: &str) -> String {
+        let Ok(parsed) = Url::parse(url) else {
+            return url.to_string();
+        };
+        let ret = parsed.to_string();
+        if parsed.query().is_none() && parsed.fragment().is_none() {
+            ret.trim_end_matches('/').to_string()
+        } else {
+            ret
         }
     }
+
+    /// Computes the match quality score:
+    /// - URL type: external ID 2.0 (+1.0 with a known "stated in"), official website 1.5, wiki external link 1.0
+    /// - 0.5 per distinct matching text, up to 5 texts
+    /// - 1.0 if the statement value matched via its label rather than an alias
+    /// - 0.5 if the website language matches the statement
+    fn compute_score(&mut self) {
+        let url_type_score = match self.url_type {
+            UrlType::ExternalId if self.stated_in.is_some() => 3.0,
+            UrlType::ExternalId => 2.0,
+            UrlType::DirectWebsite => 1.5,
+            UrlType::WikiExternal => 1.0,
+        };
+        let texts_score = self.texts.len().min(5) as f32 * 0.5;
+        let exact_score = if self.exact_match { 1.0 } else { 0.0 };
+        let language_score = if self.language_match { 0.5 } else { 0.0 };
+        self.score = url_type_score + texts_score + exact_score + language_score;
+    }
 }
 
-#[derive(Debug)]
+/// Clones share the loaded entities, the robots.txt and URL contents caches, and the
+/// fetch limit, so one `Referee` can be cloned for each item of a worklist.
+/// Properties are kept apart from items, so `with_fresh_items` can drop the items only.
+#[derive(Debug, Clone)]
 pub struct Referee {
     api: Api,
-    entities: EntityContainer,
-    client: Client,
+    entities: Arc<EntityContainer>,
+    properties: Arc<EntityContainer>,
+    fetcher: Arc<dyn ContentFetcher>,
+    url_blacklist: UrlBlacklist,
+    fetch_limiter: FetchLimiter,
+    robots_txt: Arc<RobotsTxtCache>,
+    url_contents: Arc<UrlContentsCache>,
+    property_filter: Option<HashSet<String>>,
+    fallback_languages: Vec<String>,
+    include_described_at: bool,
+    unsupported_entity_markers: Vec<(String, String)>,
+    no_refs_for_properties: HashSet<String>,
+    context_chars: usize,
+    soft_not_found: SoftNotFound,
+    /// Language for search patterns, instead of the detected language of each page
+    pattern_language: Option<String>,
+    debug_log: Option<Arc<DebugLog>>,
 }
 
 impl Referee {
     pub async fn new() -> Result<Self> {
-        let client = Client::builder()
-            .user_agent(
-                "Mozilla/5.0 (Windows; U; Windows NT 5.1; rv:1.7.3) Gecko/20041001 Firefox/0.10.1",
-            )
-            .timeout(std::time::Duration::from_secs(10))
-            .build()?;
+        Self::new_with_api_url(WIKIDATA_API_URL).await
+    }
 
+    /// Like `new`, but loads entities from another wiki API, like a test server
+    pub async fn new_with_api_url(api_url: &str) -> Result<Self> {
         Ok(Self {
-            api: Api::new("https://www.wikidata.org/w/api.php").await?,
-            entities: EntityContainer::new(),
-            client,
+            api: Api::new(api_url).await?,
+            entities: Arc::new(EntityContainer::new()),
+            properties: Arc::new(EntityContainer::new()),
+            fetcher: Arc::new(HttpFetcher::new()?),
+            url_blacklist: UrlBlacklist::from_config(Config::referee()),
+            fetch_limiter: FetchLimiter::new(
+                Config::referee()
+                    .max_concurrent_fetches
+                    .unwrap_or(DEFAULT_MAX_CONCURRENT_FETCHES),
+            ),
+            robots_txt: Arc::default(),
+            url_contents: Arc::new(UrlContentsCache::from_config(Config::referee())),
+            property_filter: None,
+            fallback_languages: Self::fallback_languages_from_config(Config::referee()),
+            include_described_at: true,
+            unsupported_entity_markers: Self::unsupported_entity_markers_from_config(
+                Config::referee(),
+            ),
+            no_refs_for_properties: Self::no_refs_for_properties_from_config(Config::referee()),
+            context_chars: DEFAULT_CONTEXT_CHARS,
+            soft_not_found: SoftNotFound::from_config(Config::referee()),
+            pattern_language: None,
+            debug_log: None,
         })
     }
 
-    fn validate_url(url: &str) -> Result<()> {
-        for bad_url in BAD_URLS {
-            if url.contains(bad_url) {
-                return Err(anyhow!("Bad URL"));
-            }
-        }
-        Ok(())
+    /// Sets the maximum number of URLs that are fetched at the same time,
+    /// across all phases of a run.
+    pub fn with_max_concurrent_fetches(mut self, max_concurrent: usize) -> Self {
+        self.fetch_limiter = FetchLimiter::new(max_concurrent);
+        self
     }
 
-    async fn load_contents_from_url(&self, url: &str) -> Result<String> {
-        Self::validate_url(url)?;
-        let url = url
-            .replace("&amp;", "&")
-            .trim()
-            .to_string()
-            .replace(" ", "%20");
+    /// Loads web pages and wiki API results with `fetcher`, instead of over HTTP
+    pub fn with_fetcher(mut self, fetcher: Arc<dyn ContentFetcher>) -> Self {
+        self.fetcher = fetcher;
+        self
+    }
 
-        let response = self.client.get(&url).send().await?;
-        let status = response.status();
+    pub fn with_url_blacklist(mut self, url_blacklist: UrlBlacklist) -> Self {
+        self.url_blacklist = url_blacklist;
+        self
+    }
 
-        if !status.is_success() {
-            return Ok(String::new());
-        }
+    pub fn with_soft_not_found(mut self, soft_not_found: SoftNotFound) -> Self {
+        self.soft_not_found = soft_not_found;
+        self
+    }
 
-        let content_type = response
-            .headers()
-            .get("content-type")
-            .map_or(String::new(), |ct| ct.to_str().unwrap_or("").to_string());
+    pub fn with_url_contents_cache(mut self, url_contents: UrlContentsCache) -> Self {
+        self.url_contents = Arc::new(url_contents);
+        self
+    }
 
-        if content_type.is_empty() {
-            return Ok(String::new());
-        }
+    /// Loads items again, instead of sharing those loaded by other clones, so edits since
+    /// then are seen. Loaded properties are still shared.
+    pub fn with_fresh_items(mut self) -> Self {
+        self.entities = Arc::new(EntityContainer::new());
+        self
+    }
 
-        let content = response.text().await?;
-        Ok(content)
+    /// Languages to take item labels and aliases from, in order, if the page language has no
+    /// label. `mul` labels are always used first.
+    pub fn with_fallback_languages(mut self, languages: Vec<String>) -> Self {
+        self.fallback_languages = languages;
+        self
     }
 
-    async fn get_contents_from_url(&self, url: &str) -> String {
-        self.load_contents_from_url(url).await.unwrap_or_default()
+    /// Reads `referee.fallback_languages` from the configuration.
+    fn fallback_languages_from_config(config: &RefereeConfig) -> Vec<String> {
+        match &config.fallback_languages {
+            Some(languages) => languages.clone(),
+            None => DEFAULT_FALLBACK_LANGUAGES
+                .iter()
+                .map(|language| language.to_string())
+                .collect(),
+        }
     }
 
-    // Statements methods
-    async fn get_statements_needing_references(
-        &mut self,
-        entity: &str,
-    ) -> Result<Vec<EntityStatement>> {
-        let mut ret = Vec::new();
-        let entity = entity.trim().to_uppercase();
-        self.entities.load_entity(&self.api, &entity).await?;
+    /// No references are generated for items with any of these (property, target) claims
+    pub fn with_unsupported_entity_markers(mut self, markers: Vec<(String, String)>) -> Self {
+        self.unsupported_entity_markers = markers;
+        self
+    }
 
-        let item = match self.entities.get_entity(&entity) {
-            Some(i) => i,
-            None => return Ok(ret),
+    /// Reads `referee.unsupported_entity_markers`, as `[property, target]` pairs,
+    /// from the configuration.
+    fn unsupported_entity_markers_from_config(config: &RefereeConfig) -> Vec<(String, String)> {
+        match &config.unsupported_entity_markers {
+            Some(markers) => markers
+                .iter()
+                .map(|(property, target)| (property.to_uppercase(), target.to_uppercase()))
+                .collect(),
+            None => UNSUPPORTED_ENTITY_MARKERS
+                .iter()
+                .map(|(property, target)| (property.to_string(), target.to_string()))
+                .collect(),
+        }
+    }
+
+    /// No references are generated for statements with `property`, in addition to the
+    /// configured ones
+    pub fn with_no_refs_for_property(mut self, property: &str) -> Self {
+        self.no_refs_for_properties.insert(property.to_uppercase());
+        self
+    }
+
+    /// Reads `referee.no_refs_for_properties` from the configuration, in addition to the
+    /// built-in properties, or instead of them if `referee.replace_no_refs_for_properties`
+    /// is set. Entries that are not property IDs are skipped.
+    fn no_refs_for_properties_from_config(config: &RefereeConfig) -> HashSet<String> {
+        let mut ret: HashSet<String> = if config.replace_no_refs_for_properties {
+            HashSet::new()
+        } else {
+            NO_REFS_FOR_PROPERTIES
+                .iter()
+                .map(|property| property.to_string())
+                .collect()
         };
+        for property in &config.no_refs_for_properties {
+            let property = property.trim().to_uppercase();
+            if RE_PROPERTY_ID.is_match(&property) {
+                ret.insert(property);
+            } else {
+                tracing::warn!("Skipping invalid property in no_refs_for_properties: '{property}'");
+            }
+        }
+        ret
+    }
 
-        let claims = item.claims();
+    /// Whether the "described at URL" (P973) pages of the item are used as candidates,
+    /// which is the default. They describe the item, but are often not sources for
+    /// its statements.
+    pub const fn with_described_at(mut self, include_described_at: bool) -> Self {
+        self.include_described_at = include_described_at;
+        self
+    }
+
+    /// Only looks for references for statements with these properties.
+    /// Use `parse_properties` to validate user input.
+    pub fn with_properties(mut self, properties: HashSet<String>) -> Self {
+        self.property_filter = Some(properties);
+        self
+    }
+
+    /// Keeps up to `context_chars` characters before and after each match, for review.
+    /// At most `MAX_CONTEXT_CHARS` (300).
+    pub fn with_context_chars(mut self, context_chars: usize) -> Result<Self> {
+        if context_chars > MAX_CONTEXT_CHARS {
+            return Err(anyhow!(
+                "Context of {context_chars} characters is more than {MAX_CONTEXT_CHARS}"
+            ));
+        }
+        self.context_chars = context_chars;
+        Ok(self)
+    }
+
+    /// Generates search patterns, like dates with month names, in `language` for all pages.
+    /// The detected page language is still used otherwise, like for language matches.
+    pub fn with_pattern_language(mut self, language: &str) -> Result<Self> {
+        let language = language.trim().to_lowercase();
+        if language.is_empty() || !language.chars().all(|c| c.is_ascii_lowercase() || c == '-') {
+            return Err(anyhow!("Invalid language: {language}"));
+        }
+        self.pattern_language = Some(language);
+        Ok(self)
+    }
+
+    /// Records the outcome for each URL in a new `DebugLog`, shared with clones.
+    /// Outcomes are also logged at debug level, with or without a `DebugLog`.
+    pub fn with_debug_log(mut self) -> Self {
+        self.debug_log = Some(Arc::new(DebugLog::default()));
+        self
+    }
+
+    pub fn debug_log(&self) -> Option<&DebugLog> {
+        self.debug_log.as_deref()
+    }
+
+    fn record_outcome(
+        &self,
+        url: &str,
+        statement_id: Option<&str>,
+        outcome: UrlOutcome,
+        language: Option<&str>,
+    ) {
+        tracing::debug!(
+            url,
+            ?statement_id,
+            ?outcome,
+            ?language,
+            "Referee URL outcome"
+        );
+        if let Some(debug_log) = &self.debug_log {
+            debug_log.record(UrlDebugEntry {
+                url: url.to_string(),
+                statement_id: statement_id.map(str::to_string),
+                outcome,
+                language: language.map(str::to_string),
+            });
+        }
+    }
+
+    /// Matches `pattern` with up to `context_chars` characters before and after it
+    fn context_regex(pattern: &str, context_chars: usize) -> Option<Regex> {
+        let context = format!(".{{0,{context_chars}}}");
+        Regex::new(&format!(r"\b({context})\b({pattern})\b({context})\b")).ok()
+    }
+
+    /// Parses a comma-separated list of property IDs, like "P19,P569".
+    /// Properties that this `Referee` never creates references for are dropped.
+    pub fn parse_properties(&self, list: &str) -> Result<HashSet<String>> {
+        let mut ret = HashSet::new();
+        for property in list.split(',') {
+            let property = property.trim().to_uppercase();
+            if property.is_empty() {
+                continue;
+            }
+            if !RE_PROPERTY_ID.is_match(&property) {
+                return Err(anyhow!("Invalid property ID: {property}"));
+            }
+            if !self.no_refs_for_properties.contains(&property) {
+                ret.insert(property);
+            }
+        }
+        Ok(ret)
+    }
+
+    fn validate_url(&self, url: &str) -> Result<()> {
+        self.url_blacklist.validate_url(url)
+    }
+
+    async fn load_contents_from_url(&self, url: &str) -> Result<String> {
+        self.validate_url(url)
+            .inspect_err(|_| self.record_outcome(url, None, UrlOutcome::Blacklisted, None))?;
+        let raw_url = url;
+        let url = url
+            .replace("&amp;", "&")
+            .trim()
+            .to_string()
+            .replace(" ", "%20");
+
+        if let Some(contents) = self.url_contents.get(&url) {
+            return Ok(contents);
+        }
+        if !self.is_allowed_by_robots_txt(&url).await {
+            self.record_outcome(raw_url, None, UrlOutcome::BlockedByRobotsTxt, None);
+            return Ok(String::new());
+        }
+        let contents = self
+            .fetch_limiter
+            .run(self.fetcher.fetch(&url))
+            .await
+            .inspect_err(|_| self.record_outcome(raw_url, None, UrlOutcome::FetchFailed, None))?;
+        self.url_contents.insert(&url, &contents);
+        Ok(contents)
+    }
+
+    /// Checks the robots.txt of the URL host, loading it if necessary.
+    async fn is_allowed_by_robots_txt(&self, url: &str) -> bool {
+        if let Some(allowed) = self.robots_txt.is_allowed(url) {
+            return allowed;
+        }
+        let Some(origin) = RobotsTxtCache::origin(url) else {
+            return true;
+        };
+        let robots_url = format!("{origin}/robots.txt");
+        let body = self
+            .fetch_limiter
+            .run(self.fetcher.fetch(&robots_url))
+            .await
+            .unwrap_or_default();
+        let allowed = RobotsTxtCache::is_allowed_by_robots_txt(&body, url);
+        self.robots_txt.insert(origin, body);
+        allowed
+    }
+
+    async fn get_contents_from_url(&self, url: &str) -> String {
+        self.load_contents_from_url(url).await.unwrap_or_default()
+    }
+
+    /// The text of a loaded page, unless it is empty or a "not found" page
+    fn page_text(&self, url: &str, contents: &str) -> Option<String> {
+        if contents.is_empty() {
+            self.record_outcome(url, None, UrlOutcome::FetchEmpty, None);
+            return None;
+        }
+        let text = Self::html2text(contents);
+        if self.soft_not_found.is_soft_not_found(contents, &text) {
+            self.record_outcome(url, None, UrlOutcome::SoftNotFound, None);
+            return None;
+        }
+        Some(text)
+    }
+
+    // Statements methods
+    async fn get_statements_needing_references(
+        &mut self,
+        entity: &str,
+    ) -> Result<Vec<EntityStatement>> {
+        let entity = entity.trim().to_uppercase();
+        self.entities.load_entity(&self.api, &entity).await?;
+
+        let item = match self.entities.get_entity(&entity) {
+            Some(i) => i,
+            None => return Ok(vec![]),
+        };
 
+        Ok(self.statements_needing_references(&entity, item.claims()))
+    }
+
+    fn statements_needing_references(
+        &self,
+        entity: &str,
+        claims: &[Statement],
+    ) -> Vec<EntityStatement> {
+        let mut ret = Vec::new();
         for claim in claims {
             let property = claim.property();
-            if NO_REFS_FOR_PROPERTIES.contains(&property) {
+            if self.no_refs_for_properties.contains(property) {
+                continue;
+            }
+            if self
+                .property_filter
+                .as_ref()
+                .is_some_and(|filter| !filter.contains(property))
+            {
                 continue;
             }
 
@@ -295,7 +1312,7 @@ impl Referee {
             }
 
             let statement = EntityStatement {
-                entity: entity.clone(),
+                entity: entity.to_string(),
                 property: property.to_string(),
                 id: claim.id().unwrap_or_default(),
                 claim: claim.clone(),
@@ -303,8 +1320,7 @@ impl Referee {
 
             ret.push(statement);
         }
-
-        Ok(ret)
+        ret
     }
 
     fn html2text(html: &str) -> String {
@@ -340,20 +1356,21 @@ impl Referee {
         RE_SPACES.replace_all(&ret, " ").into_owned()
     }
 
+    /// Guesses the language of a page text, as ISO 639-1 code.
+    /// Falls back to "en" if the text is too short, or the detector is not confident enough.
     fn guess_page_language_from_text(text: &str) -> String {
-        let candidates: &[(&str, &Regex)] = &[
-            ("en", &RE_LANG_EN),
-            ("de", &RE_LANG_DE),
-            ("it", &RE_LANG_IT),
-            ("fr", &RE_LANG_FR),
-            ("es", &RE_LANG_ES),
-        ];
-        candidates
-            .iter()
-            .map(|(lang, re)| (*lang, re.find_iter(text).count()))
-            .filter(|(_lang, count)| *count > 0)
-            .max_by_key(|(_lang, count)| *count)
-            .map_or_else(|| "en".to_string(), |(lang, _)| lang.to_string())
+        let text = text.trim();
+        if text.chars().count() < MIN_LANGUAGE_DETECTION_TEXT_LENGTH {
+            return DEFAULT_LANGUAGE.to_string();
+        }
+        let text: String = text
+            .chars()
+            .take(MAX_LANGUAGE_DETECTION_TEXT_LENGTH)
+            .collect();
+        LANGUAGE_DETECTOR.detect_language_of(text).map_or_else(
+            || DEFAULT_LANGUAGE.to_string(),
+            |language| language.iso_code_639_1().to_string(),
+        )
     }
 
     async fn get_candidate_urls_from_wikis(&self, entity: &str) -> UniqueUrlCandidates {
@@ -442,10 +1459,7 @@ impl Referee {
 
     async fn generate_url_candidate(&self, url: &str) -> Option<UrlCandidate> {
         let contents = self.get_contents_from_url(url).await;
-        if contents.is_empty() {
-            return None;
-        }
-        let text = Self::html2text(&contents);
+        let text = self.page_text(url, &contents)?;
         let language = Self::guess_page_language_from_text(&text);
         let ret = UrlCandidate {
             url: url.to_string(),
@@ -455,12 +1469,21 @@ impl Referee {
             stated_in: None,
             language,
             text,
+            website_language: None,
         };
         Some(ret)
     }
 
     // Helper method: get web server for wiki
-    fn get_web_server_for_wiki(wiki: &str) -> String {
+    pub(crate) fn get_web_server_for_wiki(wiki: &str) -> String {
+        // Wikimedia wikis that are not language editions
+        match wiki {
+            "commonswiki" => return "commons.wikimedia.org".to_string(),
+            "specieswiki" => return "species.wikimedia.org".to_string(),
+            "metawiki" => return "meta.wikimedia.org".to_string(),
+            _ => {}
+        }
+
         let lang = wiki.split("wik").next().unwrap_or("");
 
         if wiki.ends_with("wiki") {
@@ -471,6 +1494,14 @@ impl Referee {
             format!("{lang}.wiktionary.org")
         } else if wiki.ends_with("wikiquote") {
             format!("{lang}.wikiquote.org")
+        } else if wiki.ends_with("wikibooks") {
+            format!("{lang}.wikibooks.org")
+        } else if wiki.ends_with("wikinews") {
+            format!("{lang}.wikinews.org")
+        } else if wiki.ends_with("wikivoyage") {
+            format!("{lang}.wikivoyage.org")
+        } else if wiki.ends_with("wikiversity") {
+            format!("{lang}.wikiversity.org")
         } else {
             format!("{lang}.wikipedia.org") // Default fallback
         }
@@ -505,27 +1536,50 @@ impl Referee {
 
     async fn add_stated_in(&self, concise_urls: &mut HashMap<String, UrlCandidate>) -> Result<()> {
         // Ensure all used properties are loaded
-        let mut properties: Vec<String> = concise_urls
-            .iter()
-            .filter_map(|(_k, v)| v.property.to_owned())
-            .filter(|p| !self.entities.has_entity(p))
-            .collect();
-        properties.sort();
-        properties.dedup();
-        self.entities.load_entities(&self.api, &properties).await?;
+        let properties = self.unloaded_properties(
+            concise_urls
+                .values()
+                .filter(|uc| uc.url_type == UrlType::ExternalId)
+                .filter_map(|uc| uc.property.as_deref()),
+        );
+        self.properties
+            .load_entities(&self.api, &properties)
+            .await?;
 
         // Add "stated in" where possible
-        concise_urls.iter_mut().for_each(|(_k, uc)| {
-            if let Some(stated_in) = self.add_stated_in_to_url_candidate(uc) {
-                uc.stated_in = Some(stated_in);
-            }
-        });
+        concise_urls
+            .values_mut()
+            .filter(|uc| uc.url_type == UrlType::ExternalId)
+            .for_each(|uc| {
+                if let Some(stated_in) = self.add_stated_in_to_url_candidate(uc) {
+                    uc.stated_in = Some(stated_in);
+                }
+            });
+        Ok(())
+    }
+
+    /// The properties that are not loaded yet, by this or an earlier run
+    fn unloaded_properties<'a>(&self, properties: impl Iterator<Item = &'a str>) -> Vec<String> {
+        let mut ret: Vec<String> = properties
+            .filter(|p| !self.properties.has_entity(*p))
+            .map(str::to_string)
+            .collect();
+        ret.sort();
+        ret.dedup();
+        ret
+    }
+
+    /// Loads the entity again, in case it was edited since an earlier run.
+    /// Referenced items and properties stay cached.
+    async fn reload_entity(&self, entity: &str) -> Result<()> {
+        self.entities.remove_entity(entity);
+        self.entities.load_entity(&self.api, entity).await?;
         Ok(())
     }
 
     fn add_stated_in_to_url_candidate(&self, uc: &mut UrlCandidate) -> Option<String> {
         let property = uc.property.as_ref()?;
-        let prop = self.entities.get_entity(property)?;
+        let prop = self.properties.get_entity(property)?;
         let claims = prop.claims_with_property("P9073");
         let claim = claims.first()?;
         let dv = claim.main_snak().data_value().as_ref()?;
@@ -569,7 +1623,7 @@ impl Referee {
             .collect::<Vec<String>>();
         properties.sort();
         properties.dedup();
-        match self.entities.load_entities(&self.api, &properties).await {
+        match self.properties.load_entities(&self.api, &properties).await {
             Ok(_) => {}
             Err(_) => return HashMap::new(),
         }
@@ -578,7 +1632,7 @@ impl Referee {
         let mut url_in_use = HashSet::new();
         for (property, external_id) in &prop_id {
             // Get property formatter URL
-            let ip = match self.entities.get_entity(property) {
+            let ip = match self.properties.get_entity(property) {
                 Some(i) => i,
                 None => continue,
             };
@@ -614,10 +1668,7 @@ impl Referee {
         url: String,
     ) -> Option<UrlCandidate> {
         let contents = self.get_contents_from_url(&url).await;
-        if contents.is_empty() {
-            return None;
-        }
-        let text = Self::html2text(&contents);
+        let text = self.page_text(&url, &contents)?;
         let language = Self::guess_page_language_from_text(&text);
         let ret = UrlCandidate {
             url,
@@ -627,6 +1678,7 @@ impl Referee {
             stated_in: None,
             language,
             text,
+            website_language: None,
         };
         Some(ret)
     }
@@ -644,44 +1696,93 @@ impl Referee {
             })
             .collect()
     }
+
+    /// The URL values of `property`, with the language of their P407 qualifier
+    fn get_direct_websites_for_property(
+        item: &Entity,
+        property: &'static str,
+    ) -> Vec<DirectWebsite> {
+        item.claims_with_property(property)
+            .into_iter()
+            .filter_map(|claim| {
+                let url = match claim.main_snak().data_value().as_ref()?.value() {
+                    wikibase::Value::StringValue(url) => url.to_owned(),
+                    _ => return None,
+                };
+                let language = claim
+                    .qualifiers()
+                    .iter()
+                    .filter(|snak| snak.property() == "P407")
+                    .find_map(|snak| match snak.data_value().as_ref()?.value() {
+                        wikibase::Value::Entity(ev) => Wikidata::language_code(ev.id()),
+                        _ => None,
+                    })
+                    .map(str::to_string);
+                Some(DirectWebsite {
+                    url,
+                    property,
+                    language,
+                })
+            })
+            .collect()
+    }
+
+    /// Official websites (P856), and "described at URL" (P973) if included.
+    /// A URL that is both is kept as official website.
+    fn direct_website_urls(
+        official_websites: Vec<DirectWebsite>,
+        described_at_url: Vec<DirectWebsite>,
+        include_described_at: bool,
+    ) -> Vec<DirectWebsite> {
+        let described_at_url = described_at_url
+            .into_iter()
+            .filter(|_| include_described_at);
+        let mut websites: Vec<_> = official_websites
+            .into_iter()
+            .chain(described_at_url)
+            .collect();
+        websites.sort();
+        websites.dedup_by(|a, b| a.url == b.url);
+        websites
+    }
+
     async fn get_direct_websites(&self, entity: &str) -> UniqueUrlCandidates {
         let item = match self.entities.get_entity(entity) {
             Some(i) => i,
             None => return HashMap::new(),
         };
-        let official_websites = Self::get_string_values_for_property(&item, "P856");
-        let described_at_url = Self::get_string_values_for_property(&item, "P973");
-        let mut websites: Vec<_> = official_websites
-            .into_iter()
-            .chain(described_at_url.into_iter())
-            .collect();
-        websites.sort();
-        websites.dedup();
+        let official_websites = Self::get_direct_websites_for_property(&item, "P856");
+        let described_at_url = Self::get_direct_websites_for_property(&item, "P973");
+        let websites = Self::direct_website_urls(
+            official_websites,
+            described_at_url,
+            self.include_described_at,
+        );
         let mut futures = vec![];
         for website in &websites {
-            let future = self.get_contents_from_url(website);
+            let future = self.get_contents_from_url(&website.url);
             futures.push(future);
         }
         let ret: UniqueUrlCandidates = join_all(futures)
             .await
             .into_iter()
             .zip(websites)
-            .filter(|(html, _url)| !html.is_empty())
-            .map(|(html, url)| {
-                let text = Self::html2text(&html);
+            .filter_map(|(html, website)| {
+                let text = self.page_text(&website.url, &html)?;
                 let language = Self::guess_page_language_from_text(&text);
-                (
-                    url.to_string(),
+                Some((
+                    website.url.clone(),
                     UrlCandidate {
-                        url: url.to_string(),
+                        url: website.url,
                         url_type: UrlType::DirectWebsite,
-                        property: None,
+                        property: Some(website.property.to_string()),
                         external_id: None,
                         stated_in: None,
                         language,
                         text,
+                        website_language: website.language,
                     },
-                )
+                ))
             })
             .collect();
         ret
@@ -691,10 +1792,10 @@ impl Referee {
         &self,
         statement: &EntityStatement,
         language: &str,
-    ) -> Result<Vec<String>> {
+    ) -> Result<Vec<SearchPattern>> {
         let mut ret = Vec::new();
 
-        if NO_REFS_FOR_PROPERTIES.contains(&statement.property.as_str()) {
+        if self.no_refs_for_properties.contains(&statement.property) {
             return Ok(ret);
         }
 
@@ -722,7 +1823,14 @@ impl Referee {
 
                     if precision == 9 {
                         // Year precision
-                        ret.push(year.to_string());
+                        ret.push(SearchPattern::exact(year.to_string()));
+                    } else if precision == 10 {
+                        // Month precision
+                        let month_num = month.parse::<u32>().unwrap_or(1);
+                        let year_num = year.parse::<i32>().unwrap_or(2000);
+                        let month_patterns =
+                            Self::get_month_patterns(language, year_num, month_num);
+                        ret.extend(month_patterns.into_iter().map(SearchPattern::exact));
                     } else if precision == 11 {
                         // Day precision
                         let month_num = month.parse::<u32>().unwrap_or(1);
@@ -730,23 +1838,23 @@ impl Referee {
                         let year_num = year.parse::<i32>().unwrap_or(2000);
 
                         // Add ISO format
-                        ret.push(format!("{year}-{month}-{day}"));
+                        ret.push(SearchPattern::exact(format!("{year}-{month}-{day}")));
 
                         // Add locale-specific formats
                         let date_patterns =
                             Self::get_date_patterns(language, year_num, month_num, day_num);
-                        ret.extend(date_patterns);
+                        ret.extend(date_patterns.into_iter().map(SearchPattern::exact));
                     }
                 }
             }
             DataValueType::StringType => {
                 if let wikibase::Value::StringValue(string_val) = value {
-                    ret.push(string_val.to_string());
+                    ret.push(SearchPattern::exact(string_val.to_string()));
                 }
             }
             DataValueType::MonoLingualText => {
                 if let wikibase::Value::MonoLingual(mono_text) = value {
-                    ret.push(mono_text.text().to_string());
+                    ret.push(SearchPattern::exact(mono_text.text().to_string()));
                 }
             }
             DataValueType::EntityId => {
@@ -757,39 +1865,8 @@ impl Referee {
                         Some(i) => i,
                         None => return Ok(ret),
                     };
-                    let mut aliases: Vec<String> = vi
-                        .aliases()
-                        .iter()
-                        .filter(|s| s.language() == language)
-                        .map(|s| s.value().to_owned())
-                        .collect();
-                    let label_mul: Option<String> = vi
-                        .labels()
-                        .iter()
-                        .filter(|s| s.language() == "mul")
-                        .map(|s| s.value().to_owned())
-                        .next();
-                    let label_opt: Option<String> = vi
-                        .labels()
-                        .iter()
-                        .filter(|s| s.language() == language)
-                        .map(|s| s.value().to_owned())
-                        .next();
-
-                    if let Some(label) = label_opt {
-                        aliases.insert(0, label); // Make label first entry
-                    }
-                    if let Some(label) = label_mul {
-                        aliases.insert(0, label); // Make label first entry
-                    }
-
-                    for alias in aliases {
-                        let alias_quoted = regex::escape(alias.trim());
-                        if alias_quoted.len() < 3 {
-                            continue;
-                        }
-                        ret.push(alias_quoted);
-                    }
+                    let languages = self.language_chain(language);
+                    ret.extend(Self::name_patterns(vi.labels(), vi.aliases(), &languages));
                 }
             }
             DataValueType::GlobeCoordinate => {
@@ -806,6 +1883,107 @@ impl Referee {
         Ok(ret)
     }
 
+    /// The page language, followed by the fallback languages
+    fn language_chain<'a>(&'a self, language: &'a str) -> Vec<&'a str> {
+        let mut ret = vec![language];
+        for fallback in &self.fallback_languages {
+            if !ret.contains(&fallback.as_str()) {
+                ret.push(fallback.as_str());
+            }
+        }
+        ret
+    }
+
+    /// Patterns for the `mul` label, and the label and aliases in the first of `languages`
+    /// that has a label.
+    fn name_patterns(
+        labels: &[LocaleString],
+        aliases: &[LocaleString],
+        languages: &[&str],
+    ) -> Vec<SearchPattern> {
+        let label_in = |language: &str| {
+            labels
+                .iter()
+                .find(|s| s.language() == language)
+                .map(|s| s.value().to_owned())
+        };
+        let label_mul = label_in("mul");
+        let found = languages
+            .iter()
+            .find_map(|language| Some((*language, label_in(language)?)));
+        let (language, label) = match found {
+            Some((language, label)) => (Some(language), Some(label)),
+            None => (languages.first().copied(), None),
+        };
+        let aliases = aliases
+            .iter()
+            .filter(|s| Some(s.language()) == language)
+            .map(|s| s.value().to_owned());
+
+        // Labels first, then aliases
+        let names = label_mul
+            .into_iter()
+            .chain(label)
+            .map(|label| (label, true))
+            .chain(aliases.map(|alias| (alias, false)));
+        let mut ret = vec![];
+        for (name, is_label) in names {
+            let name_quoted = regex::escape(name.trim());
+            if name_quoted.len() < 3 {
+                continue;
+            }
+            if is_label {
+                ret.push(SearchPattern::exact(name_quoted));
+            } else {
+                ret.push(SearchPattern::alias(name_quoted));
+            }
+        }
+        ret
+    }
+
+    /// The first of `patterns` found on its own, ignoring case, in `text`, as written there
+    fn find_named_as(patterns: &[SearchPattern], text: &str) -> Option<String> {
+        patterns.iter().find_map(|sp| {
+            let re = RegexBuilder::new(&format!(r"\b{}\b", sp.pattern))
+                .case_insensitive(true)
+                .build()
+                .ok()?;
+            Some(re.find(text)?.as_str().to_string())
+        })
+    }
+
+    /// Whether the website is qualified with the language of the statement value, or, for
+    /// values without a language, with the language of the page
+    fn is_website_language_match(
+        statement: &EntityStatement,
+        url_candidate: &UrlCandidate,
+    ) -> bool {
+        let Some(website_language) = &url_candidate.website_language else {
+            return false;
+        };
+        let value = statement.claim.main_snak().data_value().as_ref();
+        let value_language = match value.map(|dv| dv.value()) {
+            Some(wikibase::Value::MonoLingual(text)) => text.language(),
+            _ => url_candidate.language.as_str(),
+        };
+        website_language.as_str() == value_language
+    }
+
+    /// How the subject of `statement` is named on the page of an external ID candidate
+    fn subject_named_as(
+        &self,
+        statement: &EntityStatement,
+        url_candidate: &UrlCandidate,
+    ) -> Option<String> {
+        if url_candidate.url_type != UrlType::ExternalId {
+            return None;
+        }
+        let subject = self.entities.get_entity(&statement.entity)?;
+        let languages = self.language_chain(&url_candidate.language);
+        let patterns = Self::name_patterns(subject.labels(), subject.aliases(), &languages);
+        Self::find_named_as(&patterns, &url_candidate.text)
+    }
+
     fn snak_string_values<'a>(
         snaks: &'a [Snak],
         property: &'a str,
@@ -819,6 +1997,19 @@ impl Referee {
             })
     }
 
+    fn snak_entity_values<'a>(
+        snaks: &'a [Snak],
+        property: &'a str,
+    ) -> impl Iterator<Item = &'a str> {
+        snaks
+            .iter()
+            .filter(move |snak| snak.property() == property)
+            .filter_map(|snak| match snak.data_value().as_ref()?.value() {
+                wikibase::Value::Entity(ev) => Some(ev.id()),
+                _ => None,
+            })
+    }
+
     fn does_statement_have_this_reference(
         statement: &EntityStatement,
         url_candidate: &UrlCandidate,
@@ -842,77 +2033,194 @@ impl Referee {
                 }
             }
 
+            // Check for "stated in" (P248)
+            if let Some(stated_in) = &url_candidate.stated_in {
+                if Self::snak_entity_values(snaks, "P248").any(|q| q == stated_in) {
+                    return true;
+                }
+            }
+
             false
         })
     }
 
-    async fn is_supported_entity(&mut self, entity: &str) -> Result<bool> {
+    /// Checks the entity against the unsupported entity markers, loading it if needed
+    pub async fn entity_support(&mut self, entity: &str) -> Result<EntitySupport> {
         self.entities.load_entity(&self.api, entity).await?;
 
         let item = match self.entities.get_entity(entity) {
             Some(i) => i,
-            None => return Ok(false),
+            None => return Ok(EntitySupport::Missing),
         };
 
-        for (property, target) in UNSUPPORTED_ENTITY_MARKERS {
-            if item.has_target_entity(*property, *target) {
-                return Ok(false);
-            }
-        }
-
-        Ok(true)
+        let marker = self
+            .unsupported_entity_markers
+            .iter()
+            .find(|(property, target)| item.has_target_entity(property, target));
+        Ok(match marker {
+            Some((property, target)) => EntitySupport::Unsupported(UnsupportedEntity {
+                entity: entity.to_string(),
+                property: property.to_owned(),
+                target: target.to_owned(),
+            }),
+            None => EntitySupport::Supported,
+        })
     }
 
     pub async fn get_potential_references(
         &mut self,
         entity: &str,
     ) -> Result<Vec<ConciseUrlCandidate>> {
-        let entity = entity.trim().to_uppercase();
-
-        if !self.is_supported_entity(&entity).await? {
-            return Ok(vec![]);
-        }
-
-        let statements = self.get_statements_needing_references(&entity).await?;
-        if statements.is_empty() {
-            return Ok(vec![]);
-        }
-
-        let url_candidates = self.get_candidate_urls(&entity).await?;
-        if url_candidates.is_empty() {
+        let Some((statements, url_candidates)) = self.prepare_statements(entity).await? else {
             return Ok(vec![]);
-        }
+        };
 
         let mut futures = vec![];
         for statement in &statements {
             let future = self.process_statement(statement, &url_candidates);
             futures.push(future);
         }
-        let mut ret: Vec<ConciseUrlCandidate> = join_all(futures)
+        let ret: Vec<ConciseUrlCandidate> = join_all(futures)
             .await
             .into_iter()
             .filter_map(|r| r.ok())
             .flatten()
-            // .filter(|r| r.property != Some("P973".to_string())) // Remove references for "described at URL"
             .collect();
-        ret.sort();
-        let ret = Self::merge_cuc_candidates(ret);
+        Ok(Self::finalize_cuc_candidates(ret))
+    }
 
-        Ok(ret)
+    /// Like `get_potential_references`, but yields the candidates for each statement
+    /// as soon as that statement is processed. Candidates are ranked per statement only.
+    pub async fn stream_potential_references(
+        mut self,
+        entity: &str,
+    ) -> Result<BoxStream<'static, ConciseUrlCandidate>> {
+        let Some((statements, url_candidates)) = self.prepare_statements(entity).await? else {
+            return Ok(stream::empty().boxed());
+        };
+
+        let referee = Arc::new(self);
+        let url_candidates = Arc::new(url_candidates);
+        let per_statement: FuturesUnordered<_> = statements
+            .into_iter()
+            .map(|statement| {
+                let referee = referee.clone();
+                let url_candidates = url_candidates.clone();
+                async move {
+                    referee
+                        .process_statement(&statement, &url_candidates)
+                        .await
+                        .unwrap_or_default()
+                }
+            })
+            .collect();
+        Ok(Self::stream_cuc_candidates(per_statement).boxed())
     }
 
-    fn merge_cuc_candidates(mut input: Vec<ConciseUrlCandidate>) -> Vec<ConciseUrlCandidate> {
-        if input.len() < 2 {
-            return input;
-        }
-        let mut ret = vec![input.remove(0)];
-        for current in input {
-            let last = ret.last_mut().unwrap(); //Safe
-            if current == *last {
-                last.texts.extend(current.texts);
-            } else {
-                ret.push(current);
-            }
+    /// Loads the statements needing references, and the URL candidates for them.
+    /// Returns `None` if there is nothing to do, and an `UnsupportedEntity` error
+    /// for unsupported entities.
+    async fn prepare_statements(
+        &mut self,
+        entity: &str,
+    ) -> Result<Option<(Vec<EntityStatement>, UniqueUrlCandidates)>> {
+        let entity = entity.trim().to_uppercase();
+        self.reload_entity(&entity).await?;
+
+        match self.entity_support(&entity).await? {
+            EntitySupport::Supported => {}
+            EntitySupport::Missing => return Ok(None),
+            EntitySupport::Unsupported(unsupported) => return Err(unsupported.into()),
+        }
+
+        let statements = self.get_statements_needing_references(&entity).await?;
+        if statements.is_empty() {
+            return Ok(None);
+        }
+
+        let url_candidates = self.get_candidate_urls(&entity).await?;
+        if url_candidates.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some((statements, url_candidates)))
+    }
+
+    /// Candidates are only merged if they are for the same statement,
+    /// so finalizing each statement separately gives the same candidates as the batch.
+    fn stream_cuc_candidates(
+        per_statement: impl Stream<Item = Vec<ConciseUrlCandidate>>,
+    ) -> impl Stream<Item = ConciseUrlCandidate> {
+        per_statement.flat_map(|candidates| stream::iter(Self::finalize_cuc_candidates(candidates)))
+    }
+
+    fn finalize_cuc_candidates(mut input: Vec<ConciseUrlCandidate>) -> Vec<ConciseUrlCandidate> {
+        for cuc in &mut input {
+            cuc.url = ConciseUrlCandidate::canonical_url(&cuc.url);
+        }
+        input.sort();
+        let ret = Self::merge_cuc_candidates(input);
+        Self::rank_cuc_candidates(ret)
+    }
+
+    /// Shows the statement of the candidate before and after adding its reference,
+    /// without editing anything.
+    pub async fn preview_reference(
+        &mut self,
+        candidate: &ConciseUrlCandidate,
+    ) -> Result<ReferencePreview> {
+        let entity = candidate
+            .statement_id
+            .split('$')
+            .next()
+            .unwrap_or_default()
+            .to_uppercase();
+        self.reload_entity(&entity).await?;
+        let item = self
+            .entities
+            .get_entity(&entity)
+            .ok_or_else(|| anyhow!("Entity {entity} not found"))?;
+        let statement = item
+            .claims()
+            .iter()
+            .find(|claim| claim.id().as_deref() == Some(candidate.statement_id.as_str()))
+            .ok_or_else(|| anyhow!("Statement {} not found", candidate.statement_id))?;
+        Ok(candidate.preview(statement))
+    }
+
+    /// Scores the candidates, and sorts them by descending score.
+    /// Candidates with the same score keep their `Ord` order.
+    fn rank_cuc_candidates(mut input: Vec<ConciseUrlCandidate>) -> Vec<ConciseUrlCandidate> {
+        input
+            .iter_mut()
+            .for_each(ConciseUrlCandidate::compute_score);
+        input.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.cmp(b)));
+        input
+    }
+
+    fn merge_cuc_candidates(mut input: Vec<ConciseUrlCandidate>) -> Vec<ConciseUrlCandidate> {
+        if input.len() < 2 {
+            return input;
+        }
+        let mut ret = vec![input.remove(0)];
+        for current in input {
+            let last = ret.last_mut().unwrap(); //Safe
+            if current == *last {
+                last.texts.extend(current.texts);
+                last.exact_match |= current.exact_match;
+                last.language_match |= current.language_match;
+                if last.stated_in.is_none() {
+                    last.stated_in = current.stated_in;
+                }
+                if last.named_as.is_none() {
+                    last.named_as = current.named_as;
+                }
+                // Prefer the https variant of the URL
+                if current.url.starts_with("https://") {
+                    last.url = current.url;
+                }
+            } else {
+                ret.push(current);
+            }
         }
         for cuc in &mut ret {
             cuc.texts.sort();
@@ -933,38 +2241,56 @@ impl Referee {
         };
 
         // Cache compiled regexes per language to avoid recompiling for each URL candidate
-        let mut regex_cache: HashMap<String, Vec<Regex>> = HashMap::new();
+        let mut regex_cache: HashMap<String, Vec<(Regex, bool)>> = HashMap::new();
 
         for url_candidate in url_candidates.values() {
+            let record = |outcome| {
+                self.record_outcome(
+                    &url_candidate.url,
+                    Some(statement_id.as_str()),
+                    outcome,
+                    Some(url_candidate.language.as_str()),
+                );
+            };
             if Self::does_statement_have_this_reference(statement, url_candidate) {
+                record(UrlOutcome::AlreadyReferenced);
                 continue;
             }
 
             if Self::is_bad_combination(statement, url_candidate) {
+                record(UrlOutcome::BadCombination);
                 continue;
             }
 
-            let regexes = match regex_cache.get(&url_candidate.language) {
+            let named_as = self.subject_named_as(statement, url_candidate);
+            let language_match = Self::is_website_language_match(statement, url_candidate);
+
+            let pattern_language = self
+                .pattern_language
+                .as_deref()
+                .unwrap_or(&url_candidate.language);
+            let regexes = match regex_cache.get(pattern_language) {
                 Some(cached) => cached,
                 None => {
                     let patterns = self
-                        .get_statement_search_patterns(statement, &url_candidate.language)
+                        .get_statement_search_patterns(statement, pattern_language)
                         .await?;
-                    let compiled: Vec<Regex> = patterns
+                    let compiled: Vec<(Regex, bool)> = patterns
                         .iter()
-                        .filter(|p| !p.trim().is_empty())
-                        .filter_map(|pattern| {
-                            let re_pattern = format!(r"\b(.{{0,60}})\b({pattern})\b(.{{0,60}})\b");
-                            Regex::new(&re_pattern).ok()
+                        .filter(|p| !p.pattern.trim().is_empty())
+                        .filter_map(|sp| {
+                            let re = Self::context_regex(&sp.pattern, self.context_chars)?;
+                            Some((re, sp.exact))
                         })
                         .collect();
                     regex_cache
-                        .entry(url_candidate.language.clone())
+                        .entry(pattern_language.to_string())
                         .or_insert(compiled)
                 }
             };
 
-            for re in regexes {
+            let candidates_before = ret.len();
+            for (re, exact) in regexes {
                 if let Some(caps) = re.captures(&url_candidate.text) {
                     let before = caps.get(1).map_or("", |m| m.as_str()).to_string();
                     let matched = caps.get(2).map_or("", |m| m.as_str()).to_string();
@@ -975,117 +2301,1246 @@ impl Referee {
                         regexp_match: matched,
                         after,
                     };
-                    ret.push(ConciseUrlCandidate::new(&statement_id, url_candidate, &tp));
+                    let mut cuc = ConciseUrlCandidate::new(&statement_id, url_candidate, &tp);
+                    cuc.exact_match = *exact;
+                    cuc.named_as.clone_from(&named_as);
+                    cuc.language_match = language_match;
+                    ret.push(cuc);
                 }
             }
+            record(if ret.len() > candidates_before {
+                UrlOutcome::Matched
+            } else {
+                UrlOutcome::NoPatternMatch
+            });
         }
         Ok(ret)
     }
 
-    fn get_date_patterns(language: &str, year: i32, month_num: u32, day_num: u32) -> Vec<String> {
-        let mut ret = Vec::new();
-        // ISO format
-        ret.push(format!("{year}-{month_num:02}-{day_num:02}"));
+    fn get_date_patterns(language: &str, year: i32, month_num: u32, day_num: u32) -> Vec<String> {
+        let mut ret = Vec::new();
+        // ISO format
+        ret.push(format!("{year}-{month_num:02}-{day_num:02}"));
+
+        // Generic formats
+        ret.push(format!("{day_num}. {month_num}. {year}"));
+        ret.push(format!("{day_num}.{month_num}.{year}"));
+        ret.push(format!("{day_num}/{month_num}/{year}"));
+
+        ret.push(format!("{day_num:02}. {month_num:02}. {year}"));
+        ret.push(format!("{day_num:02}.{month_num:02}.{year}"));
+        ret.push(format!("{day_num:02}/{month_num:02}/{year}"));
+
+        if let Some(lang_label) = MONTHS.get(&month_num) {
+            if let Some(long_short) = lang_label.get(language) {
+                let (long_month, short_month_opt) = long_short;
+                ret.push(format!("{day_num}. {long_month} {year}"));
+                ret.push(format!("{day_num:02}. {long_month} {year}"));
+                ret.push(format!("{day_num} {long_month} {year}"));
+                ret.push(format!("{long_month} {day_num}, {year}"));
+                ret.push(format!("{year}, {day_num} {long_month}"));
+
+                if let Some(short_month) = short_month_opt {
+                    ret.push(format!("{day_num}. {short_month} {year}"));
+                    ret.push(format!("{short_month} {day_num}, {year}"));
+                    ret.push(format!("{short_month}. {day_num}, {year}"));
+                    ret.push(format!("{day_num:02}. {short_month} {year}"));
+                }
+            }
+        }
+        ret.extend(Self::get_locale_date_patterns(
+            language, year, month_num, day_num,
+        ));
+
+        // Add lowercase versions of the date strings
+        let lc = ret
+            .iter()
+            .filter(|s| s.to_lowercase() != **s)
+            .map(|s| s.to_lowercase())
+            .collect::<Vec<_>>();
+        ret.extend(lc);
+
+        // Locale patterns can repeat generic ones, like "11 marzo 1952"
+        let mut seen = HashSet::new();
+        ret.retain(|pattern| seen.insert(pattern.clone()));
+        ret
+    }
+
+    /// Dates in the order and with the separators that are conventional in `language`,
+    /// beyond the generic patterns. Other languages get none.
+    fn get_locale_date_patterns(
+        language: &str,
+        year: i32,
+        month_num: u32,
+        day_num: u32,
+    ) -> Vec<String> {
+        let Some((long_month, _)) = MONTHS
+            .get(&month_num)
+            .and_then(|lang_label| lang_label.get(language))
+        else {
+            return vec![];
+        };
+        // The first of the month is often written as an ordinal, as in "1º marzo"
+        let days: Vec<String> = if day_num == 1 {
+            ["1", "1º", "1.º", "1°"].map(str::to_string).to_vec()
+        } else {
+            vec![day_num.to_string()]
+        };
+        match language {
+            "es" | "pt" => days
+                .iter()
+                .map(|day| format!("{day} de {long_month} de {year}"))
+                .collect(),
+            "it" => days
+                .iter()
+                .map(|day| format!("{day} {long_month} {year}"))
+                .collect(),
+            "nl" => vec![
+                format!("{day_num}-{month_num}-{year}"),
+                format!("{day_num:02}-{month_num:02}-{year}"),
+            ],
+            "pl" => {
+                let Some(genitive) = POLISH_GENITIVE_MONTHS.get(month_num as usize - 1) else {
+                    return vec![];
+                };
+                vec![format!("{day_num} {genitive} {year}")]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn get_month_patterns(language: &str, year: i32, month_num: u32) -> Vec<String> {
+        let mut ret = Vec::new();
+        // ISO format
+        ret.push(format!("{year}-{month_num:02}"));
+
+        let long_short = MONTHS
+            .get(&month_num)
+            .and_then(|lang_label| lang_label.get(language));
+        match long_short {
+            Some((long_month, short_month_opt)) => {
+                ret.push(format!("{long_month} {year}"));
+                if matches!(language, "es" | "pt") {
+                    ret.push(format!("{long_month} de {year}"));
+                }
+                if let Some(short_month) = short_month_opt {
+                    ret.push(format!("{short_month} {year}"));
+                    ret.push(format!("{short_month}. {year}"));
+                }
+            }
+            None => {
+                // No localized month name, fall back to numeric formats
+                ret.push(format!("{month_num}/{year}"));
+                ret.push(format!("{month_num:02}/{year}"));
+                ret.push(format!("{month_num:02}.{year}"));
+            }
+        }
+
+        // Add lowercase versions of the date strings
+        let lc = ret
+            .iter()
+            .filter(|s| s.to_lowercase() != **s)
+            .map(|s| s.to_lowercase())
+            .collect::<Vec<_>>();
+        ret.extend(lc);
+
+        ret
+    }
+
+    fn is_bad_combination(statement: &EntityStatement, url_candidate: &UrlCandidate) -> bool {
+        for (property, url_part) in BAD_PROP_STATEMENT {
+            if statement.property == *property && url_candidate.url.contains(url_part) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::test_server::{self, canned_wiki_api};
+    use futures::future::BoxFuture;
+
+    fn referee_config(value: Value) -> RefereeConfig {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_months() {
+        let months = MONTHS.get(&1).unwrap();
+        assert_eq!(months.get("en").unwrap().0, "January");
+        assert_eq!(months.get("de").unwrap().0, "Januar");
+        assert_eq!(months.get("fr").unwrap().0, "janvier");
+        assert_eq!(months.get("es").unwrap().0, "enero");
+
+        assert_eq!(months.get("en").unwrap().1.as_ref().unwrap(), "Jan");
+        assert_eq!(months.get("de").unwrap().1.as_ref().unwrap(), "Jan");
+    }
+
+    #[test]
+    fn test_guess_page_language_from_text() {
+        assert_eq!(
+            "en",
+            Referee::guess_page_language_from_text(
+                "He was born in London and died there many years later."
+            )
+        );
+        assert_eq!(
+            "de",
+            Referee::guess_page_language_from_text(
+                "Sind die Hühner platt wie Teller, war der Traktor wieder schneller!"
+            )
+        );
+        assert_eq!(
+            "fr",
+            Referee::guess_page_language_from_text(
+                "Il est né à Paris et il a passé toute sa vie dans cette ville."
+            )
+        );
+        assert_eq!("en", Referee::guess_page_language_from_text("12345"));
+    }
+
+    #[test]
+    fn test_validate_url_good() {
+        let blacklist = UrlBlacklist::default();
+        assert!(blacklist.validate_url("https://example.com/page").is_ok());
+        assert!(
+            blacklist
+                .validate_url("https://en.wikipedia.org/wiki/Foo")
+                .is_ok()
+        );
+        assert!(
+            blacklist
+                .validate_url("https://www.example.org/data")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_validate_url_bad() {
+        // Each entry in BAD_URLS must be rejected
+        let blacklist = UrlBlacklist::default();
+        assert!(
+            blacklist
+                .validate_url("https://viaf.org/viaf/12345")
+                .is_err()
+        );
+        assert!(
+            blacklist
+                .validate_url("https://toolforge.org/tool/foo")
+                .is_err()
+        );
+        assert!(
+            blacklist
+                .validate_url("https://wmflabs.org/something")
+                .is_err()
+        );
+        assert!(
+            blacklist
+                .validate_url("https://www.google.com/search?q=foo")
+                .is_err()
+        );
+        // "://g.co/" pattern
+        assert!(blacklist.validate_url("https://g.co/maps/foo").is_err());
+    }
+
+    #[test]
+    fn test_url_blacklist_from_config() {
+        let config = referee_config(serde_json::json!({
+            "bad_urls": ["spam.example"],
+            "bad_url_patterns": [r"^https?://[^/]*\.casino\.", "(unclosed"]
+        }));
+        let blacklist = UrlBlacklist::from_config(&config);
+        // The invalid pattern is skipped
+        assert_eq!(blacklist.patterns.len(), 1);
+        assert!(blacklist.validate_url("https://spam.example/page").is_err());
+        assert!(blacklist.validate_url("https://www.casino.biz/").is_err());
+        // Built-in defaults still apply
+        assert!(
+            blacklist
+                .validate_url("https://viaf.org/viaf/12345")
+                .is_err()
+        );
+        assert!(blacklist.validate_url("https://example.com/page").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_referee_with_custom_url_blacklist() {
+        let blacklist = UrlBlacklist::new(vec!["spam.example".to_string()], &[]);
+        let referee = Referee::new_with_api_url(&canned_wiki_api(serde_json::json!({})).await)
+            .await
+            .unwrap()
+            .with_url_blacklist(blacklist);
+        assert!(referee.validate_url("https://spam.example/page").is_err());
+        assert!(referee.validate_url("https://viaf.org/viaf/12345").is_err());
+        assert!(referee.validate_url("https://example.com/page").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_referee_reuses_properties_across_items() {
+        use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+        // Two items with VIAF IDs
+        let mut entities = canned_entities();
+        let mut other = entities["Q100"].clone();
+        other["id"] = serde_json::json!("Q101");
+        entities["Q101"] = other;
+        let property_loads = Arc::new(AtomicUsize::new(0));
+        let loads = property_loads.clone();
+        let api_url = test_server::wiki_api(move |params| {
+            let ids = params.get("ids").map(String::as_str).unwrap_or_default();
+            if ids.split('|').any(|id| id == "P214") {
+                loads.fetch_add(1, AtomicOrdering::SeqCst);
+            }
+            test_server::wbgetentities(&entities, params)
+        })
+        .await;
+        let referee = Referee::new_with_api_url(&api_url)
+            .await
+            .unwrap()
+            .with_fetcher(Arc::new(canned_pages()));
+        let mut first = referee.clone();
+        first.get_candidate_urls("Q100").await.unwrap();
+        let properties = ["P214"];
+        assert!(first.unloaded_properties(properties.into_iter()).is_empty());
+        assert_eq!(property_loads.load(AtomicOrdering::SeqCst), 1);
+
+        let mut second = referee.clone();
+        second.reload_entity("Q101").await.unwrap();
+        assert!(
+            second
+                .unloaded_properties(properties.into_iter())
+                .is_empty()
+        );
+        second.get_candidate_urls("Q101").await.unwrap();
+        assert!(referee.entities.has_entity("Q101"));
+        assert_eq!(property_loads.load(AtomicOrdering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_with_fresh_items_shares_properties() {
+        let referee = Referee::new_with_api_url(&canned_wiki_api(canned_entities()).await)
+            .await
+            .unwrap();
+        referee.reload_entity("Q100").await.unwrap();
+        let fresh = referee.clone().with_fresh_items();
+        assert!(!fresh.entities.has_entity("Q100"));
+        assert!(Arc::ptr_eq(&fresh.properties, &referee.properties));
+        fresh.reload_entity("Q100").await.unwrap();
+        assert!(fresh.entities.has_entity("Q100"));
+    }
+
+    #[test]
+    fn test_direct_website_urls_described_at() {
+        let website = |url: &str, property| DirectWebsite {
+            url: url.to_string(),
+            property,
+            language: None,
+        };
+        let official = || vec![website("https://example.org", "P856")];
+        let described_at = || {
+            vec![
+                website("https://example.com/about", "P973"),
+                website("https://example.org", "P973"),
+            ]
+        };
+        let urls = |websites: Vec<DirectWebsite>| {
+            websites
+                .into_iter()
+                .map(|website| (website.url, website.property))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            urls(Referee::direct_website_urls(
+                official(),
+                described_at(),
+                false
+            )),
+            vec![("https://example.org".to_string(), "P856")]
+        );
+        assert_eq!(
+            urls(Referee::direct_website_urls(
+                official(),
+                described_at(),
+                true
+            )),
+            vec![
+                ("https://example.com/about".to_string(), "P973"),
+                ("https://example.org".to_string(), "P856")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_direct_websites_described_at() {
+        let website = |id: &str, property: &str, url: &str| {
+            serde_json::json!({
+                "mainsnak": {
+                    "snaktype": "value",
+                    "property": property,
+                    "datavalue": {"value": url, "type": "string"},
+                    "datatype": "url"
+                },
+                "type": "statement",
+                "id": id,
+                "rank": "normal"
+            })
+        };
+        let entities = serde_json::json!({"Q200": {
+            "type": "item",
+            "id": "Q200",
+            "labels": {"en": {"language": "en", "value": "Example Museum"}},
+            "descriptions": {},
+            "aliases": {},
+            "claims": {
+                "P856": [website("Q200$official", "P856", "https://museum.example")],
+                "P973": [website("Q200$described", "P973", "https://guide.example/museum")]
+            }
+        }});
+        let fetcher = Arc::new(
+            StubFetcher::default()
+                .with_page(
+                    "https://museum.example",
+                    "<p>The museum was founded in 1901 and shows paintings from all over the world.</p>",
+                )
+                .with_page(
+                    "https://guide.example/museum",
+                    "<p>A guide to the museum, which was founded in 1901 by local collectors.</p>",
+                ),
+        );
+        let referee = Referee::new_with_api_url(&canned_wiki_api(entities).await)
+            .await
+            .unwrap()
+            .with_fetcher(fetcher);
+        referee
+            .entities
+            .load_entity(&referee.api, "Q200")
+            .await
+            .unwrap();
+        let properties = |referee: Referee| async move {
+            let candidates = referee
+                .with_url_contents_cache(UrlContentsCache::default())
+                .get_direct_websites("Q200")
+                .await;
+            let mut properties: Vec<String> = candidates
+                .into_values()
+                .filter_map(|uc| uc.property)
+                .collect();
+            properties.sort();
+            properties
+        };
+        assert_eq!(properties(referee.clone()).await, vec!["P856", "P973"]);
+        assert_eq!(
+            properties(referee.with_described_at(false)).await,
+            vec!["P856"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_direct_websites_records_languages() {
+        let website = |id: &str, url: &str, language: u64| {
+            serde_json::json!({
+                "mainsnak": {
+                    "snaktype": "value",
+                    "property": "P856",
+                    "datavalue": {"value": url, "type": "string"},
+                    "datatype": "url"
+                },
+                "qualifiers": {"P407": [{
+                    "snaktype": "value",
+                    "property": "P407",
+                    "datavalue": {
+                        "value": {"entity-type": "item", "numeric-id": language, "id": format!("Q{language}")},
+                        "type": "wikibase-entityid"
+                    },
+                    "datatype": "wikibase-item"
+                }]},
+                "type": "statement",
+                "id": id,
+                "rank": "normal"
+            })
+        };
+        let entities = serde_json::json!({"Q200": {
+            "type": "item",
+            "id": "Q200",
+            "labels": {"en": {"language": "en", "value": "Example Museum"}},
+            "descriptions": {},
+            "aliases": {},
+            "claims": {"P856": [
+                website("Q200$en", "https://museum.example/en", 1860),
+                website("Q200$de", "https://museum.example/de", 188)
+            ]}
+        }});
+        let fetcher = StubFetcher::default()
+            .with_page(
+                "https://museum.example/en",
+                "<p>The museum was founded in 1901 and shows paintings from all over the world.</p>",
+            )
+            .with_page(
+                "https://museum.example/de",
+                "<p>Das Museum wurde 1901 gegründet und zeigt Gemälde aus aller Welt.</p>",
+            );
+        let referee = Referee::new_with_api_url(&canned_wiki_api(entities).await)
+            .await
+            .unwrap()
+            .with_url_contents_cache(UrlContentsCache::default())
+            .with_fetcher(Arc::new(fetcher));
+        referee
+            .entities
+            .load_entity(&referee.api, "Q200")
+            .await
+            .unwrap();
+        let candidates = referee.get_direct_websites("Q200").await;
+        let mut languages: Vec<(&str, Option<&str>, Option<&str>)> = candidates
+            .values()
+            .map(|uc| {
+                (
+                    uc.url.as_str(),
+                    uc.property.as_deref(),
+                    uc.website_language.as_deref(),
+                )
+            })
+            .collect();
+        languages.sort();
+        assert_eq!(
+            languages,
+            vec![
+                ("https://museum.example/de", Some("P856"), Some("de")),
+                ("https://museum.example/en", Some("P856"), Some("en")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_website_language_match() {
+        let claim = Statement::new_normal(Snak::new_item("P31", "Q33506"), vec![], vec![]);
+        let statement = EntityStatement {
+            entity: "Q200".to_string(),
+            property: "P31".to_string(),
+            id: "Q200$1".to_string(),
+            claim,
+        };
+        let mut uc = make_url_candidate("https://museum.example/de");
+        assert!(!Referee::is_website_language_match(&statement, &uc));
+        uc.website_language = Some("de".to_string());
+        assert!(!Referee::is_website_language_match(&statement, &uc));
+        // Items have no language; the page language is used
+        uc.language = "de".to_string();
+        assert!(Referee::is_website_language_match(&statement, &uc));
+    }
+
+    #[test]
+    fn test_compute_score_prefers_language_match() {
+        let mut plain = ConciseUrlCandidate::new(
+            "Q1$s1",
+            &make_url_candidate("https://a.com/page"),
+            &make_text_part("t1"),
+        );
+        plain.compute_score();
+        let mut matched = ConciseUrlCandidate::new(
+            "Q1$s1",
+            &make_url_candidate("https://a.com/page"),
+            &make_text_part("t1"),
+        );
+        matched.language_match = true;
+        matched.compute_score();
+        assert!(matched.score > plain.score);
+    }
+
+    #[test]
+    fn test_name_patterns_falls_back_to_english() {
+        let labels = vec![LocaleString::new("en", "Douglas Adams")];
+        let aliases = vec![
+            LocaleString::new("en", "Douglas Noel Adams"),
+            LocaleString::new("fr", "D. Adams"),
+        ];
+        let patterns = Referee::name_patterns(&labels, &aliases, &["de", "en"]);
+        assert_eq!(
+            patterns,
+            vec![
+                SearchPattern::exact("Douglas Adams".to_string()),
+                SearchPattern::alias("Douglas Noel Adams".to_string()),
+            ]
+        );
+        let page = "Per Anhalter durch die Galaxis ist ein Roman von Douglas Adams.";
+        assert!(Regex::new(&patterns[0].pattern).unwrap().is_match(page));
+        // Without the fallback, there is nothing to match
+        assert!(Referee::name_patterns(&labels, &aliases, &["de"]).is_empty());
+    }
+
+    #[test]
+    fn test_name_patterns_prefers_page_language_after_mul() {
+        let labels = vec![
+            LocaleString::new("en", "Munich"),
+            LocaleString::new("de", "München"),
+            LocaleString::new("mul", "München (Stadt)"),
+        ];
+        let aliases = vec![LocaleString::new("en", "Munchen")];
+        let patterns = Referee::name_patterns(&labels, &aliases, &["de", "en"]);
+        let names: Vec<&str> = patterns.iter().map(|p| p.pattern.as_str()).collect();
+        assert_eq!(names, vec![r"München \(Stadt\)", "München"]);
+    }
+
+    #[test]
+    fn test_fallback_languages_from_config() {
+        let config = referee_config(serde_json::json!({"fallback_languages": ["en", "fr"]}));
+        assert_eq!(
+            Referee::fallback_languages_from_config(&config),
+            vec!["en", "fr"]
+        );
+        assert_eq!(
+            Referee::fallback_languages_from_config(&RefereeConfig::default()),
+            vec!["en"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_properties() {
+        let referee = Referee::new_with_api_url(&canned_wiki_api(serde_json::json!({})).await)
+            .await
+            .unwrap();
+        let properties = referee.parse_properties(" p19,P569,,P373 ").unwrap();
+        let expected: HashSet<String> = ["P19", "P569"].iter().map(|p| p.to_string()).collect();
+        assert_eq!(properties, expected);
+        assert!(referee.parse_properties("P19,Q5").is_err());
+        assert!(referee.parse_properties("P19;P569").is_err());
+
+        // Configured properties are dropped too
+        let referee = referee.with_no_refs_for_property("p569");
+        let properties = referee.parse_properties("P19,P569").unwrap();
+        assert_eq!(properties, HashSet::from(["P19".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn test_statements_needing_references_respects_property_filter() {
+        let claims = vec![
+            Statement::new_normal(Snak::new_item("P19", "Q64"), vec![], vec![]),
+            Statement::new_normal(Snak::new_item("P27", "Q183"), vec![], vec![]),
+            Statement::new_normal(Snak::new_item("P569", "Q1"), vec![], vec![]),
+            Statement::new_normal(Snak::new_item("P373", "Q2"), vec![], vec![]),
+        ];
+        let referee = Referee::new_with_api_url(&canned_wiki_api(serde_json::json!({})).await)
+            .await
+            .unwrap();
+        assert_eq!(
+            referee.statements_needing_references("Q1", &claims).len(),
+            3
+        );
+
+        let properties = referee.parse_properties("P19,P569,P373").unwrap();
+        let referee = referee.with_properties(properties);
+        let properties: Vec<String> = referee
+            .statements_needing_references("Q1", &claims)
+            .into_iter()
+            .map(|statement| statement.property)
+            .collect();
+        assert_eq!(properties, vec!["P19", "P569"]);
+    }
+
+    #[tokio::test]
+    async fn test_statements_needing_references_skips_extra_no_refs_property() {
+        let claims = vec![
+            Statement::new_normal(Snak::new_item("P19", "Q64"), vec![], vec![]),
+            Statement::new_normal(Snak::new_item("P27", "Q183"), vec![], vec![]),
+            Statement::new_normal(Snak::new_item("P373", "Q2"), vec![], vec![]),
+        ];
+        let referee = Referee::new_with_api_url(&canned_wiki_api(serde_json::json!({})).await)
+            .await
+            .unwrap()
+            .with_no_refs_for_property("p27");
+        let properties: Vec<String> = referee
+            .statements_needing_references("Q1", &claims)
+            .into_iter()
+            .map(|statement| statement.property)
+            .collect();
+        assert_eq!(properties, vec!["P19"]);
+    }
+
+    #[test]
+    fn test_no_refs_for_properties_from_config() {
+        let config = referee_config(serde_json::json!({
+            "no_refs_for_properties": ["p1081", "Q5", "P"]
+        }));
+        let properties = Referee::no_refs_for_properties_from_config(&config);
+        assert!(properties.contains("P1081"));
+        assert!(properties.contains("P373"));
+        assert!(!properties.contains("Q5"));
+        assert!(!properties.contains("P"));
+
+        let config = referee_config(serde_json::json!({
+            "no_refs_for_properties": ["P1081"],
+            "replace_no_refs_for_properties": true
+        }));
+        let properties = Referee::no_refs_for_properties_from_config(&config);
+        assert_eq!(properties, HashSet::from(["P1081".to_string()]));
+
+        let defaults = Referee::no_refs_for_properties_from_config(&RefereeConfig::default());
+        assert_eq!(defaults.len(), NO_REFS_FOR_PROPERTIES.len());
+    }
+
+    // ── Offline fixtures ─────────────────────────────────────────────────────
+
+    /// Canned URL contents; unknown URLs fail like unreachable ones
+    #[derive(Debug, Default)]
+    struct StubFetcher {
+        pages: HashMap<String, String>,
+    }
+
+    impl StubFetcher {
+        fn with_page(mut self, url: &str, contents: &str) -> Self {
+            self.pages.insert(url.to_string(), contents.to_string());
+            self
+        }
+    }
+
+    impl ContentFetcher for StubFetcher {
+        fn fetch<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<String>> {
+            let ret = self
+                .pages
+                .get(url)
+                .cloned()
+                .ok_or_else(|| anyhow!("No canned page for {url}"));
+            Box::pin(async move { ret })
+        }
+    }
+
+    fn canned_entities() -> Value {
+        serde_json::json!({
+            "Q100": {
+                "type": "item",
+                "id": "Q100",
+                "labels": {"en": {"language": "en", "value": "Jane Roe"}},
+                "descriptions": {},
+                "aliases": {},
+                "claims": {
+                    "P569": [{
+                        "mainsnak": {
+                            "snaktype": "value",
+                            "property": "P569",
+                            "datavalue": {
+                                "value": {
+                                    "time": "+1952-03-11T00:00:00Z",
+                                    "timezone": 0,
+                                    "before": 0,
+                                    "after": 0,
+                                    "precision": 11,
+                                    "calendarmodel": GREGORIAN_CALENDAR
+                                },
+                                "type": "time"
+                            },
+                            "datatype": "time"
+                        },
+                        "type": "statement",
+                        "id": "Q100$birth",
+                        "rank": "normal"
+                    }],
+                    "P214": [{
+                        "mainsnak": {
+                            "snaktype": "value",
+                            "property": "P214",
+                            "datavalue": {"value": "12345", "type": "string"},
+                            "datatype": "external-id"
+                        },
+                        "type": "statement",
+                        "id": "Q100$viaf",
+                        "rank": "normal"
+                    }]
+                },
+                "sitelinks": {"enwiki": {"site": "enwiki", "title": "Jane Roe", "badges": []}}
+            },
+            "P214": {
+                "type": "property",
+                "datatype": "external-id",
+                "id": "P214",
+                "labels": {"en": {"language": "en", "value": "VIAF ID"}},
+                "descriptions": {},
+                "aliases": {},
+                "claims": {
+                    "P1630": [{
+                        "mainsnak": {
+                            "snaktype": "value",
+                            "property": "P1630",
+                            "datavalue": {"value": "https://authority.example/$1", "type": "string"},
+                            "datatype": "string"
+                        },
+                        "type": "statement",
+                        "id": "P214$formatter",
+                        "rank": "normal"
+                    }]
+                }
+            }
+        })
+    }
+
+    fn canned_pages() -> StubFetcher {
+        let extlinks = serde_json::json!({"query": {"pages": {"1": {
+            "title": "Jane Roe",
+            "extlinks": [{"*": "https://news.example/jane-roe"}]
+        }}}});
+        StubFetcher::default()
+            .with_page(
+                "https://en.wikipedia.org/w/api.php?action=query&prop=extlinks&ellimit=500&elexpandurl=1&format=json&titles=Jane_Roe",
+                &extlinks.to_string(),
+            )
+            .with_page(
+                "https://authority.example/12345",
+                "<html><body><p>Roe, Jane. Born 11 March 1952 in a small town, she became a writer of many books.</p></body></html>",
+            )
+            .with_page(
+                "https://news.example/jane-roe",
+                "<html><body><p>An article about the weather, which was quite pleasant all week long.</p></body></html>",
+            )
+    }
+
+    #[tokio::test]
+    async fn test_load_contents_from_url_uses_fetcher() {
+        let referee = Referee::new_with_api_url(&canned_wiki_api(serde_json::json!({})).await)
+            .await
+            .unwrap()
+            .with_url_contents_cache(UrlContentsCache::default())
+            .with_fetcher(Arc::new(canned_pages()));
+        let contents = referee
+            .load_contents_from_url("https://news.example/jane-roe")
+            .await
+            .unwrap();
+        assert!(contents.contains("the weather"));
+        assert!(
+            referee
+                .load_contents_from_url("https://unknown.example/")
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_url_candidate_skips_soft_not_found() {
+        let fetcher = canned_pages().with_page(
+            "https://authority.example/99999",
+            "<html><head><title>Record not found | Authority</title></head><body><nav>Home | Search | About | Contact | Help</nav><p>Sorry, the requested record was not found in our database of authors.</p></body></html>",
+        );
+        let referee = Referee::new_with_api_url(&canned_wiki_api(serde_json::json!({})).await)
+            .await
+            .unwrap()
+            .with_url_contents_cache(UrlContentsCache::default())
+            .with_fetcher(Arc::new(fetcher));
+        assert!(
+            referee
+                .generate_url_candidate("https://authority.example/99999")
+                .await
+                .is_none()
+        );
+        assert!(
+            referee
+                .generate_url_candidate("https://news.example/jane-roe")
+                .await
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_is_soft_not_found() {
+        let soft_not_found = SoftNotFound::default();
+        let is_soft_not_found =
+            |html: &str| soft_not_found.is_soft_not_found(html, &Referee::html2text(html));
+        let long_text = "A biography of a writer, with many details about her life and works.";
+        assert!(!is_soft_not_found(&format!("<p>{long_text}</p>")));
+        assert!(is_soft_not_found("<p>Nothing here</p>"));
+        assert!(is_soft_not_found(&format!(
+            r#"<meta http-equiv="refresh" content="0; url=/"><p>{long_text}</p>"#
+        )));
+        assert!(is_soft_not_found(&format!(
+            r#"<meta content="5;URL='https://example.org/'" http-equiv="Refresh"><p>{long_text}</p>"#
+        )));
+        // A refresh without a target only reloads the page
+        assert!(!is_soft_not_found(&format!(
+            r#"<meta http-equiv="refresh" content="300"><p>{long_text}</p>"#
+        )));
+        assert!(is_soft_not_found(&format!(
+            "<title>Seite nicht gefunden</title><p>{long_text}</p>"
+        )));
+        assert!(is_soft_not_found(&format!(
+            "<h1 class=\"error\">Page not found</h1><p>{long_text}</p>"
+        )));
+        // Phrases in the text are not enough, even on short pages
+        let page = format!("<p>{long_text}</p><p>The village no existe any more.</p>");
+        assert!(!is_soft_not_found(&page));
+        let long_page = format!("<p>{}</p><p>Page not found</p>", long_text.repeat(40));
+        assert!(!is_soft_not_found(&long_page));
+    }
+
+    #[test]
+    fn test_soft_not_found_from_config() {
+        let config =
+            referee_config(serde_json::json!({"soft_not_found_phrases": ["Kein Treffer"]}));
+        let soft_not_found = SoftNotFound::from_config(&config);
+        assert_eq!(soft_not_found.phrases, vec!["kein treffer"]);
+        assert!(
+            !SoftNotFound::from_config(&RefereeConfig::default())
+                .phrases
+                .is_empty()
+        );
+    }
+
+    /// A referee on the canned entities and pages
+    pub(crate) async fn canned_referee() -> Referee {
+        let api_url = canned_wiki_api(canned_entities()).await;
+        Referee::new_with_api_url(&api_url)
+            .await
+            .unwrap()
+            .with_url_contents_cache(UrlContentsCache::default())
+            .with_fetcher(Arc::new(canned_pages()))
+    }
+
+    #[tokio::test]
+    async fn test_get_potential_references_offline() {
+        let api_url = canned_wiki_api(canned_entities()).await;
+        let mut referee = Referee::new_with_api_url(&api_url)
+            .await
+            .unwrap()
+            .with_url_contents_cache(UrlContentsCache::default())
+            .with_fetcher(Arc::new(canned_pages()));
+        let candidates = referee.get_potential_references("Q100").await.unwrap();
+        assert_eq!(candidates.len(), 1, "{candidates:?}");
+        let candidate = &candidates[0];
+        assert_eq!(candidate.statement_id(), "Q100$birth");
+        assert_eq!(candidate.url(), "https://authority.example/12345");
+        assert_eq!(candidate.property(), Some("P214"));
+        assert_eq!(candidate.texts()[0].regexp_match(), "11 March 1952");
+    }
+
+    #[tokio::test]
+    async fn test_get_potential_references_with_pattern_language() {
+        let pages = canned_pages().with_page(
+            "https://authority.example/12345",
+            "<html><body><p>Roe, Jane. Born 11. März 1952 in a small town, she became a writer of many books.</p></body></html>",
+        );
+        let api_url = canned_wiki_api(canned_entities()).await;
+        let referee = Referee::new_with_api_url(&api_url)
+            .await
+            .unwrap()
+            .with_fetcher(Arc::new(pages));
+
+        // The page is detected as English, so the German date is not found
+        let mut detected = referee
+            .clone()
+            .with_url_contents_cache(UrlContentsCache::default());
+        let candidates = detected.get_potential_references("Q100").await.unwrap();
+        assert!(candidates.is_empty(), "{candidates:?}");
+
+        let mut forced = referee
+            .with_url_contents_cache(UrlContentsCache::default())
+            .with_pattern_language("de")
+            .unwrap();
+        let candidates = forced.get_potential_references("Q100").await.unwrap();
+        assert_eq!(candidates.len(), 1, "{candidates:?}");
+        assert_eq!(candidates[0].texts()[0].regexp_match(), "11. März 1952");
+    }
+
+    #[tokio::test]
+    async fn test_debug_log_categorizes_urls() {
+        let api_url = canned_wiki_api(canned_entities()).await;
+        let mut referee = Referee::new_with_api_url(&api_url)
+            .await
+            .unwrap()
+            .with_url_contents_cache(UrlContentsCache::default())
+            .with_fetcher(Arc::new(canned_pages()))
+            .with_url_blacklist(UrlBlacklist::new(vec!["news.example".to_string()], &[]))
+            .with_debug_log();
+        let candidates = referee.get_potential_references("Q100").await.unwrap();
+        assert_eq!(candidates.len(), 1, "{candidates:?}");
+
+        let debug_log = referee.debug_log().unwrap();
+        let entries = debug_log.entries();
+        let outcome = |url: &str, statement_id: Option<&str>| {
+            entries
+                .iter()
+                .find(|e| e.url == url && e.statement_id.as_deref() == statement_id)
+                .map(|e| e.outcome)
+        };
+        // Not also reported as empty
+        assert_eq!(
+            outcome("https://news.example/jane-roe", None),
+            Some(UrlOutcome::Blacklisted)
+        );
+        assert_eq!(
+            outcome("https://authority.example/12345", Some("Q100$birth")),
+            Some(UrlOutcome::Matched)
+        );
+        assert_eq!(debug_log.summary()["outcomes"]["blacklisted"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_with_pattern_language_validates() {
+        let referee = Referee::new_with_api_url(&canned_wiki_api(serde_json::json!({})).await)
+            .await
+            .unwrap();
+        assert!(referee.clone().with_pattern_language("").is_err());
+        assert!(referee.clone().with_pattern_language("d e").is_err());
+        let referee = referee.with_pattern_language(" DE ").unwrap();
+        assert_eq!(referee.pattern_language.as_deref(), Some("de"));
+    }
+
+    #[tokio::test]
+    async fn test_get_potential_references_rejects_taxon() {
+        let taxon = serde_json::json!({"Q300": {
+            "type": "item",
+            "id": "Q300",
+            "labels": {"en": {"language": "en", "value": "Felis catus"}},
+            "descriptions": {},
+            "aliases": {},
+            "claims": {"P31": [{
+                "mainsnak": {
+                    "snaktype": "value",
+                    "property": "P31",
+                    "datavalue": {
+                        "value": {"entity-type": "item", "numeric-id": 16521, "id": "Q16521"},
+                        "type": "wikibase-entityid"
+                    },
+                    "datatype": "wikibase-item"
+                },
+                "type": "statement",
+                "id": "Q300$taxon",
+                "rank": "normal"
+            }]},
+            "sitelinks": {}
+        }});
+        let api_url = canned_wiki_api(taxon).await;
+        let mut referee = Referee::new_with_api_url(&api_url)
+            .await
+            .unwrap()
+            .with_unsupported_entity_markers(vec![("P31".to_string(), "Q16521".to_string())])
+            .with_fetcher(Arc::new(StubFetcher::default()));
+        let error = referee.get_potential_references("Q300").await.unwrap_err();
+        let unsupported = error.downcast::<UnsupportedEntity>().unwrap();
+        assert_eq!(unsupported.property, "P31");
+        assert_eq!(unsupported.target, "Q16521");
+    }
 
-        // Generic formats
-        ret.push(format!("{day_num}. {month_num}. {year}"));
-        ret.push(format!("{day_num}.{month_num}.{year}"));
-        ret.push(format!("{day_num}/{month_num}/{year}"));
+    #[test]
+    fn test_context_regex_window() {
+        let text = "Jane Roe was a writer of many books. She was born on 11 March 1952 in a small town near the sea.";
+        let context = |chars| {
+            let re = Referee::context_regex("11 March 1952", chars).unwrap();
+            let caps = re.captures(text).unwrap();
+            (caps[1].to_string(), caps[3].to_string())
+        };
+        let (before, after) = context(10);
+        assert!(before.len() <= 10, "{before}");
+        assert!(after.len() <= 10, "{after}");
+        let (before, after) = context(DEFAULT_CONTEXT_CHARS);
+        assert_eq!(
+            before,
+            "Jane Roe was a writer of many books. She was born on "
+        );
+        assert_eq!(after, " in a small town near the sea");
+    }
 
-        ret.push(format!("{day_num:02}. {month_num:02}. {year}"));
-        ret.push(format!("{day_num:02}.{month_num:02}.{year}"));
-        ret.push(format!("{day_num:02}/{month_num:02}/{year}"));
+    #[tokio::test]
+    async fn test_with_context_chars_validates() {
+        let referee = Referee::new_with_api_url(&canned_wiki_api(serde_json::json!({})).await)
+            .await
+            .unwrap();
+        assert!(
+            referee
+                .clone()
+                .with_context_chars(MAX_CONTEXT_CHARS + 1)
+                .is_err()
+        );
+        let referee = referee.with_context_chars(120).unwrap();
+        assert_eq!(referee.context_chars, 120);
+    }
 
-        if let Some(lang_label) = MONTHS.get(&month_num) {
-            if let Some(long_short) = lang_label.get(language) {
-                let (long_month, short_month_opt) = long_short;
-                ret.push(format!("{day_num}. {long_month} {year}"));
-                ret.push(format!("{day_num:02}. {long_month} {year}"));
-                ret.push(format!("{day_num} {long_month} {year}"));
-                ret.push(format!("{long_month} {day_num}, {year}"));
-                ret.push(format!("{year}, {day_num} {long_month}"));
+    #[test]
+    fn test_unsupported_entity_markers_from_config() {
+        let config = referee_config(serde_json::json!({
+            "unsupported_entity_markers": [["P31", "q5"]]
+        }));
+        assert_eq!(
+            Referee::unsupported_entity_markers_from_config(&config),
+            vec![("P31".to_string(), "Q5".to_string())]
+        );
+        let markers = Referee::unsupported_entity_markers_from_config(&RefereeConfig::default());
+        assert!(markers.contains(&("P31".to_string(), "Q16521".to_string())));
+    }
 
-                if let Some(short_month) = short_month_opt {
-                    ret.push(format!("{day_num}. {short_month} {year}"));
-                    ret.push(format!("{short_month} {day_num}, {year}"));
-                    ret.push(format!("{short_month}. {day_num}, {year}"));
-                    ret.push(format!("{day_num:02}. {short_month} {year}"));
-                }
-            }
-        }
+    /// Starts a local HTTP server that answers every request with a small HTML page,
+    /// and counts the requests.
+    async fn counting_server() -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+        let (url, hits) = test_server::raw(|_| {
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 12\r\nConnection: close\r\n\r\n<p>Hello</p>".to_string()
+        })
+        .await;
+        (format!("{url}page"), hits)
+    }
 
-        // Add lowercase versions of the date strings
-        let lc = ret
-            .iter()
-            .filter(|s| s.to_lowercase() != **s)
-            .map(|s| s.to_lowercase())
-            .collect::<Vec<_>>();
-        ret.extend(lc);
+    #[tokio::test]
+    async fn test_load_contents_from_url_is_cached() {
+        use std::sync::atomic::Ordering as AtomicOrdering;
+        let (url, hits) = counting_server().await;
+        let referee = Referee::new_with_api_url(&canned_wiki_api(serde_json::json!({})).await)
+            .await
+            .unwrap()
+            .with_url_contents_cache(UrlContentsCache::default());
+        let first = referee.load_contents_from_url(&url).await.unwrap();
+        let hits_after_first = hits.load(AtomicOrdering::SeqCst);
+        let second = referee.load_contents_from_url(&url).await.unwrap();
+        assert_eq!(first, "<p>Hello</p>");
+        assert_eq!(first, second);
+        assert_eq!(hits.load(AtomicOrdering::SeqCst), hits_after_first);
+    }
 
-        ret
+    #[tokio::test]
+    async fn test_load_contents_from_url_rejects_blacklisted_before_cache() {
+        let referee = Referee::new_with_api_url(&canned_wiki_api(serde_json::json!({})).await)
+            .await
+            .unwrap()
+            .with_url_contents_cache(UrlContentsCache::default());
+        let url = "https://viaf.org/viaf/12345";
+        referee.url_contents.insert(url, "cached");
+        assert!(referee.load_contents_from_url(url).await.is_err());
     }
 
-    fn is_bad_combination(statement: &EntityStatement, url_candidate: &UrlCandidate) -> bool {
-        for (property, url_part) in BAD_PROP_STATEMENT {
-            if statement.property == *property && url_candidate.url.contains(url_part) {
-                return true;
-            }
-        }
-        false
+    #[test]
+    fn test_url_contents_cache_on_disk() {
+        let directory =
+            std::env::temp_dir().join(format!("referee_url_cache_{}", std::process::id()));
+        let url = "https://example.com/page";
+        let cache = UrlContentsCache::on_disk(&directory, Duration::from_secs(3600));
+        cache.insert(url, "<p>Hello</p>");
+
+        // A new cache, like in a new run, reads the contents from disk
+        let cache = UrlContentsCache::on_disk(&directory, Duration::from_secs(3600));
+        assert_eq!(cache.get(url).as_deref(), Some("<p>Hello</p>"));
+        assert_eq!(cache.get("https://example.com/other"), None);
+
+        let stale = UrlContentsCache::on_disk(&directory, Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(stale.get(url), None);
+        let _ = std::fs::remove_dir_all(&directory);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_url_contents_cache_from_config() {
+        let cache = UrlContentsCache::from_config(&RefereeConfig::default());
+        assert!(cache.disk.is_none());
+        assert_eq!(cache.max_entries, DEFAULT_URL_CACHE_MAX_ENTRIES);
+        let config = referee_config(
+            serde_json::json!({"url_cache_dir": "/tmp/urls", "url_cache_ttl_hours": 2, "url_cache_max_entries": 50}),
+        );
+        let cache = UrlContentsCache::from_config(&config);
+        assert_eq!(cache.ttl, Duration::from_secs(7200));
+        assert_eq!(cache.max_entries, 50);
+        assert_eq!(
+            cache.disk,
+            Some((PathBuf::from("/tmp/urls"), Duration::from_secs(7200)))
+        );
+    }
 
     #[test]
-    fn test_months() {
-        let months = MONTHS.get(&1).unwrap();
-        assert_eq!(months.get("en").unwrap().0, "January");
-        assert_eq!(months.get("de").unwrap().0, "Januar");
-        assert_eq!(months.get("fr").unwrap().0, "janvier");
-        assert_eq!(months.get("es").unwrap().0, "enero");
+    fn test_url_contents_cache_skips_empty_contents() {
+        let cache = UrlContentsCache::default();
+        cache.insert("https://example.com/failed", "");
+        assert_eq!(cache.get("https://example.com/failed"), None);
+    }
 
-        assert_eq!(months.get("en").unwrap().1.as_ref().unwrap(), "Jan");
-        assert_eq!(months.get("de").unwrap().1.as_ref().unwrap(), "Jan");
+    #[test]
+    fn test_url_contents_cache_evicts_least_recently_used() {
+        let cache = UrlContentsCache::in_memory(Duration::from_secs(3600), 2);
+        cache.insert("https://example.com/a", "a");
+        cache.insert("https://example.com/b", "b");
+        assert_eq!(cache.get("https://example.com/a").as_deref(), Some("a"));
+        cache.insert("https://example.com/c", "c");
+        assert_eq!(cache.get("https://example.com/b"), None);
+        assert_eq!(cache.get("https://example.com/a").as_deref(), Some("a"));
+        assert_eq!(cache.get("https://example.com/c").as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn test_url_contents_cache_expires_in_memory() {
+        let cache = UrlContentsCache::in_memory(Duration::ZERO, 10);
+        cache.insert("https://example.com/a", "a");
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(cache.get("https://example.com/a"), None);
+        assert!(cache.contents.lock().unwrap().entries.is_empty());
     }
 
     #[tokio::test]
-    async fn test_new_guess_page_language_from_text() {
-        assert_eq!(
-            "en",
-            Referee::guess_page_language_from_text("Hello from the world!")
-        );
-        assert_eq!(
-            "de",
-            Referee::guess_page_language_from_text(
-                "Sind die Hühner platt wie Teller, war der Traktor wieder schneller!"
-            )
+    async fn test_fetch_limiter_caps_concurrency() {
+        use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+        let limiter = FetchLimiter::new(3);
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let stub_fetches = (0..20).map(|_| {
+            let in_flight = in_flight.clone();
+            let max_in_flight = max_in_flight.clone();
+            limiter.run(async move {
+                let current = in_flight.fetch_add(1, AtomicOrdering::SeqCst) + 1;
+                max_in_flight.fetch_max(current, AtomicOrdering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                in_flight.fetch_sub(1, AtomicOrdering::SeqCst);
+            })
+        });
+        join_all(stub_fetches).await;
+        assert!(max_in_flight.load(AtomicOrdering::SeqCst) <= 3);
+        assert_eq!(in_flight.load(AtomicOrdering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_robots_txt_cache_disallowed_path_is_skipped() {
+        let cache = RobotsTxtCache::default();
+        assert_eq!(cache.is_allowed("https://example.com/private/page"), None);
+        cache.insert(
+            "https://example.com".to_string(),
+            "User-agent: *\nDisallow: /private/\n".to_string(),
         );
         assert_eq!(
-            "fr",
-            Referee::guess_page_language_from_text("Bonjour le monde!")
+            cache.is_allowed("https://example.com/private/page"),
+            Some(false)
         );
-        assert_eq!("en", Referee::guess_page_language_from_text("12345"));
+        assert_eq!(cache.is_allowed("https://example.com/public"), Some(true));
+        // Other hosts are not cached
+        assert_eq!(cache.is_allowed("https://example.org/private/page"), None);
     }
 
     #[test]
-    fn test_validate_url_good() {
-        assert!(Referee::validate_url("https://example.com/page").is_ok());
-        assert!(Referee::validate_url("https://en.wikipedia.org/wiki/Foo").is_ok());
-        assert!(Referee::validate_url("https://www.example.org/data").is_ok());
+    fn test_robots_txt_missing_or_garbage_allows_everything() {
+        assert!(RobotsTxtCache::is_allowed_by_robots_txt(
+            "",
+            "https://example.com/page"
+        ));
+        assert!(RobotsTxtCache::is_allowed_by_robots_txt(
+            "<html>Not a robots.txt</html>",
+            "https://example.com/page"
+        ));
     }
 
     #[test]
-    fn test_validate_url_bad() {
-        // Each entry in BAD_URLS must be rejected
-        assert!(Referee::validate_url("https://viaf.org/viaf/12345").is_err());
-        assert!(Referee::validate_url("https://toolforge.org/tool/foo").is_err());
-        assert!(Referee::validate_url("https://wmflabs.org/something").is_err());
-        assert!(Referee::validate_url("https://www.google.com/search?q=foo").is_err());
-        // "://g.co/" pattern
-        assert!(Referee::validate_url("https://g.co/maps/foo").is_err());
+    fn test_robots_txt_rules_for_tool_agent() {
+        let robots_txt =
+            "User-agent: *\nDisallow: /\n\nUser-agent: wd-infernal\nDisallow: /private/\n";
+        assert!(RobotsTxtCache::is_allowed_by_robots_txt(
+            robots_txt,
+            "https://example.com/page"
+        ));
+        assert!(!RobotsTxtCache::is_allowed_by_robots_txt(
+            robots_txt,
+            "https://example.com/private/page"
+        ));
+        // Browser rules do not apply to the tool
+        assert!(!RobotsTxtCache::is_allowed_by_robots_txt(
+            "User-agent: Mozilla\nAllow: /\n\nUser-agent: *\nDisallow: /\n",
+            "https://example.com/page"
+        ));
+    }
+
+    #[test]
+    fn test_robots_txt_cache_is_capped() {
+        let cache = RobotsTxtCache::default();
+        for i in 0..=MAX_ROBOTS_TXT_HOSTS {
+            cache.insert(format!("https://host{i}.example"), String::new());
+        }
+        assert!(cache.bodies.lock().unwrap().len() <= MAX_ROBOTS_TXT_HOSTS);
     }
 
     #[test]
@@ -1144,6 +3599,7 @@ mod tests {
             stated_in: None,
             language: "en".to_string(),
             text: String::new(),
+            website_language: None,
         };
         assert!(Referee::is_bad_combination(&statement, &uc));
     }
@@ -1166,6 +3622,7 @@ mod tests {
             stated_in: None,
             language: "en".to_string(),
             text: String::new(),
+            website_language: None,
         };
         assert!(Referee::is_bad_combination(&statement, &uc_bad));
     }
@@ -1188,6 +3645,7 @@ mod tests {
             stated_in: None,
             language: "en".to_string(),
             text: String::new(),
+            website_language: None,
         };
         assert!(!Referee::is_bad_combination(&statement, &uc));
     }
@@ -1244,6 +3702,66 @@ mod tests {
         assert_eq!(patterns.iter().filter(|p| *p == "2000-01-15").count(), 1);
     }
 
+    #[test]
+    fn test_get_month_patterns_english() {
+        let patterns = Referee::get_month_patterns("en", 2019, 3);
+        assert!(patterns.contains(&"2019-03".to_string()));
+        assert!(patterns.contains(&"March 2019".to_string()));
+        assert!(patterns.contains(&"Mar 2019".to_string()));
+        assert!(patterns.contains(&"march 2019".to_string()));
+    }
+
+    #[test]
+    fn test_get_month_patterns_german() {
+        let patterns = Referee::get_month_patterns("de", 2019, 3);
+        assert!(patterns.contains(&"2019-03".to_string()));
+        assert!(patterns.contains(&"März 2019".to_string()));
+    }
+
+    #[test]
+    fn test_get_month_patterns_french() {
+        let patterns = Referee::get_month_patterns("fr", 2019, 3);
+        assert!(patterns.contains(&"2019-03".to_string()));
+        assert!(patterns.contains(&"mars 2019".to_string()));
+        // "mars" is already lowercase; it should appear exactly once
+        assert_eq!(patterns.iter().filter(|p| *p == "mars 2019").count(), 1);
+    }
+
+    #[test]
+    fn test_get_date_patterns_spanish() {
+        let patterns = Referee::get_date_patterns("es", 1952, 3, 11);
+        assert!(patterns.contains(&"11 de marzo de 1952".to_string()));
+        let patterns = Referee::get_date_patterns("es", 1952, 3, 1);
+        assert!(patterns.contains(&"1.º de marzo de 1952".to_string()));
+        let patterns = Referee::get_month_patterns("es", 1952, 3);
+        assert!(patterns.contains(&"marzo de 1952".to_string()));
+    }
+
+    #[test]
+    fn test_get_date_patterns_italian() {
+        let patterns = Referee::get_date_patterns("it", 1952, 3, 11);
+        assert!(patterns.contains(&"11 marzo 1952".to_string()));
+        // Also a generic pattern; it should appear exactly once
+        assert_eq!(patterns.iter().filter(|p| *p == "11 marzo 1952").count(), 1);
+        let patterns = Referee::get_date_patterns("it", 1952, 3, 1);
+        assert!(patterns.contains(&"1º marzo 1952".to_string()));
+    }
+
+    #[test]
+    fn test_get_date_patterns_polish_genitive() {
+        let patterns = Referee::get_date_patterns("pl", 1952, 3, 11);
+        assert!(patterns.contains(&"11 marca 1952".to_string()));
+    }
+
+    #[test]
+    fn test_get_month_patterns_unknown_language_falls_back_to_numeric() {
+        let patterns = Referee::get_month_patterns("xx", 2019, 3);
+        assert!(patterns.contains(&"2019-03".to_string()));
+        assert!(patterns.contains(&"3/2019".to_string()));
+        assert!(patterns.contains(&"03/2019".to_string()));
+        assert!(!patterns.iter().any(|p| p.contains("March")));
+    }
+
     fn make_url_candidate(url: &str) -> UrlCandidate {
         UrlCandidate {
             url: url.to_string(),
@@ -1253,6 +3771,7 @@ mod tests {
             stated_in: None,
             language: "en".to_string(),
             text: String::new(),
+            website_language: None,
         }
     }
 
@@ -1322,6 +3841,168 @@ mod tests {
         assert_eq!(result[0].texts.len(), 1);
     }
 
+    #[test]
+    fn test_canonical_url() {
+        let canonical = ConciseUrlCandidate::canonical_url;
+        assert_eq!(canonical("http://Example.COM:80/"), "http://example.com");
+        assert_eq!(
+            canonical("https://example.com:443/a/"),
+            "https://example.com/a"
+        );
+        assert_eq!(
+            canonical("https://example.com:8080/a/"),
+            "https://example.com:8080/a"
+        );
+        assert_eq!(
+            canonical("https://example.com/?q=1"),
+            "https://example.com/?q=1"
+        );
+        assert_eq!(canonical("not a url"), "not a url");
+    }
+
+    #[test]
+    fn test_finalize_cuc_candidates_merges_url_variants() {
+        let tp = make_text_part("t");
+        let cuc1 = ConciseUrlCandidate::new("Q1$s1", &make_url_candidate("http://x/"), &tp);
+        let mut uc2 = make_url_candidate("https://x");
+        uc2.stated_in = Some("Q54919".to_string());
+        let cuc2 = ConciseUrlCandidate::new("Q1$s1", &uc2, &tp);
+        let result = Referee::finalize_cuc_candidates(vec![cuc1, cuc2]);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].url, "https://x");
+        assert_eq!(result[0].stated_in.as_deref(), Some("Q54919"));
+        assert_eq!(result[0].texts.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stream_cuc_candidates_matches_batch() {
+        let per_statement = || {
+            let a = make_url_candidate("https://a.com");
+            let mut b = make_url_candidate("https://b.com");
+            b.url_type = UrlType::ExternalId;
+            b.property = Some("P214".to_string());
+            vec![
+                vec![
+                    ConciseUrlCandidate::new("Q1$s1", &a, &make_text_part("t1")),
+                    ConciseUrlCandidate::new("Q1$s1", &b, &make_text_part("t2")),
+                    ConciseUrlCandidate::new("Q1$s1", &a, &make_text_part("t3")),
+                ],
+                vec![],
+                vec![ConciseUrlCandidate::new("Q1$s2", &a, &make_text_part("t1"))],
+            ]
+        };
+
+        let mut batch =
+            Referee::finalize_cuc_candidates(per_statement().into_iter().flatten().collect());
+        let mut streamed: Vec<ConciseUrlCandidate> =
+            Referee::stream_cuc_candidates(stream::iter(per_statement()))
+                .collect()
+                .await;
+        batch.sort();
+        streamed.sort();
+        assert_eq!(streamed.len(), 3);
+        assert_eq!(streamed, batch);
+        for (streamed, batch) in streamed.iter().zip(&batch) {
+            assert_eq!(streamed.texts, batch.texts);
+            assert!((streamed.score - batch.score).abs() < f32::EPSILON);
+        }
+    }
+
+    #[test]
+    fn test_as_reference_for_url() {
+        let cuc = ConciseUrlCandidate::new(
+            "Q1$s1",
+            &make_url_candidate("https://a.com/page"),
+            &make_text_part("t1"),
+        );
+        let reference = cuc.as_reference();
+        let snaks = reference.snaks();
+        let properties: Vec<&str> = snaks.iter().map(|snak| snak.property()).collect();
+        assert_eq!(properties, vec!["P854", "P813", "P887"]);
+        assert_eq!(
+            Referee::snak_string_values(snaks, "P854").collect::<Vec<_>>(),
+            vec!["https://a.com/page"]
+        );
+        assert_eq!(
+            Referee::snak_entity_values(snaks, "P887").collect::<Vec<_>>(),
+            vec!["Q131287902"]
+        );
+    }
+
+    #[test]
+    fn test_as_reference_for_external_id() {
+        let mut uc = make_url_candidate("https://viaf.example/12345");
+        uc.url_type = UrlType::ExternalId;
+        uc.property = Some("P214".to_string());
+        uc.external_id = Some("12345".to_string());
+        uc.stated_in = Some("Q54919".to_string());
+        let cuc = ConciseUrlCandidate::new("Q1$s1", &uc, &make_text_part("t1"));
+        let reference = cuc.as_reference();
+        let snaks = reference.snaks();
+        let properties: Vec<&str> = snaks.iter().map(|snak| snak.property()).collect();
+        assert_eq!(properties, vec!["P248", "P214", "P813", "P887"]);
+        assert_eq!(
+            Referee::snak_string_values(snaks, "P214").collect::<Vec<_>>(),
+            vec!["12345"]
+        );
+        assert_eq!(
+            Referee::snak_entity_values(snaks, "P248").collect::<Vec<_>>(),
+            vec!["Q54919"]
+        );
+    }
+
+    #[test]
+    fn test_as_reference_for_external_id_named_as() {
+        let mut uc = make_url_candidate("https://viaf.example/12345");
+        uc.url_type = UrlType::ExternalId;
+        uc.property = Some("P214".to_string());
+        uc.external_id = Some("12345".to_string());
+        let mut cuc = ConciseUrlCandidate::new("Q1$s1", &uc, &make_text_part("t1"));
+        cuc.named_as = Some("Jane ROE".to_string());
+        let reference = cuc.as_reference();
+        let snaks = reference.snaks();
+        let properties: Vec<&str> = snaks.iter().map(|snak| snak.property()).collect();
+        assert_eq!(properties, vec!["P214", "P1810", "P813", "P887"]);
+        assert_eq!(
+            Referee::snak_string_values(snaks, "P1810").collect::<Vec<_>>(),
+            vec!["Jane ROE"]
+        );
+    }
+
+    #[test]
+    fn test_find_named_as() {
+        let labels = vec![LocaleString::new("en", "Jane Roe")];
+        let aliases = vec![LocaleString::new("en", "J. Roe")];
+        let patterns = Referee::name_patterns(&labels, &aliases, &["en"]);
+        // As written on the page
+        assert_eq!(
+            Referee::find_named_as(&patterns, "ROE, Jane; also JANE ROE (1952-)"),
+            Some("JANE ROE".to_string())
+        );
+        // Falls back to aliases
+        assert_eq!(
+            Referee::find_named_as(&patterns, "Author: J. Roe"),
+            Some("J. Roe".to_string())
+        );
+        // Only whole words
+        assert_eq!(Referee::find_named_as(&patterns, "Jane Roebuck"), None);
+    }
+
+    #[test]
+    fn test_preview_adds_reference() {
+        let claim = Statement::new_normal(Snak::new_item("P27", "Q30"), vec![], vec![]);
+        let cuc = ConciseUrlCandidate::new(
+            "Q1$s1",
+            &make_url_candidate("https://a.com/page"),
+            &make_text_part("t1"),
+        );
+        let preview = cuc.preview(&claim);
+        assert_eq!(preview.statement_id, "Q1$s1");
+        assert!(preview.before.references().is_empty());
+        assert_eq!(preview.after.references().len(), 1);
+        assert_eq!(preview.after.main_snak(), preview.before.main_snak());
+    }
+
     #[test]
     fn test_concise_url_candidate_ordering() {
         let uc = make_url_candidate("https://a.com");
@@ -1335,6 +4016,37 @@ mod tests {
         assert_eq!(cuc_a.partial_cmp(&cuc_b), Some(std::cmp::Ordering::Less));
     }
 
+    #[test]
+    fn test_rank_cuc_candidates_external_id_outranks_website() {
+        let website = make_url_candidate("https://a.com");
+        let mut external_id = make_url_candidate("https://b.com/id/123");
+        external_id.url_type = UrlType::ExternalId;
+        external_id.property = Some("P214".to_string());
+        external_id.external_id = Some("123".to_string());
+        external_id.stated_in = Some("Q54919".to_string());
+        let tp = make_text_part("t");
+        // The website candidate sorts first by `Ord`, but the external ID must win on score
+        let cuc_website = ConciseUrlCandidate::new("Q1$s1", &website, &tp);
+        let cuc_external_id = ConciseUrlCandidate::new("Q1$s1", &external_id, &tp);
+        let result = Referee::rank_cuc_candidates(vec![cuc_website, cuc_external_id]);
+        assert_eq!(result[0].url, "https://b.com/id/123");
+        assert!(result[0].score > result[1].score);
+    }
+
+    #[test]
+    fn test_rank_cuc_candidates_exact_label_and_texts_increase_score() {
+        let uc = make_url_candidate("https://a.com");
+        let mut cuc_exact = ConciseUrlCandidate::new("Q1$s1", &uc, &make_text_part("t1"));
+        cuc_exact.exact_match = true;
+        let mut cuc_more_texts = ConciseUrlCandidate::new("Q1$s2", &uc, &make_text_part("t1"));
+        cuc_more_texts.texts.push(make_text_part("t2"));
+        let cuc_plain = ConciseUrlCandidate::new("Q1$s3", &uc, &make_text_part("t1"));
+        let result = Referee::rank_cuc_candidates(vec![cuc_plain, cuc_more_texts, cuc_exact]);
+        assert_eq!(result[0].statement_id, "Q1$s1");
+        assert_eq!(result[1].statement_id, "Q1$s2");
+        assert_eq!(result[2].statement_id, "Q1$s3");
+    }
+
     #[test]
     fn test_get_web_server_for_wiki_wikipedia() {
         assert_eq!(
@@ -1363,6 +4075,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_web_server_for_wiki_more_projects() {
+        assert_eq!(
+            Referee::get_web_server_for_wiki("enwikibooks"),
+            "en.wikibooks.org"
+        );
+        assert_eq!(
+            Referee::get_web_server_for_wiki("dewikinews"),
+            "de.wikinews.org"
+        );
+        assert_eq!(
+            Referee::get_web_server_for_wiki("frwikivoyage"),
+            "fr.wikivoyage.org"
+        );
+        assert_eq!(
+            Referee::get_web_server_for_wiki("itwikiversity"),
+            "it.wikiversity.org"
+        );
+    }
+
+    #[test]
+    fn test_get_web_server_for_wiki_special_wikis() {
+        assert_eq!(
+            Referee::get_web_server_for_wiki("commonswiki"),
+            "commons.wikimedia.org"
+        );
+        assert_eq!(
+            Referee::get_web_server_for_wiki("specieswiki"),
+            "species.wikimedia.org"
+        );
+        assert_eq!(
+            Referee::get_web_server_for_wiki("metawiki"),
+            "meta.wikimedia.org"
+        );
+    }
+
     #[test]
     fn test_get_web_server_for_wiki_no_panic_on_unexpected_input() {
         // Should not panic even with unusual input
@@ -1410,7 +4158,7 @@ mod tests {
 
     #[test]
     fn test_validate_url_empty_string_is_ok() {
-        assert!(Referee::validate_url("").is_ok());
+        assert!(UrlBlacklist::default().validate_url("").is_ok());
     }
 
     #[test]
@@ -1419,10 +4167,17 @@ mod tests {
         assert_eq!(Referee::guess_page_language_from_text(""), "en");
     }
 
+    #[test]
+    fn test_guess_page_language_short_text_defaults_to_en() {
+        assert_eq!(Referee::guess_page_language_from_text("Bonjour!"), "en");
+    }
+
     #[test]
     fn test_guess_page_language_spanish() {
         assert_eq!(
-            Referee::guess_page_language_from_text("el es un de a la es dos conlas"),
+            Referee::guess_page_language_from_text(
+                "Nació en Madrid y estudió derecho en la universidad de la ciudad."
+            ),
             "es"
         );
     }
@@ -1430,11 +4185,33 @@ mod tests {
     #[test]
     fn test_guess_page_language_italian() {
         assert_eq!(
-            Referee::guess_page_language_from_text("è una della la nel si su"),
+            Referee::guess_page_language_from_text(
+                "È nato a Roma ed è stato uno dei più importanti pittori della sua epoca."
+            ),
             "it"
         );
     }
 
+    #[test]
+    fn test_guess_page_language_russian() {
+        assert_eq!(
+            Referee::guess_page_language_from_text(
+                "Он родился в Москве и провёл там всю свою жизнь."
+            ),
+            "ru"
+        );
+    }
+
+    #[test]
+    fn test_guess_page_language_japanese() {
+        assert_eq!(
+            Referee::guess_page_language_from_text(
+                "彼は東京で生まれ、大学で文学を学びました。その後、作家として活躍しました。"
+            ),
+            "ja"
+        );
+    }
+
     #[test]
     fn test_months_all_12_present() {
         for month in 1..=12 {
@@ -1460,4 +4237,29 @@ mod tests {
             &statement, &uc
         ));
     }
+
+    #[test]
+    fn test_does_statement_have_this_reference_stated_in() {
+        // A statement with a P248 "stated in" reference matching the candidate's stated_in
+        let reference = wikibase::Reference::new(vec![Snak::new_item("P248", "Q54919")]);
+        let claim = Statement::new_normal(Snak::new_item("P27", "Q30"), vec![], vec![reference]);
+        let statement = EntityStatement {
+            entity: "Q1".to_string(),
+            property: "P27".to_string(),
+            id: "Q1$test".to_string(),
+            claim,
+        };
+        let mut uc = make_url_candidate("https://viaf.example/12345");
+        uc.url_type = UrlType::ExternalId;
+        uc.property = Some("P214".to_string());
+        uc.external_id = Some("12345".to_string());
+        uc.stated_in = Some("Q54919".to_string());
+        assert!(Referee::does_statement_have_this_reference(&statement, &uc));
+
+        // A different "stated in" is not the same reference
+        uc.stated_in = Some("Q423048".to_string());
+        assert!(!Referee::does_statement_have_this_reference(
+            &statement, &uc
+        ));
+    }
 }
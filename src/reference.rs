@@ -37,13 +37,38 @@ impl DataValue {
         };
         StatementValue::Value(svc)
     }
+
+    /// Orders values by kind, then by their text
+    pub fn sort_key(&self) -> (u8, String) {
+        let kind = match self {
+            DataValue::Monolingual { .. } => 0,
+            DataValue::String(_) => 1,
+            DataValue::Entity(_) => 2,
+            DataValue::Date { .. } => 3,
+            DataValue::Quantity(_) => 4,
+        };
+        (kind, self.to_string())
+    }
+}
+
+impl std::fmt::Display for DataValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataValue::Monolingual { label, language } => write!(f, "{label}@{language}"),
+            DataValue::String(s) | DataValue::Entity(s) => write!(f, "{s}"),
+            DataValue::Date { time, .. } => write!(f, "{time}"),
+            DataValue::Quantity(amount) => write!(f, "{amount}"),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Default, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Reference {
     property: Option<String>,
     value: Option<String>,
     url: Option<String>,
+    /// The item of the source database, for P248 (stated in)
+    stated_in: Option<String>,
 }
 
 impl Reference {
@@ -52,6 +77,7 @@ impl Reference {
             property: Some(property.to_string()),
             value: Some(value.to_string()),
             url: None,
+            stated_in: None,
         }
     }
 
@@ -60,6 +86,35 @@ impl Reference {
             property: None,
             value: None,
             url: None,
+            stated_in: None,
+        }
+    }
+
+    /// The item of the source database, like Q2359213 for Goodreads, for P248 (stated in)
+    pub fn with_stated_in(mut self, item: &str) -> Self {
+        self.stated_in = Some(item.to_string());
+        self
+    }
+
+    pub fn stated_in(&self) -> Option<&str> {
+        self.stated_in.as_deref()
+    }
+
+    /// Name of the source this reference points to, if any
+    pub fn source_name(&self) -> Option<String> {
+        match (&self.property, &self.url) {
+            (Some(property), _) => Some(
+                match property.as_str() {
+                    "P675" => "Google Books",
+                    "P8383" => "Goodreads",
+                    "P648" => "OpenLibrary",
+                    "P356" => "Crossref",
+                    other => other,
+                }
+                .to_string(),
+            ),
+            (None, Some(url)) => Some(url.to_owned()),
+            (None, None) => None,
         }
     }
 
@@ -68,6 +123,7 @@ impl Reference {
             property: None,
             value: None,
             url: Some(url.to_string()),
+            stated_in: None,
         }
     }
 
@@ -86,24 +142,31 @@ impl Reference {
         })
     }
 
+    /// P248 (stated in), if set, the property and value or the URL (P854), and the
+    /// retrieval date (P813)
     pub fn as_ref_group(&self) -> Option<wikibase_rest_api::Reference> {
-        let mut ret = wikibase_rest_api::Reference::default();
-        if let (Some(property), Some(value)) = (&self.property, &self.value) {
+        let source = if let (Some(property), Some(value)) = (&self.property, &self.value) {
             let p = PropertyType::new(
                 property.to_owned(),
                 Some(wikibase_rest_api::DataType::String),
             );
             let v = StatementValue::Value(StatementValueContent::String(value.to_owned()));
-            let pv = PropertyValue::new(p, v);
-            ret.parts_mut().push(pv);
+            PropertyValue::new(p, v)
         } else if let Some(url) = &self.url {
             let p = PropertyType::new("P854", Some(wikibase_rest_api::DataType::Url));
             let v = StatementValue::Value(StatementValueContent::String(url.to_owned()));
-            let pv = PropertyValue::new(p, v);
-            ret.parts_mut().push(pv);
+            PropertyValue::new(p, v)
         } else {
             return None;
+        };
+
+        let mut ret = wikibase_rest_api::Reference::default();
+        if let Some(item) = &self.stated_in {
+            let p = PropertyType::new("P248", Some(wikibase_rest_api::DataType::WikibaseItem));
+            let v = StatementValue::Value(StatementValueContent::String(item.to_owned()));
+            ret.parts_mut().push(PropertyValue::new(p, v));
         }
+        ret.parts_mut().push(source);
 
         let p = PropertyType::new("P813", Some(wikibase_rest_api::DataType::Time));
         let v = StatementValue::Value(StatementValueContent::Time {
@@ -267,6 +330,33 @@ mod tests {
         );
     }
 
+    fn p248_value(group: &wikibase_rest_api::Reference) -> Option<String> {
+        group
+            .parts()
+            .iter()
+            .find(|pv| pv.property().id() == "P248")
+            .and_then(|pv| match pv.value() {
+                StatementValue::Value(StatementValueContent::String(s)) => Some(s.clone()),
+                _ => None,
+            })
+    }
+
+    #[test]
+    fn test_reference_group_has_stated_in() {
+        let reference = Reference::prop("P8383", "6693").with_stated_in("Q2359213");
+        assert_eq!(reference.stated_in(), Some("Q2359213"));
+        let group = reference.as_ref_group().unwrap();
+        assert_eq!(p248_value(&group), Some("Q2359213".to_string()));
+        // The ID is still part of the group
+        assert!(group.parts().iter().any(|pv| pv.property().id() == "P8383"));
+    }
+
+    #[test]
+    fn test_reference_group_without_stated_in() {
+        let group = Reference::prop("P675", "BookID").as_ref_group().unwrap();
+        assert_eq!(p248_value(&group), None);
+    }
+
     // ── Reference::is_equivalent ─────────────────────────────────────────────
 
     #[test]
@@ -359,6 +449,34 @@ mod tests {
         assert!(!r.is_equivalent(&other_group));
     }
 
+    #[test]
+    fn test_data_value_display() {
+        assert_eq!(DataValue::Quantity(320).to_string(), "320");
+        assert_eq!(DataValue::Entity("Q42".to_string()).to_string(), "Q42");
+        let monolingual = DataValue::Monolingual {
+            label: "Hello".to_string(),
+            language: "en".to_string(),
+        };
+        assert_eq!(monolingual.to_string(), "Hello@en");
+    }
+
+    #[test]
+    fn test_reference_source_name() {
+        assert_eq!(
+            Reference::prop("P675", "x").source_name().as_deref(),
+            Some("Google Books")
+        );
+        assert_eq!(
+            Reference::prop("P8383", "x").source_name().as_deref(),
+            Some("Goodreads")
+        );
+        assert_eq!(
+            Reference::prop("P1234", "x").source_name().as_deref(),
+            Some("P1234")
+        );
+        assert_eq!(Reference::none().source_name(), None);
+    }
+
     #[test]
     fn test_reference_default_is_none() {
         let r = Reference::default();
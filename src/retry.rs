@@ -0,0 +1,153 @@
+use crate::config::Config;
+use crate::rate_limit::RateLimiter;
+use reqwest::{Client, Response};
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_ATTEMPTS: u32 = 3;
+const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(250);
+
+/// Retries transient failures with exponential backoff and jitter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retry {
+    attempts: u32,
+    base_delay: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self::new(DEFAULT_ATTEMPTS, DEFAULT_BASE_DELAY)
+    }
+}
+
+impl Retry {
+    pub const fn new(attempts: u32, base_delay: Duration) -> Self {
+        Self {
+            attempts,
+            base_delay,
+        }
+    }
+
+    /// Uses `retry.attempts` from the configuration, if set.
+    pub fn from_config() -> Self {
+        let attempts = Config::retry().attempts.unwrap_or(DEFAULT_ATTEMPTS);
+        Self::new(attempts, DEFAULT_BASE_DELAY)
+    }
+
+    /// Runs `operation` until `is_retryable` returns false for its result,
+    /// or the attempts are used up. Returns the last result.
+    pub async fn run<T, E, F, Fut>(
+        &self,
+        mut operation: F,
+        is_retryable: impl Fn(&Result<T, E>) -> bool,
+    ) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 1;
+        loop {
+            let result = operation().await;
+            if attempt >= self.attempts || !is_retryable(&result) {
+                return result;
+            }
+            tokio::time::sleep(self.delay(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    /// GET request that is retried on timeouts, connection errors, and 5xx responses.
+    /// 4xx responses are returned as-is. Every attempt goes through the global rate limiter.
+    pub async fn get(&self, client: &Client, url: &str) -> reqwest::Result<Response> {
+        self.run(
+            || async {
+                RateLimiter::global().wait(url).await;
+                client.get(url).send().await
+            },
+            Self::is_retryable_response,
+        )
+        .await
+    }
+
+    pub(crate) fn is_retryable_response(result: &reqwest::Result<Response>) -> bool {
+        match result {
+            Ok(response) => response.status().is_server_error(),
+            Err(e) => e.is_timeout() || e.is_connect(),
+        }
+    }
+
+    fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self.base_delay * 2_u32.saturating_pow(attempt - 1);
+        let base_millis = self.base_delay.as_millis().max(1) as u32;
+        let jitter = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos() % base_millis);
+        backoff + Duration::from_millis(u64::from(jitter))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Starts a local HTTP server that answers 503 for the first `failures` requests, then 200.
+    async fn flaky_server(failures: usize) -> (String, Arc<AtomicUsize>) {
+        test_server::raw(move |hit| {
+            if hit < failures {
+                "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            } else {
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok"
+            }
+            .to_string()
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_get_retries_until_success() {
+        let (url, hits) = flaky_server(2).await;
+        let retry = Retry::new(3, Duration::from_millis(1));
+        let response = retry.get(&Client::new(), &url).await.unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(response.text().await.unwrap(), "ok");
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_get_gives_up_after_attempts() {
+        let (url, hits) = flaky_server(5).await;
+        let retry = Retry::new(2, Duration::from_millis(1));
+        let response = retry.get(&Client::new(), &url).await.unwrap();
+        assert!(response.status().is_server_error());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_run_does_not_retry_non_retryable() {
+        let calls = AtomicUsize::new(0);
+        let retry = Retry::new(3, Duration::from_millis(1));
+        let result: Result<(), &str> = retry
+            .run(
+                || {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    async { Err("not found") }
+                },
+                |_| false,
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_delay_grows_exponentially() {
+        let retry = Retry::new(3, Duration::from_millis(100));
+        assert!(retry.delay(1) >= Duration::from_millis(100));
+        assert!(retry.delay(1) < Duration::from_millis(200));
+        assert!(retry.delay(3) >= Duration::from_millis(400));
+        assert!(retry.delay(3) < Duration::from_millis(500));
+    }
+}
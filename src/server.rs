@@ -1,32 +1,409 @@
-use crate::initial_search::InitialSearch;
-use crate::isbn::ISBN2wiki;
-use crate::person::Person;
-use crate::referee::Referee;
-use crate::{crosscats::CrossCats, location::Location};
-use axum::extract::Query;
-use axum::routing::post;
+use crate::api_error::ApiError;
+use crate::change_wiki::{ChangeWiki, ChangeWikiSources};
+use crate::config::Config;
+use crate::crosscats::{CrossCats, CrossCatsSource, DEFAULT_MIN_COUNT, ItemInfo};
+use crate::enrich::Enrich;
+use crate::fetcher::HttpFetcher;
+use crate::initial_search::{DEFAULT_INSTANCE_OF, InitialSearch};
+use crate::isbn::{AllIsbnSources, ISBN2wiki, IsbnSource};
+use crate::location::{
+    CountryCache, DEFAULT_P131_LIMIT, DEFAULT_P131_RADIUS_KM, EARTH, Location, P131Method,
+};
+use crate::metrics::Metrics;
+use crate::person::{NameOrder, NameSearch, Person, WikidataNameSearch};
+use crate::quickstatements::{LAST, QuickStatements};
+use crate::referee::{ConciseUrlCandidate, Referee};
+use crate::viaf::{DEFAULT_MAXIMUM_RECORDS, Record, ViafIndex, ViafOrg, ViafSearch};
+use crate::wikidata::Wikidata;
+use axum::BoxError;
+use axum::body::Body;
+use axum::error_handling::HandleErrorLayer;
+use axum::extract::{MatchedPath, Query, State};
+use axum::http::{HeaderName, Request, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::routing::{MethodRouter, post};
 use axum::{
     Json, Router,
     extract::Path,
-    http::StatusCode,
-    response::{Html, IntoResponse},
+    response::{
+        Html, IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
     routing::get,
 };
-use serde::Deserialize;
+use futures::{StreamExt, stream};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+use tower::ServiceBuilder;
+use tower::limit::GlobalConcurrencyLimitLayer;
 use tower_http::{
     compression::CompressionLayer,
     cors::{Any, CorsLayer},
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
     trace::TraceLayer,
 };
-use wikibase_rest_api::Patch;
+use tracing::Span;
+use wikibase::Statement;
+use wikibase_rest_api::{Item, Patch};
+
+const X_REQUEST_ID: &str = "x-request-id";
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 32;
+const MAX_ISBN_BATCH_SIZE: usize = 50;
+const ISBN_BATCH_CONCURRENCY: usize = 5;
+const MAX_NAME_GENDER_BATCH_SIZE: usize = 50;
+
+/// Loads item labels for HTML tables from the Wikidata API, with the tool's user agent;
+/// without it, tables show item IDs
+static LABEL_FETCHER: LazyLock<Option<HttpFetcher>> = LazyLock::new(|| HttpFetcher::new().ok());
 
 #[derive(Deserialize)]
 struct Format {
     format: Option<String>,
 }
 
+impl Format {
+    fn is_html(&self) -> bool {
+        self.format.as_deref() == Some("html")
+    }
+
+    fn is_quickstatements(&self) -> bool {
+        self.format.as_deref() == Some("qs")
+    }
+
+    /// The field separator for `csv` and `tsv`
+    fn table_separator(&self) -> Option<char> {
+        match self.format.as_deref() {
+            Some("csv") => Some(','),
+            Some("tsv") => Some('\t'),
+            _ => None,
+        }
+    }
+}
+
+/// The item that `?format=qs` commands are for, if not in the path.
+/// Without it, the commands create a new item.
+#[derive(Deserialize)]
+struct QuickStatementsParams {
+    item: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct InitialSearchParams {
+    language: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct NameGenderParams {
+    name_order: Option<NameOrder>,
+}
+
+#[derive(Deserialize)]
+struct ViafParams {
+    index: Option<ViafIndex>,
+    start: Option<u32>,
+    #[serde(alias = "maximum_records")]
+    limit: Option<u32>,
+    match_wikidata: Option<String>,
+}
+
+impl ViafParams {
+    fn wants_wikidata_match(&self) -> bool {
+        self.match_wikidata.as_deref() == Some("1")
+    }
+}
+
+#[derive(Deserialize)]
+struct IsbnParams {
+    conflicts: Option<String>,
+    check_existing: Option<String>,
+}
+
+impl IsbnParams {
+    fn wants_conflicts(&self) -> bool {
+        self.conflicts.as_deref() == Some("1")
+    }
+
+    fn wants_existing_check(&self) -> bool {
+        self.check_existing.as_deref() == Some("1")
+    }
+}
+
+#[derive(Deserialize)]
+struct P131Params {
+    radius_km: Option<f64>,
+    limit: Option<usize>,
+    globe: Option<String>,
+    method: Option<P131Method>,
+    verbose: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChangeWikiParams {
+    resolve_redirects: Option<String>,
+    check_disambiguation: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RefereeParams {
+    properties: Option<String>,
+    include_described_at: Option<String>,
+    /// Characters of text around each match
+    context: Option<usize>,
+    /// Language for search patterns, instead of the detected page language
+    language: Option<String>,
+    /// "1" adds the outcome for each URL to JSON results
+    debug: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CrossCatsParams {
+    petscan_id: Option<u64>,
+    min_count: Option<usize>,
+}
+
+/// Where the handlers load data from, shared by all requests; tests use canned sources
+#[derive(Clone, Debug)]
+struct AppState {
+    change_wiki: ChangeWikiSources,
+    name_search: Arc<dyn NameSearch>,
+    isbn_source: Arc<dyn IsbnSource>,
+    viaf_search: Arc<dyn ViafSearch>,
+    cross_cats: Arc<dyn CrossCatsSource>,
+    /// Created on the first referee request, then cloned for each request
+    referee: Arc<OnceCell<Referee>>,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self {
+            change_wiki: ChangeWikiSources::default(),
+            name_search: Arc::new(WikidataNameSearch),
+            isbn_source: Arc::new(AllIsbnSources),
+            viaf_search: Arc::new(ViafOrg),
+            cross_cats: Arc::new(CrossCats),
+            referee: Arc::new(OnceCell::new()),
+        }
+    }
+}
+
+/// An entry of the route registry, which builds the router and is listed at `/api`
+struct ApiRoute {
+    method: &'static str,
+    path: &'static str,
+    query_params: &'static [&'static str],
+    summary: &'static str,
+    handler: fn() -> MethodRouter<AppState>,
+}
+
+impl ApiRoute {
+    /// Names of the `:param` and `*param` segments of the path
+    fn path_params(&self) -> Vec<&'static str> {
+        self.path
+            .split('/')
+            .filter_map(|segment| {
+                segment
+                    .strip_prefix(':')
+                    .or_else(|| segment.strip_prefix('*'))
+            })
+            .collect()
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "method": self.method,
+            "path": self.path,
+            "path_params": self.path_params(),
+            "query_params": self.query_params,
+            "summary": self.summary,
+        })
+    }
+}
+
+const ROUTES: &[ApiRoute] = &[
+    ApiRoute {
+        method: "GET",
+        path: "/",
+        query_params: &[],
+        summary: "Overview page",
+        handler: || get(Server::root),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/api",
+        query_params: &["format"],
+        summary: "This description of all routes",
+        handler: || get(Server::api),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/metrics",
+        query_params: &[],
+        summary: "Request and upstream failure metrics, in the Prometheus text format",
+        handler: || get(Server::metrics),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/P131/:latitude/:longitude",
+        query_params: &[
+            "radius_km",
+            "limit",
+            "globe",
+            "method",
+            "verbose",
+            "format",
+            "item",
+        ],
+        summary: "P131 (located in) statements for a coordinate",
+        handler: || get(Server::p131),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/P131_chain/:latitude/:longitude",
+        query_params: &[],
+        summary: "The chain of administrative units for a coordinate",
+        handler: || get(Server::p131_chain),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/name_gender/:name",
+        query_params: &["name_order", "format", "item"],
+        summary: "Given name, family name, and gender statements for a person name",
+        handler: || get(Server::name_gender),
+    },
+    ApiRoute {
+        method: "POST",
+        path: "/name_gender/batch",
+        query_params: &["name_order"],
+        summary: "Like /name_gender, for a JSON array of names",
+        handler: || post(Server::name_gender_batch),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/country_year/:item/:year",
+        query_params: &["format"],
+        summary: "P17 (country) statements for a location in a year",
+        handler: || get(Server::country_year),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/country_year/:item/:year/:property",
+        query_params: &["format"],
+        summary: "Like /country_year, with another property than P17",
+        handler: || get(Server::country_year_property),
+    },
+    ApiRoute {
+        method: "POST",
+        path: "/country_cache/invalidate/:item",
+        query_params: &[],
+        summary: "Forgets the cached P17 periods of an item, after edits to its P17 statements",
+        handler: || post(Server::invalidate_country_cache),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/referee/:item",
+        query_params: &[
+            "properties",
+            "include_described_at",
+            "context",
+            "language",
+            "debug",
+            "format",
+        ],
+        summary: "Reference candidates for the statements of an item",
+        handler: || get(Server::referee),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/referee_stream/:item",
+        query_params: &["properties", "include_described_at", "context", "language"],
+        summary: "Like /referee, as server-sent events",
+        handler: || get(Server::referee_stream),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/enrich/:item",
+        query_params: &[],
+        summary: "Statements for an item, inferred from its external IDs",
+        handler: || get(Server::enrich),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/viaf_search/:query",
+        query_params: &["index", "start", "limit", "match_wikidata", "format"],
+        summary: "VIAF records matching a query",
+        handler: || get(Server::viaf_search),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/viaf/statements/:viaf_id",
+        query_params: &["format", "item"],
+        summary: "Statements from a VIAF record",
+        handler: || get(Server::viaf_statements),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/isbn/item/:item",
+        query_params: &["conflicts"],
+        summary: "Statements for the ISBN of an existing item",
+        handler: || get(Server::isbn_item),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/isbn/isbn/:isbn",
+        query_params: &["conflicts", "check_existing"],
+        summary: "A new item for an ISBN",
+        handler: || get(Server::isbn_isbn),
+    },
+    ApiRoute {
+        method: "POST",
+        path: "/isbn/batch",
+        query_params: &[],
+        summary: "Like /isbn/isbn, for a JSON array of ISBNs",
+        handler: || post(Server::isbn_batch),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/initial_search/:query",
+        query_params: &["language", "format"],
+        summary: "Items for humans matching a name",
+        handler: || get(Server::initial_search),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/initial_search/:query/:instance_of",
+        query_params: &["language", "format"],
+        summary: "Like /initial_search, for items of another type",
+        handler: || get(Server::initial_search_of_type),
+    },
+    ApiRoute {
+        method: "POST",
+        path: "/change_wiki/:from/:to",
+        query_params: &["resolve_redirects", "check_disambiguation"],
+        summary: "Page titles on another wiki, for a JSON array of titles",
+        handler: || post(Server::change_wiki),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/title_to_item/:wiki/*title",
+        query_params: &[],
+        summary: "The item for a page on a wiki",
+        handler: || get(Server::title_to_item),
+    },
+    ApiRoute {
+        method: "GET",
+        path: "/cross_categories/:category_item/:language/:depth",
+        query_params: &["petscan_id", "min_count", "format"],
+        summary: "Pages on other wikis in a category, that are not in it on a target wiki",
+        handler: || get(Server::cross_cats),
+    },
+];
+
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Server;
 
@@ -35,34 +412,7 @@ impl Server {
     pub async fn start() -> Result<(), Box<dyn std::error::Error>> {
         tracing_subscriber::fmt::init();
 
-        let cors = CorsLayer::new()
-            .allow_origin(Any)
-            .allow_methods(Any)
-            .allow_headers(Any);
-
-        let app = Router::new()
-            .route("/", get(Self::root))
-            .route("/P131/:latitude/:longitude", get(Self::p131))
-            .route("/name_gender/:name", get(Self::name_gender))
-            .route("/country_year/:item/:year", get(Self::country_year))
-            .route("/referee/:item", get(Self::referee))
-            .route("/viaf_search/:query", get(Self::viaf_search))
-            .route("/isbn/item/:item", get(Self::isbn_item))
-            .route("/isbn/isbn/:isbn", get(Self::isbn_isbn))
-            .route("/initial_search/:query", get(Self::initial_search))
-            .route("/change_wiki/:from/:to", post(Self::change_wiki))
-            .route(
-                "/cross_categories/:category_item/:language/:depth",
-                get(Self::cross_cats),
-            )
-            .route(
-                "/country_year/:item/:year/:property",
-                get(Self::country_year_property),
-            )
-            .layer(TraceLayer::new_for_http())
-            .layer(CompressionLayer::new())
-            .layer(cors);
-
+        let app = Self::router();
         let addr = Self::get_server_address();
         tracing::debug!("listening on {addr}");
         println!("listening on http://{addr}");
@@ -71,9 +421,119 @@ impl Server {
         Ok(())
     }
 
+    fn router() -> Router {
+        Self::router_with_state(AppState::default())
+    }
+
+    fn router_with_state(state: AppState) -> Router {
+        let cors = CorsLayer::new()
+            .allow_origin(Any)
+            .allow_methods(Any)
+            .allow_headers(Any)
+            .expose_headers([HeaderName::from_static(X_REQUEST_ID)]);
+
+        let router = ROUTES
+            .iter()
+            .fold(Router::new(), |router, route| {
+                router.route(route.path, (route.handler)())
+            })
+            .with_state(state);
+        Self::with_limits(
+            router,
+            Self::get_request_timeout(),
+            Self::get_max_concurrent_requests(),
+        )
+        .layer(middleware::from_fn(Self::track_metrics))
+        .layer(TraceLayer::new_for_http().make_span_with(Self::request_span))
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .layer(CompressionLayer::new())
+        .layer(cors)
+    }
+
+    /// Each request gets its own span, tagged with the `X-Request-Id` header.
+    /// A request ID sent by the client is kept, otherwise a new one is generated.
+    fn request_span(request: &Request<Body>) -> Span {
+        let request_id = request
+            .headers()
+            .get(X_REQUEST_ID)
+            .and_then(|id| id.to_str().ok())
+            .unwrap_or_default();
+        tracing::info_span!(
+            "request",
+            method = %request.method(),
+            uri = %request.uri(),
+            request_id,
+        )
+    }
+
+    /// Applies a per-request deadline, and caps the number of requests handled at once.
+    /// Time spent waiting for a free slot counts towards the deadline.
+    fn with_limits(router: Router, timeout: Duration, max_concurrent: usize) -> Router {
+        // Router layers are applied per route, so the limit needs a shared semaphore
+        router.layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(Self::handle_middleware_error))
+                .timeout(timeout)
+                .layer(GlobalConcurrencyLimitLayer::new(max_concurrent)),
+        )
+    }
+
+    /// Counts requests and their latency by route pattern; unknown paths share one label
+    async fn track_metrics(request: Request<Body>, next: Next) -> Response {
+        let route = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or("unmatched", MatchedPath::as_str)
+            .to_string();
+        let method = request.method().to_string();
+        let start = Instant::now();
+        let response = next.run(request).await;
+        Metrics::global().record_request(
+            &method,
+            &route,
+            response.status().as_u16(),
+            start.elapsed(),
+        );
+        response
+    }
+
+    async fn handle_middleware_error(error: BoxError) -> ApiError {
+        if error.is::<tower::timeout::error::Elapsed>() {
+            ApiError::new(StatusCode::GATEWAY_TIMEOUT, "Request timed out")
+        } else {
+            ApiError::new(StatusCode::SERVICE_UNAVAILABLE, error.to_string())
+        }
+    }
+
+    fn get_request_timeout() -> Duration {
+        let seconds = std::env::var("WD_INFERNAL_REQUEST_TIMEOUT").map_or(
+            DEFAULT_REQUEST_TIMEOUT_SECS,
+            |seconds| {
+                seconds
+                    .parse::<u64>()
+                    .unwrap_or(DEFAULT_REQUEST_TIMEOUT_SECS)
+            },
+        );
+        Duration::from_secs(seconds)
+    }
+
+    fn get_max_concurrent_requests() -> usize {
+        std::env::var("WD_INFERNAL_MAX_CONCURRENT_REQUESTS").map_or(
+            DEFAULT_MAX_CONCURRENT_REQUESTS,
+            |max| {
+                max.parse::<usize>()
+                    .unwrap_or(DEFAULT_MAX_CONCURRENT_REQUESTS)
+            },
+        )
+    }
+
     fn get_server_address() -> SocketAddr {
         let port: u16 = std::env::var("WD_INFERNAL_PORT")
-            .map_or(8000, |port| port.as_str().parse::<u16>().unwrap_or(8000));
+            .ok()
+            .and_then(|port| port.parse::<u16>().ok())
+            .or_else(|| Config::get().and_then(|config| config.port))
+            .unwrap_or(8000);
 
         let address = [0, 0, 0, 0];
         // TODOO env::var("WD_INFERNAL_ADDRESS")
@@ -81,18 +541,53 @@ impl Server {
         SocketAddr::from((address, port))
     }
 
+    /// Request and upstream failure metrics, in the Prometheus text format
+    async fn metrics() -> impl IntoResponse {
+        (
+            [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+            Metrics::global().render(),
+        )
+    }
+
+    /// All routes from `ROUTES`, as JSON, or as an HTML table with `?format=html`
+    async fn api(format: Query<Format>) -> Response {
+        if !format.is_html() {
+            let routes: Vec<_> = ROUTES.iter().map(ApiRoute::to_json).collect();
+            return Json(json!({"routes": routes})).into_response();
+        }
+        let rows = ROUTES
+            .iter()
+            .map(|route| {
+                format!(
+                    "<tr><td>{}</td><td><tt>{}</tt></td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    route.method,
+                    Self::escape_html(route.path),
+                    route.path_params().join(", "),
+                    route.query_params.join(", "),
+                    Self::escape_html(route.summary)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        Self::html_page(&format!(
+            "<h1>API</h1><table class='table table-striped'><thead><th>Method</th><th>Path</th><th>Path parameters</th><th>Query parameters</th><th>Summary</th></thead><tbody>{rows}</tbody></table>"
+        ))
+    }
+
     async fn root() -> impl IntoResponse {
         let ret = include_str!("../static/root.html");
         Html(ret)
     }
 
-    fn items2table(items: &[String]) -> String {
+    /// Items without a label in `labels` show their ID instead
+    fn items2table(items: &[String], labels: &HashMap<String, String>) -> String {
         let mut html = items
             .iter()
             .enumerate()
             .map(|(num, q)| {
+                let label = labels.get(q).map_or_else(|| q.to_owned(), |l| Self::escape_html(l));
                 format!(
-                    "<tr><th>{}</th><td><a q='{q}'>{q}</a></td><td><tt>{q}</tt></td><td class='desc' data-q='{q}'><div class='wd-desc'></div><div class='autodesc text-muted small font-italic'></div></td><td class='birth' data-q='{q}'></td><td class='death' data-q='{q}'></td></tr>",
+                    "<tr><th>{}</th><td><a q='{q}'>{label}</a></td><td><tt>{q}</tt></td><td class='desc' data-q='{q}'><div class='wd-desc'></div><div class='autodesc text-muted small font-italic'></div></td><td class='birth' data-q='{q}'></td><td class='death' data-q='{q}'></td></tr>",
                     num + 1
                 )
             })
@@ -104,151 +599,1269 @@ impl Server {
         html
     }
 
+    /// Statements as JSON, or as QuickStatements v1 commands for `item` with `?format=qs`
+    fn statements_response(statements: &[Statement], format: &Format, item: &str) -> Response {
+        if format.is_quickstatements() {
+            let commands = QuickStatements::commands(item, statements);
+            return (
+                [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+                commands,
+            )
+                .into_response();
+        }
+        Json(statements).into_response()
+    }
+
+    /// Wraps an HTML fragment in the result page template
+    fn html_page(html: &str) -> Response {
+        let html = include_str!("../static/result.html").replace("%%RESULT%%", html);
+        Html(html).into_response()
+    }
+
+    fn escape_html(s: &str) -> String {
+        s.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+            .replace('\'', "&#39;")
+    }
+
+    /// A CSV or TSV field. TSV has no quoting, so tabs and line breaks become spaces.
+    fn table_field(value: &str, separator: char) -> String {
+        if separator == '\t' {
+            return value.replace(['\t', '\n', '\r'], " ");
+        }
+        if value.contains([separator, '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+
+    /// A CSV (`,`) or TSV (tab) download with a header row
+    fn table_response(columns: &[&str], rows: &[Vec<String>], separator: char) -> Response {
+        let content_type = if separator == '\t' {
+            "text/tab-separated-values; charset=utf-8"
+        } else {
+            "text/csv; charset=utf-8"
+        };
+        let line = |fields: &[&str]| {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|field| Self::table_field(field, separator))
+                .collect();
+            fields.join(&separator.to_string()) + "\n"
+        };
+        let mut body = line(columns);
+        for row in rows {
+            body += &line(&row.iter().map(String::as_str).collect::<Vec<&str>>());
+        }
+        ([(header::CONTENT_TYPE, content_type)], body).into_response()
+    }
+
+    /// Most frequent first
+    fn sorted_crosscats(results: &HashMap<String, ItemInfo>) -> Vec<(&String, &ItemInfo)> {
+        let mut results: Vec<(&String, &ItemInfo)> = results.iter().collect();
+        results.sort_by(|a, b| b.1.count().cmp(&a.1.count()).then(a.0.cmp(b.0)));
+        results
+    }
+
+    fn crosscats2rows(results: &HashMap<String, ItemInfo>) -> Vec<Vec<String>> {
+        Self::sorted_crosscats(results)
+            .iter()
+            .map(|(q, info)| {
+                vec![
+                    q.to_string(),
+                    info.local_page().unwrap_or_default().to_string(),
+                    info.count().to_string(),
+                ]
+            })
+            .collect()
+    }
+
+    /// One row per matched text
+    fn referee2rows(candidates: &[ConciseUrlCandidate]) -> Vec<Vec<String>> {
+        candidates
+            .iter()
+            .flat_map(|c| {
+                c.texts().iter().map(|tp| {
+                    vec![
+                        c.statement_id().to_string(),
+                        c.property().unwrap_or_default().to_string(),
+                        c.url().to_string(),
+                        format!("{}{}{}", tp.before(), tp.regexp_match(), tp.after()),
+                    ]
+                })
+            })
+            .collect()
+    }
+
+    fn viaf2table(records: &[Record]) -> String {
+        let rows = records
+            .iter()
+            .map(|record| {
+                let id = Self::escape_html(&record.id);
+                let codes = record
+                    .ids
+                    .iter()
+                    .map(|rid| Self::escape_html(&rid.code))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                format!(
+                    "<tr><td><a href='https://viaf.org/viaf/{id}' target='_blank'>{id}</a></td><td>{}</td><td>{}</td><td>{}</td><td>{codes}</td></tr>",
+                    Self::escape_html(&record.label),
+                    Self::escape_html(record.born.as_deref().unwrap_or_default()),
+                    Self::escape_html(record.died.as_deref().unwrap_or_default()),
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        format!(
+            "<table class='table table-striped'><thead><th>VIAF</th><th>Label</th><th>Born</th><th>Died</th><th>Sources</th></thead><tbody>{rows}</tbody></table>"
+        )
+    }
+
+    fn crosscats2table(results: &HashMap<String, ItemInfo>) -> String {
+        let rows = Self::sorted_crosscats(results)
+            .iter()
+            .map(|(q, info)| {
+                let q = Self::escape_html(q);
+                format!(
+                    "<tr><td><a href='https://www.wikidata.org/wiki/{q}' target='_blank'>{q}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    Self::escape_html(info.local_page().unwrap_or_default()),
+                    info.count(),
+                    Self::escape_html(&info.sources().join(", ")),
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        format!(
+            "<table class='table table-striped'><thead><th>Item</th><th>Local page</th><th>Count</th><th>Sources</th></thead><tbody>{rows}</tbody></table>"
+        )
+    }
+
+    /// Candidates are grouped by statement, with the matched text highlighted
+    fn referee2html(candidates: &[ConciseUrlCandidate]) -> String {
+        let mut statement_ids: Vec<&str> = candidates.iter().map(|c| c.statement_id()).collect();
+        statement_ids.sort();
+        statement_ids.dedup();
+        statement_ids
+            .iter()
+            .map(|statement_id| {
+                let rows = candidates
+                    .iter()
+                    .filter(|c| c.statement_id() == *statement_id)
+                    .map(|c| {
+                        let url = Self::escape_html(c.url());
+                        let snippets = c
+                            .texts()
+                            .iter()
+                            .map(|tp| {
+                                format!(
+                                    "<div>&hellip;{}<mark>{}</mark>{}&hellip;</div>",
+                                    Self::escape_html(tp.before()),
+                                    Self::escape_html(tp.regexp_match()),
+                                    Self::escape_html(tp.after()),
+                                )
+                            })
+                            .collect::<String>();
+                        format!(
+                            "<tr><td><a href='{url}' target='_blank'>{url}</a></td><td>{}</td><td>{:.1}</td><td>{snippets}</td></tr>",
+                            Self::escape_html(c.property().unwrap_or_default()),
+                            c.score(),
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                format!(
+                    "<h3><tt>{}</tt></h3><table class='table table-striped'><thead><th>URL</th><th>Property</th><th>Score</th><th>Matches</th></thead><tbody>{rows}</tbody></table>",
+                    Self::escape_html(statement_id)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
     async fn initial_search(
         Path(query): Path<String>,
+        search_params: Query<InitialSearchParams>,
         params: Query<Format>,
-    ) -> Result<impl IntoResponse, StatusCode> {
-        let ret = InitialSearch::run(&query)
+    ) -> Result<Response, ApiError> {
+        Self::initial_search_response(&query, DEFAULT_INSTANCE_OF, &search_params, &params).await
+    }
+
+    async fn initial_search_of_type(
+        Path((query, instance_of)): Path<(String, String)>,
+        search_params: Query<InitialSearchParams>,
+        params: Query<Format>,
+    ) -> Result<Response, ApiError> {
+        Self::initial_search_response(&query, &instance_of, &search_params, &params).await
+    }
+
+    async fn initial_search_response(
+        query: &str,
+        instance_of: &str,
+        search_params: &InitialSearchParams,
+        params: &Format,
+    ) -> Result<Response, ApiError> {
+        let language = search_params.language.as_deref();
+        let ret = InitialSearch::run(query, instance_of, language)
             .await
-            .map_err(|_e| StatusCode::BAD_REQUEST)?;
-        match params.format.as_deref() {
-            Some("html") => {
-                let escaped_query = query.replace('&', "&amp;").replace('"', "&quot;");
-                let form = format!(
-                    "<form id='search-form' class='mb-3'>\
-                        <div class='input-group'>\
-                            <input type='text' id='search-input' class='form-control' value=\"{escaped_query}\" placeholder='Search name'>\
-                            <div class='input-group-append'>\
-                                <button type='submit' class='btn btn-primary'>Search</button>\
-                            </div>\
-                        </div>\
-                    </form>"
-                );
-                let body = if ret.is_empty() {
-                    format!(
-                        "<div class='alert alert-warning' role='alert'>No results found for <strong>{escaped_query}</strong>.</div>"
-                    )
-                } else {
-                    let table = Self::items2table(&ret);
-                    format!("<div class='row'>{table}</div>")
-                };
-                let html = format!("<h1>Results</h1>{form}{body}");
-                let html = include_str!("../static/result.html").replace("%%RESULT%%", &html);
-                Ok(Html(html).into_response())
-            }
-            _ => Ok(Json(ret).into_response()),
+            .map_err(|e| ApiError::from_anyhow(e, ApiError::bad_request))?;
+        if !params.is_html() {
+            return Ok(Json(ret).into_response());
         }
+        let escaped_query = query.replace('&', "&amp;").replace('"', "&quot;");
+        let form = format!(
+            "<form id='search-form' class='mb-3'>\
+                <div class='input-group'>\
+                    <input type='text' id='search-input' class='form-control' value=\"{escaped_query}\" placeholder='Search name'>\
+                    <div class='input-group-append'>\
+                        <button type='submit' class='btn btn-primary'>Search</button>\
+                    </div>\
+                </div>\
+            </form>"
+        );
+        let body = if ret.is_empty() {
+            format!(
+                "<div class='alert alert-warning' role='alert'>No results found for <strong>{escaped_query}</strong>.</div>"
+            )
+        } else {
+            let labels = match LABEL_FETCHER.as_ref() {
+                Some(fetcher) => Wikidata::labels(fetcher, &ret, language.unwrap_or("en")).await,
+                None => HashMap::new(),
+            };
+            let table = Self::items2table(&ret, &labels);
+            format!("<div class='row'>{table}</div>")
+        };
+        let html = format!("<h1>Results</h1>{form}{body}");
+        Ok(Self::html_page(&html))
     }
 
-    async fn name_gender(Path(name): Path<String>) -> Result<impl IntoResponse, StatusCode> {
-        let statements = Person::name_gender(&name).await?;
-        Ok(Json(statements))
+    async fn name_gender(
+        Path(name): Path<String>,
+        params: Query<NameGenderParams>,
+        format: Query<Format>,
+        qs: Query<QuickStatementsParams>,
+    ) -> Result<impl IntoResponse, ApiError> {
+        let order = params.name_order.unwrap_or_default();
+        let statements = Person::name_gender(&name, order).await?;
+        let item = qs.item.as_deref().unwrap_or(LAST);
+        Ok(Self::statements_response(&statements, &format, item))
+    }
+
+    // Pass a JSON array of names as POST payload
+    async fn name_gender_batch(
+        State(state): State<AppState>,
+        params: Query<NameGenderParams>,
+        Json(names): Json<Vec<String>>,
+    ) -> Result<impl IntoResponse, ApiError> {
+        if names.len() > MAX_NAME_GENDER_BATCH_SIZE {
+            return Err(ApiError::bad_request(format!(
+                "At most {MAX_NAME_GENDER_BATCH_SIZE} names per batch"
+            )));
+        }
+        let order = params.name_order.unwrap_or_default();
+        let results =
+            Person::name_gender_batch_with(state.name_search.as_ref(), &names, order).await;
+        Ok(Json(Self::name_gender_batch_json(results)))
+    }
+
+    /// The statements for each name, or the error for it
+    fn name_gender_batch_json(
+        results: HashMap<String, Result<Vec<Statement>, StatusCode>>,
+    ) -> serde_json::Map<String, serde_json::Value> {
+        results
+            .into_iter()
+            .map(|(name, result)| {
+                let value = match result {
+                    Ok(statements) => json!(statements),
+                    Err(status) => ApiError::from(status).to_json(),
+                };
+                (name, value)
+            })
+            .collect()
     }
 
     async fn p131(
         Path((latitude, longitude)): Path<(f64, f64)>,
-    ) -> Result<impl IntoResponse, StatusCode> {
-        let statements = Location::p131(latitude, longitude).await?;
+        params: Query<P131Params>,
+        format: Query<Format>,
+        qs: Query<QuickStatementsParams>,
+    ) -> Result<impl IntoResponse, ApiError> {
+        let radius_km = params.radius_km.unwrap_or(DEFAULT_P131_RADIUS_KM);
+        let limit = params.limit.unwrap_or(DEFAULT_P131_LIMIT);
+        let globe = params.globe.as_deref().unwrap_or(EARTH);
+        let method = params.method.unwrap_or_default();
+        // With the source item of each statement, for verification
+        if params.verbose.as_deref() == Some("1") {
+            let suggestions = Location::p131_suggestions_on_globe(
+                latitude, longitude, radius_km, limit, globe, method,
+            )
+            .await?;
+            return Ok(Json(suggestions).into_response());
+        }
+        let statements =
+            Location::p131_on_globe(latitude, longitude, radius_km, limit, globe, method).await?;
+        let item = qs.item.as_deref().unwrap_or(LAST);
+        Ok(Self::statements_response(&statements, &format, item))
+    }
+
+    async fn p131_chain(
+        Path((latitude, longitude)): Path<(f64, f64)>,
+    ) -> Result<impl IntoResponse, ApiError> {
+        let statements = Location::p131_chain(latitude, longitude).await?;
         Ok(Json(statements))
     }
 
     // Pass "from" and "to" wikis as parameters
     // Pass a JSON array of full titles as POST payload
     async fn change_wiki(
+        State(state): State<AppState>,
         Path((from, to)): Path<(String, String)>,
+        params: Query<ChangeWikiParams>,
         Json(payload): Json<serde_json::Value>,
-    ) -> Result<impl IntoResponse, StatusCode> {
+    ) -> Result<impl IntoResponse, ApiError> {
         let full_titles: Vec<String> = payload
             .as_array()
-            .ok_or(StatusCode::BAD_REQUEST)?
+            .ok_or_else(|| ApiError::bad_request("Payload must be a JSON array of titles"))?
             .iter()
             .filter_map(|v| v.as_str().map(str::to_string))
             .collect();
-        let cw = crate::change_wiki::ChangeWiki::new(&from, full_titles);
-        let results = cw.convert(&to).await.map_err(|_| StatusCode::NOT_FOUND)?;
-        let results = json!(results);
+        let resolve_redirects = params.resolve_redirects.as_deref() == Some("1");
+        let cw = ChangeWiki::new(&from, full_titles)
+            .with_sources(state.change_wiki)
+            .with_redirect_resolution(resolve_redirects);
+        // Target pages become objects with a `disambiguation` flag
+        // An array, to keep the input order
+        let results = if params.check_disambiguation.as_deref() == Some("1") {
+            cw.convert_all_with_disambiguations(&to)
+                .await
+                .map(Self::title_targets)
+        } else {
+            cw.convert_all(&to).await.map(Self::title_targets)
+        };
+        let results = results.map_err(|e| ApiError::from_anyhow(e, ApiError::not_found))?;
         Ok(Json(results))
     }
 
+    fn title_targets<T: Serialize>(results: Vec<(String, Option<T>)>) -> Vec<serde_json::Value> {
+        results
+            .into_iter()
+            .map(|(title, target)| json!({"title": title, "target": target}))
+            .collect()
+    }
+
+    /// Titles may contain slashes, so the title is the rest of the path
+    async fn title_to_item(
+        State(state): State<AppState>,
+        Path((wiki, title)): Path<(String, String)>,
+    ) -> Result<impl IntoResponse, ApiError> {
+        match ChangeWiki::title_to_item(&state.change_wiki, &wiki, &title)
+            .await
+            .map_err(|e| ApiError::from_anyhow(e, ApiError::internal))?
+        {
+            Some(title_item) => Ok(Json(title_item)),
+            None => Err(ApiError::not_found(format!(
+                "No item for page {title} on {wiki}"
+            ))),
+        }
+    }
+
     async fn cross_cats(
+        State(state): State<AppState>,
         Path((category_item, language, depth)): Path<(String, String, u32)>,
-    ) -> Result<impl IntoResponse, StatusCode> {
-        let results = CrossCats::cross_cats(&category_item, depth, &language).await?;
-        Ok(Json(results))
+        params: Query<CrossCatsParams>,
+        format: Query<Format>,
+    ) -> Result<impl IntoResponse, ApiError> {
+        let petscan_id = params
+            .petscan_id
+            .unwrap_or_else(|| CrossCats::petscan_id_from_config(Config::crosscats()));
+        let min_count = params.min_count.unwrap_or(DEFAULT_MIN_COUNT);
+        let results = state
+            .cross_cats
+            .suggestions(&category_item, depth, &language, petscan_id, min_count)
+            .await?;
+        if format.is_html() {
+            let html = format!(
+                "<h1>Cross-category suggestions for {}</h1>{}",
+                Self::escape_html(&category_item),
+                Self::crosscats2table(&results)
+            );
+            return Ok(Self::html_page(&html));
+        }
+        if let Some(separator) = format.table_separator() {
+            let columns = ["item", "local_page", "count"];
+            let rows = Self::crosscats2rows(&results);
+            return Ok(Self::table_response(&columns, &rows, separator));
+        }
+        Ok(Json(results).into_response())
+    }
+
+    async fn isbn_isbn(
+        State(state): State<AppState>,
+        Path(isbn): Path<String>,
+        params: Query<IsbnParams>,
+    ) -> Result<impl IntoResponse, ApiError> {
+        let (isbn2wiki, ret) = Self::item_for_isbn(state.isbn_source.as_ref(), &isbn).await?;
+        let mut ret = json!({
+            "item": ret,
+            "cover_image": isbn2wiki.cover_image(),
+            "contributors": isbn2wiki.contributors(),
+        });
+        if params.wants_conflicts() {
+            let conflicts = isbn2wiki
+                .conflicts()
+                .map_err(|e| ApiError::internal(e.to_string()))?;
+            ret["conflicts"] = json!(conflicts);
+        }
+        // Items to patch instead of creating a duplicate
+        if params.wants_existing_check() {
+            let existing_items = isbn2wiki
+                .existing_items()
+                .await
+                .map_err(|e| ApiError::new(StatusCode::SERVICE_UNAVAILABLE, e.to_string()))?;
+            ret["existing_items"] = json!(existing_items);
+        }
+        Ok(Json(ret))
     }
 
-    async fn isbn_isbn(Path(isbn): Path<String>) -> Result<impl IntoResponse, StatusCode> {
-        let mut isbn2wiki = ISBN2wiki::new(&isbn).ok_or(StatusCode::NOT_FOUND)?;
-        isbn2wiki
-            .retrieve()
+    async fn item_for_isbn(
+        source: &dyn IsbnSource,
+        isbn: &str,
+    ) -> Result<(ISBN2wiki, Item), ApiError> {
+        let mut isbn2wiki = ISBN2wiki::new(isbn)?;
+        source
+            .retrieve(&mut isbn2wiki)
             .await
-            .map_err(|_| StatusCode::NOT_FOUND)?;
-        let ret = isbn2wiki
+            .map_err(|e| ApiError::not_found(e.to_string()))?;
+        let item = isbn2wiki
             .generate_item()
-            .map_err(|_| StatusCode::NOT_FOUND)?;
-        let ret = json!({"item": ret});
-        Ok(Json(ret))
+            .map_err(|e| ApiError::not_found(e.to_string()))?;
+        Ok((isbn2wiki, item))
+    }
+
+    // Pass a JSON array of ISBNs as POST payload
+    async fn isbn_batch(
+        State(state): State<AppState>,
+        Json(isbns): Json<Vec<String>>,
+    ) -> Result<impl IntoResponse, ApiError> {
+        if isbns.len() > MAX_ISBN_BATCH_SIZE {
+            return Err(ApiError::bad_request(format!(
+                "At most {MAX_ISBN_BATCH_SIZE} ISBNs per batch"
+            )));
+        }
+        let source = state.isbn_source.as_ref();
+        let results: serde_json::Map<String, serde_json::Value> = futures::stream::iter(isbns)
+            .map(|isbn| async move {
+                let value = match Self::item_for_isbn(source, &isbn).await {
+                    Ok((isbn2wiki, item)) => {
+                        json!({
+                            "item": item,
+                            "cover_image": isbn2wiki.cover_image(),
+                            "contributors": isbn2wiki.contributors(),
+                        })
+                    }
+                    Err(e) => e.to_json(),
+                };
+                (isbn, value)
+            })
+            .buffer_unordered(ISBN_BATCH_CONCURRENCY)
+            .collect()
+            .await;
+        Ok(Json(results))
     }
 
-    async fn isbn_item(Path(item): Path<String>) -> Result<impl IntoResponse, StatusCode> {
+    async fn isbn_item(
+        State(state): State<AppState>,
+        Path(item): Path<String>,
+        params: Query<IsbnParams>,
+    ) -> Result<impl IntoResponse, ApiError> {
         let mut isbn2wiki = ISBN2wiki::new_from_item(&item)
             .await
-            .ok_or(StatusCode::NOT_FOUND)?;
-        isbn2wiki
-            .retrieve()
+            .ok_or_else(|| ApiError::not_found(format!("No ISBN found for {item}")))?;
+        state
+            .isbn_source
+            .retrieve(&mut isbn2wiki)
             .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            .map_err(|e| ApiError::internal(e.to_string()))?;
         let patch = isbn2wiki
             .generate_patch(&item)
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            .map_err(|e| ApiError::internal(e.to_string()))?;
         let ret = patch.patch().to_owned();
-        Ok(Json(ret))
+        if params.wants_conflicts() {
+            let conflicts = isbn2wiki
+                .conflicts()
+                .map_err(|e| ApiError::internal(e.to_string()))?;
+            return Ok(Json(json!({
+                "patch": ret,
+                "conflicts": conflicts,
+                "cover_image": isbn2wiki.cover_image(),
+                "contributors": isbn2wiki.contributors(),
+            })));
+        }
+        Ok(Json(json!(ret)))
+    }
+
+    async fn viaf_search(
+        State(state): State<AppState>,
+        Path(query): Path<String>,
+        params: Query<ViafParams>,
+        format: Query<Format>,
+    ) -> Result<impl IntoResponse, ApiError> {
+        let index = params.index.unwrap_or_default();
+        let start = params.start.unwrap_or(1);
+        let limit = params.limit.unwrap_or(DEFAULT_MAXIMUM_RECORDS);
+        let mut results = state
+            .viaf_search
+            .search(&query, index, start, limit)
+            .await?;
+        if params.wants_wikidata_match() {
+            crate::viaf::match_wikidata(&mut results.records).await?;
+        }
+        if format.is_html() {
+            let html = format!(
+                "<h1>VIAF results for <i>{}</i></h1>{}",
+                Self::escape_html(&query),
+                Self::viaf2table(&results.records)
+            );
+            return Ok(Self::html_page(&html));
+        }
+        Ok(Json(results).into_response())
+    }
+
+    async fn viaf_statements(
+        Path(viaf_id): Path<String>,
+        format: Query<Format>,
+        qs: Query<QuickStatementsParams>,
+    ) -> Result<impl IntoResponse, ApiError> {
+        if !crate::viaf::is_valid_viaf_id(&viaf_id) {
+            return Err(ApiError::bad_request(format!("Invalid VIAF ID: {viaf_id}")));
+        }
+        let statements = crate::viaf::cluster_statements(&viaf_id).await?;
+        let item = qs.item.as_deref().unwrap_or(LAST);
+        Ok(Self::statements_response(&statements, &format, item))
     }
 
-    async fn viaf_search(Path(query): Path<String>) -> Result<impl IntoResponse, StatusCode> {
-        let results = crate::viaf::search_viaf_for_local_names(&query)
+    async fn referee(
+        State(state): State<AppState>,
+        Path(item): Path<String>,
+        format: Query<Format>,
+        Query(params): Query<RefereeParams>,
+    ) -> Result<impl IntoResponse, ApiError> {
+        let mut referee = Self::new_referee(&state.referee, &params).await?;
+        let results = referee
+            .get_potential_references(&item)
             .await
-            .map_err(|_| StatusCode::NOT_FOUND)?;
-        Ok(Json(results))
+            .map_err(|e| ApiError::from_anyhow(e, ApiError::not_found))?;
+        if format.is_html() {
+            let html = format!(
+                "<h1>Potential references for {}</h1>{}",
+                Self::escape_html(&item),
+                Self::referee2html(&results)
+            );
+            return Ok(Self::html_page(&html));
+        }
+        if let Some(separator) = format.table_separator() {
+            let columns = ["statement_id", "property", "url", "snippet"];
+            let rows = Self::referee2rows(&results);
+            return Ok(Self::table_response(&columns, &rows, separator));
+        }
+        if let Some(debug_log) = referee.debug_log() {
+            let json = json!({"candidates": results, "debug": debug_log.summary()});
+            return Ok(Json(json).into_response());
+        }
+        Ok(Json(results).into_response())
     }
 
-    async fn referee(Path(item): Path<String>) -> Result<impl IntoResponse, StatusCode> {
-        let results = Referee::new()
+    /// Server-sent events, one `candidate` event per reference candidate,
+    /// followed by a `done` event.
+    async fn referee_stream(
+        State(state): State<AppState>,
+        Path(item): Path<String>,
+        Query(params): Query<RefereeParams>,
+    ) -> Result<impl IntoResponse, ApiError> {
+        let candidates = Self::new_referee(&state.referee, &params)
+            .await?
+            .stream_potential_references(&item)
             .await
-            .map_err(|_| StatusCode::NOT_FOUND)?
-            .get_potential_references(&item)
+            .map_err(|e| ApiError::from_anyhow(e, ApiError::not_found))?;
+        let events = candidates
+            .map(|candidate| Event::default().event("candidate").json_data(candidate))
+            .chain(stream::once(async {
+                Ok(Event::default().event("done").data(""))
+            }));
+        Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+    }
+
+    /// A clone of the shared `Referee`, so properties loaded for earlier requests are reused.
+    /// Items are loaded for each request, so edits on Wikidata are seen.
+    async fn new_referee(
+        shared: &OnceCell<Referee>,
+        params: &RefereeParams,
+    ) -> Result<Referee, ApiError> {
+        let mut referee = shared
+            .get_or_try_init(Referee::new)
             .await
-            .map_err(|_| StatusCode::NOT_FOUND)?;
+            .map_err(|e| ApiError::internal(e.to_string()))?
+            .clone()
+            .with_fresh_items();
+        if let Some(properties) = &params.properties {
+            let properties = referee
+                .parse_properties(properties)
+                .map_err(|e| ApiError::bad_request(e.to_string()))?;
+            referee = referee.with_properties(properties);
+        }
+        if let Some(context) = params.context {
+            referee = referee
+                .with_context_chars(context)
+                .map_err(|e| ApiError::bad_request(e.to_string()))?;
+        }
+        if let Some(language) = &params.language {
+            referee = referee
+                .with_pattern_language(language)
+                .map_err(|e| ApiError::bad_request(e.to_string()))?;
+        }
+        if params.debug.as_deref() == Some("1") {
+            referee = referee.with_debug_log();
+        }
+        match params.include_described_at.as_deref() {
+            Some("1") => referee = referee.with_described_at(true),
+            Some("0") => referee = referee.with_described_at(false),
+            _ => {}
+        }
+        Ok(referee)
+    }
+
+    async fn enrich(Path(item): Path<String>) -> Result<impl IntoResponse, ApiError> {
+        let results = Enrich::enrich(&item).await?;
         Ok(Json(results))
     }
 
     async fn country_year(
         Path((item, year)): Path<(String, i32)>,
-    ) -> Result<impl IntoResponse, StatusCode> {
+        format: Query<Format>,
+    ) -> Result<impl IntoResponse, ApiError> {
         let statements = Location::country_for_location_and_date(&item, year).await?;
-        Ok(Json(statements))
+        Ok(Self::statements_response(&statements, &format, &item))
     }
 
     async fn country_year_property(
         Path((item, year, property)): Path<(String, i32, String)>,
-    ) -> Result<impl IntoResponse, StatusCode> {
+        format: Query<Format>,
+    ) -> Result<impl IntoResponse, ApiError> {
         let mut statements = Location::country_for_location_and_date(&item, year).await?;
         for statement in &mut statements {
             statement.set_property(&property.to_uppercase());
         }
-        Ok(Json(statements))
+        Ok(Self::statements_response(&statements, &format, &item))
+    }
+
+    async fn invalidate_country_cache(Path(item): Path<String>) -> StatusCode {
+        CountryCache::global().invalidate(&item);
+        StatusCode::NO_CONTENT
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fetcher::ContentFetcher;
+    use crate::test_server;
+    use futures::future::BoxFuture;
+
+    /// Serves the router on a random local port, returning its base URL
+    async fn spawn_server() -> String {
+        test_server::serve(Server::router()).await
+    }
+
+    /// Like `spawn_server`, with the sources in `state`
+    async fn spawn_server_with_state(state: AppState) -> String {
+        test_server::serve(Server::router_with_state(state)).await
+    }
+
+    #[tokio::test]
+    async fn test_response_has_generated_request_id() {
+        let base = spawn_server().await;
+        let response = reqwest::get(format!("{base}/")).await.unwrap();
+        let request_id = response.headers()["x-request-id"].to_str().unwrap();
+        assert!(!request_id.is_empty());
+
+        let other = reqwest::get(format!("{base}/")).await.unwrap();
+        assert_ne!(
+            other.headers()["x-request-id"].to_str().unwrap(),
+            request_id
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metrics_after_request() {
+        let base = spawn_server().await;
+        reqwest::get(format!("{base}/")).await.unwrap();
+        let response = reqwest::get(format!("{base}/metrics")).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let text = response.text().await.unwrap();
+        assert!(text.contains("http_requests_total{method=\"GET\",route=\"/\",status=\"200\"}"));
+        // Every sample is a metric name with labels, and a number
+        for line in text.lines().filter(|line| !line.starts_with('#')) {
+            let (name, value) = line.rsplit_once(' ').unwrap();
+            assert!(name.contains('{'), "{line}");
+            assert!(value.parse::<f64>().is_ok(), "{line}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_api_lists_known_routes() {
+        let base = spawn_server().await;
+        let json: serde_json::Value = reqwest::get(format!("{base}/api"))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let routes = json["routes"].as_array().unwrap();
+        let paths: Vec<&str> = routes
+            .iter()
+            .map(|route| route["path"].as_str().unwrap())
+            .collect();
+        for path in [
+            "/",
+            "/api",
+            "/metrics",
+            "/P131/:latitude/:longitude",
+            "/P131_chain/:latitude/:longitude",
+            "/name_gender/:name",
+            "/name_gender/batch",
+            "/country_year/:item/:year",
+            "/country_year/:item/:year/:property",
+            "/referee/:item",
+            "/referee_stream/:item",
+            "/enrich/:item",
+            "/viaf_search/:query",
+            "/viaf/statements/:viaf_id",
+            "/isbn/item/:item",
+            "/isbn/isbn/:isbn",
+            "/isbn/batch",
+            "/initial_search/:query",
+            "/initial_search/:query/:instance_of",
+            "/change_wiki/:from/:to",
+            "/title_to_item/:wiki/*title",
+            "/cross_categories/:category_item/:language/:depth",
+        ] {
+            assert!(paths.contains(&path), "{path} is missing from /api");
+        }
+        let change_wiki = routes
+            .iter()
+            .find(|route| route["path"] == "/change_wiki/:from/:to")
+            .unwrap();
+        assert_eq!(change_wiki["method"], "POST");
+        assert_eq!(change_wiki["path_params"], json!(["from", "to"]));
+        assert_eq!(
+            change_wiki["query_params"],
+            json!(["resolve_redirects", "check_disambiguation"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_api_html() {
+        let base = spawn_server().await;
+        let response = reqwest::get(format!("{base}/api?format=html"))
+            .await
+            .unwrap();
+        assert!(
+            response.headers()["content-type"]
+                .to_str()
+                .unwrap()
+                .starts_with("text/html")
+        );
+        let html = response.text().await.unwrap();
+        assert!(html.contains("<tt>/title_to_item/:wiki/*title</tt>"));
+    }
+
+    #[test]
+    fn test_api_route_path_params() {
+        let route = ROUTES
+            .iter()
+            .find(|route| route.path == "/title_to_item/:wiki/*title")
+            .unwrap();
+        assert_eq!(route.path_params(), vec!["wiki", "title"]);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_country_cache() {
+        let base = spawn_server().await;
+        let response = reqwest::Client::new()
+            .post(format!("{base}/country_cache/invalidate/Q365"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_provided_request_id_is_echoed() {
+        let base = spawn_server().await;
+        let response = reqwest::Client::new()
+            .get(format!("{base}/isbn/isbn/123"))
+            .header("X-Request-Id", "report-42")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["x-request-id"], "report-42");
+    }
+
+    #[tokio::test]
+    async fn test_isbn_batch_returns_entry_per_isbn() {
+        let state = AppState {
+            isbn_source: Arc::new(GoogleBooksFixture),
+            ..AppState::default()
+        };
+        let base = spawn_server_with_state(state).await;
+        let response = reqwest::Client::new()
+            .post(format!("{base}/isbn/batch"))
+            .json(&json!(["9782267027006", "123"]))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let json: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(json["123"]["error"]["code"], 400);
+        let item = &json["9782267027006"]["item"];
+        assert!(item.is_object(), "no item in {json}");
+    }
+
+    /// Loads the Google Books fixture, which is for ISBN 9782267027006
+    #[derive(Debug)]
+    struct GoogleBooksFixture;
+
+    impl IsbnSource for GoogleBooksFixture {
+        fn retrieve<'a>(
+            &'a self,
+            isbn2wiki: &'a mut ISBN2wiki,
+        ) -> BoxFuture<'a, anyhow::Result<()>> {
+            let xml = include_str!("../test_files/google_books.xml");
+            let result =
+                crate::google_books::GoogleBooksFeed::parse_google_books_xml(isbn2wiki, xml);
+            Box::pin(async move { result })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_isbn_batch_rejects_oversized_batch() {
+        let base = spawn_server().await;
+        let isbns = vec!["9782267027006"; MAX_ISBN_BATCH_SIZE + 1];
+        let response = reqwest::Client::new()
+            .post(format!("{base}/isbn/batch"))
+            .json(&isbns)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_name_gender_batch_json_has_entry_per_name() {
+        let statement =
+            Statement::new_normal(wikibase::Snak::new_item("P21", "Q6581097"), vec![], vec![]);
+        let results = HashMap::from([
+            ("Heinrich Manske".to_string(), Ok(vec![statement.clone()])),
+            (String::new(), Ok(vec![])),
+            ("Heinrich Heine".to_string(), Err(StatusCode::BAD_GATEWAY)),
+        ]);
+        let json = json!(Server::name_gender_batch_json(results));
+        assert_eq!(json["Heinrich Manske"], json!([statement]));
+        assert_eq!(json[""], json!([]));
+        assert_eq!(json["Heinrich Heine"]["error"]["code"], 502);
+    }
+
+    #[tokio::test]
+    async fn test_name_gender_batch_rejects_oversized_batch() {
+        let base = spawn_server().await;
+        let names = vec!["Heinrich Manske"; MAX_NAME_GENDER_BATCH_SIZE + 1];
+        let response = reqwest::Client::new()
+            .post(format!("{base}/name_gender/batch"))
+            .json(&names)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    /// Canned name items for "Heinrich Manske" and "Heinrich Heine", recording every search
+    #[derive(Debug, Default)]
+    struct CountingNameSearch {
+        searches: std::sync::Mutex<Vec<(String, String)>>,
+    }
+
+    impl NameSearch for CountingNameSearch {
+        fn search<'a>(
+            &'a self,
+            name: &'a str,
+            class: &'a str,
+        ) -> BoxFuture<'a, Result<Vec<String>, StatusCode>> {
+            self.searches
+                .lock()
+                .unwrap()
+                .push((name.to_string(), class.to_string()));
+            let items = match (name, class) {
+                ("Heinrich", "Q12308941") => vec!["Q1".to_string()], // male given name
+                ("Manske", "Q101352") => vec!["Q2".to_string()],     // family name
+                ("Heine", "Q101352") => vec!["Q3".to_string()],
+                _ => vec![],
+            };
+            Box::pin(async move { Ok(items) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_name_gender_batch_searches_shared_first_name_once() {
+        let search = Arc::new(CountingNameSearch::default());
+        let state = AppState {
+            name_search: search.clone(),
+            ..AppState::default()
+        };
+        let base = spawn_server_with_state(state).await;
+        let response = reqwest::Client::new()
+            .post(format!("{base}/name_gender/batch"))
+            .json(&["Heinrich Manske", "Heinrich Heine"])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let json: serde_json::Value = response.json().await.unwrap();
+        for (name, surname) in [("Heinrich Manske", "Q2"), ("Heinrich Heine", "Q3")] {
+            let values: Vec<&str> = json[name]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter_map(|statement| statement["mainsnak"]["datavalue"]["value"]["id"].as_str())
+                .collect();
+            assert_eq!(values, vec![surname, "Q6581097", "Q1"]);
+        }
+        let searches = search.searches.lock().unwrap();
+        let first_name_searches = searches
+            .iter()
+            .filter(|(name, class)| name == "Heinrich" && class == "Q12308941")
+            .count();
+        assert_eq!(first_name_searches, 1);
+        assert_eq!(searches.len(), 4);
+    }
+
+    // ── HTML output ───────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_format_is_html() {
+        let html = Format {
+            format: Some("html".to_string()),
+        };
+        assert!(html.is_html());
+        assert!(!Format { format: None }.is_html());
+    }
+
+    #[test]
+    fn test_html_page_content_type() {
+        let response = Server::html_page("<p>test</p>");
+        let content_type = response.headers()[axum::http::header::CONTENT_TYPE]
+            .to_str()
+            .unwrap();
+        assert!(content_type.starts_with("text/html"));
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            Server::escape_html("<a href='x'>&\"</a>"),
+            "&lt;a href=&#39;x&#39;&gt;&amp;&quot;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_viaf2table_links_records() {
+        let records = vec![Record {
+            id: "113230702".to_string(),
+            label: "Adams, Douglas".to_string(),
+            raw_label: "Adams, Douglas, 1952-".to_string(),
+            born: Some("1952".to_string()),
+            died: None,
+            isni: None,
+            ids: vec![],
+            wikidata: None,
+        }];
+        let html = Server::viaf2table(&records);
+        assert!(html.contains("href='https://viaf.org/viaf/113230702'"));
+        assert!(html.contains("<td>Adams, Douglas</td>"));
+    }
+
+    #[test]
+    fn test_referee2html_empty() {
+        assert_eq!(Server::referee2html(&[]), "");
+    }
+
+    #[test]
+    fn test_format_table_separator() {
+        let format = |f: &str| Format {
+            format: Some(f.to_string()),
+        };
+        assert_eq!(format("csv").table_separator(), Some(','));
+        assert_eq!(format("tsv").table_separator(), Some('\t'));
+        assert_eq!(format("html").table_separator(), None);
+    }
+
+    #[tokio::test]
+    async fn test_statements_response_quickstatements() {
+        let statements = vec![Statement::new_normal(
+            wikibase::Snak::new_item("P17", "Q183"),
+            vec![],
+            vec![],
+        )];
+        let format = Format {
+            format: Some("qs".to_string()),
+        };
+        let response = Server::statements_response(&statements, &format, "Q64");
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(body_text(response).await, "Q64\tP17\tQ183\n");
+        let json = Server::statements_response(&statements, &Format { format: None }, "Q64");
+        assert_eq!(json.headers()[header::CONTENT_TYPE], "application/json");
+    }
+
+    #[test]
+    fn test_table_field_escaping() {
+        assert_eq!(Server::table_field("plain", ','), "plain");
+        assert_eq!(
+            Server::table_field("Adams, \"DNA\"", ','),
+            "\"Adams, \"\"DNA\"\"\""
+        );
+        assert_eq!(Server::table_field("a\tb\nc", '\t'), "a b c");
+    }
+
+    async fn body_text(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_crosscats_csv() {
+        let results: HashMap<String, ItemInfo> = serde_json::from_value(json!({
+            "Q64": {"count": 3, "local_page": "Berlin, Germany", "already_in_category": false, "sources": ["enwiki"]}
+        }))
+        .unwrap();
+        let rows = Server::crosscats2rows(&results);
+        let response = Server::table_response(&["item", "local_page", "count"], &rows, ',');
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/csv; charset=utf-8"
+        );
+        assert_eq!(
+            body_text(response).await,
+            "item,local_page,count\nQ64,\"Berlin, Germany\",3\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_referee_tsv() {
+        let candidates: Vec<ConciseUrlCandidate> = serde_json::from_value(json!([{
+            "statement_id": "Q42$abc",
+            "url": "https://example.org/adams",
+            "property": "P214",
+            "external_id": "113230702",
+            "stated_in": null,
+            "language": "en",
+            "texts": [{"before": "born ", "regexp_match": "1952", "after": "\tin Cambridge"}],
+            "score": 3.0
+        }]))
+        .unwrap();
+        let rows = Server::referee2rows(&candidates);
+        let columns = ["statement_id", "property", "url", "snippet"];
+        let response = Server::table_response(&columns, &rows, '\t');
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/tab-separated-values; charset=utf-8"
+        );
+        assert_eq!(
+            body_text(response).await,
+            "statement_id\tproperty\turl\tsnippet\nQ42$abc\tP214\thttps://example.org/adams\tborn 1952 in Cambridge\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_title_to_item_known_title() {
+        let state = AppState {
+            change_wiki: crate::change_wiki::tests::canned_sources().await,
+        };
+        let base = spawn_server_with_state(state).await;
+        let response = reqwest::get(format!("{base}/title_to_item/enwiki/Magnus_Manske"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let json: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(
+            json,
+            json!({"item": "Q13520818", "resolved_title": "Magnus Manske", "via_redirect": false})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_viaf_statements_rejects_invalid_id() {
+        let base = spawn_server().await;
+        let response = reqwest::get(format!("{base}/viaf/statements/abc"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    /// Serves the VIAF records of `test_files/viaf.json`
+    #[derive(Debug)]
+    struct ViafFixture;
+
+    impl ViafSearch for ViafFixture {
+        fn search<'a>(
+            &'a self,
+            _query: &'a str,
+            _index: ViafIndex,
+            start_record: u32,
+            _maximum_records: u32,
+        ) -> BoxFuture<'a, Result<crate::viaf::SearchResults, crate::viaf::ViafError>> {
+            Box::pin(async move {
+                let value = serde_json::from_str(include_str!("../test_files/viaf.json")).unwrap();
+                let records = crate::viaf::parse_viaf_response(&value);
+                Ok(crate::viaf::SearchResults {
+                    total: records.len() as u64,
+                    start_record,
+                    records,
+                })
+            })
+        }
+    }
+
+    /// Suggests one item for any category
+    #[derive(Debug)]
+    struct CrossCatsFixture;
+
+    impl CrossCatsSource for CrossCatsFixture {
+        fn suggestions<'a>(
+            &'a self,
+            _category_item_id: &'a str,
+            _depth: u32,
+            _target_language: &'a str,
+            _petscan_id: u64,
+            _min_count: usize,
+        ) -> BoxFuture<'a, Result<HashMap<String, ItemInfo>, crate::crosscats::CrossCatsError>>
+        {
+            let info = serde_json::from_value(json!({
+                "count": 3,
+                "local_page": "Paris",
+                "already_in_category": false,
+                "sources": ["dewiki", "frwiki", "itwiki"],
+            }))
+            .unwrap();
+            Box::pin(async move { Ok(HashMap::from([("Q90".to_string(), info)])) })
+        }
+    }
+
+    async fn get_content_type(state: AppState, path: &str) -> String {
+        let base = spawn_server_with_state(state).await;
+        let response = reqwest::get(format!("{base}{path}")).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        response.headers()[reqwest::header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_viaf_search_html_content_type() {
+        let state = AppState {
+            viaf_search: Arc::new(ViafFixture),
+            ..AppState::default()
+        };
+        let content_type =
+            get_content_type(state, "/viaf_search/Douglas%20Adams?format=html").await;
+        assert!(content_type.starts_with("text/html"));
+    }
+
+    #[tokio::test]
+    async fn test_cross_categories_html_content_type() {
+        let state = AppState {
+            cross_cats: Arc::new(CrossCatsFixture),
+            ..AppState::default()
+        };
+        let content_type = get_content_type(
+            state,
+            "/cross_categories/Q7486603/en/1?petscan_id=1&format=html",
+        )
+        .await;
+        assert!(content_type.starts_with("text/html"));
+    }
+
+    #[tokio::test]
+    async fn test_referee_html_content_type() {
+        let referee = crate::referee::tests::canned_referee().await;
+        let state = AppState {
+            referee: Arc::new(OnceCell::new_with(Some(referee))),
+            ..AppState::default()
+        };
+        let content_type = get_content_type(state, "/referee/Q100?format=html").await;
+        assert!(content_type.starts_with("text/html"));
+    }
+
+    // ── Timeouts and concurrency limits ───────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_slow_handler_times_out() {
+        let router = Router::new().route(
+            "/slow",
+            get(|| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                "done"
+            }),
+        );
+        let router = Server::with_limits(router, Duration::from_millis(50), 4);
+        let base = test_server::serve(router).await;
+
+        let response = reqwest::get(format!("{base}/slow")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        let json: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(json["error"]["code"], 504);
+    }
+
+    #[tokio::test]
+    async fn test_fast_handler_is_not_limited() {
+        let router = Router::new().route("/fast", get(|| async { "done" }));
+        let router = Server::with_limits(router, Duration::from_secs(5), 1);
+        let base = test_server::serve(router).await;
+
+        let response = reqwest::get(format!("{base}/fast")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "done");
+    }
+
+    // ── ApiError responses ────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_bad_isbn_returns_json_error() {
+        let base = spawn_server().await;
+        let response = reqwest::get(format!("{base}/isbn/isbn/123")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let json: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(json["error"]["code"], 400);
+        assert!(
+            json["error"]["message"]
+                .as_str()
+                .is_some_and(|m| m.contains("expected 10 or 13")),
+            "error message should say why the ISBN was rejected"
+        );
+    }
 
     // ── items2table ───────────────────────────────────────────────────────────
 
     #[test]
     fn test_items2table_empty_slice() {
-        let html = Server::items2table(&[]);
+        let html = Server::items2table(&[], &HashMap::new());
         // Must still produce a valid table shell
         assert!(html.contains("<table"), "should contain opening table tag");
         assert!(html.contains("<tbody></tbody>"), "tbody should be empty");
@@ -256,7 +1869,7 @@ mod tests {
 
     #[test]
     fn test_items2table_single_item() {
-        let html = Server::items2table(&["Q42".to_string()]);
+        let html = Server::items2table(&["Q42".to_string()], &HashMap::new());
         // Row number starts at 1
         assert!(html.contains("<th>1</th>"), "first row number should be 1");
         // The item ID appears as the q= attribute value
@@ -276,7 +1889,7 @@ mod tests {
     #[test]
     fn test_items2table_multiple_items_numbered_correctly() {
         let items: Vec<String> = ["Q1", "Q2", "Q3"].iter().map(|s| s.to_string()).collect();
-        let html = Server::items2table(&items);
+        let html = Server::items2table(&items, &HashMap::new());
         assert!(
             html.contains("<th>1</th>"),
             "first row should be numbered 1"
@@ -298,14 +1911,14 @@ mod tests {
     #[test]
     fn test_items2table_all_items_present() {
         let items: Vec<String> = ["Q10", "Q20"].iter().map(|s| s.to_string()).collect();
-        let html = Server::items2table(&items);
+        let html = Server::items2table(&items, &HashMap::new());
         assert!(html.contains("Q10"), "Q10 should be present");
         assert!(html.contains("Q20"), "Q20 should be present");
     }
 
     #[test]
     fn test_items2table_table_structure() {
-        let html = Server::items2table(&["Q1".to_string()]);
+        let html = Server::items2table(&["Q1".to_string()], &HashMap::new());
         // Must have a striped Bootstrap table class
         assert!(
             html.contains("table-striped"),
@@ -325,10 +1938,33 @@ mod tests {
         assert!(html.contains("<tbody>"), "should have tbody");
     }
 
+    /// Answers every URL with a canned `wbgetentities` result
+    #[derive(Debug)]
+    struct CannedFetcher(&'static str);
+
+    impl ContentFetcher for CannedFetcher {
+        fn fetch<'a>(&'a self, _url: &'a str) -> BoxFuture<'a, anyhow::Result<String>> {
+            Box::pin(async move { Ok(self.0.to_string()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_items2table_shows_fetched_labels() {
+        let fetcher = CannedFetcher(
+            r#"{"entities":{"Q42":{"labels":{"en":{"language":"en","value":"Douglas Adams"}}},"Q1":{"missing":""}}}"#,
+        );
+        let items: Vec<String> = ["Q42", "Q1"].iter().map(|s| s.to_string()).collect();
+        let labels = Wikidata::labels(&fetcher, &items, "fr").await;
+        let html = Server::items2table(&items, &labels);
+        assert!(html.contains("<a q='Q42'>Douglas Adams</a>"));
+        // Without a label, the item ID is shown
+        assert!(html.contains("<a q='Q1'>Q1</a>"));
+    }
+
     #[test]
     fn test_items2table_rows_separated_by_newlines() {
         let items: Vec<String> = ["Q1", "Q2"].iter().map(|s| s.to_string()).collect();
-        let html = Server::items2table(&items);
+        let html = Server::items2table(&items, &HashMap::new());
         // The two <tr> blocks must be joined by a newline (from .join("\n"))
         assert!(
             html.contains("</tr>\n<tr>"),
@@ -372,12 +2008,13 @@ mod tests {
         let expected: u16 = std::env::var("WD_INFERNAL_PORT")
             .ok()
             .and_then(|v| v.parse().ok())
+            .or_else(|| Config::get().and_then(|config| config.port))
             .unwrap_or(8000);
         let addr = Server::get_server_address();
         assert_eq!(
             addr.port(),
             expected,
-            "port must be WD_INFERNAL_PORT when set and valid, otherwise 8000"
+            "port must be WD_INFERNAL_PORT when set and valid, then the configured port, otherwise 8000"
         );
     }
 }
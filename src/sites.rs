@@ -0,0 +1,128 @@
+use crate::config::Config;
+use crate::wikidata::Wikidata;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use tokio::sync::OnceCell;
+use wikimisc::site_matrix::SiteMatrix;
+
+/// Loaded on first use. A failed load is not kept, so the next request tries again.
+static SITE_MATRIX: OnceCell<SiteMatrix> = OnceCell::const_new();
+
+/// A wiki that is not in the site matrix, or not in `allowed_wikis` of the configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownWiki {
+    pub wiki: String,
+}
+
+impl UnknownWiki {
+    pub fn new(wiki: &str) -> Self {
+        Self {
+            wiki: wiki.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for UnknownWiki {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown wiki: {}", self.wiki)
+    }
+}
+
+impl std::error::Error for UnknownWiki {}
+
+/// The site matrix could not be loaded, so wikis can not be looked up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SiteMatrixUnavailable;
+
+impl std::fmt::Display for SiteMatrixUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The site matrix could not be loaded")
+    }
+}
+
+impl std::error::Error for SiteMatrixUnavailable {}
+
+/// Checks wiki codes from users, so tests can run without the site matrix.
+/// Returns a boxed future, so the validator can be held as `dyn WikiValidator`.
+pub trait WikiValidator: std::fmt::Debug + Send + Sync {
+    /// Fails like `Sites::validate_wiki`
+    fn validate<'a>(&'a self, wiki: &'a str) -> BoxFuture<'a, Result<()>>;
+}
+
+impl WikiValidator for Sites {
+    fn validate<'a>(&'a self, wiki: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(Self::validate_wiki(wiki))
+    }
+}
+
+/// The wikis of the Wikimedia site matrix, for all modules that take wiki codes from users
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Sites;
+
+impl Sites {
+    pub async fn site_matrix() -> Result<&'static SiteMatrix, SiteMatrixUnavailable> {
+        SITE_MATRIX.get_or_try_init(Self::load_site_matrix).await
+    }
+
+    async fn load_site_matrix() -> Result<SiteMatrix, SiteMatrixUnavailable> {
+        let api = Wikidata::get_wikidata_api().await.map_err(|status| {
+            tracing::warn!("Could not load the site matrix: Wikidata API unavailable ({status})");
+            SiteMatrixUnavailable
+        })?;
+        SiteMatrix::new(&api).await.map_err(|e| {
+            tracing::warn!("Could not load the site matrix: {e}");
+            SiteMatrixUnavailable
+        })
+    }
+
+    /// Checks that `wiki`, like `enwiki`, exists, and is allowed by the configuration.
+    /// Fails with `UnknownWiki`, or with `SiteMatrixUnavailable` if the site matrix
+    /// can not be loaded.
+    pub async fn validate_wiki(wiki: &str) -> Result<()> {
+        let site_matrix = Self::site_matrix().await?;
+        Self::check_wiki(
+            wiki,
+            |wiki| site_matrix.get_server_url_for_wiki(wiki).is_ok(),
+            Config::allowed_wikis(),
+        )?;
+        Ok(())
+    }
+
+    /// `allowed` restricts the existing wikis, unless it is empty
+    fn check_wiki(
+        wiki: &str,
+        exists: impl Fn(&str) -> bool,
+        allowed: &[String],
+    ) -> Result<(), UnknownWiki> {
+        let is_allowed = allowed.is_empty() || allowed.iter().any(|allowed| allowed == wiki);
+        if is_allowed && (wiki == "wikidatawiki" || exists(wiki)) {
+            Ok(())
+        } else {
+            Err(UnknownWiki::new(wiki))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_check_wiki_unknown() {
+        let exists = |wiki: &str| ["enwiki", "dewiki"].contains(&wiki);
+        assert!(Sites::check_wiki("enwiki", exists, &[]).is_ok());
+        assert!(Sites::check_wiki("wikidatawiki", exists, &[]).is_ok());
+        let error = Sites::check_wiki("enwikii", exists, &[]).unwrap_err();
+        assert_eq!(error.to_string(), "Unknown wiki: enwikii");
+    }
+
+    #[test]
+    fn test_check_wiki_allowed_wikis() {
+        let exists = |wiki: &str| ["enwiki", "dewiki"].contains(&wiki);
+        let config = json!({"wikidata": {"url": "mysql://x"}, "allowed_wikis": ["dewiki"]});
+        let allowed = Config::from_value(&config).unwrap().allowed_wikis;
+        assert!(Sites::check_wiki("dewiki", exists, &allowed).is_ok());
+        assert!(Sites::check_wiki("enwiki", exists, &allowed).is_err());
+    }
+}
@@ -0,0 +1,127 @@
+use axum::Router;
+use axum::extract::{Form, Query};
+use axum::routing::get;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Serves `router` on a random local port, returning its base URL, like "http://127.0.0.1:1234"
+pub async fn serve(router: Router) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    format!("http://{addr}")
+}
+
+/// Starts a local HTTP server that answers the n-th request (counting from 0) with the raw
+/// HTTP response `respond(n)`, and closes the connection. For responses that a router can
+/// not send, like a body without a length. Returns the URL of the server, and the number
+/// of requests so far.
+pub async fn raw<F>(respond: F) -> (String, Arc<AtomicUsize>)
+where
+    F: Fn(usize) -> String + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let hits = Arc::new(AtomicUsize::new(0));
+    let server_hits = hits.clone();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let hit = server_hits.fetch_add(1, Ordering::SeqCst);
+            let mut buffer = [0_u8; 1024];
+            let _ = socket.read(&mut buffer).await;
+            let _ = socket.write_all(respond(hit).as_bytes()).await;
+        }
+    });
+    (format!("http://{addr}/"), hits)
+}
+
+/// Starts a local HTTP server that answers every request with `response`
+pub async fn fixed(response: &'static str) -> String {
+    raw(move |_| response.to_string()).await.0
+}
+
+/// Starts a local HTTP server that accepts requests, but never answers them
+pub async fn hanging() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                drop(socket);
+            });
+        }
+    });
+    format!("http://{addr}/")
+}
+
+/// Minimal site info, which `mediawiki::Api::new` loads first
+pub fn siteinfo() -> Value {
+    json!({
+        "batchcomplete": "",
+        "query": {
+            "general": {"sitename": "Wikidata", "wikiid": "wikidatawiki", "lang": "en"},
+            "namespaces": {"0": {"id": 0, "case": "first-letter", "*": ""}},
+            "namespacealiases": [],
+            "libraries": [],
+            "extensions": [],
+            "statistics": {}
+        }
+    })
+}
+
+/// Starts a local wiki API that answers GET and POST requests with `respond(params)`, and
+/// requests it has no answer for with `siteinfo`. Returns the API URL.
+pub async fn wiki_api<F>(respond: F) -> String
+where
+    F: Fn(&HashMap<String, String>) -> Option<Value> + Send + Sync + 'static,
+{
+    let respond = Arc::new(respond);
+    let answer = move |params: HashMap<String, String>| {
+        axum::Json(respond(&params).unwrap_or_else(siteinfo))
+    };
+    let get_answer = answer.clone();
+    let router = Router::new().route(
+        "/w/api.php",
+        get(move |Query(params): Query<HashMap<String, String>>| {
+            let answer = get_answer(params);
+            async move { answer }
+        })
+        .post(move |Form(params): Form<HashMap<String, String>>| {
+            let answer = answer(params);
+            async move { answer }
+        }),
+    );
+    format!("{}/w/api.php", serve(router).await)
+}
+
+/// Starts a local wiki API that answers `wbgetentities` from `entities`, by ID.
+/// Returns the API URL.
+pub async fn canned_wiki_api(entities: Value) -> String {
+    wiki_api(move |params| wbgetentities(&entities, params)).await
+}
+
+/// The answer to a `wbgetentities` request from `entities`, by ID; unknown IDs are missing.
+/// `None` for other requests.
+pub fn wbgetentities(entities: &Value, params: &HashMap<String, String>) -> Option<Value> {
+    if params.get("action").map(String::as_str) != Some("wbgetentities") {
+        return None;
+    }
+    let ids = params.get("ids").map(String::as_str).unwrap_or_default();
+    let found: serde_json::Map<String, Value> = ids
+        .split('|')
+        .map(|id| {
+            let entity = entities
+                .get(id)
+                .cloned()
+                .unwrap_or_else(|| json!({"id": id, "missing": ""}));
+            (id.to_string(), entity)
+        })
+        .collect();
+    Some(json!({"entities": found, "success": 1}))
+}
@@ -1,9 +1,28 @@
-use anyhow::{Context, Result};
+use crate::body_limit::{self, BodyError};
+use crate::config::Config;
+use crate::metrics::Metrics;
+use crate::person::NameSearch;
+use crate::rate_limit::RateLimiter;
+use crate::wikidata::{QueryService, SparqlFetcher, Wikidata};
+use axum::http::StatusCode;
+use futures::future::BoxFuture;
+use regex::Regex;
 use reqwest::Client;
 use reqwest::header;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
 use std::sync::LazyLock;
+use wikibase::{DataValue, DataValueType, Reference, Snak, SnakDataType, SnakType, Statement};
+
+/// Life dates at the end of personal name headings, like "1920-1990", "1952-", or "d. 1990".
+/// A bare year is kept, as it can be part of an event or organization name.
+static RE_LIFE_DATES: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?:(?:\d{1,4}\??-(?:\d{1,4}\??)?)|(?:-\d{1,4}\??)|(?:(?:b|d|fl|ca)\. ?\d{1,4}\??))\.?$",
+    )
+    .expect("valid regex")
+});
 
 static HTTP_CLIENT: LazyLock<Client> = LazyLock::new(|| {
     let mut headers = header::HeaderMap::new();
@@ -23,54 +42,534 @@ static HTTP_CLIENT: LazyLock<Client> = LazyLock::new(|| {
         .expect("Failed to build VIAF HTTP client")
 });
 
+#[derive(Debug)]
+pub enum ViafError {
+    /// The request could not be sent, or the response not read
+    Request(reqwest::Error),
+    /// VIAF answered with a non-success HTTP status
+    Status(u16),
+    /// The response is not valid JSON
+    Parse(serde_json::Error),
+    /// VIAF answered with an empty body
+    Empty,
+    /// The response is larger than this many bytes
+    TooLarge(usize),
+}
+
+impl ViafError {
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Self::Request(e) if e.is_timeout())
+    }
+}
+
+impl std::fmt::Display for ViafError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Request(e) => write!(f, "Failed to send request to VIAF: {e}"),
+            Self::Status(status) => write!(f, "VIAF returned error status: {status}"),
+            Self::Parse(e) => write!(f, "VIAF returned invalid JSON: {e}"),
+            Self::Empty => write!(f, "VIAF returned an empty response"),
+            Self::TooLarge(max_bytes) => {
+                write!(f, "VIAF response is larger than {max_bytes} bytes")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ViafError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Request(e) => Some(e),
+            Self::Parse(e) => Some(e),
+            Self::Status(_) | Self::Empty | Self::TooLarge(_) => None,
+        }
+    }
+}
+
+pub const DEFAULT_MAXIMUM_RECORDS: u32 = 10;
+/// VIAF rejects larger pages
+pub const MAX_MAXIMUM_RECORDS: u32 = 250;
+
+/// The VIAF CQL index to search
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViafIndex {
+    #[default]
+    PersonalNames,
+    CorporateNames,
+    UniformTitles,
+    AllNames,
+}
+
+impl ViafIndex {
+    pub const fn cql_index(self) -> &'static str {
+        match self {
+            Self::PersonalNames => "local.personalNames",
+            Self::CorporateNames => "local.corporateNames",
+            Self::UniformTitles => "local.uniformTitleWorks",
+            Self::AllNames => "local.names",
+        }
+    }
+}
+
+/// VIAF source codes and the Wikidata external-id property for each
+static SOURCE_PROPERTIES: &[(&str, &str)] = &[
+    ("VIAF", "P214"),
+    ("ISNI", "P213"),
+    ("LC", "P244"),
+    ("DNB", "P227"),
+    ("BNF", "P268"),
+    ("SUDOC", "P269"),
+    ("NDL", "P349"),
+    ("ICCU", "P396"),
+    ("NLA", "P409"),
+    ("JPG", "P245"),
+    ("NKC", "P691"),
+    ("SELIBR", "P906"),
+    ("NLI", "P949"),
+    ("BNE", "P950"),
+    ("PTBNP", "P1005"),
+    ("NTA", "P1006"),
+    ("BIBSYS", "P1015"),
+    ("NUKAT", "P1207"),
+    ("BNC", "P1273"),
+    ("EGAXA", "P1309"),
+    ("LNB", "P1368"),
+    ("NSK", "P1375"),
+    ("RERO", "P3065"),
+    ("KRNLK", "P5034"),
+    ("PLWABN", "P7293"),
+    ("BAV", "P8034"),
+    ("CAOONL", "P8179"),
+    ("J9U", "P8189"),
+];
+
+/// Wikidata property for a VIAF source code, if known
+pub fn property_for_source(code: &str) -> Option<&'static str> {
+    SOURCE_PROPERTIES
+        .iter()
+        .find(|(source, _)| *source == code)
+        .map(|(_, property)| *property)
+}
+
 #[derive(Debug, Clone, Serialize, Default)]
 pub struct RecordId {
     pub code: String,
     pub id: String,
     pub text: String,
+    pub property: Option<String>,
 }
 
 impl RecordId {
+    fn new(code: &str, id: &str) -> Self {
+        Self {
+            code: code.to_string(),
+            id: id.to_string(),
+            property: property_for_source(code).map(str::to_string),
+            ..Default::default()
+        }
+    }
+
     fn from_value(ns: usize, v: &Value) -> Option<Self> {
-        let code = v[nss(ns, "sources")][nss(ns, "s")].as_str()?.to_string();
-        let id = v[nss(ns, "sources")][nss(ns, "sid")].as_str()?.to_string();
+        let code = v[nss(ns, "sources")][nss(ns, "s")].as_str()?;
+        let id = v[nss(ns, "sources")][nss(ns, "sid")].as_str()?;
         let text = v[nss(ns, "text")].as_str().unwrap_or_default().to_string();
-        let id = id.split('|').nth(1).unwrap_or_else(|| &id).to_string();
-        Some(Self { code, id, text })
+        let id = id.split('|').nth(1).unwrap_or(id);
+        Some(Self {
+            text,
+            ..Self::new(code, id)
+        })
     }
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct Record {
     pub id: String,
+    /// The main heading, without life dates
     pub label: String,
+    /// The main heading, as in VIAF
+    pub raw_label: String,
     pub born: Option<String>,
     pub died: Option<String>,
+    pub isni: Option<String>,
     pub ids: Vec<RecordId>,
+    /// The matching Wikidata item, if looked up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wikidata: Option<String>,
+}
+
+/// One page of VIAF search results
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResults {
+    /// Total number of matching records, across all pages
+    pub total: u64,
+    /// 1-based position of the first record on this page
+    pub start_record: u32,
+    pub records: Vec<Record>,
 }
 
 fn nss(nsid: usize, postfix: &str) -> String {
     format!("ns{nsid}:{postfix}")
 }
 
-pub async fn search_viaf_for_local_names(query: &str) -> Result<Vec<Record>> {
+/// `start_record` is 1-based; `maximum_records` is clamped to 1..=`MAX_MAXIMUM_RECORDS`
+fn search_url(query: &str, index: ViafIndex, start_record: u32, maximum_records: u32) -> String {
     let encoded_query = urlencoding::encode(query);
-    let url = format!(
-        "https://viaf.org/viaf/search?query=local.names+=+{encoded_query}&maximumRecords=10"
+    let cql_index = index.cql_index();
+    let start_record = start_record.max(1);
+    let maximum_records = maximum_records.clamp(1, MAX_MAXIMUM_RECORDS);
+    format!(
+        "https://viaf.org/viaf/search?query={cql_index}+=+{encoded_query}&startRecord={start_record}&maximumRecords={maximum_records}"
+    )
+}
+
+/// Searches VIAF, so tests can serve canned records.
+/// Returns a boxed future, so the search can be held as `dyn ViafSearch`.
+pub trait ViafSearch: std::fmt::Debug + Send + Sync {
+    /// Like `search_viaf_for_local_names`
+    fn search<'a>(
+        &'a self,
+        query: &'a str,
+        index: ViafIndex,
+        start_record: u32,
+        maximum_records: u32,
+    ) -> BoxFuture<'a, Result<SearchResults, ViafError>>;
+}
+
+/// Searches viaf.org
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ViafOrg;
+
+impl ViafSearch for ViafOrg {
+    fn search<'a>(
+        &'a self,
+        query: &'a str,
+        index: ViafIndex,
+        start_record: u32,
+        maximum_records: u32,
+    ) -> BoxFuture<'a, Result<SearchResults, ViafError>> {
+        Box::pin(search_viaf_for_local_names(
+            query,
+            index,
+            start_record,
+            maximum_records,
+        ))
+    }
+}
+
+pub async fn search_viaf_for_local_names(
+    query: &str,
+    index: ViafIndex,
+    start_record: u32,
+    maximum_records: u32,
+) -> Result<SearchResults, ViafError> {
+    let url = search_url(query, index, start_record, maximum_records);
+    let value = fetch_json(&url).await?;
+    Ok(SearchResults {
+        total: number_of_records(&value),
+        start_record: start_record.max(1),
+        records: parse_viaf_response(&value),
+    })
+}
+
+/// Searches Wikidata for items by label, with the shared API
+#[derive(Clone, Copy, Debug, Default)]
+struct LabelSearch;
+
+impl NameSearch for LabelSearch {
+    fn search<'a>(
+        &'a self,
+        name: &'a str,
+        class: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<String>, StatusCode>> {
+        Box::pin(async move {
+            let api = Wikidata::get_wikidata_api().await?;
+            Wikidata::search_single_name(&api, name, class).await
+        })
+    }
+}
+
+/// Sets the matching Wikidata item on each record: the item with the VIAF ID (P214),
+/// or else the only human (P31:Q5) with the name of the record as label
+pub async fn match_wikidata(records: &mut [Record]) -> Result<(), StatusCode> {
+    match_wikidata_with(records, &QueryService, &LabelSearch).await
+}
+
+/// Like `match_wikidata`, with VIAF IDs looked up by `fetcher`, and names by `search`.
+/// Fails with 502 if a name search fails.
+pub(crate) async fn match_wikidata_with(
+    records: &mut [Record],
+    fetcher: &dyn SparqlFetcher,
+    search: &dyn NameSearch,
+) -> Result<(), StatusCode> {
+    let viaf_ids: Vec<&str> = records.iter().map(|record| record.id.as_str()).collect();
+    let matches = items_for_viaf_ids(&viaf_ids, fetcher).await?;
+    for record in records.iter_mut() {
+        record.wikidata = match matches.get(&record.id) {
+            Some(item) => Some(item.to_owned()),
+            None => match name_from_heading(&record.label) {
+                Some(name) => {
+                    let items = search.search(&name, "Q5").await.map_err(|status| {
+                        tracing::warn!("Wikidata search for {name} failed with {status}");
+                        Metrics::global().record_upstream_failure("viaf_name_search");
+                        StatusCode::BAD_GATEWAY
+                    })?;
+                    match items.as_slice() {
+                        [item] => Some(item.to_owned()),
+                        _ => None,
+                    }
+                }
+                None => None,
+            },
+        };
+    }
+    Ok(())
+}
+
+async fn items_for_viaf_ids(
+    viaf_ids: &[&str],
+    fetcher: &dyn SparqlFetcher,
+) -> Result<HashMap<String, String>, StatusCode> {
+    let viaf_ids: Vec<&str> = viaf_ids
+        .iter()
+        .copied()
+        .filter(|viaf_id| is_valid_viaf_id(viaf_id))
+        .collect();
+    if viaf_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let values = viaf_ids.join("\" \"");
+    let sparql = format!("SELECT ?q ?viaf {{ VALUES ?viaf {{ \"{values}\" }} ?q wdt:P214 ?viaf }}");
+    let json = fetcher
+        .query(&sparql)
+        .await
+        .map_err(|_| StatusCode::BAD_GATEWAY)?;
+    Ok(parse_viaf_matches(&json))
+}
+
+/// The item for each VIAF ID in a SPARQL result; IDs on several items are left out
+fn parse_viaf_matches(json: &Value) -> HashMap<String, String> {
+    let mut items: HashMap<String, Vec<String>> = HashMap::new();
+    let bindings = json["results"]["bindings"].as_array().into_iter().flatten();
+    for binding in bindings {
+        let Some(viaf_id) = binding["viaf"]["value"].as_str() else {
+            continue;
+        };
+        let Some(item) = binding["q"]["value"]
+            .as_str()
+            .and_then(|uri| uri.rsplit('/').next())
+        else {
+            continue;
+        };
+        items
+            .entry(viaf_id.to_string())
+            .or_default()
+            .push(item.to_string());
+    }
+    items
+        .into_iter()
+        .filter(|(_, items)| items.len() == 1)
+        .map(|(viaf_id, mut items)| (viaf_id, items.remove(0)))
+        .collect()
+}
+
+/// Cleans a VIAF heading: fixes UTF-8 that was decoded as Latin-1, collapses whitespace,
+/// and removes empty and trailing life-date parts. "Smith,  John, 1920-1990," becomes
+/// "Smith, John"; commas between other parts are kept.
+fn clean_heading(heading: &str) -> String {
+    let heading = fix_mojibake(heading);
+    let mut parts: Vec<String> = heading
+        .split(',')
+        .map(|part| part.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|part| !part.is_empty())
+        .collect();
+    while parts.len() > 1
+        && parts
+            .last()
+            .is_some_and(|part| RE_LIFE_DATES.is_match(part))
+    {
+        parts.pop();
+    }
+    parts.join(", ")
+}
+
+/// "MÃ¼ller" becomes "Müller". Text that is not valid UTF-8 as Latin-1 bytes is kept.
+fn fix_mojibake(text: &str) -> String {
+    if !text.contains(['Ã', 'Â']) || text.chars().any(|c| u32::from(c) > 0xFF) {
+        return text.to_string();
+    }
+    let bytes: Vec<u8> = text.chars().map(|c| c as u8).collect();
+    String::from_utf8(bytes).unwrap_or_else(|_| text.to_string())
+}
+
+/// The name in a VIAF heading like "Adams, Douglas, 1952-2001", as "Douglas Adams"
+fn name_from_heading(label: &str) -> Option<String> {
+    let parts: Vec<&str> = label
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .take_while(|part| !part.starts_with(|c: char| c.is_ascii_digit()))
+        .collect();
+    match parts.as_slice() {
+        [] => None,
+        [name] => Some(name.to_string()),
+        [family_name, given_name, ..] => Some(format!("{given_name} {family_name}")),
+    }
+}
+
+/// External-id statements for the sources of the VIAF cluster `viaf_id`, with P214 for the
+/// cluster itself. Sources without a known property are skipped.
+pub async fn cluster_statements(viaf_id: &str) -> Result<Vec<Statement>, ViafError> {
+    let url = format!("https://viaf.org/viaf/{viaf_id}/viaf.json");
+    let value = match fetch_json(&url).await {
+        Ok(value) => value,
+        // No such cluster
+        Err(ViafError::Status(404)) => return Err(ViafError::Empty),
+        Err(e) => return Err(e),
+    };
+    parse_cluster_statements(viaf_id, &value).ok_or(ViafError::Empty)
+}
+
+/// VIAF cluster IDs are numeric
+pub fn is_valid_viaf_id(viaf_id: &str) -> bool {
+    !viaf_id.is_empty() && viaf_id.chars().all(|c| c.is_ascii_digit())
+}
+
+/// The cluster and its namespace prefix, like `ns1:`, which may be missing
+fn find_cluster(value: &Value) -> Option<(String, &Value)> {
+    let object = value.as_object()?;
+    match object.iter().find(|(key, _)| key.ends_with("VIAFCluster")) {
+        Some((key, cluster)) => Some((key.trim_end_matches("VIAFCluster").to_string(), cluster)),
+        None => Some((String::new(), value)),
+    }
+}
+
+fn parse_cluster_statements(viaf_id: &str, value: &Value) -> Option<Vec<Statement>> {
+    let (prefix, cluster) = find_cluster(value)?;
+    let sources = as_list(&cluster[format!("{prefix}sources")][format!("{prefix}source")]);
+    if sources.is_empty() {
+        return None;
+    }
+    let mut ids = vec![RecordId::new("VIAF", viaf_id)];
+    ids.extend(sources.iter().filter_map(cluster_source_id));
+    let mut seen = std::collections::HashSet::new();
+    let statements = ids
+        .iter()
+        .filter_map(|id| {
+            let property = id.property.as_deref()?;
+            Some((property, wikidata_external_id(property, &id.id)))
+        })
+        .filter(|key| seen.insert(key.clone()))
+        .map(|(property, id)| external_id_statement(property, &id))
+        .collect();
+    Some(statements)
+}
+
+/// Cluster sources look like `{"#text": "LC|n  80076765", "nsid": "n80076765"}`
+fn cluster_source_id(source: &Value) -> Option<RecordId> {
+    let text = source["#text"].as_str()?;
+    let (code, id) = text.split_once('|')?;
+    if code == "VIAF" {
+        return None;
+    }
+    let id = match source["nsid"].as_str() {
+        Some(nsid) => nsid.to_string(),
+        None => id.replace(' ', ""),
+    };
+    Some(RecordId::new(code, &id))
+}
+
+/// A VIAF source ID in the format of its Wikidata property. ISNIs are written in groups
+/// of four digits, and LC control numbers are normalized, like "n  80076765" to "n80076765"
+/// or "n79-1234" to "n79001234". Other IDs are used as they are.
+fn wikidata_external_id(property: &str, id: &str) -> String {
+    let id = id.trim();
+    match property {
+        "P213" => {
+            let isni: Vec<char> = id.chars().filter(|c| !c.is_whitespace()).collect();
+            if isni.len() != 16 {
+                return id.to_string();
+            }
+            isni.chunks(4)
+                .map(|group| group.iter().collect::<String>())
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+        "P244" => normalize_lccn(id),
+        _ => id.to_string(),
+    }
+}
+
+/// Normalizes an LC control number: without blanks, and with the serial number after a
+/// hyphen padded to six digits
+fn normalize_lccn(lccn: &str) -> String {
+    let lccn: String = lccn.chars().filter(|c| !c.is_whitespace()).collect();
+    match lccn.split_once('-') {
+        Some((prefix, serial)) if !serial.is_empty() && serial.len() <= 6 => {
+            format!("{prefix}{serial:0>6}")
+        }
+        _ => lccn,
+    }
+}
+
+fn external_id_statement(property: &str, id: &str) -> Statement {
+    let snak = Snak::new(
+        SnakDataType::ExternalId,
+        property,
+        SnakType::Value,
+        Some(DataValue::new(
+            DataValueType::StringType,
+            wikibase::Value::StringValue(id.to_string()),
+        )),
     );
+    let reference = Reference::new(vec![Wikidata::infernal_reference_snak()]);
+    Statement::new_normal(snak, vec![], vec![reference])
+}
 
+/// Like `fetch_uncounted`, but counts failures other than missing records
+async fn fetch_json(url: &str) -> Result<Value, ViafError> {
+    let result = fetch_uncounted(url).await;
+    if result
+        .as_ref()
+        .is_err_and(|e| !matches!(e, ViafError::Status(404) | ViafError::Empty))
+    {
+        Metrics::global().record_upstream_failure("viaf");
+    }
+    result
+}
+
+async fn fetch_uncounted(url: &str) -> Result<Value, ViafError> {
+    RateLimiter::global().wait(url).await;
     let response = HTTP_CLIENT
-        .get(&url)
+        .get(url)
         .send()
         .await
-        .context("Failed to send request to VIAF")?;
-
-    // Check if the request was successful
+        .map_err(ViafError::Request)?;
     if !response.status().is_success() {
-        anyhow::bail!("VIAF returned error status: {}", response.status());
+        return Err(ViafError::Status(response.status().as_u16()));
     }
+    let text = body_limit::read_text(response, Config::max_body_bytes())
+        .await
+        .map_err(|e| match e {
+            BodyError::Request(e) => ViafError::Request(e),
+            BodyError::TooLarge(max_bytes) => ViafError::TooLarge(max_bytes),
+        })?;
+    if text.trim().is_empty() {
+        return Err(ViafError::Empty);
+    }
+    serde_json::from_str(&text).map_err(ViafError::Parse)
+}
+
+/// VIAF sends `numberOfRecords` as either a number or a string
+fn number_of_records(value: &Value) -> u64 {
+    let total = &value["searchRetrieveResponse"]["numberOfRecords"];
+    total
+        .as_u64()
+        .or_else(|| total.as_str().and_then(|s| s.parse().ok()))
+        .unwrap_or_default()
+}
 
-    let value: Value = response.json().await?;
+pub(crate) fn parse_viaf_response(value: &Value) -> Vec<Record> {
     let records = &value["searchRetrieveResponse"]["records"]["record"];
     let records: Vec<Value> = match records {
         Value::Array(records) => records.to_owned(),
@@ -87,8 +586,25 @@ pub async fn search_viaf_for_local_names(query: &str) -> Result<Vec<Record>> {
             None => continue,
         }
     }
+    ret
+}
+
+/// Returns the value as a list, whether VIAF sent a single object or an array
+fn as_list(value: &Value) -> Vec<Value> {
+    match value {
+        Value::Object(_) => vec![value.to_owned()],
+        Value::Array(values) => values.to_owned(),
+        _ => vec![],
+    }
+}
 
-    Ok(ret)
+/// ISNI from the cluster sources, which look like `ISNI|0000000121258167`
+fn extract_isni(ns: usize, cluster: &Value) -> Option<String> {
+    as_list(&cluster[nss(ns, "sources")][nss(ns, "source")])
+        .iter()
+        .filter_map(|source| source["#text"].as_str())
+        .find_map(|text| text.strip_prefix("ISNI|"))
+        .map(|isni| isni.replace(' ', ""))
 }
 
 fn extract_local_name(ns: usize, record: &Value) -> Option<Record> {
@@ -101,36 +617,30 @@ fn extract_local_name(ns: usize, record: &Value) -> Option<Record> {
         Some(id) => id.trim_end_matches('/').split('/').next_back()?.to_string(),
         None => return None,
     };
-    let main_headings = &cluster[nss(ns, "mainHeadings")][nss(ns, "data")];
-    let main_headings = match main_headings {
-        Value::Object(_) => vec![main_headings.to_owned()],
-        Value::Array(headings) => headings.to_owned(),
-        _ => vec![],
-    };
-    let mut ids = vec![RecordId {
-        code: "VIAF".to_string(),
-        id: id.clone(),
-        ..Default::default()
-    }];
+    let main_headings = as_list(&cluster[nss(ns, "mainHeadings")][nss(ns, "data")]);
+    let mut ids = vec![RecordId::new("VIAF", &id)];
     ids.extend(
         main_headings
             .iter()
             .filter_map(|h| RecordId::from_value(ns, h)),
     );
-    let label = match &cluster[nss(ns, "mainHeadings")][nss(ns, "data")][nss(ns, "text")].as_str() {
+    let raw_label = match main_headings.first()?[nss(ns, "text")].as_str() {
         Some(text) => text.to_string(),
         None => return None,
     };
     let new_record = Record {
         id,
-        label,
+        label: clean_heading(&raw_label),
+        raw_label,
         born: cluster[nss(ns, "birthDate")]
             .as_str()
             .map(|s| s.to_string()),
         died: cluster[nss(ns, "deathDate")]
             .as_str()
             .map(|s| s.to_string()),
+        isni: extract_isni(ns, cluster),
         ids,
+        wikidata: None,
     };
     Some(new_record)
 }
@@ -138,6 +648,7 @@ fn extract_local_name(ns: usize, record: &Value) -> Option<Record> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_server;
     use serde_json::json;
 
     // ── nss ───────────────────────────────────────────────────────────────────
@@ -268,6 +779,295 @@ mod tests {
         assert!(extract_local_name(2, &record).is_none());
     }
 
+    // ── search_url ────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_search_url_default_index() {
+        assert_eq!(
+            search_url(
+                "Douglas Adams",
+                ViafIndex::default(),
+                1,
+                DEFAULT_MAXIMUM_RECORDS
+            ),
+            "https://viaf.org/viaf/search?query=local.personalNames+=+Douglas%20Adams&startRecord=1&maximumRecords=10"
+        );
+    }
+
+    #[test]
+    fn test_search_url_cql_indexes() {
+        let url = |index| search_url("x", index, 1, 5);
+        assert!(url(ViafIndex::CorporateNames).contains("query=local.corporateNames+=+x&"));
+        assert!(url(ViafIndex::UniformTitles).contains("query=local.uniformTitleWorks+=+x&"));
+        assert!(url(ViafIndex::AllNames).contains("query=local.names+=+x&"));
+        assert!(url(ViafIndex::AllNames).ends_with("&maximumRecords=5"));
+    }
+
+    #[test]
+    fn test_search_url_pagination() {
+        let url = search_url("x", ViafIndex::AllNames, 21, 20);
+        assert!(url.ends_with("&startRecord=21&maximumRecords=20"));
+    }
+
+    #[test]
+    fn test_search_url_clamps_parameters() {
+        let url = search_url("x", ViafIndex::AllNames, 0, 10_000);
+        assert!(url.ends_with("&startRecord=1&maximumRecords=250"));
+        let url = search_url("x", ViafIndex::AllNames, 1, 0);
+        assert!(url.ends_with("&maximumRecords=1"));
+    }
+
+    #[test]
+    fn test_number_of_records() {
+        let records = |total| json!({"searchRetrieveResponse": {"numberOfRecords": total}});
+        assert_eq!(number_of_records(&records(json!(42))), 42);
+        assert_eq!(number_of_records(&records(json!("1234"))), 1234);
+        assert_eq!(number_of_records(&json!({})), 0);
+    }
+
+    #[test]
+    fn test_parse_viaf_response_start_beyond_end() {
+        // VIAF returns the total but no records for a start past the end
+        let value = json!({"searchRetrieveResponse": {"numberOfRecords": "3"}});
+        assert_eq!(number_of_records(&value), 3);
+        assert!(parse_viaf_response(&value).is_empty());
+    }
+
+    // ── fetch_json ────────────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_fetch_json_non_success_is_status_error() {
+        let url = test_server::fixed(
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+        let error = fetch_json(&url).await.unwrap_err();
+        assert!(matches!(error, ViafError::Status(503)));
+        assert!(!error.is_timeout());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_json_empty_and_invalid_bodies() {
+        let url =
+            test_server::fixed("HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await;
+        assert!(matches!(fetch_json(&url).await, Err(ViafError::Empty)));
+        let url = test_server::fixed(
+            "HTTP/1.1 200 OK\r\nContent-Length: 6\r\nConnection: close\r\n\r\n<html>",
+        )
+        .await;
+        assert!(matches!(fetch_json(&url).await, Err(ViafError::Parse(_))));
+    }
+
+    #[test]
+    fn test_viaf_index_deserialize() {
+        let index: ViafIndex = serde_json::from_str("\"corporate_names\"").unwrap();
+        assert_eq!(index, ViafIndex::CorporateNames);
+    }
+
+    // ── parse_viaf_response ───────────────────────────────────────────────────
+
+    fn fixture_record() -> Record {
+        let value: Value = serde_json::from_str(include_str!("../test_files/viaf.json")).unwrap();
+        let mut records = parse_viaf_response(&value);
+        assert_eq!(records.len(), 1);
+        records.remove(0)
+    }
+
+    #[test]
+    fn test_parse_viaf_matches() {
+        let json = json!({"results": {"bindings": [
+            {"q": {"value": "http://www.wikidata.org/entity/Q42"}, "viaf": {"value": "113230702"}},
+            {"q": {"value": "http://www.wikidata.org/entity/Q1"}, "viaf": {"value": "1"}},
+            {"q": {"value": "http://www.wikidata.org/entity/Q2"}, "viaf": {"value": "1"}}
+        ]}});
+        let matches = parse_viaf_matches(&json);
+        assert_eq!(matches.get("113230702").map(String::as_str), Some("Q42"));
+        // Ambiguous VIAF IDs are not matched
+        assert_eq!(matches.get("1"), None);
+    }
+
+    /// Answers every query with `bindings`, recording the queries
+    #[derive(Debug, Default)]
+    struct CannedSparql {
+        bindings: Value,
+        queries: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl SparqlFetcher for CannedSparql {
+        fn query<'a>(&'a self, sparql: &'a str) -> BoxFuture<'a, anyhow::Result<Value>> {
+            self.queries.lock().unwrap().push(sparql.to_string());
+            let json = json!({"results": {"bindings": self.bindings}});
+            Box::pin(async move { Ok(json) })
+        }
+    }
+
+    /// Finds the items in `items` for every name, or fails with `error`
+    #[derive(Debug)]
+    struct StubSearch {
+        items: Vec<&'static str>,
+        error: Option<StatusCode>,
+    }
+
+    impl StubSearch {
+        fn finding(items: &[&'static str]) -> Self {
+            Self {
+                items: items.to_vec(),
+                error: None,
+            }
+        }
+    }
+
+    impl NameSearch for StubSearch {
+        fn search<'a>(
+            &'a self,
+            _name: &'a str,
+            _class: &'a str,
+        ) -> BoxFuture<'a, Result<Vec<String>, StatusCode>> {
+            let result = match self.error {
+                Some(status) => Err(status),
+                None => Ok(self.items.iter().map(|item| item.to_string()).collect()),
+            };
+            Box::pin(async move { result })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_match_wikidata_by_viaf_id() {
+        let mut records = vec![fixture_record()];
+        let fetcher = CannedSparql {
+            bindings: json!([{
+                "q": {"value": "http://www.wikidata.org/entity/Q42"},
+                "viaf": {"value": records[0].id.clone()}
+            }]),
+            ..CannedSparql::default()
+        };
+        match_wikidata_with(&mut records, &fetcher, &StubSearch::finding(&["Q1"]))
+            .await
+            .unwrap();
+        assert_eq!(records[0].wikidata.as_deref(), Some("Q42"));
+        let queries = fetcher.queries.lock().unwrap();
+        assert_eq!(queries.len(), 1);
+        assert!(queries[0].contains(&format!("\"{}\"", records[0].id)));
+    }
+
+    #[tokio::test]
+    async fn test_match_wikidata_by_name() {
+        let fetcher = CannedSparql {
+            bindings: json!([]),
+            ..CannedSparql::default()
+        };
+        let mut records = vec![fixture_record()];
+        match_wikidata_with(&mut records, &fetcher, &StubSearch::finding(&["Q1"]))
+            .await
+            .unwrap();
+        assert_eq!(records[0].wikidata.as_deref(), Some("Q1"));
+        // Several humans with the name are not a match
+        let search = StubSearch::finding(&["Q1", "Q2"]);
+        match_wikidata_with(&mut records, &fetcher, &search)
+            .await
+            .unwrap();
+        assert_eq!(records[0].wikidata, None);
+    }
+
+    #[tokio::test]
+    async fn test_match_wikidata_reports_failed_name_search() {
+        let fetcher = CannedSparql {
+            bindings: json!([]),
+            ..CannedSparql::default()
+        };
+        let search = StubSearch {
+            items: vec![],
+            error: Some(StatusCode::INTERNAL_SERVER_ERROR),
+        };
+        let mut records = vec![fixture_record()];
+        let status = match_wikidata_with(&mut records, &fetcher, &search)
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+    }
+
+    #[test]
+    fn test_name_from_heading() {
+        assert_eq!(
+            name_from_heading("Adams, Douglas, 1952-2001").as_deref(),
+            Some("Douglas Adams")
+        );
+        assert_eq!(name_from_heading("Homer").as_deref(), Some("Homer"));
+        assert_eq!(name_from_heading(", 1952-"), None);
+    }
+
+    #[test]
+    fn test_clean_heading_personal_name_with_dates() {
+        assert_eq!(clean_heading("Smith,  John, 1920-1990"), "Smith, John");
+        assert_eq!(clean_heading("Smith, John, 1920-,"), "Smith, John");
+        assert_eq!(clean_heading("Smith, John, d. 1990"), "Smith, John");
+        assert_eq!(clean_heading(" Homer "), "Homer");
+    }
+
+    #[test]
+    fn test_clean_heading_organization_keeps_commas() {
+        assert_eq!(
+            clean_heading("Harvard University,  Department of Physics"),
+            "Harvard University, Department of Physics"
+        );
+        assert_eq!(
+            clean_heading("Olympic Games, 1936, Berlin"),
+            "Olympic Games, 1936, Berlin"
+        );
+        assert_eq!(clean_heading("Olympic Games, 1936"), "Olympic Games, 1936");
+    }
+
+    #[test]
+    fn test_clean_heading_fixes_mojibake() {
+        assert_eq!(clean_heading("MÃ¼ller, JÃ¼rgen, 1950-"), "Müller, Jürgen");
+        // Already correct text is left alone
+        assert_eq!(clean_heading("Müller, Jürgen"), "Müller, Jürgen");
+    }
+
+    fn record_id<'a>(record: &'a Record, code: &str) -> &'a RecordId {
+        record.ids.iter().find(|rid| rid.code == code).unwrap()
+    }
+
+    #[test]
+    fn test_parse_viaf_response_fixture() {
+        let record = fixture_record();
+        assert_eq!(record.id, "113230702");
+        assert_eq!(record.label, "Adams, Douglas");
+        assert_eq!(record.raw_label, "Adams, Douglas, 1952-2001");
+        assert_eq!(record.ids.len(), 4);
+    }
+
+    #[test]
+    fn test_parse_viaf_response_maps_properties() {
+        let record = fixture_record();
+        assert_eq!(record_id(&record, "VIAF").property.as_deref(), Some("P214"));
+        assert_eq!(record_id(&record, "LC").property.as_deref(), Some("P244"));
+        let dnb = record_id(&record, "DNB");
+        assert_eq!(dnb.property.as_deref(), Some("P227"));
+        assert_eq!(dnb.id, "119033364");
+        assert_eq!(record_id(&record, "XYZ").property, None);
+    }
+
+    #[test]
+    fn test_parse_viaf_response_extracts_isni() {
+        assert_eq!(fixture_record().isni.as_deref(), Some("0000000121258167"));
+    }
+
+    #[test]
+    fn test_extract_isni_missing() {
+        let record = viaf_record("12345", "Test", None, None);
+        let cluster = &record["recordData"]["ns2:VIAFCluster"];
+        assert_eq!(extract_isni(2, cluster), None);
+    }
+
+    #[test]
+    fn test_property_for_source() {
+        assert_eq!(property_for_source("LC"), Some("P244"));
+        assert_eq!(property_for_source("BNF"), Some("P268"));
+        assert_eq!(property_for_source("WKP"), None);
+    }
+
     // ── RecordId::from_value ──────────────────────────────────────────────────
 
     #[test]
@@ -312,4 +1112,78 @@ mod tests {
         assert_eq!(rid.text, "");
         assert_eq!(rid.id, "Q42");
     }
+
+    // ── parse_cluster_statements ──────────────────────────────────────────────
+
+    fn statement_values(statements: &[Statement]) -> Vec<(String, String)> {
+        statements
+            .iter()
+            .filter_map(|statement| {
+                let snak = statement.main_snak();
+                match snak.data_value().as_ref()?.value() {
+                    wikibase::Value::StringValue(s) => {
+                        Some((snak.property().to_string(), s.to_owned()))
+                    }
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_cluster_statements_fixture() {
+        let value: Value =
+            serde_json::from_str(include_str!("../test_files/viaf_cluster.json")).unwrap();
+        let statements = parse_cluster_statements("113230702", &value).unwrap();
+        let pair = |property: &str, id: &str| (property.to_string(), id.to_string());
+        // Unknown sources (XYZ) and sources without a property (WKP) are skipped
+        assert_eq!(
+            statement_values(&statements),
+            vec![
+                pair("P214", "113230702"),
+                pair("P244", "n80076765"),
+                pair("P227", "119033364"),
+                pair("P213", "0000 0001 2125 8167"),
+            ]
+        );
+        assert!(statements.iter().all(|statement| {
+            statement.references().iter().all(|reference| {
+                reference
+                    .snaks()
+                    .iter()
+                    .any(|snak| snak.property() == "P887")
+            })
+        }));
+    }
+
+    #[test]
+    fn test_wikidata_external_id() {
+        assert_eq!(
+            wikidata_external_id("P213", "0000000121258167"),
+            "0000 0001 2125 8167"
+        );
+        assert_eq!(
+            wikidata_external_id("P213", "000000012125816X"),
+            "0000 0001 2125 816X"
+        );
+        assert_eq!(wikidata_external_id("P213", "12345"), "12345");
+        assert_eq!(wikidata_external_id("P244", "n  80076765"), "n80076765");
+        assert_eq!(wikidata_external_id("P244", "n79-1234"), "n79001234");
+        assert_eq!(wikidata_external_id("P227", "119033364"), "119033364");
+    }
+
+    #[test]
+    fn test_parse_cluster_statements_without_namespace() {
+        let value = json!({"sources": {"source": {"#text": "DNB|119033364"}}});
+        let statements = parse_cluster_statements("113230702", &value).unwrap();
+        assert_eq!(statements.len(), 2);
+        assert!(parse_cluster_statements("113230702", &json!({})).is_none());
+    }
+
+    #[test]
+    fn test_is_valid_viaf_id() {
+        assert!(is_valid_viaf_id("113230702"));
+        assert!(!is_valid_viaf_id(""));
+        assert!(!is_valid_viaf_id("11323/../1"));
+    }
 }
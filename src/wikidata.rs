@@ -1,20 +1,185 @@
+use crate::config::{Config, DEFAULT_SOURCE_TIMEOUT};
+use crate::fetcher::ContentFetcher;
+use crate::rate_limit::RateLimiter;
+use crate::retry::Retry;
+use anyhow::Result;
 use axum::http::StatusCode;
+use futures::future::{BoxFuture, join_all};
 use mediawiki::{Api, hashmap};
+use reqwest::{Client, RequestBuilder};
+use serde_json::Value;
 use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+use tokio::sync::RwLock;
 use wikibase::Snak;
 
+/// `wbgetentities` accepts at most this many IDs per request
+const LABEL_BATCH_SIZE: usize = 50;
+/// Labels are loaded for at most this many items; the others are left out
+const MAX_LABEL_ITEMS: usize = 500;
+const WIKIDATA_API_URL: &str = "https://www.wikidata.org/w/api.php";
+
+static HTTP_CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    Client::builder()
+        .user_agent(Config::user_agent())
+        .timeout(Config::timeout("sparql", DEFAULT_SOURCE_TIMEOUT))
+        .build()
+        .expect("Failed to build SPARQL HTTP client")
+});
+
+/// Wikidata items for the ISO 639-1 codes in `language_items.json`
+static LANGUAGE_ITEMS: LazyLock<HashMap<String, String>> = LazyLock::new(|| {
+    let json_string = include_str!("../static/language_items.json");
+    serde_json::from_str(json_string).unwrap()
+});
+
+/// Shared Wikidata API; creating one fetches siteinfo, so it is only done once.
+static WIKIDATA_API: LazyLock<RwLock<Option<Arc<Api>>>> = LazyLock::new(|| RwLock::new(None));
+
+/// Runs SPARQL queries, so tests can count them and use canned results.
+/// Returns a boxed future, so the fetcher can be held as `dyn SparqlFetcher`.
+pub trait SparqlFetcher: std::fmt::Debug + Send + Sync {
+    fn query<'a>(&'a self, sparql: &'a str) -> BoxFuture<'a, Result<Value>>;
+}
+
+/// Runs queries with `Wikidata::sparql_query`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct QueryService;
+
+impl SparqlFetcher for QueryService {
+    fn query<'a>(&'a self, sparql: &'a str) -> BoxFuture<'a, Result<Value>> {
+        Box::pin(Wikidata::sparql_query(sparql))
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Wikidata;
 
 impl Wikidata {
+    /// The language item for an ISO 639-1 code, like Q1860 for "en"
+    pub fn language_item(code: &str) -> Option<&'static str> {
+        LANGUAGE_ITEMS.get(code).map(String::as_str)
+    }
+
+    /// The ISO 639-1 code for a language item, like "en" for Q1860
+    pub fn language_code(item: &str) -> Option<&'static str> {
+        LANGUAGE_ITEMS
+            .iter()
+            .find(|(_, language_item)| *language_item == item)
+            .map(|(code, _)| code.as_str())
+    }
+
     pub fn infernal_reference_snak() -> Snak {
         Snak::new_item("P887", "Q131287902") // based on heuristic: Wikidata Infernal
     }
 
-    pub async fn get_wikidata_api() -> Result<Api, StatusCode> {
-        Api::new("https://www.wikidata.org/w/api.php")
+    /// Returns the shared Wikidata API, creating it if needed.
+    pub async fn get_wikidata_api() -> Result<Arc<Api>, StatusCode> {
+        Self::shared_api(&WIKIDATA_API, WIKIDATA_API_URL).await
+    }
+
+    /// The API in `cached`, or a new one for `api_url` that is kept there
+    async fn shared_api(
+        cached: &RwLock<Option<Arc<Api>>>,
+        api_url: &str,
+    ) -> Result<Arc<Api>, StatusCode> {
+        // Fast path: read lock
+        if let Some(api) = cached.read().await.as_ref() {
+            return Ok(api.clone());
+        }
+
+        let mut cached = cached.write().await;
+        // Another task may have created it while we waited for the write lock
+        if let Some(api) = cached.as_ref() {
+            return Ok(api.clone());
+        }
+        let api = Api::new(api_url)
             .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let api = Arc::new(api);
+        *cached = Some(api.clone());
+        Ok(api)
+    }
+
+    /// Drops the shared Wikidata API; the next `get_wikidata_api` call creates a new one.
+    pub async fn invalidate_wikidata_api() {
+        *WIKIDATA_API.write().await = None;
+    }
+
+    /// Runs a SPARQL query on `sparql_endpoint` from the configuration, so queries can go to
+    /// a mirror, or to one of the split graphs of the query service.
+    pub async fn sparql_query(sparql: &str) -> Result<Value> {
+        let endpoint = Config::sparql_endpoint();
+        let response = Retry::from_config()
+            .run(
+                || async {
+                    RateLimiter::global().wait(endpoint).await;
+                    Self::sparql_request(&HTTP_CLIENT, endpoint, sparql)
+                        .send()
+                        .await
+                },
+                Retry::is_retryable_response,
+            )
+            .await?
+            .error_for_status()?;
+        let text = response.text().await?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    fn sparql_request(client: &Client, endpoint: &str, sparql: &str) -> RequestBuilder {
+        client
+            .post(endpoint)
+            .header(reqwest::header::ACCEPT, "application/sparql-results+json")
+            .form(&[("query", sparql), ("format", "json")])
+    }
+
+    /// Labels of `items` in `language`, or in English if there is none, by item ID.
+    /// Items without either label, or from a failed request, are left out.
+    pub async fn labels(
+        fetcher: &dyn ContentFetcher,
+        items: &[String],
+        language: &str,
+    ) -> HashMap<String, String> {
+        let languages = Self::label_languages(language);
+        let items = &items[..items.len().min(MAX_LABEL_ITEMS)];
+        let urls = items.chunks(LABEL_BATCH_SIZE).map(|batch| {
+            format!(
+                "https://www.wikidata.org/w/api.php?action=wbgetentities&format=json&props=labels&ids={}&languages={}",
+                urlencoding::encode(&batch.join("|")),
+                urlencoding::encode(&languages.join("|"))
+            )
+        });
+        let results = join_all(urls.map(|url| async move { fetcher.fetch(&url).await })).await;
+        results
+            .into_iter()
+            .filter_map(|result| serde_json::from_str::<Value>(&result.ok()?).ok())
+            .flat_map(|json| Self::parse_labels(&json, &languages))
+            .collect()
+    }
+
+    fn label_languages(language: &str) -> Vec<String> {
+        let language = language.trim().to_lowercase();
+        if language.is_empty() || language == "en" {
+            vec!["en".to_string()]
+        } else {
+            vec![language, "en".to_string()]
+        }
+    }
+
+    /// The label in the first of `languages` that has one, for each entity of a
+    /// `wbgetentities` result
+    fn parse_labels(json: &Value, languages: &[String]) -> Vec<(String, String)> {
+        json["entities"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(id, entity)| {
+                let label = languages
+                    .iter()
+                    .find_map(|language| entity["labels"][language]["value"].as_str())?;
+                Some((id.to_owned(), label.to_string()))
+            })
+            .collect()
     }
 
     /// Perform a Wikidata API search and return matching item titles.
@@ -62,7 +227,7 @@ impl Wikidata {
           }}"#
         );
 
-        let json = match api.sparql_query(&sparql).await {
+        let json = match Self::sparql_query(&sparql).await {
             Ok(json) => json,
             Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
         };
@@ -94,6 +259,75 @@ mod tests {
         assert_eq!(results, vec!["Q1891133"]);
     }
 
+    #[tokio::test]
+    async fn test_shared_api_loads_siteinfo_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let hits = Arc::new(AtomicUsize::new(0));
+        let api_hits = hits.clone();
+        let api_url = crate::test_server::wiki_api(move |params| {
+            if params.get("meta").map(String::as_str) == Some("siteinfo") {
+                api_hits.fetch_add(1, Ordering::SeqCst);
+            }
+            None
+        })
+        .await;
+        let cached = RwLock::new(None);
+        let api1 = Wikidata::shared_api(&cached, &api_url).await.unwrap();
+        let api2 = Wikidata::shared_api(&cached, &api_url).await.unwrap();
+        assert!(Arc::ptr_eq(&api1, &api2));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_language_item_and_code() {
+        assert_eq!(Wikidata::language_item("en"), Some("Q1860"));
+        assert_eq!(Wikidata::language_code("Q188"), Some("de"));
+        assert_eq!(Wikidata::language_item("xx"), None);
+        assert_eq!(Wikidata::language_code("Q42"), None);
+    }
+
+    #[test]
+    fn test_parse_labels_falls_back_to_english() {
+        let json = serde_json::json!({"entities": {
+            "Q42": {"labels": {"de": {"language": "de", "value": "Douglas Adams (Autor)"}}},
+            "Q64": {"labels": {"en": {"language": "en", "value": "Berlin"}}},
+            "Q1": {"labels": {}},
+            "Q2": {"missing": ""}
+        }});
+        let languages = Wikidata::label_languages("de");
+        let mut labels = Wikidata::parse_labels(&json, &languages);
+        labels.sort();
+        assert_eq!(
+            labels,
+            vec![
+                ("Q42".to_string(), "Douglas Adams (Autor)".to_string()),
+                ("Q64".to_string(), "Berlin".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sparql_request_uses_endpoint() {
+        let endpoint = "https://query-scholarly.wikidata.org/sparql";
+        let request = Wikidata::sparql_request(&HTTP_CLIENT, endpoint, "SELECT ?q {}")
+            .build()
+            .unwrap();
+        assert_eq!(request.method(), reqwest::Method::POST);
+        assert_eq!(request.url().as_str(), endpoint);
+        let body = request.body().and_then(|body| body.as_bytes()).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(body),
+            "query=SELECT+%3Fq+%7B%7D&format=json"
+        );
+    }
+
+    #[test]
+    fn test_label_languages() {
+        assert_eq!(Wikidata::label_languages("en"), vec!["en"]);
+        assert_eq!(Wikidata::label_languages(""), vec!["en"]);
+        assert_eq!(Wikidata::label_languages("DE"), vec!["de", "en"]);
+    }
+
     #[tokio::test]
     async fn test_wd_infernal_reference() {
         let snak = Wikidata::infernal_reference_snak();